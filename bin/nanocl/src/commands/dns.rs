@@ -0,0 +1,40 @@
+use nanocld_client::NanocldClient;
+
+use crate::utils::print::*;
+use crate::error::CliError;
+use crate::models::{DnsArgs, DnsCommands, DnsResolveOpts, DnsEntryRow};
+
+async fn exec_dns_ls(client: &NanocldClient) -> Result<(), CliError> {
+  let items = client.list_dns_records().await?;
+  let entries = items
+    .into_iter()
+    .map(DnsEntryRow::from)
+    .collect::<Vec<DnsEntryRow>>();
+  print_table(entries);
+  Ok(())
+}
+
+async fn exec_dns_resolve(
+  client: &NanocldClient,
+  options: &DnsResolveOpts,
+) -> Result<(), CliError> {
+  let resolve = client.resolve_dns(&options.name).await?;
+  print_yml(resolve)?;
+  Ok(())
+}
+
+async fn exec_dns_sync(client: &NanocldClient) -> Result<(), CliError> {
+  client.sync_dns().await?;
+  Ok(())
+}
+
+pub async fn exec_dns(
+  client: &NanocldClient,
+  args: &DnsArgs,
+) -> Result<(), CliError> {
+  match &args.commands {
+    DnsCommands::List => exec_dns_ls(client).await,
+    DnsCommands::Resolve(options) => exec_dns_resolve(client, options).await,
+    DnsCommands::Sync => exec_dns_sync(client).await,
+  }
+}
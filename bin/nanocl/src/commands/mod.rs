@@ -10,6 +10,7 @@ mod setup;
 mod vm;
 mod vm_image;
 mod system;
+mod dns;
 
 pub use system::exec_process;
 pub use namespace::exec_namespace;
@@ -22,3 +23,4 @@ pub use state::exec_state;
 pub use info::exec_info;
 pub use setup::exec_setup;
 pub use vm::exec_vm;
+pub use dns::exec_dns;
@@ -1,16 +1,33 @@
 use dialoguer::Confirm;
 use dialoguer::theme::ColorfulTheme;
 use nanocld_client::NanocldClient;
+use nanocld_client::stubs::resource::{ResourceQuery, ResourcePatch};
+use nanocld_client::stubs::proxy::{ResourceProxyRule, ProxyRule, LocationTarget};
 
 use crate::utils::print::*;
+use crate::utils::proxy::{parse_weight, set_weight, shift_weight};
 use crate::error::CliError;
 use crate::models::{
   ResourceArgs, ResourceCommands, ResourceRow, ResourceRemoveOpts,
   ResourceInspectOpts, ResourceResetOpts, ResourceHistoryOpts,
+  ResourceMigrateOpts, ResourcePruneOpts, ResourceListOpts, ResourcePatchOpts,
 };
 
-async fn exec_resource_ls(client: &NanocldClient) -> Result<(), CliError> {
-  let resources = client.list_resource(None).await?;
+async fn exec_resource_ls(
+  client: &NanocldClient,
+  args: &ResourceArgs,
+  opts: &ResourceListOpts,
+) -> Result<(), CliError> {
+  let query = ResourceQuery {
+    namespace: args.namespace.clone(),
+    kind: opts.kind.clone(),
+    labels: opts.labels.clone(),
+    config_path: opts.config_path.clone(),
+    limit: opts.limit,
+    offset: opts.offset,
+    ..Default::default()
+  };
+  let resources = client.list_resource(Some(query)).await?;
 
   let row = resources
     .into_iter()
@@ -23,6 +40,7 @@ async fn exec_resource_ls(client: &NanocldClient) -> Result<(), CliError> {
 
 async fn exec_resource_rm(
   client: &NanocldClient,
+  args: &ResourceArgs,
   options: &ResourceRemoveOpts,
 ) -> Result<(), CliError> {
   if !options.skip_confirm {
@@ -40,7 +58,7 @@ async fn exec_resource_rm(
     }
   }
   for name in &options.names {
-    client.delete_resource(name).await?;
+    client.delete_resource(name, args.namespace.clone()).await?;
   }
 
   Ok(())
@@ -48,9 +66,12 @@ async fn exec_resource_rm(
 
 async fn exec_resource_inspect(
   client: &NanocldClient,
+  args: &ResourceArgs,
   opts: &ResourceInspectOpts,
 ) -> Result<(), CliError> {
-  let resource = client.inspect_resource(&opts.name).await?;
+  let resource = client
+    .inspect_resource(&opts.name, args.namespace.clone())
+    .await?;
 
   print_yml(resource)?;
   Ok(())
@@ -58,9 +79,12 @@ async fn exec_resource_inspect(
 
 async fn exec_resource_history(
   client: &NanocldClient,
+  args: &ResourceArgs,
   opts: &ResourceHistoryOpts,
 ) -> Result<(), CliError> {
-  let history = client.list_history_resource(&opts.name).await?;
+  let history = client
+    .list_history_resource(&opts.name, args.namespace.clone())
+    .await?;
 
   print_yml(history)?;
   Ok(())
@@ -68,9 +92,131 @@ async fn exec_resource_history(
 
 async fn exec_resource_reset(
   client: &NanocldClient,
+  args: &ResourceArgs,
   opts: &ResourceResetOpts,
 ) -> Result<(), CliError> {
-  let resource = client.reset_resource(&opts.name, &opts.key).await?;
+  let resource = client
+    .reset_resource(&opts.name, &opts.key, args.namespace.clone())
+    .await?;
+
+  print_yml(resource)?;
+  Ok(())
+}
+
+async fn exec_resource_migrate(
+  client: &NanocldClient,
+  opts: &ResourceMigrateOpts,
+) -> Result<(), CliError> {
+  let resources = client.migrate_resource(&opts.kind, &opts.to).await?;
+
+  let row = resources
+    .into_iter()
+    .map(ResourceRow::from)
+    .collect::<Vec<ResourceRow>>();
+
+  print_table(row);
+  Ok(())
+}
+
+async fn exec_resource_prune(
+  client: &NanocldClient,
+  opts: &ResourcePruneOpts,
+) -> Result<(), CliError> {
+  if !opts.skip_confirm {
+    let query = ResourceQuery {
+      orphaned: Some(true),
+      ..Default::default()
+    };
+    let resources = client.list_resource(Some(query)).await?;
+    if resources.is_empty() {
+      return Ok(());
+    }
+    let keys = resources
+      .into_iter()
+      .map(|resource| resource.key)
+      .collect::<Vec<String>>();
+    let result = Confirm::with_theme(&ColorfulTheme::default())
+      .with_prompt(format!("Delete orphaned resources {}?", keys.join(",")))
+      .default(false)
+      .interact();
+    match result {
+      Ok(true) => {}
+      _ => {
+        return Err(CliError::Custom {
+          msg: "Aborted".into(),
+        })
+      }
+    }
+  }
+  let resources = client.prune_resource().await?;
+
+  let row = resources
+    .into_iter()
+    .map(ResourceRow::from)
+    .collect::<Vec<ResourceRow>>();
+
+  print_table(row);
+  Ok(())
+}
+
+async fn exec_resource_patch(
+  client: &NanocldClient,
+  args: &ResourceArgs,
+  opts: &ResourcePatchOpts,
+) -> Result<(), CliError> {
+  let weights = opts
+    .weights
+    .iter()
+    .map(|weight| parse_weight(weight))
+    .collect::<Result<Vec<_>, _>>()?;
+  let shift = opts.shift.as_deref().map(parse_weight).transpose()?;
+  let resource = client
+    .inspect_resource(&opts.name, args.namespace.clone())
+    .await?;
+  if resource.kind != "ProxyRule" {
+    return Err(CliError::Custom {
+      msg: format!("Resource {} is not a ProxyRule", opts.name),
+    });
+  }
+  let mut proxy_rule =
+    serde_json::from_value::<ResourceProxyRule>(resource.config)?;
+  let ProxyRule::Http(rule) = &mut proxy_rule.rule else {
+    return Err(CliError::Custom {
+      msg: format!("Resource {} is not an http proxy rule", opts.name),
+    });
+  };
+  let mut patched = 0;
+  for location in rule.locations.iter_mut() {
+    if opts
+      .path
+      .as_ref()
+      .map_or(false, |path| path != &location.path)
+    {
+      continue;
+    }
+    let LocationTarget::Split(targets) = &mut location.target else {
+      continue;
+    };
+    for (key, weight) in &weights {
+      set_weight(targets, key, *weight)?;
+    }
+    if let Some((key, step)) = &shift {
+      shift_weight(targets, key, *step)?;
+    }
+    patched += 1;
+  }
+  if patched == 0 {
+    return Err(CliError::Custom {
+      msg: format!("Resource {} has no split target to patch", opts.name),
+    });
+  }
+  let patch = ResourcePatch {
+    version: resource.version,
+    config: serde_json::to_value(proxy_rule)?,
+  };
+  let resource = client
+    .patch_resource(&opts.name, &patch, args.namespace.clone())
+    .await?;
 
   print_yml(resource)?;
   Ok(())
@@ -82,14 +228,25 @@ pub async fn exec_resource(
 ) -> Result<(), CliError> {
   match &args.commands {
     // ResourceCommands::Create(opts) => exec_create(client, opts).await,
-    ResourceCommands::List => exec_resource_ls(client).await,
-    ResourceCommands::Remove(opts) => exec_resource_rm(client, opts).await,
+    ResourceCommands::List(opts) => exec_resource_ls(client, args, opts).await,
+    ResourceCommands::Remove(opts) => {
+      exec_resource_rm(client, args, opts).await
+    }
     ResourceCommands::Inspect(opts) => {
-      exec_resource_inspect(client, opts).await
+      exec_resource_inspect(client, args, opts).await
     }
     ResourceCommands::History(opts) => {
-      exec_resource_history(client, opts).await
+      exec_resource_history(client, args, opts).await
+    }
+    ResourceCommands::Reset(opts) => {
+      exec_resource_reset(client, args, opts).await
+    }
+    ResourceCommands::Migrate(opts) => {
+      exec_resource_migrate(client, opts).await
+    }
+    ResourceCommands::Prune(opts) => exec_resource_prune(client, opts).await,
+    ResourceCommands::Patch(opts) => {
+      exec_resource_patch(client, args, opts).await
     }
-    ResourceCommands::Reset(opts) => exec_resource_reset(client, opts).await,
   }
 }
@@ -130,6 +130,8 @@ async fn install_dependencies(
   install_image("ghcr.io/nxthat/nanocld", version, docker_api).await?;
   install_image("ghcr.io/nxthat/nproxy", "1.23.4.0", docker_api).await?;
   install_image("ghcr.io/nxthat/ncdproxy", "0.3.1", docker_api).await?;
+  install_image("ghcr.io/nxthat/ndns", "0.0.1", docker_api).await?;
+  install_image("ghcr.io/nxthat/ncddns", "0.1.0", docker_api).await?;
   println!("Dependencies installed.");
   Ok(())
 }
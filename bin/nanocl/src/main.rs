@@ -32,6 +32,7 @@ async fn execute_args(args: &Cli) -> Result<(), CliError> {
     Commands::Setup(opts) => commands::exec_setup(opts).await,
     Commands::Vm(args) => commands::exec_vm(&client, args).await,
     Commands::Ps(opts) => commands::exec_process(&client, opts).await,
+    Commands::Dns(args) => commands::exec_dns(&client, args).await,
   }
 }
 
@@ -187,6 +188,15 @@ mod tests {
     // List resources
     let args = Cli::parse_from(["nanocl", "resource", "ls"]);
     assert!(execute_args(&args).await.is_ok());
+    // Find resources by config
+    let args = Cli::parse_from([
+      "nanocl",
+      "resource",
+      "ls",
+      "--config-path",
+      "$.Rule.Http.Domain=deploy-example.com",
+    ]);
+    assert!(execute_args(&args).await.is_ok());
     // Inspect resource
     let args =
       Cli::parse_from(["nanocl", "resource", "inspect", "resource-example"]);
@@ -199,7 +209,7 @@ mod tests {
 
     let client = NanocldClient::connect_with_unix_default();
     let history = client
-      .list_history_resource("resource-example")
+      .list_history_resource("resource-example", None)
       .await
       .unwrap()
       .first()
@@ -215,6 +225,58 @@ mod tests {
     ]);
     assert!(execute_args(&args).await.is_ok());
 
+    // Patch the weights of a split target
+    let args = Cli::parse_from([
+      "nanocl",
+      "state",
+      "apply",
+      "-yf",
+      "../../examples/resource_split_example.yml",
+    ]);
+    assert!(execute_args(&args).await.is_ok());
+    let args = Cli::parse_from([
+      "nanocl",
+      "resource",
+      "patch",
+      "split-example",
+      "--path",
+      "/",
+      "--weight",
+      "deploy-example2.global=20",
+      "--shift",
+      "deploy-example2.global=10",
+    ]);
+    assert!(execute_args(&args).await.is_ok());
+    let split = client
+      .inspect_resource("split-example", None)
+      .await
+      .unwrap()
+      .config["Rule"]["Http"]["Locations"][0]["Target"]["Split"]
+      .clone();
+    assert_eq!(split[0]["Weight"], 80);
+    assert_eq!(split[1]["Weight"], 30);
+    let args = Cli::parse_from([
+      "nanocl",
+      "resource",
+      "patch",
+      "split-example",
+      "--weight",
+      "unknown.global=20",
+    ]);
+    assert!(execute_args(&args).await.is_err());
+    let args = Cli::parse_from([
+      "nanocl",
+      "resource",
+      "patch",
+      "resource-example",
+      "--shift",
+      "deploy-example.global=10",
+    ]);
+    assert!(execute_args(&args).await.is_err());
+    let args =
+      Cli::parse_from(["nanocl", "resource", "rm", "-y", "split-example"]);
+    assert!(execute_args(&args).await.is_ok());
+
     // Remove resource
     let args =
       Cli::parse_from(["nanocl", "resource", "rm", "-y", "resource-example"]);
@@ -294,6 +356,36 @@ mod tests {
     assert!(execute_args(&args).await.is_ok());
   }
 
+  /// Test Dns commands
+  #[ntex::test]
+  async fn dns() {
+    use crate::models::DnsCommands;
+
+    let args = Cli::parse_from(["nanocl", "dns", "ls"]);
+    assert!(matches!(
+      &args.command,
+      Commands::Dns(dns) if matches!(dns.commands, DnsCommands::List)
+    ));
+    // A name is required to resolve
+    assert!(Cli::try_parse_from(["nanocl", "dns", "resolve"]).is_err());
+    // Try to sync the dns entries
+    let args = Cli::parse_from(["nanocl", "dns", "sync"]);
+    assert!(execute_args(&args).await.is_ok());
+    // Try to list the dns entries
+    let args = Cli::parse_from(["nanocl", "dns", "ls"]);
+    assert!(execute_args(&args).await.is_ok());
+    // Try to resolve a name of the zone and a forwarded name
+    let args = Cli::parse_from([
+      "nanocl",
+      "dns",
+      "resolve",
+      "nstore.system.nanocl.internal",
+    ]);
+    assert!(execute_args(&args).await.is_ok());
+    let args = Cli::parse_from(["nanocl", "dns", "resolve", "nanocl.io"]);
+    assert!(execute_args(&args).await.is_ok());
+  }
+
   #[ntex::test]
   async fn info() {
     let args = Cli::parse_from(["nanocl", "info"]);
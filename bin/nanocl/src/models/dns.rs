@@ -0,0 +1,49 @@
+use tabled::Tabled;
+use clap::{Parser, Subcommand};
+
+use nanocld_client::stubs::dns::DnsEntrySummary;
+
+/// Dns commands
+#[derive(Debug, Subcommand)]
+pub enum DnsCommands {
+  /// List the entries served by the dns controller
+  #[clap(alias("ls"))]
+  List,
+  /// Show what a name resolve to and why
+  Resolve(DnsResolveOpts),
+  /// Force the dns controller to sync its entries
+  Sync,
+}
+
+/// Manage the dns controller
+#[derive(Debug, Parser)]
+#[clap(name = "nanocl dns")]
+pub struct DnsArgs {
+  #[clap(subcommand)]
+  pub commands: DnsCommands,
+}
+
+#[derive(Debug, Parser)]
+pub struct DnsResolveOpts {
+  /// name to resolve
+  pub name: String,
+}
+
+#[derive(Tabled)]
+pub struct DnsEntryRow {
+  pub(crate) source: String,
+  pub(crate) kind: String,
+  pub(crate) name: String,
+  pub(crate) value: String,
+}
+
+impl From<DnsEntrySummary> for DnsEntryRow {
+  fn from(item: DnsEntrySummary) -> Self {
+    Self {
+      source: item.source,
+      kind: item.kind,
+      name: item.name,
+      value: item.value,
+    }
+  }
+}
@@ -8,6 +8,7 @@ mod setup;
 mod vm;
 mod vm_image;
 mod system;
+mod dns;
 
 pub use system::*;
 pub use vm::*;
@@ -19,6 +20,7 @@ pub use resource::*;
 pub use version::*;
 pub use state::*;
 pub use setup::*;
+pub use dns::*;
 
 use clap::{Parser, Subcommand};
 
@@ -44,6 +46,8 @@ pub enum Commands {
   Vm(VmArgs),
   /// Manage resources
   Resource(ResourceArgs),
+  /// Manage the dns controller
+  Dns(DnsArgs),
   /// Watch daemon events
   Events,
   /// Apply or Reverse a state from a configuration file
@@ -12,19 +12,28 @@ pub enum ResourceCommands {
   Remove(ResourceRemoveOpts),
   /// List existing namespaces
   #[clap(alias("ls"))]
-  List,
+  List(ResourceListOpts),
   /// Inspect a resource
   Inspect(ResourceInspectOpts),
   /// Browse history of a resource
   History(ResourceHistoryOpts),
   /// Reset a resource to a specific history
   Reset(ResourceResetOpts),
+  /// Migrate every resource of a kind to a new version
+  Migrate(ResourceMigrateOpts),
+  /// Remove resources whose owners are all gone
+  Prune(ResourcePruneOpts),
+  /// Change the weights of the split targets of a proxy rule
+  Patch(ResourcePatchOpts),
 }
 
 /// Manage resources
 #[derive(Debug, Parser)]
 #[clap(name = "nanocl-resource")]
 pub struct ResourceArgs {
+  /// namespace to target by default resources are not namespaced
+  #[clap(long, short)]
+  pub namespace: Option<String>,
   #[clap(subcommand)]
   pub commands: ResourceCommands,
 }
@@ -32,6 +41,7 @@ pub struct ResourceArgs {
 #[derive(Debug, Tabled)]
 pub struct ResourceRow {
   pub name: String,
+  pub namespace: String,
   pub kind: String,
   pub config_version: String,
   pub created_at: String,
@@ -55,6 +65,7 @@ impl From<Resource> for ResourceRow {
 
     Self {
       name: resource.name,
+      namespace: resource.namespace_name.unwrap_or_default(),
       config_version: resource.version,
       kind: resource.kind,
       created_at: format!("{created_at}"),
@@ -63,6 +74,26 @@ impl From<Resource> for ResourceRow {
   }
 }
 
+#[derive(Debug, Parser)]
+pub struct ResourceListOpts {
+  /// Filter by kind
+  #[clap(long, short)]
+  pub kind: Option<String>,
+  /// Label selector like env=prod,tier!=db
+  #[clap(long, short)]
+  pub labels: Option<String>,
+  /// Json path predicates on the config like $.Rule.Http.Domain=example.com
+  /// use := to match a json value like $.Port:=80
+  #[clap(long)]
+  pub config_path: Option<String>,
+  /// Max amount of resources to list
+  #[clap(long)]
+  pub limit: Option<i64>,
+  /// Offset of the first resource to list
+  #[clap(long)]
+  pub offset: Option<i64>,
+}
+
 #[derive(Debug, Parser)]
 pub struct ResourceRemoveOpts {
   /// Skip confirmation
@@ -91,3 +122,34 @@ pub struct ResourceResetOpts {
   /// The key of the history to reset to
   pub key: String,
 }
+
+#[derive(Debug, Parser)]
+pub struct ResourcePruneOpts {
+  /// Skip confirmation
+  #[clap(short = 'y')]
+  pub skip_confirm: bool,
+}
+
+#[derive(Debug, Parser)]
+pub struct ResourceMigrateOpts {
+  /// The kind of the resources to migrate
+  pub kind: String,
+  /// The version of the kind to migrate to
+  #[clap(long)]
+  pub to: String,
+}
+
+#[derive(Debug, Parser)]
+pub struct ResourcePatchOpts {
+  /// The name of the proxy rule to patch
+  pub name: String,
+  /// Only patch the location with this path
+  #[clap(long)]
+  pub path: Option<String>,
+  /// Set the weight of a cargo like app-v2.prod=20
+  #[clap(long = "weight")]
+  pub weights: Vec<String>,
+  /// Move weight from the other cargoes to a cargo like app-v2.prod=10
+  #[clap(long)]
+  pub shift: Option<String>,
+}
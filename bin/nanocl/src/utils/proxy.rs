@@ -0,0 +1,125 @@
+use nanocld_client::stubs::proxy::SplitTarget;
+
+use crate::error::CliError;
+
+/// Parse a weight argument like app-v2.prod=20
+pub fn parse_weight(arg: &str) -> Result<(String, u16), CliError> {
+  let invalid = || CliError::Custom {
+    msg: format!("Invalid weight {arg} expect cargo_key=weight"),
+  };
+  let (key, weight) = arg.split_once('=').ok_or_else(invalid)?;
+  let weight = weight.parse::<u16>().map_err(|_| invalid())?;
+  Ok((key.to_owned(), weight))
+}
+
+fn find_target<'a>(
+  targets: &'a mut [SplitTarget],
+  key: &str,
+) -> Result<&'a mut SplitTarget, CliError> {
+  targets
+    .iter_mut()
+    .find(|target| target.cargo.key == key)
+    .ok_or_else(|| CliError::Custom {
+      msg: format!("Cargo {key} is not a split target"),
+    })
+}
+
+/// Set the weight of the cargo with the given key
+pub fn set_weight(
+  targets: &mut [SplitTarget],
+  key: &str,
+  weight: u16,
+) -> Result<(), CliError> {
+  find_target(targets, key)?.weight = weight;
+  Ok(())
+}
+
+/// Move `step` of weight from the other cargoes to the cargo with the given key
+/// The weight is taken from the heaviest cargoes first
+pub fn shift_weight(
+  targets: &mut [SplitTarget],
+  key: &str,
+  step: u16,
+) -> Result<(), CliError> {
+  find_target(targets, key)?;
+  let mut moved = 0;
+  while moved < step {
+    let Some(heaviest) = targets
+      .iter_mut()
+      .filter(|target| target.cargo.key != key && target.weight > 0)
+      .max_by_key(|target| target.weight)
+    else {
+      break;
+    };
+    heaviest.weight -= 1;
+    moved += 1;
+  }
+  let target = find_target(targets, key)?;
+  target.weight = target.weight.saturating_add(moved);
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn targets(weights: &[(&str, u16)]) -> Vec<SplitTarget> {
+    weights
+      .iter()
+      .map(|(key, weight)| {
+        serde_json::from_value(serde_json::json!({
+          "Weight": weight,
+          "Cargo": { "Key": key, "Port": 9000 },
+        }))
+        .unwrap()
+      })
+      .collect()
+  }
+
+  fn weights(targets: &[SplitTarget]) -> Vec<u16> {
+    targets.iter().map(|target| target.weight).collect()
+  }
+
+  #[test]
+  fn parse() {
+    assert_eq!(
+      parse_weight("app-v2.prod=20").unwrap(),
+      ("app-v2.prod".to_owned(), 20)
+    );
+    assert!(parse_weight("app-v2.prod").is_err());
+    assert!(parse_weight("app-v2.prod=-1").is_err());
+    assert!(parse_weight("app-v2.prod=heavy").is_err());
+  }
+
+  #[test]
+  fn set() {
+    let mut split = targets(&[("app-v1.prod", 90), ("app-v2.prod", 10)]);
+    set_weight(&mut split, "app-v2.prod", 20).unwrap();
+    assert_eq!(weights(&split), [90, 20]);
+    assert!(set_weight(&mut split, "app-v3.prod", 20).is_err());
+    assert_eq!(weights(&split), [90, 20]);
+  }
+
+  #[test]
+  fn shift() {
+    let mut split = targets(&[
+      ("app-v1.prod", 60),
+      ("app-v2.prod", 30),
+      ("app-v3.prod", 10),
+    ]);
+    // The weight is taken from the heaviest cargo
+    shift_weight(&mut split, "app-v3.prod", 20).unwrap();
+    assert_eq!(weights(&split), [40, 30, 30]);
+    // then from the heaviest one in turn
+    shift_weight(&mut split, "app-v3.prod", 15).unwrap();
+    assert_eq!(weights(&split), [28, 27, 45]);
+    // The weights don't go below zero
+    shift_weight(&mut split, "app-v3.prod", 100).unwrap();
+    assert_eq!(weights(&split), [0, 0, 100]);
+    shift_weight(&mut split, "app-v3.prod", 10).unwrap();
+    assert_eq!(weights(&split), [0, 0, 100]);
+    // An unknown cargo is refused without changing the weights
+    assert!(shift_weight(&mut split, "app-v4.prod", 10).is_err());
+    assert_eq!(weights(&split), [0, 0, 100]);
+  }
+}
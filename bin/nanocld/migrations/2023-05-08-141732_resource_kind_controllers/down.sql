@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE "resource_kind_versions" DROP COLUMN IF EXISTS "url";
+ALTER TABLE "resource_kind_versions" ALTER COLUMN "schema" SET NOT NULL;
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE "resource_kind_versions" ALTER COLUMN "schema" DROP NOT NULL;
+ALTER TABLE "resource_kind_versions" ADD COLUMN IF NOT EXISTS "url" VARCHAR;
@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE "resource_kind_versions" DROP COLUMN IF EXISTS "migration";
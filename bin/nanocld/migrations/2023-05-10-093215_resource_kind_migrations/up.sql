@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE "resource_kind_versions" ADD COLUMN IF NOT EXISTS "migration" JSONB;
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE "resources" DROP COLUMN IF EXISTS "namespace_name";
+ALTER TABLE "resources" DROP COLUMN IF EXISTS "name";
@@ -0,0 +1,5 @@
+-- Your SQL goes here
+ALTER TABLE "resources" ADD COLUMN IF NOT EXISTS "name" VARCHAR;
+UPDATE "resources" SET "name" = "key" WHERE "name" IS NULL;
+ALTER TABLE "resources" ALTER COLUMN "name" SET NOT NULL;
+ALTER TABLE "resources" ADD COLUMN IF NOT EXISTS "namespace_name" VARCHAR REFERENCES namespaces("name");
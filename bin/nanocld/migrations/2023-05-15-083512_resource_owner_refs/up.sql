@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE "resources" ADD COLUMN IF NOT EXISTS "owner_refs" JSONB NOT NULL DEFAULT '[]';
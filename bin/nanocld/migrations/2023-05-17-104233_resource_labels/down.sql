@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS "resource_configs_data_idx";
+DROP INDEX IF EXISTS "resources_labels_idx";
+ALTER TABLE "resources" DROP COLUMN IF EXISTS "labels";
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+ALTER TABLE "resources" ADD COLUMN IF NOT EXISTS "labels" JSONB NOT NULL DEFAULT '{}';
+CREATE INDEX IF NOT EXISTS "resources_labels_idx" ON "resources" USING GIN ("labels");
+CREATE INDEX IF NOT EXISTS "resource_configs_data_idx" ON "resource_configs" USING GIN ("data");
@@ -0,0 +1,16 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS stream_metrics (
+  "key" UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+  "created_at" TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  "expire_at" TIMESTAMPTZ NOT NULL DEFAULT NOW() + INTERVAL '4 month',
+  "date_gmt" TIMESTAMPTZ NOT NULL,
+  "session_time" FLOAT8 NOT NULL,
+  "bytes_sent" INT NOT NULL,
+  "bytes_received" INT NOT NULL,
+  "status" INT NOT NULL,
+  "server_port" INT NOT NULL,
+  "protocol" VARCHAR NOT NULL,
+  "node_name" VARCHAR NOT NULL,
+  "remote_addr" VARCHAR NOT NULL,
+  "upstream_addr" VARCHAR
+) WITH (ttl_expiration_expression = 'expire_at');
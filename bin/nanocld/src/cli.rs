@@ -35,6 +35,10 @@ pub struct Cli {
   /// Address to advertise to other nodes
   #[clap(long = "advertise-addr")]
   pub(crate) advertise_addr: Option<String>,
+  /// File containing the 32 bytes key used to encrypt secrets
+  /// It must be the same on every node of the cluster and is read at boot
+  #[clap(long)]
+  pub(crate) secret_key_file: Option<String>,
 }
 
 /// Cli arguments unit test
@@ -51,6 +55,7 @@ mod tests {
     assert_eq!(args.docker_host, None);
     assert_eq!(args.state_dir, None);
     assert_eq!(args.conf_dir, String::from("/etc/nanocl"));
+    assert_eq!(args.secret_key_file, None);
   }
 
   /// Test cli arguments with custom values
@@ -60,6 +60,11 @@ fn gen_daemon_conf(
     gateway.clone()
   };
 
+  let secret_key_file = args
+    .secret_key_file
+    .clone()
+    .or(config.secret_key_file.clone());
+
   Ok(DaemonConfig {
     hosts,
     state_dir,
@@ -68,6 +73,7 @@ fn gen_daemon_conf(
     hostname,
     advertise_addr,
     nodes: args.nodes.clone(),
+    secret_key_file,
   })
 }
 
@@ -158,6 +164,7 @@ mod tests {
       hostname: None,
       advertise_addr: None,
       nodes: Vec::default(),
+      secret_key_file: None,
     };
 
     let config = DaemonConfigFile {
@@ -166,6 +173,7 @@ mod tests {
       docker_host: Some(String::from("/run/docker.sock")),
       gateway: None,
       hostname: None,
+      secret_key_file: Some(String::from("/etc/nanocl/secret.key")),
     };
 
     let merged = gen_daemon_conf(&args, &config).unwrap();
@@ -173,6 +181,7 @@ mod tests {
     assert_eq!(merged.hosts, args.hosts.unwrap());
     assert_eq!(merged.state_dir, args.state_dir.unwrap());
     assert_eq!(merged.docker_host, args.docker_host.unwrap());
+    assert_eq!(merged.secret_key_file, config.secret_key_file);
   }
 
   /// Test read config file
@@ -244,6 +253,7 @@ mod tests {
       advertise_addr: None,
       hostname: None,
       nodes: Vec::default(),
+      secret_key_file: None,
     };
 
     let config = init(&args).unwrap();
@@ -179,6 +179,7 @@ mod tests {
   use super::*;
 
   use futures::StreamExt;
+  use nanocl_stubs::vm::VmInspect;
   use nanocl_stubs::cargo::CargoInspect;
 
   use crate::utils::tests::*;
@@ -239,6 +240,12 @@ mod tests {
     let event = client.next().await.unwrap().unwrap();
     let _ = serde_json::from_slice::<Event>(&event).unwrap();
 
+    // Send vm started event
+    let vm = VmInspect::default();
+    event_emitter.emit(Event::VmStarted(Box::new(vm))).await?;
+    let event = client.next().await.unwrap().unwrap();
+    let _ = serde_json::from_slice::<Event>(&event).unwrap();
+
     Ok(())
   }
 }
@@ -67,7 +67,6 @@ async fn main() -> std::io::Result<()> {
     std::process::exit(err.code);
   }
 
-  utils::proxy::spawn_logger(&daemon_state);
   utils::metric::spawn_logger(&daemon_state);
 
   match server::generate(daemon_state).await {
@@ -75,10 +74,10 @@ async fn main() -> std::io::Result<()> {
       log::error!("Error while generating server {err}");
       std::process::exit(1);
     }
-    Ok(server) => {
-      // Start http server and wait for shutdown
-      // Server should never shutdown unless it's explicitly asked
-      if let Err(err) = server.await {
+    Ok(servers) => {
+      // Start http servers and wait for shutdown
+      // Servers should never shutdown unless it's explicitly asked
+      if let Err(err) = futures::future::try_join_all(servers).await {
         log::error!("Error while running server {err}");
         std::process::exit(1);
       }
@@ -4,7 +4,7 @@ use serde::{Serialize, Deserialize, Deserializer};
 
 use crate::schema::http_metrics;
 
-fn deserialize_empty_string<'de, D>(
+pub(crate) fn deserialize_empty_string<'de, D>(
   deserializer: D,
 ) -> Result<Option<String>, D::Error>
 where
@@ -18,7 +18,9 @@ where
   }
 }
 
-fn deserialize_string_to_i64<'de, D>(deserializer: D) -> Result<i64, D::Error>
+pub(crate) fn deserialize_string_to_i64<'de, D>(
+  deserializer: D,
+) -> Result<i64, D::Error>
 where
   D: Deserializer<'de>,
 {
@@ -37,7 +39,9 @@ where
   Ok(res)
 }
 
-fn deserialize_string_to_f64<'de, D>(deserializer: D) -> Result<f64, D::Error>
+pub(crate) fn deserialize_string_to_f64<'de, D>(
+  deserializer: D,
+) -> Result<f64, D::Error>
 where
   D: Deserializer<'de>,
 {
@@ -148,17 +152,3 @@ pub struct HttpMetricDbModel {
   pub http_referrer: Option<String>,
   pub http_accept_language: Option<String>,
 }
-
-// TODO - implement Stream Metrics support for tcp/udp protocols
-// #[derive(Clone, Debug, Serialize, Deserialize)]
-// #[serde(rename_all(serialize = "PascalCase"))]
-// pub struct StreamMetricPartial {
-//   pub date_gmt: DateTime<FixedOffset>,
-//   pub remote_addr: String,
-//   pub upstream_addr: String,
-//   pub protocol: String,
-//   pub status: i64,
-//   pub session_time: String,
-//   pub bytes_sent: String,
-//   pub bytes_received: String,
-// }
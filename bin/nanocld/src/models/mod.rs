@@ -17,6 +17,9 @@ pub use metric::*;
 mod http_metric;
 pub use http_metric::*;
 
+mod stream_metric;
+pub use stream_metric::*;
+
 mod namespace;
 pub use namespace::*;
 
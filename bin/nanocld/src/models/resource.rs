@@ -14,6 +14,10 @@ pub struct ResourceDbModel {
   pub(crate) created_at: chrono::NaiveDateTime,
   pub(crate) kind: String,
   pub(crate) config_key: uuid::Uuid,
+  pub(crate) name: String,
+  pub(crate) namespace_name: Option<String>,
+  pub(crate) owner_refs: serde_json::Value,
+  pub(crate) labels: serde_json::Value,
 }
 
 #[derive(AsChangeset)]
@@ -21,6 +25,8 @@ pub struct ResourceDbModel {
 pub struct ResourceUpdateModel {
   pub(crate) key: Option<String>,
   pub(crate) config_key: Option<uuid::Uuid>,
+  pub(crate) owner_refs: Option<serde_json::Value>,
+  pub(crate) labels: Option<serde_json::Value>,
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
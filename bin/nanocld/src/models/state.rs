@@ -17,11 +17,21 @@ pub struct DaemonState {
   pub(crate) config: DaemonConfig,
   /// The event emitter
   pub(crate) event_emitter: EventEmitter,
+  /// The secret key used to encrypt sensitive values, read at boot
+  pub(crate) secret_key: Option<Vec<u8>>,
   /// Latest version of the daemon or version of current request
   #[allow(dead_code)]
   pub(crate) version: String,
 }
 
+/// Listener a request has been received on,
+/// each listener register his own kind as state
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Listener {
+  Unix,
+  Tcp,
+}
+
 #[derive(Debug)]
 pub enum StateData {
   Deployment(StateDeployment),
@@ -0,0 +1,76 @@
+use uuid::Uuid;
+use chrono::{DateTime, FixedOffset};
+use serde::{Serialize, Deserialize};
+
+use crate::schema::stream_metrics;
+
+use super::http_metric::{
+  deserialize_empty_string, deserialize_string_to_i64,
+  deserialize_string_to_f64,
+};
+
+/// An entry of the json stream access log of the proxy
+/// The date is the end of the session
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all(serialize = "PascalCase"))]
+pub struct StreamMetricPartial {
+  pub date_gmt: DateTime<FixedOffset>,
+  pub remote_addr: String,
+  pub protocol: String,
+  #[serde(deserialize_with = "deserialize_string_to_i64")]
+  pub server_port: i64,
+  #[serde(deserialize_with = "deserialize_string_to_i64")]
+  pub status: i64,
+  #[serde(deserialize_with = "deserialize_string_to_f64")]
+  pub session_time: f64,
+  #[serde(deserialize_with = "deserialize_string_to_i64")]
+  pub bytes_sent: i64,
+  #[serde(deserialize_with = "deserialize_string_to_i64")]
+  pub bytes_received: i64,
+  #[serde(deserialize_with = "deserialize_empty_string")]
+  pub upstream_addr: Option<String>,
+}
+
+impl StreamMetricPartial {
+  pub(crate) fn to_db_model(&self, node_name: &str) -> StreamMetricDbModel {
+    let session_time =
+      chrono::Duration::milliseconds((self.session_time * 1000.0) as i64);
+    StreamMetricDbModel {
+      key: Uuid::new_v4(),
+      created_at: chrono::Utc::now().naive_utc(),
+      expire_at: chrono::Utc::now().naive_utc() + chrono::Duration::days(30),
+      date_gmt: (self.date_gmt - session_time).naive_utc(),
+      session_time: self.session_time,
+      bytes_sent: self.bytes_sent,
+      bytes_received: self.bytes_received,
+      status: self.status,
+      server_port: self.server_port,
+      protocol: self.protocol.clone(),
+      node_name: node_name.to_owned(),
+      remote_addr: self.remote_addr.clone(),
+      upstream_addr: self.upstream_addr.clone(),
+    }
+  }
+}
+
+#[derive(
+  Clone, Debug, Identifiable, Insertable, Queryable, Serialize, Deserialize,
+)]
+#[diesel(primary_key(key))]
+#[diesel(table_name = stream_metrics)]
+#[serde(rename_all = "PascalCase")]
+pub struct StreamMetricDbModel {
+  pub key: Uuid,
+  pub created_at: chrono::NaiveDateTime,
+  pub expire_at: chrono::NaiveDateTime,
+  pub date_gmt: chrono::NaiveDateTime,
+  pub session_time: f64,
+  pub bytes_sent: i64,
+  pub bytes_received: i64,
+  pub status: i64,
+  pub server_port: i64,
+  pub protocol: String,
+  pub node_name: String,
+  pub remote_addr: String,
+  pub upstream_addr: Option<String>,
+}
@@ -10,27 +10,27 @@ use crate::models::{Pool, HttpMetricDbModel};
 
 use super::error::{db_error, db_blocking_error};
 
-pub async fn create(
-  item: &HttpMetricDbModel,
+pub async fn create_many(
+  items: &[HttpMetricDbModel],
   pool: &Pool,
-) -> Result<HttpMetricDbModel, HttpError> {
+) -> Result<usize, HttpError> {
   use crate::schema::http_metrics::dsl;
 
-  let item = item.clone();
+  let items = items.to_vec();
   let pool = pool.clone();
 
-  let item = web::block(move || {
+  let count = web::block(move || {
     let mut conn = utils::store::get_pool_conn(&pool)?;
     let res = diesel::insert_into(dsl::http_metrics)
-      .values(item)
-      .get_result(&mut conn)
+      .values(&items)
+      .execute(&mut conn)
       .map_err(db_error("http_metrics"))?;
     Ok::<_, HttpError>(res)
   })
   .await
   .map_err(db_blocking_error)?;
 
-  Ok(item)
+  Ok(count)
 }
 
 pub async fn list(
@@ -1,9 +1,13 @@
 use ntex::web;
 use ntex::http::StatusCode;
 use diesel::prelude::*;
+use diesel::dsl::{not, sql};
+use diesel::sql_types::Bool;
 
 use nanocl_stubs::generic::GenericDelete;
-use nanocl_stubs::resource::{Resource, ResourcePartial, ResourceQuery};
+use nanocl_stubs::resource::{
+  Resource, ResourcePartial, ResourceQuery, ResourceOwnerRef,
+};
 
 use crate::repositories::error::db_error;
 use crate::{utils, repositories};
@@ -15,6 +19,168 @@ use crate::models::{
 use super::resource_config;
 use super::error::db_blocking_error;
 
+/// Serialize a metadata of a resource (owner refs, labels) to be stored
+fn to_json_value<T>(
+  value: &T,
+  context: &str,
+) -> Result<serde_json::Value, HttpError>
+where
+  T: serde::Serialize + ?Sized,
+{
+  serde_json::to_value(value).map_err(|err| HttpError {
+    status: StatusCode::INTERNAL_SERVER_ERROR,
+    msg: format!("Unable to serialize {context}: {err}"),
+  })
+}
+
+/// Deserialize a stored metadata of a resource
+fn from_json_value<T>(
+  value: serde_json::Value,
+  context: &str,
+) -> Result<T, HttpError>
+where
+  T: serde::de::DeserializeOwned,
+{
+  serde_json::from_value(value).map_err(|err| HttpError {
+    status: StatusCode::INTERNAL_SERVER_ERROR,
+    msg: format!("Unable to deserialize {context}: {err}"),
+  })
+}
+
+/// Sql condition matching the resources whose owners are all gone
+/// A resource without owners is never orphaned
+const ORPHANED_SQL: &str =
+  "resources.owner_refs <> '[]'::jsonb AND NOT EXISTS (
+  SELECT 1 FROM jsonb_array_elements(resources.owner_refs) AS owner
+  WHERE (owner->>'Kind' = 'Cargo'
+    AND EXISTS (SELECT 1 FROM cargoes WHERE cargoes.key = owner->>'Key'))
+  OR (owner->>'Kind' = 'Vm'
+    AND EXISTS (SELECT 1 FROM vms WHERE vms.key = owner->>'Key'))
+  OR (owner->>'Kind' = 'Namespace'
+    AND EXISTS (SELECT 1 FROM namespaces WHERE namespaces.name = owner->>'Key'))
+)";
+
+/// Build a json object matching the given value at the given path
+/// so it can be used with the jsonb containment operator
+fn json_at_path(path: &[&str], value: serde_json::Value) -> serde_json::Value {
+  path.iter().rev().fold(value, |acc, key| {
+    let mut map = serde_json::Map::new();
+    map.insert((*key).to_owned(), acc);
+    serde_json::Value::Object(map)
+  })
+}
+
+/// A predicate parsed from a selector like `key=value` or `key!=value`
+/// `key:=value` and `key!:=value` give a typed json value
+struct Predicate<'a> {
+  key: &'a str,
+  value: &'a str,
+  negate: bool,
+  typed: bool,
+}
+
+/// Parse a comma separated list of `key=value`, `key!=value`,
+/// `key:=value` or `key!:=value` predicates
+/// The operator is the first `=` with its `!` and `:` prefixes
+/// everything after it is the value
+fn parse_predicates<'a>(
+  selector: &'a str,
+  context: &str,
+) -> Result<Vec<Predicate<'a>>, HttpError> {
+  selector
+    .split(',')
+    .filter(|item| !item.trim().is_empty())
+    .map(|item| {
+      let item = item.trim();
+      let invalid = || HttpError {
+        status: StatusCode::BAD_REQUEST,
+        msg: format!(
+          "Invalid {context} {item} expected key=value, key!=value, key:=value or key!:=value"
+        ),
+      };
+      let (key, value) = item.split_once('=').ok_or_else(invalid)?;
+      let (key, typed) = match key.strip_suffix(':') {
+        Some(key) => (key, true),
+        None => (key, false),
+      };
+      let (key, negate) = match key.strip_suffix('!') {
+        Some(key) => (key, true),
+        None => (key, false),
+      };
+      let key = key.trim();
+      if key.is_empty() {
+        return Err(invalid());
+      }
+      Ok(Predicate {
+        key,
+        value: value.trim(),
+        negate,
+        typed,
+      })
+    })
+    .collect()
+}
+
+/// Convert a label selector into json objects to match with the labels
+fn parse_label_selector(
+  selector: &str,
+) -> Result<Vec<(serde_json::Value, bool)>, HttpError> {
+  parse_predicates(selector, "label selector")?
+    .into_iter()
+    .map(|predicate| {
+      if predicate.typed {
+        return Err(HttpError {
+          status: StatusCode::BAD_REQUEST,
+          msg: format!("Label {} can only match a string", predicate.key),
+        });
+      }
+      let value = json_at_path(
+        &[predicate.key],
+        serde_json::Value::String(predicate.value.to_owned()),
+      );
+      Ok((value, predicate.negate))
+    })
+    .collect()
+}
+
+/// Convert json path predicates like `$.Rule.Http.Domain=example.com`
+/// into json objects to match with the config.
+/// The value is a string unless the typed form `$.Path:=value` is used
+/// then it's parsed as json like `$.Port:=80` or `$.Enabled:=true`.
+fn parse_config_path(
+  config_path: &str,
+) -> Result<Vec<(serde_json::Value, bool)>, HttpError> {
+  parse_predicates(config_path, "config path")?
+    .into_iter()
+    .map(|predicate| {
+      let path = predicate
+        .key
+        .trim_start_matches('$')
+        .trim_start_matches('.');
+      let path = path.split('.').collect::<Vec<&str>>();
+      if path.iter().any(|key| key.is_empty()) {
+        return Err(HttpError {
+          status: StatusCode::BAD_REQUEST,
+          msg: format!("Invalid config path {}", predicate.key),
+        });
+      }
+      let value = match predicate.typed {
+        false => serde_json::Value::String(predicate.value.to_owned()),
+        true => {
+          serde_json::from_str(predicate.value).map_err(|err| HttpError {
+            status: StatusCode::BAD_REQUEST,
+            msg: format!(
+              "Invalid json value {} for config path {}: {err}",
+              predicate.value, predicate.key
+            ),
+          })?
+        }
+      };
+      Ok((json_at_path(&path, value), predicate.negate))
+    })
+    .collect()
+}
+
 /// ## Create resource
 ///
 /// Create a resource item in database
@@ -38,6 +204,9 @@ use super::error::db_blocking_error;
 ///
 /// let item = ResourcePartial {
 ///  name: String::from("my-resource"),
+///  namespace: None,
+///  owner_refs: None,
+///  labels: None,
 ///  // fill your values
 /// };
 ///
@@ -51,10 +220,11 @@ pub async fn create(
   use crate::schema::resources::dsl;
 
   let pool = pool.clone();
+  let key = utils::key::gen_resource_key(&item.namespace, &item.name);
   let config = ResourceConfigDbModel {
     key: uuid::Uuid::new_v4(),
     created_at: chrono::Utc::now().naive_utc(),
-    resource_key: item.name.to_owned(),
+    resource_key: key.to_owned(),
     version: item.version.to_owned(),
     data: item.config.clone(),
   };
@@ -62,10 +232,17 @@ pub async fn create(
   let config = resource_config::create(&config, &pool).await?;
 
   let new_item = ResourceDbModel {
-    key: item.name.to_owned(),
+    key,
     created_at: chrono::Utc::now().naive_utc(),
     kind: item.kind.clone(),
     config_key: config.key.to_owned(),
+    name: item.name.to_owned(),
+    namespace_name: item.namespace.to_owned(),
+    owner_refs: to_json_value(
+      item.owner_refs.as_deref().unwrap_or_default(),
+      "owner refs",
+    )?,
+    labels: to_json_value(&item.labels.clone().unwrap_or_default(), "labels")?,
   };
 
   let item = web::block(move || {
@@ -80,7 +257,11 @@ pub async fn create(
   .map_err(db_blocking_error)?;
 
   let item = Resource {
-    name: item.key,
+    key: item.key,
+    name: item.name,
+    namespace_name: item.namespace_name,
+    owner_refs: from_json_value(item.owner_refs, "owner refs")?,
+    labels: from_json_value(item.labels, "labels")?,
     created_at: item.created_at,
     updated_at: config.created_at,
     kind: item.kind,
@@ -179,6 +360,10 @@ pub async fn find(
           if let Some(kind) = &qs.kind {
             req = req.filter(resources::kind.eq(kind.to_string()));
           }
+          if let Some(namespace) = &qs.namespace {
+            req =
+              req.filter(resources::namespace_name.eq(namespace.to_string()));
+          }
           if let Some(contains) = &qs.contains {
             let contains = serde_json::from_str::<serde_json::Value>(contains)
               .map_err(|err| HttpError {
@@ -187,6 +372,39 @@ pub async fn find(
               })?;
             req = req.filter(resource_configs::data.contains(contains));
           }
+          if let Some(labels) = &qs.labels {
+            for (labels, negate) in parse_label_selector(labels)? {
+              if negate {
+                req = req.filter(not(resources::labels.contains(labels)));
+              } else {
+                req = req.filter(resources::labels.contains(labels));
+              }
+            }
+          }
+          if let Some(orphaned) = qs.orphaned {
+            let condition = sql::<Bool>(ORPHANED_SQL);
+            if orphaned {
+              req = req.filter(condition);
+            } else {
+              req = req.filter(not(condition));
+            }
+          }
+          if let Some(config_path) = &qs.config_path {
+            for (data, negate) in parse_config_path(config_path)? {
+              if negate {
+                req = req.filter(not(resource_configs::data.contains(data)));
+              } else {
+                req = req.filter(resource_configs::data.contains(data));
+              }
+            }
+          }
+          req = req.order(resources::key.asc());
+          if let Some(limit) = qs.limit {
+            req = req.limit(limit);
+          }
+          if let Some(offset) = qs.offset {
+            req = req.offset(offset);
+          }
 
           req.load(&mut conn)
         }
@@ -208,7 +426,11 @@ pub async fn find(
       let resource = e.0;
       let config = e.1;
       Ok::<_, HttpError>(Resource {
-        name: resource.key,
+        key: resource.key,
+        name: resource.name,
+        namespace_name: resource.namespace_name,
+        owner_refs: from_json_value(resource.owner_refs, "owner refs")?,
+        labels: from_json_value(resource.labels, "labels")?,
         created_at: resource.created_at,
         updated_at: config.created_at,
         kind: resource.kind,
@@ -221,6 +443,75 @@ pub async fn find(
   Ok(items)
 }
 
+/// ## Find resource by owner
+///
+/// Find the resources that reference the given owner in their owner refs
+///
+/// ## Arguments
+///
+/// - [owner](ResourceOwnerRef) - The owner reference
+/// - [pool](Pool) - Database connection pool
+///
+/// ## Returns
+///
+/// - [Result](Result) - The result of the operation
+///   - [Ok](Vec<Resource>) - List of resources
+///   - [Err](HttpResponseError) - Error during the operation
+///
+/// ## Examples
+///
+/// ```rust,norun
+/// use crate::repositories;
+///
+/// let owner = ResourceOwnerRef {
+///   kind: ResourceOwnerKind::Cargo,
+///   key: String::from("my-cargo.global"),
+/// };
+/// let items = repositories::resource::find_by_owner(&owner, &pool).await;
+/// ```
+///
+pub async fn find_by_owner(
+  owner: &ResourceOwnerRef,
+  pool: &Pool,
+) -> Result<Vec<Resource>, HttpError> {
+  use crate::schema::resources;
+  use crate::schema::resource_configs;
+
+  let pool = pool.clone();
+  let owner_refs = to_json_value(&[owner.clone()], "owner refs")?;
+  let res: Vec<(ResourceDbModel, ResourceConfigDbModel)> =
+    web::block(move || {
+      let mut conn = utils::store::get_pool_conn(&pool)?;
+      let res = resources::table
+        .inner_join(resource_configs::table)
+        .filter(resources::owner_refs.contains(owner_refs))
+        .load(&mut conn)
+        .map_err(db_error("resource"))?;
+      Ok::<_, HttpError>(res)
+    })
+    .await
+    .map_err(db_blocking_error)?;
+
+  res
+    .into_iter()
+    .map(|(resource, config)| {
+      Ok::<_, HttpError>(Resource {
+        key: resource.key,
+        name: resource.name,
+        namespace_name: resource.namespace_name,
+        owner_refs: from_json_value(resource.owner_refs, "owner refs")?,
+        labels: from_json_value(resource.labels, "labels")?,
+        created_at: resource.created_at,
+        updated_at: config.created_at,
+        kind: resource.kind,
+        version: config.version,
+        config_key: resource.config_key,
+        config: config.data,
+      })
+    })
+    .collect()
+}
+
 /// ## Inspect resource by key
 ///
 /// Inspect a resource item in database by key
@@ -267,7 +558,11 @@ pub async fn inspect_by_key(
   .map_err(db_blocking_error)?;
 
   let item = Resource {
-    name: res.0.key,
+    key: res.0.key,
+    name: res.0.name,
+    namespace_name: res.0.namespace_name,
+    owner_refs: from_json_value(res.0.owner_refs, "owner refs")?,
+    labels: from_json_value(res.0.labels, "labels")?,
     created_at: res.0.created_at,
     updated_at: res.1.created_at,
     kind: res.0.kind,
@@ -310,23 +605,32 @@ pub async fn patch(
   use crate::schema::resources;
 
   let pool = pool.clone();
-  let key = item.name.clone();
-  let resource =
-    repositories::resource::inspect_by_key(&item.name, &pool).await?;
+  let key = utils::key::gen_resource_key(&item.namespace, &item.name);
+  let resource = repositories::resource::inspect_by_key(&key, &pool).await?;
 
   let config = ResourceConfigDbModel {
     key: uuid::Uuid::new_v4(),
     created_at: chrono::Utc::now().naive_utc(),
-    resource_key: resource.name.to_owned(),
+    resource_key: resource.key.to_owned(),
     version: item.version.clone(),
     data: item.config.clone(),
   };
 
   let config = resource_config::create(&config, &pool).await?;
 
+  let owner_refs = match &item.owner_refs {
+    Some(owner_refs) => owner_refs.clone(),
+    None => resource.owner_refs,
+  };
+  let labels = match &item.labels {
+    Some(labels) => labels.clone(),
+    None => resource.labels,
+  };
   let resource_update = ResourceUpdateModel {
     key: None,
     config_key: Some(config.key.to_owned()),
+    owner_refs: Some(to_json_value(&owner_refs, "owner refs")?),
+    labels: Some(to_json_value(&labels, "labels")?),
   };
 
   web::block(move || {
@@ -342,7 +646,11 @@ pub async fn patch(
   .map_err(db_blocking_error)?;
 
   let item = Resource {
+    key: resource.key,
     name: resource.name,
+    namespace_name: resource.namespace_name,
+    owner_refs,
+    labels,
     created_at: resource.created_at,
     updated_at: config.created_at,
     kind: resource.kind,
@@ -353,11 +661,109 @@ pub async fn patch(
   Ok(item)
 }
 
+/// ## Patch many
+///
+/// Write a new config for each resource in a single transaction,
+/// no resource is updated when one of them fail
+///
+/// ## Arguments
+///
+/// - [items](Vec<ResourcePartial>) - Resources to patch
+/// - [pool](Pool) - Database connection pool
+///
+/// ## Returns
+///
+/// - [Result](Result) - The result of the operation
+///   - [Ok](Vec<Resource>) - The patched resources
+///   - [Err](HttpResponseError) - Error during the operation
+///
+/// ## Examples
+///
+/// ```rust,norun
+/// use crate::repositories;
+///
+/// let items = repositories::resource::patch_many(&items, &pool).await;
+/// ```
+///
+pub async fn patch_many(
+  items: &[ResourcePartial],
+  pool: &Pool,
+) -> Result<Vec<Resource>, HttpError> {
+  use crate::schema::{resources, resource_configs};
+
+  let mut updates = Vec::new();
+  let mut patched = Vec::new();
+  for item in items {
+    let key = utils::key::gen_resource_key(&item.namespace, &item.name);
+    let resource = inspect_by_key(&key, pool).await?;
+    let config = ResourceConfigDbModel {
+      key: uuid::Uuid::new_v4(),
+      created_at: chrono::Utc::now().naive_utc(),
+      resource_key: resource.key.to_owned(),
+      version: item.version.clone(),
+      data: item.config.clone(),
+    };
+    let owner_refs = match &item.owner_refs {
+      Some(owner_refs) => owner_refs.clone(),
+      None => resource.owner_refs,
+    };
+    let labels = match &item.labels {
+      Some(labels) => labels.clone(),
+      None => resource.labels,
+    };
+    let resource_update = ResourceUpdateModel {
+      key: None,
+      config_key: Some(config.key.to_owned()),
+      owner_refs: Some(to_json_value(&owner_refs, "owner refs")?),
+      labels: Some(to_json_value(&labels, "labels")?),
+    };
+    patched.push(Resource {
+      key: resource.key.to_owned(),
+      name: resource.name,
+      namespace_name: resource.namespace_name,
+      owner_refs,
+      labels,
+      created_at: resource.created_at,
+      updated_at: config.created_at,
+      kind: resource.kind,
+      version: config.version.to_owned(),
+      config_key: config.key,
+      config: config.data.clone(),
+    });
+    updates.push((resource.key, config, resource_update));
+  }
+
+  let pool = pool.clone();
+  web::block(move || {
+    let mut conn = utils::store::get_pool_conn(&pool)?;
+    conn
+      .transaction(|conn| {
+        for (key, config, resource_update) in &updates {
+          diesel::insert_into(resource_configs::table)
+            .values(config)
+            .execute(conn)?;
+          diesel::update(resources::table)
+            .filter(resources::key.eq(key))
+            .set(resource_update)
+            .execute(conn)?;
+        }
+        Ok::<_, diesel::result::Error>(())
+      })
+      .map_err(db_error("resource"))?;
+    Ok::<_, HttpError>(())
+  })
+  .await
+  .map_err(db_blocking_error)?;
+
+  Ok(patched)
+}
+
 pub async fn create_or_patch(
   resource: &ResourcePartial,
   pool: &Pool,
 ) -> Result<Resource, HttpError> {
-  if inspect_by_key(&resource.name, pool).await.is_ok() {
+  let key = utils::key::gen_resource_key(&resource.namespace, &resource.name);
+  if inspect_by_key(&key, pool).await.is_ok() {
     return patch(resource, pool).await;
   }
   create(resource, pool).await
@@ -19,6 +19,8 @@ pub async fn create_version(
     resource_kind_name: item.name.clone(),
     version: item.version.clone(),
     schema: item.schema.clone(),
+    url: item.url.clone(),
+    migration: item.migration.clone(),
     created_at: chrono::Utc::now().naive_utc(),
   };
   let pool = pool.clone();
@@ -60,6 +62,28 @@ pub async fn get_version(
   Ok(item)
 }
 
+pub async fn list_versions(
+  name: &str,
+  pool: &Pool,
+) -> Result<Vec<ResourceKindVersionDbModel>, HttpError> {
+  use crate::schema::resource_kind_versions::dsl;
+
+  let pool = pool.clone();
+  let name = name.to_owned();
+  let items = web::block(move || {
+    let mut conn = utils::store::get_pool_conn(&pool)?;
+    dsl::resource_kind_versions
+      .filter(dsl::resource_kind_name.eq(name))
+      .order(dsl::created_at.asc())
+      .load(&mut conn)
+      .map_err(db_error("resource kind version"))
+  })
+  .await
+  .map_err(db_blocking_error)?;
+
+  Ok(items)
+}
+
 pub async fn find_by_name(
   name: &str,
   pool: &Pool,
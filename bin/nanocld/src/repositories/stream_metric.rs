@@ -0,0 +1,101 @@
+use ntex::web;
+use diesel::prelude::*;
+
+use nanocl_stubs::generic::GenericCount;
+use nanocl_stubs::stream_metric::{StreamMetricListQuery, StreamMetricCountQuery};
+
+use crate::utils;
+use crate::error::HttpError;
+use crate::models::{Pool, StreamMetricDbModel};
+
+use super::error::{db_error, db_blocking_error};
+
+pub async fn create_many(
+  items: &[StreamMetricDbModel],
+  pool: &Pool,
+) -> Result<usize, HttpError> {
+  use crate::schema::stream_metrics::dsl;
+
+  let items = items.to_vec();
+  let pool = pool.clone();
+
+  let count = web::block(move || {
+    let mut conn = utils::store::get_pool_conn(&pool)?;
+    let res = diesel::insert_into(dsl::stream_metrics)
+      .values(&items)
+      .execute(&mut conn)
+      .map_err(db_error("stream_metrics"))?;
+    Ok::<_, HttpError>(res)
+  })
+  .await
+  .map_err(db_blocking_error)?;
+
+  Ok(count)
+}
+
+pub async fn list(
+  filter: &StreamMetricListQuery,
+  pool: &Pool,
+) -> Result<Vec<StreamMetricDbModel>, HttpError> {
+  use crate::schema::stream_metrics::dsl;
+
+  let filter = filter.clone();
+  let pool = pool.clone();
+
+  let items = web::block(move || {
+    let mut conn = utils::store::get_pool_conn(&pool)?;
+    let mut query = dsl::stream_metrics
+      .into_boxed()
+      .order((dsl::date_gmt, dsl::created_at.desc()));
+
+    if let Some(limit) = filter.limit {
+      query = query.limit(limit);
+    }
+    if let Some(offset) = filter.offset {
+      query = query.offset(offset);
+    }
+
+    let res = query
+      .get_results(&mut conn)
+      .map_err(db_error("stream_metrics"))?;
+    Ok::<_, HttpError>(res)
+  })
+  .await
+  .map_err(db_blocking_error)?;
+
+  Ok(items)
+}
+
+pub async fn count(
+  filter: &StreamMetricCountQuery,
+  pool: &Pool,
+) -> Result<GenericCount, HttpError> {
+  use crate::schema::stream_metrics::dsl;
+
+  let filter = filter.clone();
+  let pool = pool.clone();
+
+  let count = web::block(move || {
+    let mut conn = utils::store::get_pool_conn(&pool)?;
+    let mut query = dsl::stream_metrics.into_boxed();
+
+    if let Some(status) = filter.status {
+      if let Some(status_max) = status.1 {
+        query = query.filter(dsl::status.between(status.0, status_max));
+      } else {
+        query = query.filter(dsl::status.eq(status.0));
+      }
+    }
+
+    let res = query
+      .count()
+      .get_result(&mut conn)
+      .map(|count: i64| GenericCount { count })
+      .map_err(db_error("stream_metrics"))?;
+    Ok::<_, HttpError>(res)
+  })
+  .await
+  .map_err(db_blocking_error)?;
+
+  Ok(count)
+}
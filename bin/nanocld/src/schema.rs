@@ -103,7 +103,9 @@ diesel::table! {
         resource_kind_name -> Varchar,
         created_at -> Timestamptz,
         version -> Varchar,
-        schema -> Jsonb,
+        schema -> Nullable<Jsonb>,
+        url -> Nullable<Varchar>,
+        migration -> Nullable<Jsonb>,
     }
 }
 
@@ -120,6 +122,28 @@ diesel::table! {
         created_at -> Timestamptz,
         kind -> Varchar,
         config_key -> Uuid,
+        name -> Varchar,
+        namespace_name -> Nullable<Varchar>,
+        owner_refs -> Jsonb,
+        labels -> Jsonb,
+    }
+}
+
+diesel::table! {
+    stream_metrics (key) {
+        key -> Uuid,
+        created_at -> Timestamptz,
+        expire_at -> Timestamptz,
+        date_gmt -> Timestamptz,
+        session_time -> Float8,
+        bytes_sent -> Int8,
+        bytes_received -> Int8,
+        status -> Int8,
+        server_port -> Int8,
+        protocol -> Varchar,
+        node_name -> Varchar,
+        remote_addr -> Varchar,
+        upstream_addr -> Nullable<Varchar>,
     }
 }
 
@@ -178,6 +202,7 @@ diesel::allow_tables_to_appear_in_same_query!(
     resource_kind_versions,
     resource_kinds,
     resources,
+    stream_metrics,
     vm_configs,
     vm_images,
     vms,
@@ -2,55 +2,66 @@ use ntex::web;
 use ntex_cors::Cors;
 
 use crate::services;
-use crate::models::DaemonState;
+use crate::models::{DaemonState, Listener};
 
 pub async fn generate(
   daemon_state: DaemonState,
-) -> std::io::Result<ntex::server::Server> {
+) -> std::io::Result<Vec<ntex::server::Server>> {
   log::info!("Preparing server");
   let hosts = daemon_state.config.hosts.clone();
-  let mut server = web::HttpServer::new(move || {
-    web::App::new()
-      // bind config state
-      .state(daemon_state.clone())
-      .state(
-        web::types::PayloadConfig::new(20_000_000_000), // <- limit size of the payload
-      )
-      .wrap(
-        Cors::new()
-          .disable_preflight()
-          .disable_vary_header()
-          .finish(),
-      )
-      // Default logger middleware
-      // .wrap(web::middleware::Logger::default())
-      // Set Json body max size
-      // .state(web::types::JsonConfig::default().limit(4096))
-      .configure(services::ntex_config)
-      .default_service(web::route().to(services::unhandled))
-  });
-  let mut count = 0;
-  let len = hosts.len();
-  while count < len {
-    let host = &hosts[count];
+  // Each kind of listener get his own server so handlers know
+  // if a request come from the unix socket
+  let factory = move |listener: Listener| {
+    let daemon_state = daemon_state.clone();
+    move || {
+      web::App::new()
+        // bind config state
+        .state(daemon_state.clone())
+        .state(listener)
+        .state(
+          web::types::PayloadConfig::new(20_000_000_000), // <- limit size of the payload
+        )
+        .wrap(
+          Cors::new()
+            .disable_preflight()
+            .disable_vary_header()
+            .finish(),
+        )
+        // Default logger middleware
+        // .wrap(web::middleware::Logger::default())
+        // Set Json body max size, batches of http metrics can be large
+        .state(web::types::JsonConfig::default().limit(4_000_000))
+        .configure(services::ntex_config)
+        .default_service(web::route().to(services::unhandled))
+    }
+  };
+  let mut unix_server = None;
+  let mut tcp_server = None;
+  for host in &hosts {
     if host.starts_with("unix://") {
       let addr = host.replace("unix://", "");
-      server = match server.bind_uds(&addr) {
+      let server = unix_server
+        .take()
+        .unwrap_or_else(|| web::HttpServer::new(factory(Listener::Unix)));
+      unix_server = match server.bind_uds(&addr) {
         Err(err) => {
           log::error!("Error binding to unix socket {}: {}", &addr, &err);
           return Err(err);
         }
-        Ok(server) => server,
+        Ok(server) => Some(server),
       };
       log::info!("Listening on {}", &host);
     } else if host.starts_with("tcp://") {
       let addr = host.replace("tcp://", "");
-      server = match server.bind(&addr) {
+      let server = tcp_server
+        .take()
+        .unwrap_or_else(|| web::HttpServer::new(factory(Listener::Tcp)));
+      tcp_server = match server.bind(&addr) {
         Err(err) => {
           log::error!("Error binding to tcp host {}: {}", &addr, &err);
           return Err(err);
         }
-        Ok(server) => server,
+        Ok(server) => Some(server),
       };
       log::info!("Listening on {}", &host);
     } else {
@@ -63,18 +74,26 @@ pub async fn generate(
         "Invalid protocol use tcp:// or unix://",
       ));
     }
-    count += 1;
   }
 
   #[cfg(feature = "dev")]
   {
-    server = server.bind("0.0.0.0:8585")?;
+    let server = tcp_server
+      .take()
+      .unwrap_or_else(|| web::HttpServer::new(factory(Listener::Tcp)));
+    tcp_server = Some(server.bind("0.0.0.0:8585")?);
     log::debug!("Running in dev mode, binding to: http://0.0.0.0:8585");
     log::debug!("OpenAPI explorer available at: http://0.0.0.0:8585/explorer");
   }
 
   log::info!("Server ready");
-  Ok(server.run())
+  Ok(
+    [unix_server, tcp_server]
+      .into_iter()
+      .flatten()
+      .map(|server| server.run())
+      .collect(),
+  )
 }
 
 /// Server init test
@@ -0,0 +1,164 @@
+use ntex::web;
+
+use crate::error::HttpError;
+use crate::utils::ctrl_client::{CtrlClient, DNS_CTRL_URL};
+
+/// List the entries served by the dns controller
+#[cfg_attr(feature = "dev", utoipa::path(
+  get,
+  tag = "Dns",
+  path = "/dns/records",
+  responses(
+    (status = 200, description = "Array of dns entries", body = Vec<DnsEntrySummary>),
+    (status = 500, description = "The dns controller is not reachable", body = ApiError),
+  ),
+))]
+#[web::get("/dns/records")]
+pub(crate) async fn list_dns_records() -> Result<web::HttpResponse, HttpError> {
+  let ctrl_client = CtrlClient::new("DnsRecord", DNS_CTRL_URL)?;
+  let records = ctrl_client.list_dns_records().await?;
+  Ok(web::HttpResponse::Ok().json(&records))
+}
+
+/// Explain how a name is resolved by the dns controller
+#[cfg_attr(feature = "dev", utoipa::path(
+  get,
+  tag = "Dns",
+  path = "/dns/resolve/{Name}",
+  params(
+    ("Name" = String, Path, description = "The name to resolve"),
+  ),
+  responses(
+    (status = 200, description = "How the name is resolved", body = DnsResolve),
+    (status = 500, description = "The dns controller is not reachable", body = ApiError),
+  ),
+))]
+#[web::get("/dns/resolve/{name}")]
+pub(crate) async fn resolve_dns(
+  path: web::types::Path<(String, String)>,
+) -> Result<web::HttpResponse, HttpError> {
+  let ctrl_client = CtrlClient::new("DnsRecord", DNS_CTRL_URL)?;
+  let resolve = ctrl_client.resolve_dns(&path.1).await?;
+  Ok(web::HttpResponse::Ok().json(&resolve))
+}
+
+/// Sync the entries of the dns controller with the state of the daemon
+#[cfg_attr(feature = "dev", utoipa::path(
+  post,
+  tag = "Dns",
+  path = "/dns/sync",
+  responses(
+    (status = 200, description = "The dns entries are synced"),
+    (status = 500, description = "The dns controller is not reachable", body = ApiError),
+  ),
+))]
+#[web::post("/dns/sync")]
+pub(crate) async fn sync_dns() -> Result<web::HttpResponse, HttpError> {
+  let ctrl_client = CtrlClient::new("DnsRecord", DNS_CTRL_URL)?;
+  ctrl_client.sync_dns().await?;
+  Ok(web::HttpResponse::Ok().finish())
+}
+
+pub fn ntex_config(config: &mut web::ServiceConfig) {
+  config.service(list_dns_records);
+  config.service(resolve_dns);
+  config.service(sync_dns);
+}
+
+#[cfg(test)]
+mod tests {
+
+  use ntex::http;
+  use nanocl_stubs::resource::ResourcePartial;
+  use nanocl_stubs::dns::{DnsEntrySummary, DnsResolve, DnsResolveAnswer};
+
+  use crate::services::ntex_config;
+  use crate::utils::tests::*;
+
+  const RECORD_NAME: &str = "dns-test.internal";
+
+  async fn resolve(
+    srv: &TestServer,
+    name: &str,
+  ) -> Result<DnsResolve, Box<dyn std::error::Error + 'static>> {
+    let mut resp = srv.get(format!("/v0.5/dns/resolve/{name}")).send().await?;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    Ok(resp.json::<DnsResolve>().await?)
+  }
+
+  async fn test_create_record(srv: &TestServer) -> TestRet {
+    let resource = ResourcePartial {
+      name: "dns-test".to_owned(),
+      namespace: None,
+      version: "v0.1".to_owned(),
+      kind: "DnsRecord".to_owned(),
+      config: serde_json::json!({
+        "Name": RECORD_NAME,
+        "Records": [
+          { "A": { "Ip": "10.0.0.9" } },
+          { "Txt": ["owner=dns-test"] }
+        ]
+      }),
+      owner_refs: None,
+      labels: None,
+    };
+    let resp = srv.post("/v0.2/resources").send_json(&resource).await?;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    Ok(())
+  }
+
+  async fn test_list(srv: &TestServer) -> TestRet {
+    let mut resp = srv.get("/v0.5/dns/records").send().await?;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let entries = resp.json::<Vec<DnsEntrySummary>>().await?;
+    let record = entries
+      .iter()
+      .filter(|entry| entry.name == RECORD_NAME)
+      .collect::<Vec<_>>();
+    assert_eq!(record.len(), 2);
+    assert!(record.iter().all(|entry| entry.source.ends_with(".record")));
+    assert!(record.iter().any(|entry| entry.value == "10.0.0.9"));
+    Ok(())
+  }
+
+  async fn test_resolve(srv: &TestServer) -> TestRet {
+    let record = resolve(srv, RECORD_NAME).await?;
+    assert_eq!(record.answer, DnsResolveAnswer::Local);
+    assert_eq!(record.entries.len(), 2);
+    let forwarded = resolve(srv, "nanocl.io").await?;
+    assert_eq!(forwarded.answer, DnsResolveAnswer::Forwarded);
+    assert!(forwarded.entries.is_empty());
+    let zone = forwarded.zone;
+    let not_found = resolve(srv, &format!("not-found.global.{zone}")).await?;
+    assert_eq!(not_found.answer, DnsResolveAnswer::NotFound);
+    Ok(())
+  }
+
+  async fn test_sync(srv: &TestServer) -> TestRet {
+    let resp = srv.post("/v0.5/dns/sync").send().await?;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    // The records are kept after a sync
+    let record = resolve(srv, RECORD_NAME).await?;
+    assert_eq!(record.answer, DnsResolveAnswer::Local);
+    Ok(())
+  }
+
+  async fn test_delete_record(srv: &TestServer) -> TestRet {
+    let resp = srv.delete("/v0.2/resources/dns-test").send().await?;
+    assert!(resp.status().is_success());
+    let record = resolve(srv, RECORD_NAME).await?;
+    assert_eq!(record.answer, DnsResolveAnswer::Forwarded);
+    Ok(())
+  }
+
+  #[ntex::test]
+  async fn basic() -> TestRet {
+    let srv = generate_server(ntex_config).await;
+    test_create_record(&srv).await?;
+    test_list(&srv).await?;
+    test_resolve(&srv).await?;
+    test_sync(&srv).await?;
+    test_delete_record(&srv).await?;
+    Ok(())
+  }
+}
@@ -4,7 +4,7 @@ use nanocl_stubs::http_metric::{HttpMetricListQuery, HttpMetricCountQuery};
 
 use crate::repositories;
 use crate::error::HttpError;
-use crate::models::DaemonState;
+use crate::models::{DaemonState, HttpMetricPartial};
 
 /// Get http metrics of all peer nodes
 #[cfg_attr(feature = "dev", utoipa::path(
@@ -49,7 +49,32 @@ pub(crate) async fn count_http_metric(
   Ok(web::HttpResponse::Ok().json(&count))
 }
 
+/// Save a batch of entries of the json http access log of the proxy
+#[cfg_attr(feature = "dev", utoipa::path(
+  post,
+  tag = "HttpMetrics",
+  path = "/http_metrics",
+  request_body = Vec<Object>,
+  responses(
+    (status = 201, description = "Http metrics created"),
+    (status = 400, description = "Invalid http metric"),
+  ),
+))]
+#[web::post("/http_metrics")]
+pub(crate) async fn create_http_metric(
+  payload: web::types::Json<Vec<HttpMetricPartial>>,
+  state: web::types::State<DaemonState>,
+) -> Result<web::HttpResponse, HttpError> {
+  let metrics = payload
+    .iter()
+    .map(|metric| metric.to_db_model(&state.config.hostname))
+    .collect::<Vec<_>>();
+  repositories::http_metric::create_many(&metrics, &state.pool).await?;
+  Ok(web::HttpResponse::Created().finish())
+}
+
 pub fn ntex_config(config: &mut web::ServiceConfig) {
+  config.service(create_http_metric);
   config.service(list_http_metric);
   config.service(count_http_metric);
 }
@@ -71,6 +96,39 @@ mod tests {
     Ok(())
   }
 
+  async fn test_create(srv: &TestServer) -> TestRet {
+    let metric = serde_json::json!({
+      "date_gmt": "2023-04-03T12:00:00+00:00",
+      "remote_addr": "127.0.0.1",
+      "realip_remote_addr": "127.0.0.1",
+      "proxy_host": "",
+      "upstream_addr": "",
+      "server_protocol": "HTTP/1.1",
+      "request_method": "GET",
+      "host": "localhost",
+      "uri": "/",
+      "query_string": "",
+      "request_body": "",
+      "content_type": "",
+      "content_length": "",
+      "status": "200",
+      "bytes_sent": "612",
+      "request_time": "0.001",
+      "body_bytes_sent": "612",
+      "http_referrer": "",
+      "http_accept_language": "",
+      "http_user_agent": "curl/7.81.0"
+    });
+    let resp = srv.post("/v0.5/http_metrics").send_json(&[metric]).await?;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let resp = srv
+      .post("/v0.5/http_metrics")
+      .send_json(&[serde_json::json!({ "uri": "/" })])
+      .await?;
+    assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+    Ok(())
+  }
+
   async fn test_count(srv: &TestServer) -> TestRet {
     let mut resp = srv.get("/v0.5/http_metrics/count").send().await?;
     assert_eq!(resp.status(), http::StatusCode::OK);
@@ -81,6 +139,7 @@ mod tests {
   #[ntex::test]
   async fn basic() -> TestRet {
     let srv = generate_server(ntex_config).await;
+    test_create(&srv).await?;
     test_list(&srv).await?;
     test_count(&srv).await?;
     Ok(())
@@ -17,8 +17,10 @@ mod cargo;
 mod cargo_image;
 mod metric;
 mod http_metric;
+mod stream_metric;
 mod vm;
 mod vm_image;
+mod dns;
 
 pub struct Versionning;
 
@@ -41,7 +43,10 @@ where
 {
   type Response = WebResponse;
   type Error = Error;
-  type Future<'f> = Either<S::Future<'f>, Ready<Result<Self::Response, Self::Error>>> where Self: 'f;
+  type Future<'f>
+    = Either<S::Future<'f>, Ready<Result<Self::Response, Self::Error>>>
+  where
+    Self: 'f;
 
   ntex::forward_poll_ready!(service);
 
@@ -94,7 +99,9 @@ pub fn ntex_config(config: &mut web::ServiceConfig) {
       .configure(vm_image::ntex_config)
       .configure(vm::ntex_config)
       .configure(metric::ntex_config)
-      .configure(http_metric::ntex_config),
+      .configure(http_metric::ntex_config)
+      .configure(stream_metric::ntex_config)
+      .configure(dns::ntex_config),
   );
 }
 
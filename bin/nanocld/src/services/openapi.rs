@@ -30,7 +30,12 @@ use bollard_next::service::{
 use nanocl_stubs::generic::GenericCount;
 use nanocl_stubs::system::{Version, HostInfo};
 use nanocl_stubs::metric::Metric;
+use nanocl_stubs::dns::{
+  ResourceDnsRecord, DnsRecord, DnsRecordTarget, DnsSrvRecord, DnsEntrySummary,
+  DnsResolve, DnsResolveAnswer,
+};
 use nanocl_stubs::http_metric::HttpMetric;
+use nanocl_stubs::stream_metric::StreamMetric;
 use nanocl_stubs::vm_image::{VmImage, VmImageResizePayload};
 use nanocl_stubs::generic::GenericDelete;
 use nanocl_stubs::node::{Node, NodeContainerSummary};
@@ -49,19 +54,24 @@ use nanocl_stubs::vm_config::{
   VmConfig, VmConfigPartial, VmConfigUpdate, VmDiskConfig, VmHostConfig,
 };
 use nanocl_stubs::resource::{
-  Resource, ResourcePatch, ResourceConfig, ResourcePartial,
+  Resource, ResourcePatch, ResourceConfig, ResourcePartial, ResourceMigrate,
+  ResourceKindMigration, ResourceOwnerRef, ResourceOwnerKind, ResourceSecret,
 };
 use nanocl_stubs::proxy::{
   ResourceProxyRule, ProxyRuleHttp, ProxyHttpLocation, ProxySslConfig,
-  ProxyRuleStream, StreamTarget, ProxyStreamProtocol, UriTarget,
-  LocationTarget, HttpTarget, UrlRedirect, CargoTarget, ProxyRule,
+  ProxyHttpSsl, ProxySslAcme, ProxyAcmeConfig, ProxyRuleStream, StreamTarget,
+  ProxyStreamProtocol, UriTarget, ProxyUpstreamSsl, LocationTarget,
+  SplitTarget, SniRoute, VmTarget, ProxyNetwork, HttpTarget, UrlRedirect,
+  CargoTarget, LoadBalancing, ProxyRule, ResourceCertificate,
+  ResourceCertificateInspect, ProxyHeaders, ProxyBasicAuth, ProxyRateLimit,
+  ProxyTimeouts, ProxyCache, ProxyCacheValid,
 };
 
 use crate::error::HttpError;
 
 use super::{
   node, system, namespace, cargo, cargo_image, vm, vm_image, resource, metric,
-  http_metric,
+  http_metric, stream_metric, dns,
 };
 
 /// When returning a [HttpError](HttpError) the status code is stripped and the error is returned as a json object with the message field set to the error message.
@@ -262,12 +272,26 @@ impl Modify for VersionModifier {
     resource::delete_resource,
     resource::patch_resource,
     resource::list_resource_history,
+    resource::inspect_resource_certificate,
+    resource::inspect_resource_secret,
+    resource::purge_resource_cache,
     resource::reset_resource,
+    resource::migrate_resource,
+    resource::prune_resource,
     // Metric
     metric::list_metric,
     // Http Metric
+    http_metric::create_http_metric,
     http_metric::list_http_metric,
     http_metric::count_http_metric,
+    // Stream Metric
+    stream_metric::create_stream_metric,
+    stream_metric::list_stream_metric,
+    stream_metric::count_stream_metric,
+    // Dns
+    dns::list_dns_records,
+    dns::resolve_dns,
+    dns::sync_dns,
   ),
   components(schemas(
     // Node
@@ -385,24 +409,56 @@ impl Modify for VersionModifier {
     ResourcePatch,
     ResourceConfig,
     ResourcePartial,
+    ResourceMigrate,
+    ResourceKindMigration,
+    ResourceOwnerRef,
+    ResourceOwnerKind,
+    ResourceSecret,
     // ProxyRules
     ResourceProxyRule,
     ProxyRule,
     ProxyRuleHttp,
     ProxyHttpLocation,
+    ProxyHeaders,
+    ProxyBasicAuth,
+    ProxyRateLimit,
+    ProxyTimeouts,
+    ProxyCache,
+    ProxyCacheValid,
     ProxySslConfig,
+    ResourceCertificate,
+    ResourceCertificateInspect,
+    ProxyHttpSsl,
+    ProxySslAcme,
+    ProxyAcmeConfig,
     ProxyRuleStream,
     StreamTarget,
     ProxyStreamProtocol,
     UriTarget,
+    ProxyUpstreamSsl,
     LocationTarget,
+    SplitTarget,
+    SniRoute,
+    VmTarget,
+    ProxyNetwork,
     HttpTarget,
     UrlRedirect,
     CargoTarget,
+    LoadBalancing,
     // Metric
     Metric,
     // HttpMetric
     HttpMetric,
+    // Dns
+    ResourceDnsRecord,
+    DnsRecord,
+    DnsRecordTarget,
+    DnsSrvRecord,
+    DnsEntrySummary,
+    DnsResolve,
+    DnsResolveAnswer,
+    // StreamMetric
+    StreamMetric,
     // Error
     ApiError,
     // Generic Types
@@ -422,6 +478,8 @@ impl Modify for VersionModifier {
     (name = "Vms", description = "Virtual machines management endpoints."),
     (name = "Metrics", description = "Metrics management endpoints."),
     (name = "HttpMetrics", description = "HTTP Metrics management endpoints."),
+    (name = "StreamMetrics", description = "Stream Metrics management endpoints."),
+    (name = "Dns", description = "Dns controller inspection endpoints."),
   ),
   modifiers(&VersionModifier),
 )]
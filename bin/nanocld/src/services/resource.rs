@@ -4,14 +4,17 @@
 
 use ntex::rt;
 use ntex::web;
+use ntex::http::StatusCode;
 
 use nanocl_stubs::system::Event;
-use nanocl_stubs::resource::ResourcePatch;
+use nanocl_stubs::generic::GenericNspQuery;
+use nanocl_stubs::proxy::ResourceCertificateQuery;
+use nanocl_stubs::resource::{ResourcePatch, ResourceMigrate};
 use nanocl_stubs::resource::{ResourcePartial, ResourceQuery};
 
 use crate::{utils, repositories};
 use crate::error::HttpError;
-use crate::models::{DaemonState, ResourceResetPath};
+use crate::models::{DaemonState, Listener, ResourceResetPath};
 
 /// List resources
 #[cfg_attr(feature = "dev", utoipa::path(
@@ -20,7 +23,13 @@ use crate::models::{DaemonState, ResourceResetPath};
   path = "/resources",
   params(
     ("Kind" = Option<String>, Query, description = "Filter by resource kind"),
+    ("Namespace" = Option<String>, Query, description = "Filter by resource namespace"),
     ("Contains" = Option<String>, Query, description = "Filter by resource content"),
+    ("Orphaned" = Option<bool>, Query, description = "Filter resources whose owners are all gone"),
+    ("Labels" = Option<String>, Query, description = "Label selector like env=prod,tier!=db"),
+    ("ConfigPath" = Option<String>, Query, description = "Json path predicates on the config like $.Rule.Http.Domain=example.com or $.Port:=80 for a json value"),
+    ("Limit" = Option<i64>, Query, description = "Max amount of resources in response"),
+    ("Offset" = Option<i64>, Query, description = "Offset of the first resource in response"),
   ),
   responses(
     (status = 200, description = "List of resources", body = [Resource]),
@@ -41,7 +50,8 @@ pub(crate) async fn list_resource(
   tag = "Resources",
   path = "/resources/{Name}",
   params(
-    ("Name" = String, Path, description = "The resource name to inspect")
+    ("Name" = String, Path, description = "The resource name to inspect"),
+    ("Namespace" = Option<String>, Query, description = "The namespace of the resource"),
   ),
   responses(
     (status = 200, description = "Detailed information about a resource", body = Resource),
@@ -50,11 +60,13 @@ pub(crate) async fn list_resource(
 ))]
 #[web::get("/resources/{name}")]
 pub(crate) async fn inspect_resource(
+  web::types::Query(qs): web::types::Query<GenericNspQuery>,
   path: web::types::Path<(String, String)>,
   state: web::types::State<DaemonState>,
 ) -> Result<web::HttpResponse, HttpError> {
+  let key = utils::key::gen_resource_key(&qs.namespace, &path.1);
   let resource =
-    repositories::resource::inspect_by_key(&path.1, &state.pool).await?;
+    repositories::resource::inspect_by_key(&key, &state.pool).await?;
   Ok(web::HttpResponse::Ok().json(&resource))
 }
 
@@ -73,7 +85,7 @@ pub(crate) async fn create_resource(
   web::types::Json(payload): web::types::Json<ResourcePartial>,
   state: web::types::State<DaemonState>,
 ) -> Result<web::HttpResponse, HttpError> {
-  let resource = utils::resource::create(&payload, &state.pool).await?;
+  let resource = utils::resource::create(&payload, &state).await?;
   let resource_ptr = resource.clone();
   rt::spawn(async move {
     let _ = state
@@ -90,7 +102,8 @@ pub(crate) async fn create_resource(
   tag = "Resources",
   path = "/resources/{Name}",
   params(
-    ("Name" = String, Path, description = "The resource name to delete")
+    ("Name" = String, Path, description = "The resource name to delete"),
+    ("Namespace" = Option<String>, Query, description = "The namespace of the resource"),
   ),
   responses(
     (status = 202, description = "The resource and his history has been deleted"),
@@ -99,11 +112,13 @@ pub(crate) async fn create_resource(
 ))]
 #[web::delete("/resources/{name}")]
 pub(crate) async fn delete_resource(
+  web::types::Query(qs): web::types::Query<GenericNspQuery>,
   path: web::types::Path<(String, String)>,
   state: web::types::State<DaemonState>,
 ) -> Result<web::HttpResponse, HttpError> {
+  let key = utils::key::gen_resource_key(&qs.namespace, &path.1);
   let resource =
-    repositories::resource::inspect_by_key(&path.1, &state.pool).await?;
+    repositories::resource::inspect_by_key(&key, &state.pool).await?;
   utils::resource::delete(resource.clone(), &state.pool).await?;
   rt::spawn(async move {
     let _ = state
@@ -121,7 +136,8 @@ pub(crate) async fn delete_resource(
   tag = "Resources",
   path = "/resources/{Name}",
   params(
-    ("Name" = String, Path, description = "The resource name to patch")
+    ("Name" = String, Path, description = "The resource name to patch"),
+    ("Namespace" = Option<String>, Query, description = "The namespace of the resource"),
   ),
   responses(
     (status = 200, description = "The patched resource", body = Resource),
@@ -131,19 +147,24 @@ pub(crate) async fn delete_resource(
 #[web::patch("/resources/{name}")]
 pub(crate) async fn patch_resource(
   web::types::Json(payload): web::types::Json<ResourcePatch>,
+  web::types::Query(qs): web::types::Query<GenericNspQuery>,
   path: web::types::Path<(String, String)>,
   state: web::types::State<DaemonState>,
 ) -> Result<web::HttpResponse, HttpError> {
+  let key = utils::key::gen_resource_key(&qs.namespace, &path.1);
   let resource =
-    repositories::resource::inspect_by_key(&path.1, &state.pool).await?;
+    repositories::resource::inspect_by_key(&key, &state.pool).await?;
 
   let new_resource = ResourcePartial {
-    name: path.1.clone(),
+    name: resource.name,
+    namespace: resource.namespace_name,
     version: payload.version,
     kind: resource.kind,
     config: payload.config,
+    owner_refs: None,
+    labels: None,
   };
-  let resource = utils::resource::patch(new_resource, &state.pool).await?;
+  let resource = utils::resource::patch(new_resource, &state).await?;
   let resource_ptr = resource.clone();
   rt::spawn(async move {
     let _ = state
@@ -160,7 +181,8 @@ pub(crate) async fn patch_resource(
   tag = "Resources",
   path = "/resources/{Name}/histories",
   params(
-    ("Name" = String, Path, description = "The resource name to list history")
+    ("Name" = String, Path, description = "The resource name to list history"),
+    ("Namespace" = Option<String>, Query, description = "The namespace of the resource"),
   ),
   responses(
     (status = 200, description = "The resource history", body = [ResourceConfig]),
@@ -169,15 +191,124 @@ pub(crate) async fn patch_resource(
 ))]
 #[web::get("/resources/{name}/histories")]
 pub(crate) async fn list_resource_history(
+  web::types::Query(qs): web::types::Query<GenericNspQuery>,
   path: web::types::Path<(String, String)>,
   state: web::types::State<DaemonState>,
 ) -> Result<web::HttpResponse, HttpError> {
+  let key = utils::key::gen_resource_key(&qs.namespace, &path.1);
   let items =
-    repositories::resource_config::list_by_resource(&path.1, &state.pool)
-      .await?;
+    repositories::resource_config::list_by_resource(&key, &state.pool).await?;
   Ok(web::HttpResponse::Ok().json(&items))
 }
 
+/// Get the material of a `Certificate` resource
+/// The decrypted key is only given to the local controllers
+/// connected to the unix socket of the daemon
+#[cfg_attr(feature = "dev", utoipa::path(
+  get,
+  tag = "Resources",
+  path = "/resources/{Name}/certificate",
+  params(
+    ("Name" = String, Path, description = "The certificate resource name"),
+    ("Namespace" = Option<String>, Query, description = "The namespace of the resource"),
+    ("WithKey" = Option<bool>, Query, description = "Include the decrypted key, only allowed over the unix socket"),
+  ),
+  responses(
+    (status = 200, description = "The certificate material", body = ResourceCertificateInspect),
+    (status = 400, description = "Resource is not a certificate", body = ApiError),
+    (status = 403, description = "The key is requested over tcp", body = ApiError),
+    (status = 404, description = "Resource is not existing", body = ApiError),
+  ),
+))]
+#[web::get("/resources/{name}/certificate")]
+pub(crate) async fn inspect_resource_certificate(
+  web::types::Query(qs): web::types::Query<ResourceCertificateQuery>,
+  path: web::types::Path<(String, String)>,
+  state: web::types::State<DaemonState>,
+  listener: web::types::State<Listener>,
+) -> Result<web::HttpResponse, HttpError> {
+  let with_key = qs.with_key.unwrap_or_default();
+  if with_key && *listener != Listener::Unix {
+    return Err(HttpError {
+      status: StatusCode::FORBIDDEN,
+      msg: "The certificate key is only available over the unix socket"
+        .to_owned(),
+    });
+  }
+  let key = utils::key::gen_resource_key(&qs.namespace, &path.1);
+  let resource =
+    repositories::resource::inspect_by_key(&key, &state.pool).await?;
+  let certificate =
+    utils::certificate::inspect_resource(&resource, with_key, &state)?;
+  Ok(web::HttpResponse::Ok().json(&certificate))
+}
+
+/// Get the data of a `Secret` resource with its values decrypted
+/// The values are only given to the local controllers
+/// connected to the unix socket of the daemon
+#[cfg_attr(feature = "dev", utoipa::path(
+  get,
+  tag = "Resources",
+  path = "/resources/{Name}/secret",
+  params(
+    ("Name" = String, Path, description = "The secret resource name"),
+    ("Namespace" = Option<String>, Query, description = "The namespace of the resource"),
+  ),
+  responses(
+    (status = 200, description = "The secret data", body = ResourceSecret),
+    (status = 400, description = "Resource is not a secret", body = ApiError),
+    (status = 403, description = "The secret is requested over tcp", body = ApiError),
+    (status = 404, description = "Resource is not existing", body = ApiError),
+  ),
+))]
+#[web::get("/resources/{name}/secret")]
+pub(crate) async fn inspect_resource_secret(
+  web::types::Query(qs): web::types::Query<GenericNspQuery>,
+  path: web::types::Path<(String, String)>,
+  state: web::types::State<DaemonState>,
+  listener: web::types::State<Listener>,
+) -> Result<web::HttpResponse, HttpError> {
+  if *listener != Listener::Unix {
+    return Err(HttpError {
+      status: StatusCode::FORBIDDEN,
+      msg: "Secrets are only available over the unix socket".to_owned(),
+    });
+  }
+  let key = utils::key::gen_resource_key(&qs.namespace, &path.1);
+  let resource =
+    repositories::resource::inspect_by_key(&key, &state.pool).await?;
+  let secret = utils::secret::decrypt_resource(&resource, &state)?;
+  Ok(web::HttpResponse::Ok().json(&secret))
+}
+
+/// Remove the cached responses of a `ProxyRule` resource
+#[cfg_attr(feature = "dev", utoipa::path(
+  post,
+  tag = "Resources",
+  path = "/resources/{Name}/cache/purge",
+  params(
+    ("Name" = String, Path, description = "The proxy rule resource name"),
+    ("Namespace" = Option<String>, Query, description = "The namespace of the resource"),
+  ),
+  responses(
+    (status = 200, description = "The cache is purged"),
+    (status = 400, description = "Resource is not a proxy rule", body = ApiError),
+    (status = 404, description = "Resource is not existing", body = ApiError),
+  ),
+))]
+#[web::post("/resources/{name}/cache/purge")]
+pub(crate) async fn purge_resource_cache(
+  web::types::Query(qs): web::types::Query<GenericNspQuery>,
+  path: web::types::Path<(String, String)>,
+  state: web::types::State<DaemonState>,
+) -> Result<web::HttpResponse, HttpError> {
+  let key = utils::key::gen_resource_key(&qs.namespace, &path.1);
+  let resource =
+    repositories::resource::inspect_by_key(&key, &state.pool).await?;
+  utils::resource::purge_cache(&resource).await?;
+  Ok(web::HttpResponse::Ok().finish())
+}
+
 /// Reset a resource to a specific history
 #[cfg_attr(feature = "dev", utoipa::path(
   patch,
@@ -185,7 +316,8 @@ pub(crate) async fn list_resource_history(
   path = "/resources/{Name}/histories/{Id}/reset",
   params(
     ("Name" = String, Path, description = "The resource name to reset"),
-    ("Id" = String, Path, description = "The resource history id to reset to")
+    ("Id" = String, Path, description = "The resource history id to reset to"),
+    ("Namespace" = Option<String>, Query, description = "The namespace of the resource"),
   ),
   responses(
     (status = 200, description = "The resource has been reset", body = Resource),
@@ -194,22 +326,27 @@ pub(crate) async fn list_resource_history(
 ))]
 #[web::patch("/resources/{name}/histories/{id}/reset")]
 pub(crate) async fn reset_resource(
+  web::types::Query(qs): web::types::Query<GenericNspQuery>,
   path: web::types::Path<ResourceResetPath>,
   state: web::types::State<DaemonState>,
 ) -> Result<web::HttpResponse, HttpError> {
   let history =
     repositories::resource_config::find_by_key(&path.id, &state.pool).await?;
 
+  let key = utils::key::gen_resource_key(&qs.namespace, &path.name);
   let resource =
-    repositories::resource::inspect_by_key(&path.name, &state.pool).await?;
+    repositories::resource::inspect_by_key(&key, &state.pool).await?;
 
   let new_resource = ResourcePartial {
     name: resource.name,
+    namespace: resource.namespace_name,
     version: history.version,
     kind: resource.kind,
     config: history.data,
+    owner_refs: None,
+    labels: None,
   };
-  let resource = utils::resource::patch(new_resource, &state.pool).await?;
+  let resource = utils::resource::patch(new_resource, &state).await?;
   let resource_ptr = resource.clone();
   rt::spawn(async move {
     let _ = state
@@ -220,6 +357,54 @@ pub(crate) async fn reset_resource(
   Ok(web::HttpResponse::Ok().json(&resource))
 }
 
+/// Migrate every resource of a kind to a version of the kind
+#[cfg_attr(feature = "dev", utoipa::path(
+  post,
+  request_body = ResourceMigrate,
+  tag = "Resources",
+  path = "/resources/migrate",
+  responses(
+    (status = 200, description = "The migrated resources", body = [Resource]),
+    (status = 404, description = "Kind version is not existing", body = ApiError),
+  ),
+))]
+#[web::post("/resources/migrate")]
+pub(crate) async fn migrate_resource(
+  web::types::Json(payload): web::types::Json<ResourceMigrate>,
+  state: web::types::State<DaemonState>,
+) -> Result<web::HttpResponse, HttpError> {
+  let resources =
+    utils::resource::migrate(&payload.kind, &payload.version, &state.pool)
+      .await?;
+  let resources_ptr = resources.clone();
+  rt::spawn(async move {
+    for resource in resources_ptr {
+      let _ = state
+        .event_emitter
+        .emit(Event::ResourcePatched(Box::new(resource)))
+        .await;
+    }
+  });
+  Ok(web::HttpResponse::Ok().json(&resources))
+}
+
+/// Delete every resource whose owners are all gone
+#[cfg_attr(feature = "dev", utoipa::path(
+  post,
+  tag = "Resources",
+  path = "/resources/prune",
+  responses(
+    (status = 200, description = "The deleted resources", body = [Resource]),
+  ),
+))]
+#[web::post("/resources/prune")]
+pub(crate) async fn prune_resource(
+  state: web::types::State<DaemonState>,
+) -> Result<web::HttpResponse, HttpError> {
+  let resources = utils::resource::prune(&state).await?;
+  Ok(web::HttpResponse::Ok().json(&resources))
+}
+
 /// Endpoint to allow CORS preflight
 #[web::options("/resources{all}*")]
 pub(crate) async fn options_resource() -> Result<web::HttpResponse, HttpError> {
@@ -241,7 +426,12 @@ pub fn ntex_config(config: &mut web::ServiceConfig) {
   config.service(inspect_resource);
   config.service(patch_resource);
   config.service(list_resource_history);
+  config.service(inspect_resource_certificate);
+  config.service(inspect_resource_secret);
+  config.service(purge_resource_cache);
   config.service(reset_resource);
+  config.service(migrate_resource);
+  config.service(prune_resource);
 }
 
 #[cfg(test)]
@@ -252,7 +442,12 @@ mod tests {
   use ntex::http::StatusCode;
 
   use crate::utils::tests::*;
-  use nanocl_stubs::resource::{Resource, ResourcePartial, ResourcePatch};
+  use nanocl_stubs::generic::GenericNspQuery;
+  use nanocl_stubs::proxy::ResourceCertificateInspect;
+  use nanocl_stubs::resource::{
+    Resource, ResourcePartial, ResourcePatch, ResourceMigrate, ResourceQuery,
+    ResourceOwnerRef, ResourceOwnerKind,
+  };
 
   #[ntex::test]
   async fn basic() -> TestRet {
@@ -276,9 +471,12 @@ mod tests {
 
     let resource = ResourcePartial {
       name: "test_resource".to_owned(),
+      namespace: None,
       version: "v0.0.1".to_owned(),
       kind: "Custom".to_owned(),
       config: config.clone(),
+      owner_refs: None,
+      labels: None,
     };
 
     let mut resp = srv
@@ -308,6 +506,14 @@ mod tests {
     assert_eq!(resource.kind, String::from("Custom"));
     assert_eq!(&resource.config, &config);
 
+    // Only proxy rules have a cache
+    let resp = srv
+      .post("/v0.2/resources/test_resource/cache/purge")
+      .send()
+      .await
+      .unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
     // History
     let _ = srv
       .get("/v0.2/resources/test_resource/histories")
@@ -339,4 +545,624 @@ mod tests {
     assert_eq!(resp.status(), StatusCode::ACCEPTED);
     Ok(())
   }
+
+  #[ntex::test]
+  async fn custom_kind_schema() -> TestRet {
+    let srv = generate_server(ntex_config).await;
+
+    let kind = ResourcePartial {
+      name: "test_kind".to_owned(),
+      namespace: None,
+      version: "v0.0.1".to_owned(),
+      kind: "Custom".to_owned(),
+      config: serde_json::json!({
+        "Schema": {
+          "type": "object",
+          "required": ["Watch"],
+          "properties": {
+            "Watch": {
+              "type": "array",
+              "items": { "type": "string" }
+            }
+          }
+        }
+      }),
+      owner_refs: None,
+      labels: None,
+    };
+    let resp = srv.post("/v0.2/resources").send_json(&kind).await?;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+
+    let invalid = ResourcePartial {
+      name: "test_kind_invalid".to_owned(),
+      namespace: None,
+      version: "v0.0.1".to_owned(),
+      kind: "test_kind".to_owned(),
+      config: serde_json::json!({}),
+      owner_refs: None,
+      labels: None,
+    };
+    let resp = srv.post("/v0.2/resources").send_json(&invalid).await?;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let valid = ResourcePartial {
+      name: "test_kind_valid".to_owned(),
+      namespace: None,
+      version: "v0.0.1".to_owned(),
+      kind: "test_kind".to_owned(),
+      config: serde_json::json!({ "Watch": ["test.global"] }),
+      owner_refs: None,
+      labels: None,
+    };
+    let resp = srv.post("/v0.2/resources").send_json(&valid).await?;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+
+    let bad_url = ResourcePartial {
+      name: "test_kind_bad_url".to_owned(),
+      namespace: None,
+      version: "v0.0.1".to_owned(),
+      kind: "Custom".to_owned(),
+      config: serde_json::json!({ "Url": "ftp://controller" }),
+      owner_refs: None,
+      labels: None,
+    };
+    let resp = srv.post("/v0.2/resources").send_json(&bad_url).await?;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let resp = srv.delete("/v0.2/resources/test_kind_valid").send().await?;
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+    let resp = srv.delete("/v0.2/resources/test_kind").send().await?;
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+    Ok(())
+  }
+
+  #[ntex::test]
+  async fn namespaced() -> TestRet {
+    let srv = generate_server(ntex_config).await;
+
+    let kind = ResourcePartial {
+      name: "test_nsp_kind".to_owned(),
+      namespace: Some("global".to_owned()),
+      version: "v0.0.1".to_owned(),
+      kind: "Custom".to_owned(),
+      config: serde_json::json!({}),
+      owner_refs: None,
+      labels: None,
+    };
+    let resp = srv.post("/v0.2/resources").send_json(&kind).await?;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let kind = ResourcePartial {
+      namespace: None,
+      ..kind
+    };
+    let resp = srv.post("/v0.2/resources").send_json(&kind).await?;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+
+    let resource = ResourcePartial {
+      name: "test_nsp".to_owned(),
+      namespace: Some("global".to_owned()),
+      version: "v0.0.1".to_owned(),
+      kind: "test_nsp_kind".to_owned(),
+      config: serde_json::json!({}),
+      owner_refs: None,
+      labels: None,
+    };
+    let resp = srv.post("/v0.2/resources").send_json(&resource).await?;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    // A name can't be used to escape a directory
+    for name in ["../test_nsp", ".test_nsp", ""] {
+      let invalid = ResourcePartial {
+        name: name.to_owned(),
+        namespace: None,
+        ..resource.clone()
+      };
+      let resp = srv.post("/v0.2/resources").send_json(&invalid).await?;
+      assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+    // A name can't collide with the key of a namespaced resource
+    let colliding = ResourcePartial {
+      name: "test_nsp.global".to_owned(),
+      namespace: None,
+      ..resource.clone()
+    };
+    let resp = srv.post("/v0.2/resources").send_json(&colliding).await?;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+    // Names can still contain dots
+    let dotted = ResourcePartial {
+      name: "test-nsp.example.com".to_owned(),
+      namespace: None,
+      ..resource.clone()
+    };
+    let resp = srv.post("/v0.2/resources").send_json(&dotted).await?;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let resp = srv
+      .delete("/v0.2/resources/test-nsp.example.com")
+      .send()
+      .await?;
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+    let resource = ResourcePartial {
+      namespace: None,
+      ..resource
+    };
+    let resp = srv.post("/v0.2/resources").send_json(&resource).await?;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+
+    let mut resp = srv
+      .get("/v0.2/resources")
+      .query(&ResourceQuery {
+        namespace: Some("global".to_owned()),
+        ..Default::default()
+      })?
+      .send()
+      .await?;
+    let items: Vec<Resource> = resp.json().await?;
+    assert!(items.iter().any(|item| item.key == "test_nsp.global"));
+    assert!(items.iter().all(|item| item.key != "test_nsp"));
+
+    let mut resp = srv
+      .get("/v0.2/resources/test_nsp")
+      .query(&GenericNspQuery {
+        namespace: Some("global".to_owned()),
+      })?
+      .send()
+      .await?;
+    let item: Resource = resp.json().await?;
+    assert_eq!(item.namespace_name, Some("global".to_owned()));
+
+    let resp = srv
+      .delete("/v0.2/resources/test_nsp")
+      .query(&GenericNspQuery {
+        namespace: Some("global".to_owned()),
+      })?
+      .send()
+      .await?;
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+    let resp = srv.delete("/v0.2/resources/test_nsp").send().await?;
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+    let resp = srv.delete("/v0.2/resources/test_nsp_kind").send().await?;
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+    Ok(())
+  }
+
+  #[ntex::test]
+  async fn migrate() -> TestRet {
+    let srv = generate_server(ntex_config).await;
+
+    let kind = ResourcePartial {
+      name: "test_migrate_kind".to_owned(),
+      namespace: None,
+      version: "v1".to_owned(),
+      kind: "Custom".to_owned(),
+      config: serde_json::json!({
+        "Schema": {
+          "type": "object",
+          "required": ["Watch"],
+        }
+      }),
+      owner_refs: None,
+      labels: None,
+    };
+    let resp = srv.post("/v0.2/resources").send_json(&kind).await?;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+
+    let resource = ResourcePartial {
+      name: "test_migrate".to_owned(),
+      namespace: None,
+      version: "v1".to_owned(),
+      kind: "test_migrate_kind".to_owned(),
+      config: serde_json::json!({ "Watch": ["test.global"] }),
+      owner_refs: None,
+      labels: None,
+    };
+    let resp = srv.post("/v0.2/resources").send_json(&resource).await?;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+
+    let kind_v2 = ResourcePatch {
+      version: "v2".to_owned(),
+      config: serde_json::json!({
+        "Schema": {
+          "type": "object",
+          "required": ["Targets"],
+        },
+        "Migration": {
+          "Patch": [
+            { "op": "move", "from": "/Watch", "path": "/Targets" }
+          ]
+        }
+      }),
+    };
+    let resp = srv
+      .patch("/v0.2/resources/test_migrate_kind")
+      .send_json(&kind_v2)
+      .await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let migrate = ResourceMigrate {
+      kind: "test_migrate_kind".to_owned(),
+      version: "v2".to_owned(),
+    };
+    let mut resp = srv
+      .post("/v0.2/resources/migrate")
+      .send_json(&migrate)
+      .await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let resources = resp.json::<Vec<Resource>>().await?;
+    assert_eq!(resources.len(), 1);
+    assert_eq!(resources[0].version, "v2");
+    assert_eq!(
+      resources[0].config,
+      serde_json::json!({ "Targets": ["test.global"] })
+    );
+
+    let resp = srv.delete("/v0.2/resources/test_migrate").send().await?;
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+    let resp = srv
+      .delete("/v0.2/resources/test_migrate_kind")
+      .send()
+      .await?;
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+    Ok(())
+  }
+
+  #[ntex::test]
+  async fn orphaned() -> TestRet {
+    let srv = generate_server(ntex_config).await;
+
+    let kind = ResourcePartial {
+      name: "test_gc_kind".to_owned(),
+      namespace: None,
+      version: "v0.0.1".to_owned(),
+      kind: "Custom".to_owned(),
+      config: serde_json::json!({}),
+      owner_refs: None,
+      labels: None,
+    };
+    let resp = srv.post("/v0.2/resources").send_json(&kind).await?;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+
+    let orphan = ResourcePartial {
+      name: "test_gc_orphan".to_owned(),
+      namespace: None,
+      version: "v0.0.1".to_owned(),
+      kind: "test_gc_kind".to_owned(),
+      config: serde_json::json!({}),
+      owner_refs: Some(vec![ResourceOwnerRef {
+        kind: ResourceOwnerKind::Cargo,
+        key: "test_gc_missing.global".to_owned(),
+      }]),
+      labels: None,
+    };
+    let resp = srv.post("/v0.2/resources").send_json(&orphan).await?;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+
+    let owned = ResourcePartial {
+      name: "test_gc_owned".to_owned(),
+      owner_refs: Some(vec![ResourceOwnerRef {
+        kind: ResourceOwnerKind::Namespace,
+        key: "global".to_owned(),
+      }]),
+      ..orphan.clone()
+    };
+    let resp = srv.post("/v0.2/resources").send_json(&owned).await?;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+
+    let mut resp = srv
+      .get("/v0.2/resources")
+      .query(&ResourceQuery {
+        orphaned: Some(true),
+        ..Default::default()
+      })?
+      .send()
+      .await?;
+    let items: Vec<Resource> = resp.json().await?;
+    assert!(items.iter().any(|item| item.key == "test_gc_orphan"));
+    assert!(items.iter().all(|item| item.key != "test_gc_owned"));
+
+    let mut resp = srv.post("/v0.2/resources/prune").send().await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let items: Vec<Resource> = resp.json().await?;
+    assert!(items.iter().any(|item| item.key == "test_gc_orphan"));
+
+    let resp = srv.get("/v0.2/resources/test_gc_orphan").send().await?;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    let resp = srv.delete("/v0.2/resources/test_gc_owned").send().await?;
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+    let resp = srv.delete("/v0.2/resources/test_gc_kind").send().await?;
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+    Ok(())
+  }
+
+  #[ntex::test]
+  async fn query() -> TestRet {
+    let srv = generate_server(ntex_config).await;
+
+    let kind = ResourcePartial {
+      name: "test_query_kind".to_owned(),
+      namespace: None,
+      version: "v0.0.1".to_owned(),
+      kind: "Custom".to_owned(),
+      config: serde_json::json!({}),
+      owner_refs: None,
+      labels: None,
+    };
+    let resp = srv.post("/v0.2/resources").send_json(&kind).await?;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+
+    let prod = ResourcePartial {
+      name: "test_query_prod".to_owned(),
+      namespace: None,
+      version: "v0.0.1".to_owned(),
+      kind: "test_query_kind".to_owned(),
+      config: serde_json::json!({
+        "Rule": { "Http": { "Domain": "prod.test-query.com" } }
+      }),
+      owner_refs: None,
+      labels: Some(
+        [("env", "prod"), ("tier", "web")]
+          .into_iter()
+          .map(|(key, value)| (key.to_owned(), value.to_owned()))
+          .collect(),
+      ),
+    };
+    let resp = srv.post("/v0.2/resources").send_json(&prod).await?;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+
+    let db = ResourcePartial {
+      name: "test_query_db".to_owned(),
+      config: serde_json::json!({
+        "Rule": { "Http": { "Domain": "db.test-query.com" } },
+        "Tag": "123",
+        "Replicas": 2,
+      }),
+      labels: Some(
+        [("env", "prod"), ("tier", "db")]
+          .into_iter()
+          .map(|(key, value)| (key.to_owned(), value.to_owned()))
+          .collect(),
+      ),
+      ..prod.clone()
+    };
+    let resp = srv.post("/v0.2/resources").send_json(&db).await?;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+
+    let mut resp = srv
+      .get("/v0.2/resources")
+      .query(&ResourceQuery {
+        labels: Some("env=prod,tier!=db".to_owned()),
+        ..Default::default()
+      })?
+      .send()
+      .await?;
+    let items: Vec<Resource> = resp.json().await?;
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].name, "test_query_prod");
+
+    let mut resp = srv
+      .get("/v0.2/resources")
+      .query(&ResourceQuery {
+        config_path: Some("$.Rule.Http.Domain=db.test-query.com".to_owned()),
+        ..Default::default()
+      })?
+      .send()
+      .await?;
+    let items: Vec<Resource> = resp.json().await?;
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].name, "test_query_db");
+
+    // Values are strings unless the typed form is used
+    for (config_path, expected) in [
+      ("$.Tag=123", 1),
+      ("$.Tag:=123", 0),
+      ("$.Replicas=2", 0),
+      ("$.Replicas:=2", 1),
+      ("$.Tag!:=123,$.Replicas:=2", 1),
+    ] {
+      let mut resp = srv
+        .get("/v0.2/resources")
+        .query(&ResourceQuery {
+          kind: Some("test_query_kind".to_owned()),
+          config_path: Some(config_path.to_owned()),
+          ..Default::default()
+        })?
+        .send()
+        .await?;
+      let items: Vec<Resource> = resp.json().await?;
+      assert_eq!(items.len(), expected, "{config_path}");
+    }
+
+    let resp = srv
+      .get("/v0.2/resources")
+      .query(&ResourceQuery {
+        config_path: Some("$.Replicas:=two".to_owned()),
+        ..Default::default()
+      })?
+      .send()
+      .await?;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let mut resp = srv
+      .get("/v0.2/resources")
+      .query(&ResourceQuery {
+        kind: Some("test_query_kind".to_owned()),
+        limit: Some(1),
+        offset: Some(1),
+        ..Default::default()
+      })?
+      .send()
+      .await?;
+    let items: Vec<Resource> = resp.json().await?;
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].name, "test_query_prod");
+
+    let resp = srv
+      .get("/v0.2/resources")
+      .query(&ResourceQuery {
+        labels: Some("env".to_owned()),
+        ..Default::default()
+      })?
+      .send()
+      .await?;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let resp = srv.delete("/v0.2/resources/test_query_prod").send().await?;
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+    let resp = srv.delete("/v0.2/resources/test_query_db").send().await?;
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+    let resp = srv.delete("/v0.2/resources/test_query_kind").send().await?;
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+    Ok(())
+  }
+
+  /// Generate a self signed certificate and his private key as pem
+  fn gen_certificate() -> (String, String) {
+    use openssl::rsa::Rsa;
+    use openssl::pkey::PKey;
+    use openssl::hash::MessageDigest;
+    use openssl::x509::{X509, X509NameBuilder};
+
+    let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+    let mut name = X509NameBuilder::new().unwrap();
+    name
+      .append_entry_by_text("CN", "test.nanocl.internal")
+      .unwrap();
+    let name = name.build();
+    let mut builder = X509::builder().unwrap();
+    builder.set_version(2).unwrap();
+    builder.set_subject_name(&name).unwrap();
+    builder.set_issuer_name(&name).unwrap();
+    builder.set_pubkey(&key).unwrap();
+    let not_before = openssl::asn1::Asn1Time::days_from_now(0).unwrap();
+    let not_after = openssl::asn1::Asn1Time::days_from_now(1).unwrap();
+    builder.set_not_before(&not_before).unwrap();
+    builder.set_not_after(&not_after).unwrap();
+    builder.sign(&key, MessageDigest::sha256()).unwrap();
+    let cert = builder.build().to_pem().unwrap();
+    let key = key.private_key_to_pem_pkcs8().unwrap();
+    (
+      String::from_utf8(cert).unwrap(),
+      String::from_utf8(key).unwrap(),
+    )
+  }
+
+  #[ntex::test]
+  async fn certificate() -> TestRet {
+    let srv = generate_server(ntex_config).await;
+    let (certificate, certificate_key) = gen_certificate();
+
+    let resource = ResourcePartial {
+      name: "test_certificate".to_owned(),
+      namespace: None,
+      version: "v0.1".to_owned(),
+      kind: "Certificate".to_owned(),
+      config: serde_json::json!({
+        "Certificate": certificate,
+        "CertificateKey": certificate_key,
+      }),
+      owner_refs: None,
+      labels: None,
+    };
+    let mut resp = srv.post("/v0.2/resources").send_json(&resource).await?;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let created: Resource = resp.json().await?;
+    let stored_key = created.config["CertificateKey"].as_str().unwrap();
+    assert_ne!(stored_key, certificate_key);
+    assert!(!stored_key.contains("PRIVATE KEY"));
+
+    let secret_key = std::fs::read("/var/lib/nanocl/secret.key")?;
+    let decrypted = crate::utils::secret::decrypt(stored_key, &secret_key)?;
+    assert_eq!(decrypted, certificate_key);
+
+    // The key is left out by default
+    let mut resp = srv
+      .get("/v0.2/resources/test_certificate/certificate")
+      .send()
+      .await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let material: ResourceCertificateInspect = resp.json().await?;
+    assert_eq!(material.certificate, certificate);
+    assert_eq!(material.certificate_key, None);
+    assert_eq!(material.dh_param, None);
+
+    // and only available over the unix socket
+    let resp = srv
+      .get("/v0.2/resources/test_certificate/certificate?WithKey=true")
+      .send()
+      .await?;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    // Applying the stored config again keep the encrypted key valid
+    let resp = srv
+      .patch("/v0.2/resources/test_certificate")
+      .send_json(&ResourcePatch {
+        version: "v0.2".to_owned(),
+        config: created.config.clone(),
+      })
+      .await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let mut resp = srv.get("/v0.2/resources/test_certificate").send().await?;
+    let patched: Resource = resp.json().await?;
+    let stored_key = patched.config["CertificateKey"].as_str().unwrap();
+    let decrypted = crate::utils::secret::decrypt(stored_key, &secret_key)?;
+    assert_eq!(decrypted, certificate_key);
+
+    let invalid = ResourcePartial {
+      name: "test_certificate_invalid".to_owned(),
+      config: serde_json::json!({
+        "Certificate": "not a certificate",
+        "CertificateKey": certificate_key,
+      }),
+      ..resource
+    };
+    let resp = srv.post("/v0.2/resources").send_json(&invalid).await?;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let resp = srv
+      .delete("/v0.2/resources/test_certificate")
+      .send()
+      .await?;
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+    Ok(())
+  }
+
+  #[ntex::test]
+  async fn secret() -> TestRet {
+    let srv = generate_server(ntex_config).await;
+
+    let resource = ResourcePartial {
+      name: "test_secret".to_owned(),
+      namespace: None,
+      version: "v0.1".to_owned(),
+      kind: "Secret".to_owned(),
+      config: serde_json::json!({
+        "Data": { "admin": "password" },
+      }),
+      owner_refs: None,
+      labels: None,
+    };
+    let mut resp = srv.post("/v0.2/resources").send_json(&resource).await?;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let created: Resource = resp.json().await?;
+    assert_ne!(created.config["Data"]["admin"], "password");
+
+    let secret_key = std::fs::read("/var/lib/nanocl/secret.key")?;
+    let stored = created.config["Data"]["admin"].as_str().unwrap();
+    let decrypted = crate::utils::secret::decrypt(stored, &secret_key)?;
+    assert_eq!(decrypted, "password");
+
+    // The values are only available over the unix socket
+    let resp = srv.get("/v0.2/resources/test_secret/secret").send().await?;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    let resp = srv
+      .get("/v0.2/resources/test_secret/certificate")
+      .send()
+      .await?;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let resp = srv.delete("/v0.2/resources/test_secret").send().await?;
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+    Ok(())
+  }
 }
@@ -0,0 +1,147 @@
+use ntex::web;
+
+use nanocl_stubs::stream_metric::{StreamMetricListQuery, StreamMetricCountQuery};
+
+use crate::repositories;
+use crate::error::HttpError;
+use crate::models::{DaemonState, StreamMetricPartial};
+
+/// Get stream metrics of all peer nodes
+#[cfg_attr(feature = "dev", utoipa::path(
+  get,
+  tag = "StreamMetrics",
+  path = "/stream_metrics",
+  params(
+    ("Limit" = Option<i64>, Query, description = "Limit of the list"),
+    ("Offset" = Option<i64>, Query, description = "Offset of the list"),
+  ),
+  responses(
+    (status = 200, description = "Array of stream metrics founds", body = Vec<StreamMetric>),
+  ),
+))]
+#[web::get("/stream_metrics")]
+pub(crate) async fn list_stream_metric(
+  qs: web::types::Query<StreamMetricListQuery>,
+  state: web::types::State<DaemonState>,
+) -> Result<web::HttpResponse, HttpError> {
+  let metrics = repositories::stream_metric::list(&qs, &state.pool).await?;
+  Ok(web::HttpResponse::Ok().json(&metrics))
+}
+
+/// Count stream metrics of all peer nodes
+#[cfg_attr(feature = "dev", utoipa::path(
+  get,
+  tag = "StreamMetrics",
+  path = "/stream_metrics/count",
+  params(
+    ("Status" = Option<String>, Query, description = "Filter by status", example = "200,299"),
+  ),
+  responses(
+    (status = 200, description = "Count of stream metrics founds", body = GenericCount),
+  ),
+))]
+#[web::get("/stream_metrics/count")]
+pub(crate) async fn count_stream_metric(
+  qs: web::types::Query<StreamMetricCountQuery>,
+  state: web::types::State<DaemonState>,
+) -> Result<web::HttpResponse, HttpError> {
+  let count = repositories::stream_metric::count(&qs, &state.pool).await?;
+  Ok(web::HttpResponse::Ok().json(&count))
+}
+
+/// Save a batch of entries of the json stream access log of the proxy
+#[cfg_attr(feature = "dev", utoipa::path(
+  post,
+  tag = "StreamMetrics",
+  path = "/stream_metrics",
+  request_body = Vec<Object>,
+  responses(
+    (status = 201, description = "Stream metrics created"),
+    (status = 400, description = "Invalid stream metric"),
+  ),
+))]
+#[web::post("/stream_metrics")]
+pub(crate) async fn create_stream_metric(
+  payload: web::types::Json<Vec<StreamMetricPartial>>,
+  state: web::types::State<DaemonState>,
+) -> Result<web::HttpResponse, HttpError> {
+  let metrics = payload
+    .iter()
+    .map(|metric| metric.to_db_model(&state.config.hostname))
+    .collect::<Vec<_>>();
+  repositories::stream_metric::create_many(&metrics, &state.pool).await?;
+  Ok(web::HttpResponse::Created().finish())
+}
+
+pub fn ntex_config(config: &mut web::ServiceConfig) {
+  config.service(create_stream_metric);
+  config.service(list_stream_metric);
+  config.service(count_stream_metric);
+}
+
+#[cfg(test)]
+mod tests {
+
+  use ntex::http;
+  use nanocl_stubs::generic::GenericCount;
+  use nanocl_stubs::stream_metric::StreamMetric;
+
+  use crate::services::ntex_config;
+  use crate::utils::tests::*;
+
+  async fn test_create(srv: &TestServer) -> TestRet {
+    let metric = serde_json::json!({
+      "date_gmt": "2023-05-22T12:00:10+00:00",
+      "remote_addr": "127.0.0.1",
+      "upstream_addr": "172.18.0.2:5432",
+      "protocol": "TCP",
+      "server_port": "5432",
+      "status": "200",
+      "session_time": "10.000",
+      "bytes_sent": "2048",
+      "bytes_received": "512",
+      "upstream_bytes_sent": "512",
+      "upstream_bytes_received": "2048",
+      "upstream_connect_time": "0.001"
+    });
+    let resp = srv
+      .post("/v0.5/stream_metrics")
+      .send_json(&[metric])
+      .await?;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let resp = srv
+      .post("/v0.5/stream_metrics")
+      .send_json(&[serde_json::json!({ "protocol": "TCP" })])
+      .await?;
+    assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+    Ok(())
+  }
+
+  async fn test_list(srv: &TestServer) -> TestRet {
+    let mut resp = srv.get("/v0.5/stream_metrics").send().await?;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let _ = resp.json::<Vec<StreamMetric>>().await?;
+    Ok(())
+  }
+
+  async fn test_count(srv: &TestServer) -> TestRet {
+    let mut resp = srv
+      .get("/v0.5/stream_metrics/count")
+      .query(&[("Status", "200,299")])?
+      .send()
+      .await?;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let count = resp.json::<GenericCount>().await?;
+    assert!(count.count > 0);
+    Ok(())
+  }
+
+  #[ntex::test]
+  async fn basic() -> TestRet {
+    let srv = generate_server(ntex_config).await;
+    test_create(&srv).await?;
+    test_list(&srv).await?;
+    test_count(&srv).await?;
+    Ok(())
+  }
+}
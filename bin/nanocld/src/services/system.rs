@@ -63,6 +63,7 @@ pub(crate) async fn get_info(
   let host_gateway = state.config.gateway.clone();
   let info = HostInfo {
     host_gateway,
+    advertise_addr: state.config.advertise_addr.clone(),
     docker,
   };
   Ok(web::HttpResponse::Ok().json(&info))
@@ -18,6 +18,8 @@ use futures::future::ready;
 use bollard_next::container::AttachContainerOptions;
 
 use nanocl_stubs::cargo::OutputLog;
+use nanocl_stubs::system::Event;
+use nanocl_stubs::resource::{ResourceOwnerRef, ResourceOwnerKind};
 use nanocl_stubs::generic::GenericNspQuery;
 use nanocl_stubs::vm_config::{VmConfigPartial, VmConfigUpdate};
 
@@ -104,6 +106,15 @@ pub(crate) async fn start_vm(
 
   repositories::vm::find_by_key(&key, &state.pool).await?;
   utils::vm::start(&key, &state.docker_api).await?;
+  rt::spawn(async move {
+    let vm = utils::vm::inspect(&key, &state.docker_api, &state.pool)
+      .await
+      .unwrap();
+    let _ = state
+      .event_emitter
+      .emit(Event::VmStarted(Box::new(vm)))
+      .await;
+  });
 
   Ok(web::HttpResponse::Ok().finish())
 }
@@ -133,6 +144,15 @@ pub(crate) async fn stop_vm(
 
   repositories::vm::find_by_key(&key, &state.pool).await?;
   utils::vm::stop_by_key(&key, &state.docker_api, &state.pool).await?;
+  rt::spawn(async move {
+    let vm = utils::vm::inspect(&key, &state.docker_api, &state.pool)
+      .await
+      .unwrap();
+    let _ = state
+      .event_emitter
+      .emit(Event::VmStopped(Box::new(vm)))
+      .await;
+  });
 
   Ok(web::HttpResponse::Ok().finish())
 }
@@ -160,7 +180,21 @@ pub(crate) async fn delete_vm(
   let namespace = utils::key::resolve_nsp(&qs.namespace);
   let key = utils::key::gen_key(&namespace, &name);
 
+  let vm = utils::vm::inspect(&key, &state.docker_api, &state.pool).await?;
   utils::vm::delete(&key, true, &state.docker_api, &state.pool).await?;
+  let owner = ResourceOwnerRef {
+    kind: ResourceOwnerKind::Vm,
+    key: key.to_owned(),
+  };
+  if let Err(err) = utils::resource::delete_by_owner(&owner, &state).await {
+    log::warn!("Unable to delete resources owned by {key}: {err}");
+  }
+  rt::spawn(async move {
+    let _ = state
+      .event_emitter
+      .emit(Event::VmDeleted(Box::new(vm)))
+      .await;
+  });
 
   Ok(web::HttpResponse::Ok().finish())
 }
@@ -245,6 +279,15 @@ pub(crate) async fn patch_vm(
   let version = path.0.clone();
 
   let vm = utils::vm::patch(&key, &payload, &version, &state).await?;
+  rt::spawn(async move {
+    let vm = utils::vm::inspect(&key, &state.docker_api, &state.pool)
+      .await
+      .unwrap();
+    let _ = state
+      .event_emitter
+      .emit(Event::VmPatched(Box::new(vm)))
+      .await;
+  });
 
   Ok(web::HttpResponse::Ok().json(&vm))
 }
@@ -412,6 +455,7 @@ mod tests {
   use crate::services::ntex_config;
 
   use ntex::http::StatusCode;
+  use nanocl_stubs::vm_config::{VmConfigPartial, VmConfigUpdate};
 
   use crate::utils::tests::*;
 
@@ -429,4 +473,27 @@ mod tests {
     );
     Ok(())
   }
+  #[ntex::test]
+  pub(crate) async fn invalid_hostname() -> TestRet {
+    let srv = generate_server(ntex_config).await;
+    let hostname = Some("vm\nhost-record=example.com".to_owned());
+    let resp = srv
+      .post("/v0.2/vms")
+      .send_json(&VmConfigPartial {
+        name: "api-test-vm-hostname".to_owned(),
+        hostname: hostname.clone(),
+        ..Default::default()
+      })
+      .await?;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let resp = srv
+      .patch("/v0.2/vms/api-test-vm-hostname")
+      .send_json(&VmConfigUpdate {
+        hostname,
+        ..Default::default()
+      })
+      .await?;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    Ok(())
+  }
 }
@@ -2,7 +2,7 @@ use tokio::fs;
 
 use nanocl_stubs::config::DaemonConfig;
 
-use crate::{event, repositories};
+use crate::{event, repositories, utils};
 use crate::models::{Pool, DaemonState, NodeDbModel};
 
 use crate::error::CliError;
@@ -46,23 +46,29 @@ pub async fn init(daemon_conf: &DaemonConfig) -> Result<DaemonState, CliError> {
   #[cfg(feature = "test")]
   let mut proxy_conf =
     include_str!("../../specs/controllers/test.proxy.yml").to_owned();
-  // #[cfg(feature = "dev")]
-  // let mut dns_conf =
-  //   include_str!("../../specs/controllers/dev.dns.yml").to_owned();
-  // #[cfg(feature = "release")]
-  // let mut dns_conf = include_str!("../../specs/controllers/dns.yml").to_owned();
-  // #[cfg(feature = "test")]
-  // let mut dns_conf =
-  //   include_str!("../../specs/controllers/test.dns.yml").to_owned();
+  #[cfg(feature = "dev")]
+  let mut dns_conf =
+    include_str!("../../specs/controllers/dev.dns.yml").to_owned();
+  #[cfg(feature = "release")]
+  let mut dns_conf = include_str!("../../specs/controllers/dns.yml").to_owned();
+  #[cfg(feature = "test")]
+  let mut dns_conf =
+    include_str!("../../specs/controllers/test.dns.yml").to_owned();
   let mut metrics_conf = include_str!("../../specs/metrics.yml").to_owned();
   let mut store_conf = include_str!("../../specs/store.yml").to_owned();
-  // dns_conf = dns_conf.replace("{state_dir}", &daemon_conf.state_dir);
+  dns_conf = dns_conf.replace("{state_dir}", &daemon_conf.state_dir);
   store_conf = store_conf.replace("{state_dir}", &daemon_conf.state_dir);
   proxy_conf = proxy_conf.replace("{state_dir}", &daemon_conf.state_dir);
   metrics_conf = metrics_conf.replace("{state_dir}", &daemon_conf.state_dir);
   store_conf =
     store_conf.replace("{advertise_addr}", &daemon_conf.advertise_addr);
 
+  // Fail fast instead of on the first request using the key
+  let secret_key = match &daemon_conf.secret_key_file {
+    Some(path) => Some(utils::secret::read_secret_key(path)?),
+    None => None,
+  };
+
   let docker = bollard_next::Docker::connect_with_unix(
     &daemon_conf.docker_host,
     120,
@@ -81,7 +87,7 @@ pub async fn init(daemon_conf: &DaemonConfig) -> Result<DaemonState, CliError> {
   super::system::ensure_network("system", &docker).await?;
   super::system::start_subsystem(&docker, &store_conf).await?;
   super::system::start_subsystem(&docker, &metrics_conf).await?;
-  // super::system::boot_controller(&docker, &dns_conf).await?;
+  super::system::boot_controller(&docker, &dns_conf).await?;
   super::system::boot_controller(&docker, &proxy_conf).await?;
 
   let pool = super::store::init(&docker).await?;
@@ -91,6 +97,7 @@ pub async fn init(daemon_conf: &DaemonConfig) -> Result<DaemonState, CliError> {
     docker_api: docker.clone(),
     config: daemon_conf.to_owned(),
     event_emitter: event::EventEmitter::new(),
+    secret_key,
     version: VERSION.to_owned(),
   };
   super::system::register_namespace("system", false, &daemon_state).await?;
@@ -125,6 +132,7 @@ mod tests {
       nodes: Vec::default(),
       hostname: None,
       advertise_addr: None,
+      secret_key_file: None,
     };
 
     let config = config::init(&args).expect("Expect to init config");
@@ -16,10 +16,12 @@ use bollard_next::service::{RestartPolicy, RestartPolicyNameEnum};
 use bollard_next::container::{ListContainersOptions, RemoveContainerOptions};
 
 use nanocl_stubs::cargo::{CargoKillOptions, GenericCargoListQuery};
+use nanocl_stubs::resource::{ResourceOwnerRef, ResourceOwnerKind};
 use nanocl_stubs::cargo_config::Config as ContainerConfig;
 use nanocl_stubs::cargo_config::{CargoConfigPartial, CargoConfigUpdate};
 use nanocl_stubs::cargo::{
-  Cargo, CargoSummary, CargoInspect, OutputLog, CreateExecOptions, CargoLogQuery
+  Cargo, CargoSummary, CargoInspect, OutputLog, CreateExecOptions,
+  CargoLogQuery,
 };
 
 use crate::models::DaemonState;
@@ -352,6 +354,14 @@ pub async fn delete(
   repositories::cargo_config::delete_by_cargo_key(cargo_key, &state.pool)
     .await?;
 
+  let owner = ResourceOwnerRef {
+    kind: ResourceOwnerKind::Cargo,
+    key: cargo_key.to_owned(),
+  };
+  if let Err(err) = utils::resource::delete_by_owner(&owner, state).await {
+    log::warn!("Unable to delete resources owned by {cargo_key}: {err}");
+  }
+
   Ok(())
 }
 
@@ -0,0 +1,84 @@
+use ntex::http::StatusCode;
+use openssl::pkey::PKey;
+use openssl::x509::X509;
+
+use nanocl_stubs::proxy::{ResourceCertificate, ResourceCertificateInspect};
+use nanocl_stubs::resource::{Resource, ResourcePartial};
+
+use crate::error::HttpError;
+use crate::models::DaemonState;
+
+use super::secret::{ENCRYPTED_PREFIX, crypto_error, secret_key, encrypt, decrypt};
+
+fn parse_config(
+  config: &serde_json::Value,
+) -> Result<ResourceCertificate, HttpError> {
+  serde_json::from_value::<ResourceCertificate>(config.clone()).map_err(|err| {
+    HttpError {
+      status: StatusCode::BAD_REQUEST,
+      msg: format!("Invalid certificate {err}"),
+    }
+  })
+}
+
+fn invalid(msg: &str) -> HttpError {
+  HttpError {
+    status: StatusCode::BAD_REQUEST,
+    msg: format!("Invalid certificate {msg}"),
+  }
+}
+
+/// Validate the pem material of a `Certificate` resource
+/// and encrypt his key before it's stored.
+/// A key already encrypted by the daemon is kept as is.
+pub fn hook_apply(
+  resource: &ResourcePartial,
+  state: &DaemonState,
+) -> Result<ResourcePartial, HttpError> {
+  let mut config = parse_config(&resource.config)?;
+  X509::stack_from_pem(config.certificate.as_bytes())
+    .map_err(|err| invalid(&format!("chain: {err}")))?;
+  let key = secret_key(state)?;
+  if !config.certificate_key.starts_with(ENCRYPTED_PREFIX) {
+    PKey::private_key_from_pem(config.certificate_key.as_bytes())
+      .map_err(|err| invalid(&format!("key: {err}")))?;
+    config.certificate_key = encrypt(&config.certificate_key, key)?;
+  } else {
+    decrypt(&config.certificate_key, key)?;
+  }
+  if let Some(dh_param) = &config.dh_param {
+    openssl::dh::Dh::params_from_pem(dh_param.as_bytes())
+      .map_err(|err| invalid(&format!("dh param: {err}")))?;
+  }
+  Ok(ResourcePartial {
+    config: serde_json::to_value(config).map_err(crypto_error)?,
+    ..resource.clone()
+  })
+}
+
+/// Get the material of a `Certificate` resource
+/// The key is decrypted only when `with_key` is set
+pub fn inspect_resource(
+  resource: &Resource,
+  with_key: bool,
+  state: &DaemonState,
+) -> Result<ResourceCertificateInspect, HttpError> {
+  if resource.kind != "Certificate" {
+    return Err(HttpError {
+      status: StatusCode::BAD_REQUEST,
+      msg: format!("Resource {} is not a Certificate", resource.name),
+    });
+  }
+  let config = parse_config(&resource.config)?;
+  let certificate_key = if with_key {
+    let key = secret_key(state)?;
+    Some(decrypt(&config.certificate_key, key)?)
+  } else {
+    None
+  };
+  Ok(ResourceCertificateInspect {
+    certificate: config.certificate,
+    certificate_key,
+    dh_param: config.dh_param,
+  })
+}
@@ -0,0 +1,261 @@
+use ntex::rt;
+use ntex::http::{Client, StatusCode};
+use ntex::http::client::{
+  Connector, ClientResponse,
+  error::{SendRequestError, JsonPayloadError},
+};
+use thiserror::Error;
+
+use nanocl_stubs::resource::{ResourcePartial, ResourceRuleQuery};
+use nanocl_stubs::dns::{DnsEntrySummary, DnsResolve};
+
+use crate::error::HttpError;
+
+/// Url of the controller in charge of the `ProxyRule` kind
+pub(crate) const PROXY_CTRL_URL: &str = "unix:///run/nanocl/proxy.sock";
+
+/// Url of the controller in charge of the `DnsRecord` kind
+pub(crate) const DNS_CTRL_URL: &str = "unix:///run/nanocl/dns.sock";
+
+/// Http client used to reach the controller of a resource kind.
+/// A controller must implement the following endpoints:
+///
+/// - `POST /rules/validate` to validate a [resource](ResourcePartial)
+/// - `PUT /rules` to apply a [resource](ResourcePartial) and return it, eventually mutated,
+///   the key the resource is stored with is given in the `Key` query parameter
+/// - `DELETE /rules/{name}` to remove a resource
+///
+/// If the kind ship a `Controller` migration it must also implement
+/// `POST /rules/migrate/{version}` to return the resource migrated to `version`.
+///
+/// Any error returned with a `msg` will be forwarded to the api consumer.
+pub struct CtrlClient {
+  pub(crate) name: String,
+  pub(crate) client: Client,
+  pub(crate) url: String,
+}
+
+#[derive(Debug, Error)]
+pub enum CtrlClientError {
+  #[error("Failed to send request: {0}")]
+  SendRequest(#[from] SendRequestError),
+  #[error("Failed to parse json: {0}")]
+  JsonPayload(#[from] JsonPayloadError),
+  #[error(transparent)]
+  HttpResponse(#[from] HttpError),
+}
+
+impl From<CtrlClientError> for HttpError {
+  fn from(err: CtrlClientError) -> Self {
+    match err {
+      CtrlClientError::HttpResponse(err) => err,
+      _ => HttpError {
+        status: StatusCode::INTERNAL_SERVER_ERROR,
+        msg: format!("{err}"),
+      },
+    }
+  }
+}
+
+/// Ensure the given url can be used to reach a controller
+pub(crate) fn validate_url(url: &str) -> Result<(), HttpError> {
+  if url.starts_with("unix://")
+    || url.starts_with("http://")
+    || url.starts_with("https://")
+  {
+    return Ok(());
+  }
+  Err(HttpError {
+    status: StatusCode::BAD_REQUEST,
+    msg: format!(
+      "Invalid controller url {url} expected unix://, http:// or https://"
+    ),
+  })
+}
+
+impl CtrlClient {
+  pub(crate) fn new(name: &str, url: &str) -> Result<Self, HttpError> {
+    validate_url(url)?;
+    let (client, url) = match url {
+      url if url.starts_with("unix://") => {
+        let path = url.trim_start_matches("unix://").to_owned();
+        let client = Client::build()
+          .connector(
+            Connector::default()
+              .connector(ntex::service::fn_service(move |_| {
+                let path = path.clone();
+                async move { Ok::<_, _>(rt::unix_connect(path).await?) }
+              }))
+              .timeout(ntex::time::Millis::from_secs(20))
+              .finish(),
+          )
+          .finish();
+
+        (client, "http://localhost".to_owned())
+      }
+      url => {
+        let client = Client::build().finish();
+        (client, url.trim_end_matches('/').to_owned())
+      }
+    };
+
+    Ok(Self {
+      name: name.to_owned(),
+      client,
+      url,
+    })
+  }
+
+  fn format_url(&self, path: &str) -> String {
+    format!("{}{}", self.url, path)
+  }
+
+  async fn is_api_error(
+    &self,
+    res: &mut ClientResponse,
+    status: &StatusCode,
+  ) -> Result<(), CtrlClientError> {
+    if status.is_server_error() || status.is_client_error() {
+      let body = res.json::<serde_json::Value>().await?;
+      let msg = body["msg"].as_str().ok_or(HttpError {
+        status: *status,
+        msg: format!("Controller {} returned {status}", self.name),
+      })?;
+      return Err(CtrlClientError::HttpResponse(HttpError {
+        status: *status,
+        msg: msg.to_owned(),
+      }));
+    }
+    Ok(())
+  }
+
+  async fn res_json<T>(res: &mut ClientResponse) -> Result<T, CtrlClientError>
+  where
+    T: serde::de::DeserializeOwned,
+  {
+    let body = res.json::<T>().await?;
+    Ok(body)
+  }
+
+  pub(crate) async fn validate_rule(
+    &self,
+    resource: &ResourcePartial,
+  ) -> Result<(), CtrlClientError> {
+    let mut res = self
+      .client
+      .post(self.format_url("/rules/validate"))
+      .send_json(resource)
+      .await?;
+    let status = res.status();
+    self.is_api_error(&mut res, &status).await?;
+
+    Ok(())
+  }
+
+  pub(crate) async fn apply_rule(
+    &self,
+    key: &str,
+    resource: &ResourcePartial,
+  ) -> Result<ResourcePartial, CtrlClientError> {
+    let query = ResourceRuleQuery {
+      key: Some(key.to_owned()),
+    };
+    let mut res = self
+      .client
+      .put(self.format_url("/rules"))
+      .query(&query)
+      .map_err(|err| HttpError {
+        status: StatusCode::INTERNAL_SERVER_ERROR,
+        msg: format!("Unable to encode the key {key}: {err}"),
+      })?
+      .send_json(resource)
+      .await?;
+    let status = res.status();
+    self.is_api_error(&mut res, &status).await?;
+
+    Self::res_json(&mut res).await
+  }
+
+  pub(crate) async fn migrate_rule(
+    &self,
+    resource: &ResourcePartial,
+    version: &str,
+  ) -> Result<ResourcePartial, CtrlClientError> {
+    let mut res = self
+      .client
+      .post(self.format_url(&format!("/rules/migrate/{version}")))
+      .send_json(resource)
+      .await?;
+    let status = res.status();
+    self.is_api_error(&mut res, &status).await?;
+
+    Self::res_json(&mut res).await
+  }
+
+  /// Remove the cached responses of a `ProxyRule`
+  pub(crate) async fn purge_cache(
+    &self,
+    name: &str,
+  ) -> Result<(), CtrlClientError> {
+    let mut res = self
+      .client
+      .post(self.format_url(&format!("/rules/{name}/cache/purge")))
+      .send()
+      .await?;
+    let status = res.status();
+    self.is_api_error(&mut res, &status).await?;
+
+    Ok(())
+  }
+
+  /// List the entries served by the dns controller
+  pub(crate) async fn list_dns_records(
+    &self,
+  ) -> Result<Vec<DnsEntrySummary>, CtrlClientError> {
+    let mut res = self.client.get(self.format_url("/records")).send().await?;
+    let status = res.status();
+    self.is_api_error(&mut res, &status).await?;
+
+    Self::res_json(&mut res).await
+  }
+
+  /// Explain how a name is answered by the dns controller
+  pub(crate) async fn resolve_dns(
+    &self,
+    name: &str,
+  ) -> Result<DnsResolve, CtrlClientError> {
+    let mut res = self
+      .client
+      .get(self.format_url(&format!("/resolve/{name}")))
+      .send()
+      .await?;
+    let status = res.status();
+    self.is_api_error(&mut res, &status).await?;
+
+    Self::res_json(&mut res).await
+  }
+
+  /// Force the dns controller to sync its entries with the daemon state
+  pub(crate) async fn sync_dns(&self) -> Result<(), CtrlClientError> {
+    let mut res = self.client.post(self.format_url("/sync")).send().await?;
+    let status = res.status();
+    self.is_api_error(&mut res, &status).await?;
+
+    Ok(())
+  }
+
+  pub(crate) async fn delete_rule(
+    &self,
+    name: &str,
+  ) -> Result<(), CtrlClientError> {
+    let mut res = self
+      .client
+      .delete(self.format_url(&format!("/rules/{name}")))
+      .send()
+      .await?;
+    let status = res.status();
+    self.is_api_error(&mut res, &status).await?;
+
+    Ok(())
+  }
+}
@@ -55,6 +55,32 @@ pub fn gen_key(nsp: &str, name: &str) -> String {
   name.to_owned() + "." + nsp
 }
 
+/// ## Generate resource key
+/// Return the key of a resource, resources are not bound to a namespace by default
+/// so the key is the name of the resource unless a namespace is given
+///
+/// ## Arguments
+///
+/// - [nsp](Option<String>) The namespace of the resource
+/// - [name](str) The name of the resource
+///
+/// ## Return
+///
+/// - [key](String) The generated key based on params
+///
+/// ## Example
+///
+/// ```rust,norun
+/// let key = gen_resource_key(&Some(String::from("global")), "web");
+/// ```
+///
+pub fn gen_resource_key(nsp: &Option<String>, name: &str) -> String {
+  match nsp {
+    None => name.to_owned(),
+    Some(nsp) => gen_key(nsp, name),
+  }
+}
+
 pub fn validate_name(name: &str) -> Result<(), HttpError> {
   // Ensure name only contain a-z, A-Z, 0-9, - and _
   if !name
@@ -68,3 +94,40 @@ pub fn validate_name(name: &str) -> Result<(), HttpError> {
   }
   Ok(())
 }
+
+/// Ensure a resource name only contain a-z, A-Z, 0-9, -, _ and .
+/// and doesn't start with a . so it can't escape a directory
+/// when a controller use it as a file name
+pub fn validate_resource_name(name: &str) -> Result<(), HttpError> {
+  if name.is_empty()
+    || name.starts_with('.')
+    || !name
+      .chars()
+      .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+  {
+    return Err(HttpError {
+      status: StatusCode::BAD_REQUEST,
+      msg: format!("Resource name {name} is invalid"),
+    });
+  }
+  Ok(())
+}
+
+/// Ensure a hostname is a dns label of a-z, 0-9 and - up to 63 characters
+/// so it can be used as the domain of a vm
+pub fn validate_hostname(hostname: &str) -> Result<(), HttpError> {
+  if hostname.is_empty()
+    || hostname.len() > 63
+    || hostname.starts_with('-')
+    || hostname.ends_with('-')
+    || !hostname
+      .chars()
+      .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+  {
+    return Err(HttpError {
+      status: StatusCode::BAD_REQUEST,
+      msg: format!("Hostname {hostname} is invalid"),
+    });
+  }
+  Ok(())
+}
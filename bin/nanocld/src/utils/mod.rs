@@ -5,8 +5,10 @@ pub mod network;
 
 pub mod store;
 pub mod state;
-pub mod proxy;
+pub mod ctrl_client;
 pub mod resource;
+pub mod secret;
+pub mod certificate;
 pub mod namespace;
 pub mod vm;
 pub mod vm_image;
@@ -26,7 +28,7 @@ pub mod tests {
 
   use nanocl_stubs::config::DaemonConfig;
 
-  use crate::models::DaemonState;
+  use crate::models::{DaemonState, Listener};
   use crate::services;
   use crate::event::EventEmitter;
   use crate::models::Pool;
@@ -83,6 +85,8 @@ pub mod tests {
     // Build a test daemon config
     let config = DaemonConfig {
       state_dir: String::from("/var/lib/nanocl"),
+      // Shared with the daemon the controllers are connected to
+      secret_key_file: Some(String::from("/var/lib/nanocl/secret.key")),
       ..Default::default()
     };
     let event_emitter = EventEmitter::new();
@@ -90,17 +94,23 @@ pub mod tests {
     let docker_api = gen_docker_client();
     // Create postgres pool
     let pool = gen_postgre_pool().await;
+    let secret_key = config
+      .secret_key_file
+      .as_deref()
+      .map(|path| secret::read_secret_key(path).unwrap());
     let daemon_state = DaemonState {
       config,
       docker_api,
       pool,
       event_emitter,
+      secret_key,
       version: VERSION.to_owned(),
     };
     // Create test server
     test::server(move || {
       App::new()
         .state(daemon_state.clone())
+        .state(Listener::Tcp)
         .configure(routes)
         .default_service(web::route().to(services::unhandled))
     })
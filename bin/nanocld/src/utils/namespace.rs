@@ -7,6 +7,7 @@ use bollard_next::container::ListContainersOptions;
 use bollard_next::network::{CreateNetworkOptions, InspectNetworkOptions};
 
 use nanocl_stubs::generic::GenericDelete;
+use nanocl_stubs::resource::{ResourceOwnerRef, ResourceOwnerKind};
 use nanocl_stubs::namespace::{
   Namespace, NamespaceSummary, NamespaceInspect, NamespacePartial,
   NamespaceListQuery,
@@ -78,7 +79,7 @@ pub async fn create(
 
 /// ## Remove a namespace
 ///
-/// Remove a namespace and his associated network with all his cargoes
+/// Remove a namespace and his associated network with all his cargoes and resources
 ///
 /// ## Arguments
 ///
@@ -105,11 +106,20 @@ pub async fn delete_by_name(
   name: &str,
   state: &DaemonState,
 ) -> Result<GenericDelete, HttpError> {
+  utils::resource::delete_by_namespace(name, state).await?;
   utils::cargo::delete_by_namespace(name, state).await?;
   if let Err(err) = state.docker_api.remove_network(name).await {
     log::error!("Unable to remove network {} got error: {}", name, err);
   }
-  repositories::namespace::delete_by_name(name, &state.pool).await
+  let res = repositories::namespace::delete_by_name(name, &state.pool).await?;
+  let owner = ResourceOwnerRef {
+    kind: ResourceOwnerKind::Namespace,
+    key: name.to_owned(),
+  };
+  if let Err(err) = utils::resource::delete_by_owner(&owner, state).await {
+    log::warn!("Unable to delete resources owned by {name}: {err}");
+  }
+  Ok(res)
 }
 
 /// ## List existing container in a namespace
@@ -1,26 +1,118 @@
+use ntex::rt;
 use ntex::http::StatusCode;
 use jsonschema::{JSONSchema, Draft};
 
-use nanocl_stubs::resource::{Resource, ResourcePartial};
-use nanocl_stubs::proxy::{ProxyRule, ResourceProxyRule};
+use nanocl_stubs::system::Event;
+use nanocl_stubs::resource::{
+  Resource, ResourcePartial, ResourceQuery, ResourceKindMigration,
+  ResourceOwnerRef, ResourceOwnerKind,
+};
 
 use crate::repositories;
 use crate::error::HttpError;
-use crate::models::{Pool, ResourceKindPartial};
+use crate::models::{
+  Pool, DaemonState, ResourceKindPartial, ResourceKindVersionDbModel,
+};
 
-use super::proxy::ProxyClient;
+use super::ctrl_client::{self, CtrlClient, PROXY_CTRL_URL, DNS_CTRL_URL};
+
+/// Parse the config of a `Custom` resource into a resource kind.
+/// The config is either the json schema of the kind or an object with
+/// an optional `Schema`, an optional controller `Url`
+/// and an optional [Migration](ResourceKindMigration) from the previous version.
+fn parse_resource_kind(
+  resource: &ResourcePartial,
+) -> Result<ResourceKindPartial, HttpError> {
+  let config = &resource.config;
+  let schema = config.get("Schema");
+  let url = config.get("Url");
+  let migration = config.get("Migration");
+  if schema.is_none() && url.is_none() && migration.is_none() {
+    return Ok(ResourceKindPartial {
+      name: resource.name.to_owned(),
+      version: resource.version.to_owned(),
+      schema: Some(config.clone()),
+      url: None,
+      migration: None,
+    });
+  }
+  let url = match url {
+    Some(url) => {
+      let url = url.as_str().ok_or(HttpError {
+        status: StatusCode::BAD_REQUEST,
+        msg: String::from("Invalid config Url must be a string"),
+      })?;
+      ctrl_client::validate_url(url)?;
+      Some(url.to_owned())
+    }
+    None => None,
+  };
+  if let Some(migration) = migration {
+    let migration = parse_migration(migration)?;
+    if let ResourceKindMigration::Controller = migration {
+      if url.is_none() {
+        return Err(HttpError {
+          status: StatusCode::BAD_REQUEST,
+          msg: String::from(
+            "Invalid config Controller migration require an Url",
+          ),
+        });
+      }
+    }
+  }
+  Ok(ResourceKindPartial {
+    name: resource.name.to_owned(),
+    version: resource.version.to_owned(),
+    schema: schema.cloned(),
+    url,
+    migration: migration.cloned(),
+  })
+}
+
+/// Parse a kind version migration and ensure json patch operations are valid
+fn parse_migration(
+  migration: &serde_json::Value,
+) -> Result<ResourceKindMigration, HttpError> {
+  let migration =
+    serde_json::from_value::<ResourceKindMigration>(migration.clone())
+      .map_err(|err| HttpError {
+        status: StatusCode::BAD_REQUEST,
+        msg: format!("Invalid migration {err}"),
+      })?;
+  if let ResourceKindMigration::Patch(operations) = &migration {
+    parse_patch(operations)?;
+  }
+  Ok(migration)
+}
+
+fn parse_patch(
+  operations: &[serde_json::Value],
+) -> Result<json_patch::Patch, HttpError> {
+  serde_json::from_value::<json_patch::Patch>(serde_json::Value::Array(
+    operations.to_vec(),
+  ))
+  .map_err(|err| HttpError {
+    status: StatusCode::BAD_REQUEST,
+    msg: format!("Invalid migration patch {err}"),
+  })
+}
 
 pub async fn validate_resource(
   resource: &ResourcePartial,
   pool: &Pool,
 ) -> Result<(), HttpError> {
+  if let Some(namespace) = &resource.namespace {
+    if resource.kind == "Custom" {
+      return Err(HttpError {
+        status: StatusCode::BAD_REQUEST,
+        msg: String::from("Custom resources cannot be namespaced"),
+      });
+    }
+    repositories::namespace::find_by_name(namespace, pool).await?;
+  }
   match resource.kind.as_str() {
     "Custom" => {
-      let resource_kind = ResourceKindPartial {
-        name: resource.name.to_owned(),
-        version: resource.version.to_owned(),
-        schema: resource.config.clone(),
-      };
+      let resource_kind = parse_resource_kind(resource)?;
 
       if repositories::resource_kind::find_by_name(&resource.name, pool)
         .await
@@ -30,6 +122,10 @@ pub async fn validate_resource(
       }
       repositories::resource_kind::create_version(&resource_kind, pool).await?;
     }
+    // ProxyRule and DnsRecord are validated by their controller
+    "ProxyRule" | "DnsRecord" => {}
+    // Certificate and Secret are validated before being encrypted
+    "Certificate" | "Secret" => {}
     _ => {
       let kind = repositories::resource_kind::get_version(
         &resource.kind,
@@ -37,9 +133,12 @@ pub async fn validate_resource(
         pool,
       )
       .await?;
+      let Some(schema) = &kind.schema else {
+        return Ok(());
+      };
       let schema: JSONSchema = JSONSchema::options()
         .with_draft(Draft::Draft7)
-        .compile(&kind.schema)
+        .compile(schema)
         .map_err(|err| HttpError {
           status: StatusCode::BAD_REQUEST,
           msg: format!("Invalid schema {}", err),
@@ -59,85 +158,375 @@ pub async fn validate_resource(
   Ok(())
 }
 
-async fn hook_apply_proxy_rule(
-  resource: &ResourcePartial,
+/// Get the controller in charge of a resource kind if it has one.
+/// `ProxyRule` is handled by ncdproxy and `DnsRecord` by ncddns,
+/// other kinds can declare a controller with the `Url` of their version.
+async fn get_ctrl_client(
+  kind: &str,
+  version: &str,
   pool: &Pool,
-) -> Result<ResourcePartial, HttpError> {
-  if resource.kind != "ProxyRule" {
-    validate_resource(resource, pool).await?;
-    return Ok(resource.to_owned());
+) -> Result<Option<CtrlClient>, HttpError> {
+  match kind {
+    "Custom" | "Certificate" | "Secret" => Ok(None),
+    "ProxyRule" => Ok(Some(CtrlClient::new(kind, PROXY_CTRL_URL)?)),
+    "DnsRecord" => Ok(Some(CtrlClient::new(kind, DNS_CTRL_URL)?)),
+    _ => {
+      let kind_version =
+        repositories::resource_kind::get_version(kind, version, pool).await?;
+      match kind_version.url {
+        Some(url) => Ok(Some(CtrlClient::new(kind, &url)?)),
+        None => Ok(None),
+      }
+    }
   }
-  let proxy = ProxyClient::unix_default();
-  let hooked_resource =
-    proxy.apply_rule(resource).await.map_err(|err| HttpError {
-      status: StatusCode::BAD_REQUEST,
-      msg: format!("{}", err),
-    })?;
-  Ok(hooked_resource)
 }
 
-async fn hook_remove_proxy_rule(resource: &Resource) -> Result<(), HttpError> {
-  if resource.kind != "ProxyRule" {
-    return Ok(());
+/// Validate a resource and call the controller of his kind if any.
+/// The controller can reject the resource or return a mutated version of it
+/// that will be saved instead.
+async fn hook_apply_resource(
+  resource: &ResourcePartial,
+  state: &DaemonState,
+) -> Result<ResourcePartial, HttpError> {
+  let pool = &state.pool;
+  validate_resource(resource, pool).await?;
+  match resource.kind.as_str() {
+    "Certificate" => return super::certificate::hook_apply(resource, state),
+    "Secret" => return super::secret::hook_apply(resource, state),
+    _ => {}
   }
+  let Some(ctrl_client) =
+    get_ctrl_client(&resource.kind, &resource.version, pool).await?
+  else {
+    return Ok(resource.to_owned());
+  };
+  ctrl_client.validate_rule(resource).await?;
+  let key = super::key::gen_resource_key(&resource.namespace, &resource.name);
+  let hooked_resource = ctrl_client.apply_rule(&key, resource).await?;
+  Ok(ResourcePartial {
+    name: resource.name.to_owned(),
+    namespace: resource.namespace.to_owned(),
+    kind: resource.kind.to_owned(),
+    owner_refs: hooked_resource
+      .owner_refs
+      .or_else(|| resource.owner_refs.clone()),
+    labels: hooked_resource.labels.or_else(|| resource.labels.clone()),
+    ..hooked_resource
+  })
+}
 
-  let proxy_rule =
-    serde_json::from_value::<ResourceProxyRule>(resource.config.clone())
-      .map_err(|err| HttpError {
-        status: StatusCode::BAD_REQUEST,
-        msg: format!("{}", err),
-      })?;
-
-  let kind = match proxy_rule.rule {
-    ProxyRule::Http(_) => "site",
-    ProxyRule::Stream(_) => "stream",
+/// Notify the controller of a resource kind that the resource is removed
+async fn hook_delete_resource(
+  resource: &Resource,
+  pool: &Pool,
+) -> Result<(), HttpError> {
+  let Some(ctrl_client) =
+    get_ctrl_client(&resource.kind, &resource.version, pool).await?
+  else {
+    return Ok(());
   };
+  ctrl_client.delete_rule(&resource.key).await?;
+  Ok(())
+}
 
-  let proxy = ProxyClient::unix_default();
-  proxy
-    .delete_rule(&resource.name, kind)
-    .await
-    .map_err(|err| HttpError {
+/// Remove the cached responses of a `ProxyRule` resource
+pub async fn purge_cache(resource: &Resource) -> Result<(), HttpError> {
+  if resource.kind != "ProxyRule" {
+    return Err(HttpError {
       status: StatusCode::BAD_REQUEST,
-      msg: format!("{}", err),
-    })?;
+      msg: format!("Resource {} is not a ProxyRule", resource.name),
+    });
+  }
+  let ctrl_client = CtrlClient::new(&resource.kind, PROXY_CTRL_URL)?;
+  ctrl_client.purge_cache(&resource.key).await?;
   Ok(())
 }
 
-pub async fn create(
+/// Ensure the key of a resource isn't used by another resource.
+/// The key of a namespaced resource `name.namespace` can be the name
+/// of a resource without namespace.
+async fn validate_key(
   resource: &ResourcePartial,
   pool: &Pool,
+) -> Result<(), HttpError> {
+  let key = super::key::gen_resource_key(&resource.namespace, &resource.name);
+  let Ok(existing) = repositories::resource::inspect_by_key(&key, pool).await
+  else {
+    return Ok(());
+  };
+  if existing.name != resource.name
+    || existing.namespace_name != resource.namespace
+  {
+    return Err(HttpError {
+      status: StatusCode::CONFLICT,
+      msg: format!(
+        "Resource {} can't be saved its key {key} is used by another resource",
+        resource.name
+      ),
+    });
+  }
+  Ok(())
+}
+
+pub async fn create(
+  resource: &ResourcePartial,
+  state: &DaemonState,
 ) -> Result<Resource, HttpError> {
-  hook_apply_proxy_rule(resource, pool).await?;
-  repositories::resource::create(resource, pool).await
+  super::key::validate_resource_name(&resource.name)?;
+  validate_key(resource, &state.pool).await?;
+  let resource = hook_apply_resource(resource, state).await?;
+  repositories::resource::create(&resource, &state.pool).await
 }
 
 pub async fn patch(
   resource: ResourcePartial,
-  pool: &Pool,
+  state: &DaemonState,
 ) -> Result<Resource, HttpError> {
-  hook_apply_proxy_rule(&resource, pool).await?;
-  repositories::resource::patch(&resource, pool).await
+  validate_key(&resource, &state.pool).await?;
+  let resource = hook_apply_resource(&resource, state).await?;
+  repositories::resource::patch(&resource, &state.pool).await
 }
 
 pub async fn create_or_patch(
   resource: ResourcePartial,
-  pool: &Pool,
+  state: &DaemonState,
 ) -> Result<Resource, HttpError> {
-  hook_apply_proxy_rule(&resource, pool).await?;
-  repositories::resource::create_or_patch(&resource, pool).await
+  super::key::validate_resource_name(&resource.name)?;
+  validate_key(&resource, &state.pool).await?;
+  let resource = hook_apply_resource(&resource, state).await?;
+  repositories::resource::create_or_patch(&resource, &state.pool).await
 }
 
 pub async fn delete(resource: Resource, pool: &Pool) -> Result<(), HttpError> {
-  if let Err(err) = hook_remove_proxy_rule(&resource).await {
+  if let Err(err) = hook_delete_resource(&resource, pool).await {
     log::warn!("{err}");
   }
   if resource.kind.as_str() == "Custom" {
     repositories::resource_kind::delete_version(&resource.name, pool).await?;
     repositories::resource_kind::delete(&resource.name, pool).await?;
   }
-  repositories::resource::delete_by_key(&resource.name, pool).await?;
-  repositories::resource_config::delete_by_resource_key(&resource.name, pool)
+  repositories::resource::delete_by_key(&resource.key, pool).await?;
+  repositories::resource_config::delete_by_resource_key(&resource.key, pool)
     .await?;
   Ok(())
 }
+
+/// Delete a resource and notify his deletion
+async fn delete_and_notify(
+  resource: Resource,
+  state: &DaemonState,
+) -> Result<(), HttpError> {
+  delete(resource.clone(), &state.pool).await?;
+  let state = state.clone();
+  rt::spawn(async move {
+    let _ = state
+      .event_emitter
+      .emit(Event::ResourceDeleted(Box::new(resource)))
+      .await;
+  });
+  Ok(())
+}
+
+/// Delete every resource of a namespace and notify their deletion
+pub async fn delete_by_namespace(
+  namespace: &str,
+  state: &DaemonState,
+) -> Result<(), HttpError> {
+  let query = ResourceQuery {
+    namespace: Some(namespace.to_owned()),
+    ..Default::default()
+  };
+  let resources =
+    repositories::resource::find(&state.pool, Some(query)).await?;
+  for resource in resources {
+    delete_and_notify(resource, state).await?;
+  }
+  Ok(())
+}
+
+/// Check if the owner of a resource still exists
+async fn owner_exists(
+  owner: &ResourceOwnerRef,
+  pool: &Pool,
+) -> Result<bool, HttpError> {
+  match owner.kind {
+    ResourceOwnerKind::Cargo => Ok(
+      repositories::cargo::find_by_key(&owner.key, pool)
+        .await
+        .is_ok(),
+    ),
+    ResourceOwnerKind::Vm => Ok(
+      repositories::vm::find_by_key(&owner.key, pool)
+        .await
+        .is_ok(),
+    ),
+    ResourceOwnerKind::Namespace => {
+      repositories::namespace::exist_by_name(&owner.key, pool).await
+    }
+  }
+}
+
+/// A resource is orphaned when it has owners and all of them are gone
+pub async fn is_orphaned(
+  resource: &Resource,
+  pool: &Pool,
+) -> Result<bool, HttpError> {
+  if resource.owner_refs.is_empty() {
+    return Ok(false);
+  }
+  for owner in &resource.owner_refs {
+    if owner_exists(owner, pool).await? {
+      return Ok(false);
+    }
+  }
+  Ok(true)
+}
+
+/// Garbage collect the resources of an owner that has been deleted.
+/// A resource is only deleted when none of his other owners remain.
+pub async fn delete_by_owner(
+  owner: &ResourceOwnerRef,
+  state: &DaemonState,
+) -> Result<(), HttpError> {
+  let resources =
+    repositories::resource::find_by_owner(owner, &state.pool).await?;
+  for resource in resources {
+    if !is_orphaned(&resource, &state.pool).await? {
+      continue;
+    }
+    log::info!(
+      "Deleting resource {} owned by {:?} {}",
+      resource.key,
+      owner.kind,
+      owner.key
+    );
+    delete_and_notify(resource, state).await?;
+  }
+  Ok(())
+}
+
+/// Delete every orphaned resource and return them
+pub async fn prune(state: &DaemonState) -> Result<Vec<Resource>, HttpError> {
+  let query = ResourceQuery {
+    orphaned: Some(true),
+    ..Default::default()
+  };
+  let resources =
+    repositories::resource::find(&state.pool, Some(query)).await?;
+  for resource in resources.clone() {
+    delete_and_notify(resource, state).await?;
+  }
+  Ok(resources)
+}
+
+/// Apply the migration of a kind version to a resource
+async fn migrate_resource(
+  resource: ResourcePartial,
+  kind_version: &ResourceKindVersionDbModel,
+) -> Result<ResourcePartial, HttpError> {
+  let version = kind_version.version.to_owned();
+  let Some(migration) = &kind_version.migration else {
+    return Ok(ResourcePartial {
+      version,
+      ..resource
+    });
+  };
+  match parse_migration(migration)? {
+    ResourceKindMigration::Patch(operations) => {
+      let patch = parse_patch(&operations)?;
+      let mut config = resource.config.clone();
+      json_patch::patch(&mut config, &patch).map_err(|err| HttpError {
+        status: StatusCode::BAD_REQUEST,
+        msg: format!(
+          "Unable to migrate resource {} to {version}: {err}",
+          resource.name
+        ),
+      })?;
+      Ok(ResourcePartial {
+        version,
+        config,
+        ..resource
+      })
+    }
+    ResourceKindMigration::Controller => {
+      let url = kind_version.url.clone().ok_or(HttpError {
+        status: StatusCode::INTERNAL_SERVER_ERROR,
+        msg: format!(
+          "Kind {} {version} has a Controller migration without Url",
+          resource.kind
+        ),
+      })?;
+      let ctrl_client = CtrlClient::new(&resource.kind, &url)?;
+      let migrated = ctrl_client.migrate_rule(&resource, &version).await?;
+      Ok(ResourcePartial {
+        version,
+        config: migrated.config,
+        ..resource
+      })
+    }
+  }
+}
+
+/// Migrate every resource of a kind to the given version.
+/// The migrations of each version between the version of a resource
+/// and the target version are applied in their creation order.
+/// Every migrated resource is validated before any is written,
+/// then they are patched in a single transaction to record a new history.
+pub async fn migrate(
+  kind: &str,
+  version: &str,
+  pool: &Pool,
+) -> Result<Vec<Resource>, HttpError> {
+  let versions = repositories::resource_kind::list_versions(kind, pool).await?;
+  let target = versions
+    .iter()
+    .position(|kind_version| kind_version.version == version)
+    .ok_or(HttpError {
+      status: StatusCode::NOT_FOUND,
+      msg: format!("Kind {kind} has no version {version}"),
+    })?;
+  let query = ResourceQuery {
+    kind: Some(kind.to_owned()),
+    ..Default::default()
+  };
+  let resources = repositories::resource::find(pool, Some(query)).await?;
+  let mut items = Vec::new();
+  for resource in resources {
+    if resource.version == version {
+      continue;
+    }
+    let current = versions
+      .iter()
+      .position(|kind_version| kind_version.version == resource.version)
+      .ok_or(HttpError {
+        status: StatusCode::BAD_REQUEST,
+        msg: format!(
+          "Resource {} use an unknown version {} of {kind}",
+          resource.name, resource.version
+        ),
+      })?;
+    if current > target {
+      return Err(HttpError {
+        status: StatusCode::BAD_REQUEST,
+        msg: format!(
+          "Resource {} version {} is newer than {version}",
+          resource.name, resource.version
+        ),
+      });
+    }
+    let mut item: ResourcePartial = resource.into();
+    for kind_version in &versions[current + 1..=target] {
+      item = migrate_resource(item, kind_version).await?;
+    }
+    items.push(item);
+  }
+  for item in &items {
+    validate_resource(item, pool).await?;
+    if let Some(ctrl_client) =
+      get_ctrl_client(&item.kind, &item.version, pool).await?
+    {
+      ctrl_client.validate_rule(item).await?;
+    }
+  }
+  repositories::resource::patch_many(&items, pool).await
+}
@@ -0,0 +1,141 @@
+use std::fs;
+
+use ntex::http::StatusCode;
+use openssl::base64;
+use openssl::rand::rand_bytes;
+use openssl::symm::{Cipher, encrypt_aead, decrypt_aead};
+
+use nanocl_stubs::resource::{Resource, ResourcePartial, ResourceSecret};
+
+use crate::error::{CliError, HttpError};
+use crate::models::DaemonState;
+
+/// Prefix of an encrypted value
+pub(crate) const ENCRYPTED_PREFIX: &str = "nanocl:aes-256-gcm:";
+const IV_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+
+pub(crate) fn crypto_error(err: impl std::fmt::Display) -> HttpError {
+  HttpError {
+    status: StatusCode::INTERNAL_SERVER_ERROR,
+    msg: format!("Unable to handle encrypted value: {err}"),
+  }
+}
+
+/// Length of the aes-256-gcm key
+const KEY_LEN: usize = 32;
+
+/// Read the secret key of the daemon used to encrypt sensitive values
+/// The key is configured with `--secret-key-file` and must be the same
+/// on every node so each of them can decrypt the stored values.
+/// It's read once at boot so a missing or invalid key stop the daemon.
+pub(crate) fn read_secret_key(path: &str) -> Result<Vec<u8>, CliError> {
+  let key = fs::read(path).map_err(|err| {
+    CliError::new(1, format!("Unable to read secret key {path}: {err}"))
+  })?;
+  if key.len() != KEY_LEN {
+    return Err(CliError::new(
+      1,
+      format!(
+        "Secret key {path} must contain {KEY_LEN} bytes got {}",
+        key.len()
+      ),
+    ));
+  }
+  Ok(key)
+}
+
+/// Get the secret key of the daemon read at boot
+pub(crate) fn secret_key(state: &DaemonState) -> Result<&[u8], HttpError> {
+  state.secret_key.as_deref().ok_or_else(|| HttpError {
+    status: StatusCode::INTERNAL_SERVER_ERROR,
+    msg: "No secret key configured, start the daemon with --secret-key-file pointing to a 32 bytes key shared by every node".into(),
+  })
+}
+
+pub(crate) fn encrypt(data: &str, key: &[u8]) -> Result<String, HttpError> {
+  let mut iv = [0; IV_LEN];
+  rand_bytes(&mut iv).map_err(crypto_error)?;
+  let mut tag = [0; TAG_LEN];
+  let encrypted = encrypt_aead(
+    Cipher::aes_256_gcm(),
+    key,
+    Some(&iv),
+    &[],
+    data.as_bytes(),
+    &mut tag,
+  )
+  .map_err(crypto_error)?;
+  let payload = [&iv[..], &tag[..], &encrypted[..]].concat();
+  Ok(format!(
+    "{ENCRYPTED_PREFIX}{}",
+    base64::encode_block(&payload)
+  ))
+}
+
+pub(crate) fn decrypt(data: &str, key: &[u8]) -> Result<String, HttpError> {
+  let payload = data
+    .strip_prefix(ENCRYPTED_PREFIX)
+    .ok_or(crypto_error("value is not encrypted"))?;
+  let payload = base64::decode_block(payload).map_err(crypto_error)?;
+  if payload.len() < IV_LEN + TAG_LEN {
+    return Err(crypto_error("encrypted value is too short"));
+  }
+  let (iv, payload) = payload.split_at(IV_LEN);
+  let (tag, encrypted) = payload.split_at(TAG_LEN);
+  let decrypted =
+    decrypt_aead(Cipher::aes_256_gcm(), key, Some(iv), &[], encrypted, tag)
+      .map_err(crypto_error)?;
+  String::from_utf8(decrypted).map_err(crypto_error)
+}
+
+fn parse_config(
+  config: &serde_json::Value,
+) -> Result<ResourceSecret, HttpError> {
+  serde_json::from_value::<ResourceSecret>(config.clone()).map_err(|err| {
+    HttpError {
+      status: StatusCode::BAD_REQUEST,
+      msg: format!("Invalid secret {err}"),
+    }
+  })
+}
+
+/// Encrypt the values of a `Secret` resource before it's stored.
+/// Values already encrypted by the daemon are kept as is.
+pub fn hook_apply(
+  resource: &ResourcePartial,
+  state: &DaemonState,
+) -> Result<ResourcePartial, HttpError> {
+  let mut config = parse_config(&resource.config)?;
+  let key = secret_key(state)?;
+  for value in config.data.values_mut() {
+    if value.starts_with(ENCRYPTED_PREFIX) {
+      decrypt(value, key)?;
+    } else {
+      *value = encrypt(value, key)?;
+    }
+  }
+  Ok(ResourcePartial {
+    config: serde_json::to_value(config).map_err(crypto_error)?,
+    ..resource.clone()
+  })
+}
+
+/// Get the data of a `Secret` resource with its values decrypted
+pub fn decrypt_resource(
+  resource: &Resource,
+  state: &DaemonState,
+) -> Result<ResourceSecret, HttpError> {
+  if resource.kind != "Secret" {
+    return Err(HttpError {
+      status: StatusCode::BAD_REQUEST,
+      msg: format!("Resource {} is not a Secret", resource.name),
+    });
+  }
+  let mut config = parse_config(&resource.config)?;
+  let key = secret_key(state)?;
+  for value in config.data.values_mut() {
+    *value = decrypt(value, key)?;
+  }
+  Ok(config)
+}
@@ -186,9 +186,15 @@ pub async fn apply_deployment(
 
     if let Some(resources) = &data.resources {
       for resource in resources {
-        let key = resource.name.to_owned();
+        let mut resource = resource.clone();
+        // Resources follow the deployment namespace, kinds are not namespaced
+        if resource.namespace.is_none() && resource.kind != "Custom" {
+          resource.namespace = data.namespace.clone();
+        }
+        let key =
+          utils::key::gen_resource_key(&resource.namespace, &resource.name);
         let res =
-          utils::resource::create_or_patch(resource.clone(), &state.pool).await;
+          utils::resource::create_or_patch(resource.clone(), &state).await;
 
         if let Err(err) = res {
           if sx
@@ -375,9 +381,10 @@ pub async fn apply_resource(
     };
 
     for resource in &data.resources {
-      let key = resource.name.to_owned();
+      let key =
+        utils::key::gen_resource_key(&resource.namespace, &resource.name);
       let res =
-        utils::resource::create_or_patch(resource.clone(), &state.pool).await;
+        utils::resource::create_or_patch(resource.clone(), &state).await;
 
       if let Err(err) = res {
         if sx
@@ -506,7 +513,12 @@ pub async fn revert_deployment(
       };
 
       for resource in resources {
-        let key = resource.name.to_owned();
+        let mut resource = resource.clone();
+        if resource.namespace.is_none() && resource.kind != "Custom" {
+          resource.namespace = data.namespace.clone();
+        }
+        let key =
+          utils::key::gen_resource_key(&resource.namespace, &resource.name);
         let resource =
           match repositories::resource::inspect_by_key(&key, &state.pool).await
           {
@@ -641,7 +653,8 @@ pub async fn revert_resource(
     };
 
     for resource in &data.resources {
-      let key = resource.name.to_owned();
+      let key =
+        utils::key::gen_resource_key(&resource.namespace, &resource.name);
       let resource =
         match repositories::resource::inspect_by_key(&key, &state.pool).await {
           Ok(resource) => resource,
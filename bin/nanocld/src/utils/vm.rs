@@ -260,6 +260,9 @@ pub async fn create(
   state: &DaemonState,
 ) -> Result<Vm, HttpError> {
   let vm_key = utils::key::gen_key(namespace, &vm.name);
+  if let Some(hostname) = &vm.hostname {
+    utils::key::validate_hostname(hostname)?;
+  }
 
   let mut vm = vm.clone();
   if repositories::vm::find_by_key(&vm_key, &state.pool)
@@ -307,6 +310,9 @@ pub async fn patch(
   version: &str,
   state: &DaemonState,
 ) -> Result<Vm, HttpError> {
+  if let Some(hostname) = &config.hostname {
+    utils::key::validate_hostname(hostname)?;
+  }
   let vm = repositories::vm::find_by_key(cargo_key, &state.pool).await?;
 
   let old_config =
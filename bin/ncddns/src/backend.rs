@@ -0,0 +1,235 @@
+use std::sync::Arc;
+use std::collections::HashMap;
+
+use nanocld_client::stubs::dns::{DnsEntrySummary, DnsResolve, DnsResolveAnswer};
+
+use crate::error::ErrorHint;
+
+/// Maximum number of cname followed to resolve a name
+pub(crate) const MAX_CNAME_DEPTH: usize = 8;
+
+/// A dns entry served by a backend
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum DnsEntry {
+  /// Resolve a domain and all its subdomains to an ip address
+  Address { domain: String, ip_address: String },
+  /// Resolve a name to an ip address, a name can own multiple host entries
+  Host { name: String, ip_address: String },
+  /// Alias a name to another one
+  Cname { name: String, target: String },
+  /// Text values of a name
+  Txt { name: String, values: Vec<String> },
+  /// Service location of a name
+  Srv {
+    name: String,
+    target: String,
+    port: u16,
+    priority: u16,
+    weight: u16,
+  },
+}
+
+impl DnsEntry {
+  /// The name the entry is answering for
+  pub(crate) fn name(&self) -> &str {
+    match self {
+      DnsEntry::Address { domain, .. } => domain,
+      DnsEntry::Host { name, .. }
+      | DnsEntry::Cname { name, .. }
+      | DnsEntry::Txt { name, .. }
+      | DnsEntry::Srv { name, .. } => name,
+    }
+  }
+
+  /// Summarize the entry for the inspection api
+  pub(crate) fn to_summary(&self, source: &str) -> DnsEntrySummary {
+    let (kind, value) = match self {
+      DnsEntry::Address { ip_address, .. } => {
+        ("Address", ip_address.to_owned())
+      }
+      DnsEntry::Host { ip_address, .. } => ("Host", ip_address.to_owned()),
+      DnsEntry::Cname { target, .. } => ("Cname", target.to_owned()),
+      DnsEntry::Txt { values, .. } => ("Txt", values.join(" ")),
+      DnsEntry::Srv {
+        target,
+        port,
+        priority,
+        weight,
+        ..
+      } => (
+        "Srv",
+        format!("{target}:{port} priority={priority} weight={weight}"),
+      ),
+    };
+    DnsEntrySummary {
+      source: source.to_owned(),
+      kind: kind.to_owned(),
+      name: self.name().to_owned(),
+      value,
+    }
+  }
+}
+
+/// Check if a name is the domain or one of its subdomains
+pub(crate) fn is_subdomain(name: &str, domain: &str) -> bool {
+  let domain = domain.to_lowercase();
+  name == domain || name.ends_with(&format!(".{domain}"))
+}
+
+/// Find the entries answering for a lowercase name with the name of their group
+/// An `Address` answer for its domain and all its subdomains
+pub(crate) fn find_entries<'a>(
+  entries: &'a HashMap<String, Vec<DnsEntry>>,
+  name: &str,
+) -> Vec<(&'a str, &'a DnsEntry)> {
+  entries
+    .iter()
+    .flat_map(|(source, entries)| {
+      entries.iter().map(move |entry| (source.as_str(), entry))
+    })
+    .filter(|(_, entry)| match entry {
+      DnsEntry::Address { domain, .. } => is_subdomain(name, domain),
+      _ => entry.name().eq_ignore_ascii_case(name),
+    })
+    .collect()
+}
+
+/// A backend serving the dns entries generated by the controller
+/// The entries are grouped by a name like the key of a cargo
+/// and each group is replaced as a whole
+pub(crate) trait DnsBackend: Send + Sync {
+  /// The zone of the cargo domains
+  fn zone(&self) -> &str;
+
+  /// Prepare the backend before any entry is written
+  fn ensure(&self) -> Result<(), ErrorHint>;
+
+  /// Replace the entries of a group
+  fn write_entries(
+    &self,
+    name: &str,
+    entries: &[DnsEntry],
+  ) -> Result<(), ErrorHint>;
+
+  /// Remove the entries of a group, a missing group is ignored
+  fn remove_entries(&self, name: &str) -> Result<(), ErrorHint>;
+
+  /// List the names of the groups
+  fn list_entries(&self) -> Result<Vec<String>, ErrorHint>;
+
+  /// Get the entries of every group
+  fn entries(&self) -> Result<HashMap<String, Vec<DnsEntry>>, ErrorHint>;
+
+  /// Check if the dns cargo must be restarted to apply the changes
+  fn need_restart(&self) -> bool;
+
+  /// Remove the groups not in the given list
+  fn remove_stale_entries(
+    &self,
+    names: &[String],
+  ) -> Result<Vec<String>, ErrorHint> {
+    let stale = self
+      .list_entries()?
+      .into_iter()
+      .filter(|name| !names.contains(name))
+      .collect::<Vec<_>>();
+    for name in &stale {
+      self.remove_entries(name)?;
+    }
+    Ok(stale)
+  }
+}
+
+/// Summarize the entries of a backend sorted by group and name
+pub(crate) fn list_summaries(
+  backend: &dyn DnsBackend,
+) -> Result<Vec<DnsEntrySummary>, ErrorHint> {
+  let mut summaries = backend
+    .entries()?
+    .iter()
+    .flat_map(|(source, entries)| {
+      entries.iter().map(|entry| entry.to_summary(source))
+    })
+    .collect::<Vec<_>>();
+  summaries.sort_by(|a, b| (&a.source, &a.name).cmp(&(&b.source, &b.name)));
+  Ok(summaries)
+}
+
+/// Explain how a name is answered by a backend
+/// The targets of the cnames are followed like a query would
+pub(crate) fn resolve(
+  backend: &dyn DnsBackend,
+  name: &str,
+) -> Result<DnsResolve, ErrorHint> {
+  let entries = backend.entries()?;
+  let name = name.trim_end_matches('.').to_lowercase();
+  let mut summaries = Vec::new();
+  let mut current = Some(name.to_owned());
+  let mut depth = 0;
+  while let Some(lookup) = current.take() {
+    for (source, entry) in find_entries(&entries, &lookup) {
+      if let DnsEntry::Cname { target, .. } = entry {
+        if depth < MAX_CNAME_DEPTH {
+          current = Some(target.to_lowercase());
+        }
+      }
+      summaries.push(entry.to_summary(source));
+    }
+    depth += 1;
+  }
+  let answer = match summaries.is_empty() {
+    false => DnsResolveAnswer::Local,
+    true if is_subdomain(&name, backend.zone()) => DnsResolveAnswer::NotFound,
+    true => DnsResolveAnswer::Forwarded,
+  };
+  Ok(DnsResolve {
+    name,
+    zone: backend.zone().to_owned(),
+    answer,
+    entries: summaries,
+  })
+}
+
+/// Backend shared between the event loop and the http server
+pub(crate) type Backend = Arc<dyn DnsBackend>;
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn resolve_entries() {
+    let dns = crate::native::new("127.0.0.1:0", None, &[]);
+    let entries = [
+      DnsEntry::Host {
+        name: "app.global.nanocl.internal".to_owned(),
+        ip_address: "10.0.0.2".to_owned(),
+      },
+      DnsEntry::Cname {
+        name: "www.example.com".to_owned(),
+        target: "app.global.nanocl.internal".to_owned(),
+      },
+    ];
+    dns.write_entries("app.global", &entries[..1]).unwrap();
+    dns.write_entries("www.record", &entries[1..]).unwrap();
+    let www = resolve(&dns, "WWW.example.com.").unwrap();
+    assert_eq!(www.answer, DnsResolveAnswer::Local);
+    assert_eq!(
+      www
+        .entries
+        .iter()
+        .map(|entry| (entry.source.as_str(), entry.kind.as_str()))
+        .collect::<Vec<_>>(),
+      vec![("www.record", "Cname"), ("app.global", "Host")]
+    );
+    assert_eq!(
+      resolve(&dns, "db.global.nanocl.internal").unwrap().answer,
+      DnsResolveAnswer::NotFound
+    );
+    assert_eq!(
+      resolve(&dns, "nanocl.io").unwrap().answer,
+      DnsResolveAnswer::Forwarded
+    );
+    assert_eq!(list_summaries(&dns).unwrap().len(), 2);
+  }
+}
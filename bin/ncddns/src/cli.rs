@@ -1,4 +1,13 @@
-use clap::Parser;
+use clap::{Parser, ValueEnum};
+
+/// Backend serving the dns entries
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub(crate) enum BackendKind {
+  /// Write the entries as dnsmasq config and restart the dns cargo
+  Dnsmasq,
+  /// Serve the entries from memory with the embedded dns server
+  Native,
+}
 
 /// Nanocl controller dns
 #[derive(Debug, Parser)]
@@ -9,6 +18,17 @@ pub(crate) struct Cli {
   /// Dns server address to resolve domain name if not existing in local
   #[clap(long)]
   pub(crate) dns: Vec<String>,
+  /// Zone of the cargo domains like `<cargo>.<namespace>.<zone>`
+  /// default to `nanocl.internal`
+  #[clap(long)]
+  pub(crate) zone: Option<String>,
+  /// Backend serving the dns entries
+  #[clap(long, value_enum, default_value_t = BackendKind::Dnsmasq)]
+  pub(crate) backend: BackendKind,
+  /// Address where the native dns server is listening
+  /// The names outside of the zone are only resolved for the local clients
+  #[clap(long, default_value = "0.0.0.0:53")]
+  pub(crate) listen: String,
 }
 
 /// Parse the cli arguments
@@ -1,6 +1,8 @@
 use std::fs;
+use std::collections::HashMap;
 
 use crate::error::ErrorHint;
+use crate::backend::{DnsBackend, DnsEntry};
 
 /// Dnsmasq configuration manager
 #[derive(Clone)]
@@ -8,6 +10,7 @@ pub(crate) struct Dnsmasq {
   pub(crate) config_dir: String,
   pub(crate) config_path: String,
   pub(crate) dns: Vec<String>,
+  pub(crate) zone: String,
 }
 
 impl Dnsmasq {
@@ -17,6 +20,7 @@ impl Dnsmasq {
       config_dir: config_path.to_owned(),
       config_path: format!("{}/dnsmasq.conf", &config_path.to_owned()),
       dns: Vec::new(),
+      zone: crate::zone::DEFAULT_ZONE.to_owned(),
     }
   }
 
@@ -27,6 +31,20 @@ impl Dnsmasq {
       config_dir: self.config_dir.to_owned(),
       config_path: self.config_path.to_owned(),
       dns: self.dns.to_owned(),
+      zone: self.zone.to_owned(),
+    }
+  }
+
+  /// Set the zone of the cargo domains like `<cargo>.<namespace>.<zone>`
+  pub(crate) fn with_zone(&mut self, zone: Option<String>) -> Self {
+    if let Some(zone) = zone {
+      self.zone = zone.trim_matches('.').to_owned();
+    }
+    Self {
+      config_dir: self.config_dir.to_owned(),
+      config_path: self.config_path.to_owned(),
+      dns: self.dns.to_owned(),
+      zone: self.zone.to_owned(),
     }
   }
 
@@ -56,30 +74,14 @@ impl Dnsmasq {
 
   /// Generate the main dnsmasq config
   /// This config is used to require all other configs from the dnsmasq.d directory
+  /// The zone is answered locally and never forwarded to the dns servers
   #[inline]
   fn gen_main_conf(&self) -> Result<(), ErrorHint> {
-    let contents = format!("conf-dir={}/dnsmasq.d,*.conf\n", &self.config_dir);
-    self.write_main_conf(&contents)?;
-    Ok(())
-  }
-
-  /// Ensure that dnsmasq as a minimal config
-  #[inline]
-  pub(crate) fn ensure(&self) -> Result<(), ErrorHint> {
-    println!(
-      "[INFO] Ensuring a minimal dnsmasq config inside {}",
-      &self.config_dir
+    let contents = format!(
+      "conf-dir={}/dnsmasq.d,*.conf\nlocal=/{}/\n",
+      &self.config_dir, &self.zone
     );
-    self.gen_main_conf()?;
-    self.set_dns()?;
-    fs::create_dir_all(format!("{}/dnsmasq.d", &self.config_dir)).map_err(
-      |err| {
-        ErrorHint::Error(format!(
-          "unable to create dnsmasq.d directory got error: {err}"
-        ))
-      },
-    )?;
-    println!("[INFO] Minimal dnsmasq config is ensured");
+    self.write_main_conf(&contents)?;
     Ok(())
   }
 
@@ -100,6 +102,7 @@ impl Dnsmasq {
         continue;
       }
       new_data.push_str(line);
+      new_data.push('\n');
     }
     for dns in &self.dns {
       new_data.push_str(format!("server={dns}\n").as_str());
@@ -107,21 +110,134 @@ impl Dnsmasq {
     self.write_main_conf(&new_data)?;
     Ok(())
   }
+}
 
-  /// Generate domain records file for dnsmasq
-  #[inline]
-  pub(crate) fn generate_domains_file(
+/// Render the entries as dnsmasq config lines
+pub(crate) fn render(entries: &[DnsEntry]) -> String {
+  entries
+    .iter()
+    .map(|entry| match entry {
+      DnsEntry::Address { domain, ip_address } => {
+        format!("address=/{domain}/{ip_address}\n")
+      }
+      DnsEntry::Host { name, ip_address } => {
+        format!("host-record={name},{ip_address}\n")
+      }
+      DnsEntry::Cname { name, target } => format!("cname={name},{target}\n"),
+      DnsEntry::Txt { name, values } => {
+        let values = values
+          .iter()
+          .map(|value| format!("\"{value}\""))
+          .collect::<Vec<_>>()
+          .join(",");
+        format!("txt-record={name},{values}\n")
+      }
+      DnsEntry::Srv {
+        name,
+        target,
+        port,
+        priority,
+        weight,
+      } => format!("srv-host={name},{target},{port},{priority},{weight}\n"),
+    })
+    .collect()
+}
+
+/// Parse the config lines written by `render` back into entries
+/// Unknown or invalid lines are ignored
+pub(crate) fn parse(data: &str) -> Vec<DnsEntry> {
+  data
+    .lines()
+    .filter_map(|line| {
+      let (key, value) = line.trim().split_once('=')?;
+      match key {
+        "address" => {
+          let (domain, ip_address) =
+            value.trim_start_matches('/').split_once('/')?;
+          Some(DnsEntry::Address {
+            domain: domain.to_owned(),
+            ip_address: ip_address.to_owned(),
+          })
+        }
+        "host-record" => {
+          let (name, ip_address) = value.split_once(',')?;
+          Some(DnsEntry::Host {
+            name: name.to_owned(),
+            ip_address: ip_address.to_owned(),
+          })
+        }
+        "cname" => {
+          let (name, target) = value.split_once(',')?;
+          Some(DnsEntry::Cname {
+            name: name.to_owned(),
+            target: target.to_owned(),
+          })
+        }
+        "txt-record" => {
+          let (name, values) = value.split_once(',')?;
+          let values = values
+            .strip_prefix('"')?
+            .strip_suffix('"')?
+            .split("\",\"")
+            .map(|value| value.to_owned())
+            .collect();
+          Some(DnsEntry::Txt {
+            name: name.to_owned(),
+            values,
+          })
+        }
+        "srv-host" => {
+          let fields = value.split(',').collect::<Vec<_>>();
+          match fields.as_slice() {
+            [name, target, port, priority, weight] => Some(DnsEntry::Srv {
+              name: name.to_string(),
+              target: target.to_string(),
+              port: port.parse().ok()?,
+              priority: priority.parse().ok()?,
+              weight: weight.parse().ok()?,
+            }),
+            _ => None,
+          }
+        }
+        _ => None,
+      }
+    })
+    .collect()
+}
+
+/// Each group of entries is written in its own file inside `dnsmasq.d`
+/// dnsmasq is restarted to load them
+impl DnsBackend for Dnsmasq {
+  fn zone(&self) -> &str {
+    &self.zone
+  }
+
+  /// Ensure that dnsmasq as a minimal config
+  fn ensure(&self) -> Result<(), ErrorHint> {
+    println!(
+      "[INFO] Ensuring a minimal dnsmasq config inside {}",
+      &self.config_dir
+    );
+    self.gen_main_conf()?;
+    self.set_dns()?;
+    fs::create_dir_all(format!("{}/dnsmasq.d", &self.config_dir)).map_err(
+      |err| {
+        ErrorHint::Error(format!(
+          "unable to create dnsmasq.d directory got error: {err}"
+        ))
+      },
+    )?;
+    println!("[INFO] Minimal dnsmasq config is ensured");
+    Ok(())
+  }
+
+  fn write_entries(
     &self,
     name: &str,
-    domains: &[(String, String)],
+    entries: &[DnsEntry],
   ) -> Result<(), ErrorHint> {
-    let mut data = String::new();
-    for domain in domains {
-      let (name, ip) = domain;
-      data.push_str(format!("address=/{name}/{ip}\n").as_str());
-    }
     let file_path = format!("{}/dnsmasq.d/{name}.conf", &self.config_dir);
-    fs::write(file_path, data).map_err(|err| {
+    fs::write(file_path, render(entries)).map_err(|err| {
       ErrorHint::Warning(format!(
         "unable to create domains file for {name} got error: {err}"
       ))
@@ -129,12 +245,11 @@ impl Dnsmasq {
     Ok(())
   }
 
-  /// Remove domain records file for dnsmasq
-  pub(crate) fn remove_domains_file(
-    &self,
-    name: &str,
-  ) -> Result<(), ErrorHint> {
+  fn remove_entries(&self, name: &str) -> Result<(), ErrorHint> {
     let file_path = format!("{}/dnsmasq.d/{name}.conf", &self.config_dir);
+    if !std::path::Path::new(&file_path).exists() {
+      return Ok(());
+    }
     fs::remove_file(file_path).map_err(|err| {
       ErrorHint::Warning(format!(
         "unable to remove domains file for {name} got error: {err}"
@@ -143,20 +258,39 @@ impl Dnsmasq {
     Ok(())
   }
 
-  /// Clear all domain records file for dnsmasq
-  pub(crate) fn clear_domains(&self) -> Result<(), ErrorHint> {
+  fn list_entries(&self) -> Result<Vec<String>, ErrorHint> {
     let domain_dir = format!("{}/dnsmasq.d", &self.config_dir);
-    fs::remove_dir_all(&domain_dir).map_err(|err| {
+    let entries = fs::read_dir(&domain_dir).map_err(|err| {
       ErrorHint::Warning(format!(
-        "unable to remove domains directory got error: {err}"
+        "unable to read domains directory got error: {err}"
       ))
     })?;
-    fs::create_dir_all(&domain_dir).map_err(|err| {
-      ErrorHint::Warning(format!(
-        "unable to create domains directory got error: {err}"
-      ))
-    })?;
-    Ok(())
+    let names = entries
+      .flatten()
+      .filter_map(|entry| {
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        file_name.strip_suffix(".conf").map(|name| name.to_owned())
+      })
+      .collect::<Vec<_>>();
+    Ok(names)
+  }
+
+  fn entries(&self) -> Result<HashMap<String, Vec<DnsEntry>>, ErrorHint> {
+    let mut entries = HashMap::new();
+    for name in self.list_entries()? {
+      let file_path = format!("{}/dnsmasq.d/{name}.conf", &self.config_dir);
+      let data = fs::read_to_string(&file_path).map_err(|err| {
+        ErrorHint::Warning(format!(
+          "unable to read domains file for {name} got error: {err}"
+        ))
+      })?;
+      entries.insert(name, parse(&data));
+    }
+    Ok(entries)
+  }
+
+  fn need_restart(&self) -> bool {
+    true
   }
 }
 
@@ -164,3 +298,67 @@ impl Dnsmasq {
 pub(crate) fn new(config_path: &str) -> Dnsmasq {
   Dnsmasq::new(config_path)
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn stale_domains() {
+    let dnsmasq = new("/tmp/ncddns-stale-domains");
+    fs::create_dir_all("/tmp/ncddns-stale-domains").unwrap();
+    dnsmasq.ensure().unwrap();
+    let entries = [DnsEntry::Address {
+      domain: "nanocl.app.local".to_owned(),
+      ip_address: "10.0.0.2".to_owned(),
+    }];
+    dnsmasq.write_entries("app.global", &entries).unwrap();
+    dnsmasq.write_entries("old.global", &entries).unwrap();
+    assert_eq!(
+      fs::read_to_string("/tmp/ncddns-stale-domains/dnsmasq.d/app.global.conf")
+        .unwrap(),
+      "address=/nanocl.app.local/10.0.0.2\n"
+    );
+    let stale = dnsmasq
+      .remove_stale_entries(&["app.global".to_owned()])
+      .unwrap();
+    assert_eq!(stale, vec!["old.global".to_owned()]);
+    assert_eq!(
+      dnsmasq.list_entries().unwrap(),
+      vec!["app.global".to_owned()]
+    );
+    dnsmasq.remove_entries("old.global").unwrap();
+    fs::remove_dir_all("/tmp/ncddns-stale-domains").unwrap();
+  }
+
+  #[test]
+  fn parse_rendered() {
+    let entries = vec![
+      DnsEntry::Address {
+        domain: "nanocl.app.local".to_owned(),
+        ip_address: "10.0.0.2".to_owned(),
+      },
+      DnsEntry::Host {
+        name: "app.global.nanocl.internal".to_owned(),
+        ip_address: "10.0.0.3".to_owned(),
+      },
+      DnsEntry::Cname {
+        name: "www.nanocl.app.local".to_owned(),
+        target: "nanocl.app.local".to_owned(),
+      },
+      DnsEntry::Txt {
+        name: "nanocl.app.local".to_owned(),
+        values: vec!["v=spf1".to_owned(), "-all".to_owned()],
+      },
+      DnsEntry::Srv {
+        name: "_80._tcp.app.global.nanocl.internal".to_owned(),
+        target: "app.global.nanocl.internal".to_owned(),
+        port: 80,
+        priority: 0,
+        weight: 10,
+      },
+    ];
+    assert_eq!(parse(&render(&entries)), entries);
+    assert!(parse("conf-dir=/etc/dnsmasq.d\nbogus").is_empty());
+  }
+}
@@ -2,6 +2,7 @@ use ntex::web;
 use ntex::http::StatusCode;
 
 /// Error hint in case of an error
+#[derive(Debug)]
 pub enum ErrorHint {
   /// Critical process exited
   Error(String),
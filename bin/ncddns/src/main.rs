@@ -5,74 +5,182 @@
  * The dns entry will be the cargo generated from the cargo key.
  * We will replace - and _ by a . and will be generated this way:
  * `nanocl.<key>.local`
+ * Each cargo also own a domain in the zone of its namespace
+ * `<cargo>.<namespace>.<zone>` with srv records for its ports
  * This process should never stop by itself or by a crash.
  * It will loop till it have a connection to nanocl daemon
  * and be able to watch for his events.
  *
 */
 
-use ntex::web;
-use ntex::server::Server;
+use std::sync::Arc;
+
+use ntex::{rt, web};
+use futures::StreamExt;
+use nanocld_client::NanocldClient;
 use nanocld_client::stubs::system::Event;
+use nanocld_client::stubs::proxy::ResourceProxyRule;
+
+use backend::{Backend, DnsBackend};
 
 mod cli;
 mod error;
 mod utils;
+mod backend;
 mod dnsmasq;
+mod native;
+mod record;
 mod service;
+mod zone;
 
 /// Handle events from nanocl daemon
 async fn on_event(
-  client: nanocld_client::NanocldClient,
-  dnsmasq: dnsmasq::Dnsmasq,
+  client: &NanocldClient,
+  backend: &dyn DnsBackend,
   event: Event,
 ) -> Result<(), error::ErrorHint> {
   match &event {
-    Event::CargoStarted(cargo) => {
-      if cargo.name != "dns" && cargo.namespace_name != "system" {
-        println!("[INFO] Generating dns entries for cargo : {}", &cargo.key);
-        let domains = utils::gen_cargo_domains(cargo)?;
-        dnsmasq.generate_domains_file(&cargo.key, &domains)?;
-        return utils::restart_dns_service(&client).await;
-      }
-      Ok(())
+    Event::CargoStarted(cargo) | Event::CargoPatched(cargo)
+      if utils::is_managed(cargo) =>
+    {
+      println!("[INFO] Generating dns entries for cargo : {}", &cargo.key);
+      let proxy_rules = zone::list_proxy_rules(client).await?;
+      utils::update_cargo_domains(cargo, backend, &proxy_rules)?;
+      record::update_records_targeting(client, backend, &cargo.key).await?;
+      utils::restart_dns_service(client, backend).await
     }
-    Event::CargoDeleted(cargo) => {
+    Event::CargoStopped(cargo) | Event::CargoDeleted(cargo)
+      if utils::is_managed(cargo) =>
+    {
       println!("[INFO] Removing dns entries for cargo : {}", &cargo.key);
-      dnsmasq.remove_domains_file(&cargo.key)?;
-      utils::restart_dns_service(&client).await
+      backend.remove_entries(&cargo.key)?;
+      record::update_records_targeting(client, backend, &cargo.key).await?;
+      utils::restart_dns_service(client, backend).await
+    }
+    Event::ResourceCreated(resource)
+    | Event::ResourcePatched(resource)
+    | Event::ResourceDeleted(resource)
+      if resource.kind == "ProxyRule" =>
+    {
+      let Ok(proxy_rule) =
+        serde_json::from_value::<ResourceProxyRule>(resource.config.to_owned())
+      else {
+        return Ok(());
+      };
+      println!(
+        "[INFO] Updating srv records of proxy rule : {}",
+        &resource.key
+      );
+      let proxy_rules = zone::list_proxy_rules(client).await?;
+      for key in zone::proxy_rule_targets(&proxy_rule) {
+        let Some((name, namespace)) = key.split_once('.') else {
+          continue;
+        };
+        let Ok(cargo) =
+          client.inspect_cargo(name, Some(namespace.to_owned())).await
+        else {
+          continue;
+        };
+        if !utils::is_managed(&cargo) {
+          continue;
+        }
+        utils::update_cargo_domains(&cargo, backend, &proxy_rules)?;
+      }
+      utils::restart_dns_service(client, backend).await
+    }
+    Event::VmStarted(vm) | Event::VmPatched(vm) => {
+      println!("[INFO] Generating dns entries for vm : {}", &vm.key);
+      utils::update_vm_domains(vm, backend)?;
+      utils::restart_dns_service(client, backend).await
+    }
+    Event::VmStopped(vm) | Event::VmDeleted(vm) => {
+      println!("[INFO] Removing dns entries for vm : {}", &vm.key);
+      backend.remove_entries(&utils::vm_entries_name(&vm.key))?;
+      utils::restart_dns_service(client, backend).await
     }
     // We don't care about other events
     _ => Ok(()),
   }
 }
 
-fn setup_server() -> Server {
-  let server = web::HttpServer::new(web::App::new);
-
-  server.run()
+/// Watch the events of the daemon and reconnect when the connection is lost
+/// The dns entries are synced on each connection to catch the missed events
+async fn r#loop(client: &NanocldClient, backend: &dyn DnsBackend) {
+  loop {
+    println!("[INFO] Connecting to nanocl daemon...");
+    match client.watch_events().await {
+      Err(err) => {
+        eprintln!(
+          "[WARNING] Unable to connect to nanocl daemon got error: {err}"
+        );
+      }
+      Ok(mut stream) => {
+        println!("[INFO] Connected!");
+        if let Err(err) = utils::sync_daemon_state(client, backend).await {
+          eprintln!("{err}");
+        }
+        while let Some(event) = stream.next().await {
+          let Ok(event) = event else {
+            break;
+          };
+          if let Err(err) = on_event(client, backend, event).await {
+            eprintln!("{err}");
+          }
+        }
+      }
+    }
+    eprintln!(
+      "[WARNING] Disconnected from nanocl daemon trying to reconnect in 2 seconds"
+    );
+    ntex::time::sleep(std::time::Duration::from_secs(2)).await;
+  }
 }
 
 /// Main function
 /// Is parsing the command line arguments,
-/// ensure a minimal dnsmasq config is present,
-/// it will exit with code 1 if it can't create the minimal dnsmasq config
-/// then it will try to sync the cargo dns entries with the current nanocl daemon state
-/// and finally it will start the main loop
+/// ensure the backend is ready, a minimal dnsmasq config is present
+/// or the native dns server is listening,
+/// it will exit with code 1 if the backend isn't ready
+/// then it will watch the daemon events
+/// and sync the cargo dns entries on each connection
+/// while serving the controller api of the `DnsRecord` kind
 #[ntex::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
   println!("nanocl-ncddns v{}", env!("CARGO_PKG_VERSION"));
   let cli = cli::parse();
-  let conf_dir = cli.conf_dir.to_owned().unwrap_or("/etc".into());
-  let dnsmasq = dnsmasq::new(&conf_dir).with_dns(cli.dns);
-  if let Err(err) = dnsmasq.ensure() {
+  let backend: Backend = match cli.backend {
+    cli::BackendKind::Dnsmasq => {
+      let conf_dir = cli.conf_dir.to_owned().unwrap_or("/etc".into());
+      let dnsmasq = dnsmasq::new(&conf_dir)
+        .with_dns(cli.dns.to_owned())
+        .with_zone(cli.zone.to_owned());
+      Arc::new(dnsmasq)
+    }
+    cli::BackendKind::Native => {
+      Arc::new(native::new(&cli.listen, cli.zone.to_owned(), &cli.dns))
+    }
+  };
+  if let Err(err) = backend.ensure() {
     eprintln!("{err}");
     std::process::exit(1);
   }
 
-  let server = setup_server();
+  let loop_backend = backend.clone();
+  rt::Arbiter::new().exec_fn(move || {
+    let client = NanocldClient::connect_with_unix_default();
+    rt::spawn(async move {
+      r#loop(&client, &*loop_backend).await;
+    });
+  });
+
+  let server = web::HttpServer::new(move || {
+    web::App::new()
+      .state(backend.clone())
+      .configure(service::configure)
+  })
+  .bind_uds("/run/nanocl/dns.sock")?;
 
-  server.await?;
+  server.run().await?;
 
   Ok(())
 }
@@ -0,0 +1,791 @@
+use std::thread;
+use std::time::Duration;
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::{
+  IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream, UdpSocket,
+};
+
+use crate::zone;
+use crate::error::ErrorHint;
+use crate::backend::{
+  DnsBackend, DnsEntry, MAX_CNAME_DEPTH, find_entries, is_subdomain,
+};
+
+/// Time to live of the answers in seconds
+/// Kept low since the entries follow the cargo instances
+const TTL: u32 = 10;
+
+/// Time to wait for the answer of an upstream server in seconds
+const FORWARD_TIMEOUT: u64 = 2;
+
+/// Number of threads forwarding the queries to the upstream servers
+const FORWARD_WORKERS: usize = 8;
+
+/// Number of forwarded queries waiting for a worker
+/// The next queries are answered with a server failure
+const FORWARD_QUEUE: usize = 256;
+
+/// Number of tcp connections served at the same time
+const MAX_TCP_CONNECTIONS: usize = 64;
+
+const TYPE_A: u16 = 1;
+const TYPE_CNAME: u16 = 5;
+const TYPE_TXT: u16 = 16;
+const TYPE_AAAA: u16 = 28;
+const TYPE_SRV: u16 = 33;
+const TYPE_ANY: u16 = 255;
+const CLASS_IN: u16 = 1;
+
+const RCODE_FORMERR: u16 = 1;
+const RCODE_SERVFAIL: u16 = 2;
+const RCODE_NXDOMAIN: u16 = 3;
+const RCODE_NOTIMP: u16 = 4;
+const RCODE_REFUSED: u16 = 5;
+
+/// Embedded dns server answering from the entries kept in memory
+/// It is authoritative for the zone and the names of the entries
+/// and forward the other queries of the local clients to the upstream dns servers
+#[derive(Clone)]
+pub(crate) struct NativeDns {
+  listen: String,
+  zone: String,
+  upstreams: Vec<SocketAddr>,
+  entries: Arc<RwLock<HashMap<String, Vec<DnsEntry>>>>,
+  /// Queue of the queries to forward shared by the workers
+  forward_sender: SyncSender<ForwardJob>,
+  forward_receiver: Arc<Mutex<Receiver<ForwardJob>>>,
+  /// Number of tcp connections being served
+  tcp_connections: Arc<AtomicUsize>,
+}
+
+/// A record of an answer
+#[derive(Debug)]
+struct Answer {
+  name: String,
+  rtype: u16,
+  rdata: Vec<u8>,
+}
+
+/// The question of a query
+#[derive(Debug)]
+struct Question {
+  name: String,
+  qtype: u16,
+  qclass: u16,
+  /// Offset of the end of the question in the query
+  end: usize,
+}
+
+/// What to do with a query
+enum Reply {
+  Answer(Vec<u8>),
+  Forward,
+}
+
+/// A query waiting to be forwarded with the callback sending its answer
+type ForwardJob = (Vec<u8>, Box<dyn FnOnce(Vec<u8>) + Send>);
+
+/// Parse an upstream dns server like `1.1.1.1`, `1.1.1.1#5353`
+/// or `[::1]:5353`, the port default to 53
+fn parse_upstream(dns: &str) -> Option<SocketAddr> {
+  if let Ok(addr) = dns.parse::<SocketAddr>() {
+    return Some(addr);
+  }
+  let (ip, port) = match dns.split_once('#') {
+    Some((ip, port)) => (ip, port.parse::<u16>().ok()?),
+    None => (dns, 53),
+  };
+  let ip = ip.parse::<IpAddr>().ok()?;
+  Some(SocketAddr::new(ip, port))
+}
+
+/// Whether the queries of a client can be forwarded to the upstream servers
+/// Only the loopback and private networks used by the cargoes are allowed
+/// so the server can't be used as an open resolver
+fn is_recursion_allowed(ip: IpAddr) -> bool {
+  match ip {
+    IpAddr::V4(ip) => ip.is_loopback() || ip.is_private() || ip.is_link_local(),
+    IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+      Some(ip) => is_recursion_allowed(IpAddr::V4(ip)),
+      None => {
+        let segment = ip.segments()[0];
+        ip.is_loopback()
+          || segment & 0xFE00 == 0xFC00
+          || segment & 0xFFC0 == 0xFE80
+      }
+    },
+  }
+}
+
+/// Read a possibly compressed name from a packet
+/// Returns the lowercase name and the offset after it
+fn read_name(packet: &[u8], start: usize) -> Option<(String, usize)> {
+  let mut labels = Vec::new();
+  let mut pos = start;
+  let mut end = None;
+  let mut jumps = 0;
+  loop {
+    let len = *packet.get(pos)? as usize;
+    if len == 0 {
+      pos += 1;
+      break;
+    }
+    if len & 0xC0 == 0xC0 {
+      let offset = ((len & 0x3F) << 8) | *packet.get(pos + 1)? as usize;
+      end.get_or_insert(pos + 2);
+      jumps += 1;
+      if jumps > 16 {
+        return None;
+      }
+      pos = offset;
+      continue;
+    }
+    let label = packet.get(pos + 1..pos + 1 + len)?;
+    labels.push(String::from_utf8_lossy(label).to_lowercase());
+    pos += 1 + len;
+  }
+  Some((labels.join("."), end.unwrap_or(pos)))
+}
+
+/// Write a name without compression
+fn write_name(buf: &mut Vec<u8>, name: &str) {
+  for label in name.split('.').filter(|label| !label.is_empty()) {
+    let label = &label.as_bytes()[..label.len().min(63)];
+    buf.push(label.len() as u8);
+    buf.extend_from_slice(label);
+  }
+  buf.push(0);
+}
+
+fn read_u16(packet: &[u8], pos: usize) -> Option<u16> {
+  let bytes = packet.get(pos..pos + 2)?;
+  Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+/// Parse the question of a query
+fn read_question(query: &[u8]) -> Option<Question> {
+  let (name, pos) = read_name(query, 12)?;
+  Some(Question {
+    name,
+    qtype: read_u16(query, pos)?,
+    qclass: read_u16(query, pos + 2)?,
+    end: pos + 4,
+  })
+}
+
+/// Push the address record of an ip matching the query type
+fn push_address(
+  answers: &mut Vec<Answer>,
+  name: &str,
+  ip_address: &str,
+  qtype: u16,
+) {
+  let (rtype, rdata) = match ip_address.parse::<IpAddr>() {
+    Ok(IpAddr::V4(ip)) => (TYPE_A, ip.octets().to_vec()),
+    Ok(IpAddr::V6(ip)) => (TYPE_AAAA, ip.octets().to_vec()),
+    Err(_) => return,
+  };
+  if qtype == rtype || qtype == TYPE_ANY {
+    answers.push(Answer {
+      name: name.to_owned(),
+      rtype,
+      rdata,
+    });
+  }
+}
+
+/// Find the answers of a name in the entries
+/// Returns false when no entry own the name
+fn lookup(
+  entries: &HashMap<String, Vec<DnsEntry>>,
+  name: &str,
+  qtype: u16,
+  depth: usize,
+  answers: &mut Vec<Answer>,
+) -> bool {
+  let found = find_entries(entries, name);
+  for (_, entry) in &found {
+    match entry {
+      DnsEntry::Address { ip_address, .. }
+      | DnsEntry::Host { ip_address, .. } => {
+        push_address(answers, name, ip_address, qtype);
+      }
+      DnsEntry::Cname { target, .. } => {
+        let mut rdata = Vec::new();
+        write_name(&mut rdata, target);
+        answers.push(Answer {
+          name: name.to_owned(),
+          rtype: TYPE_CNAME,
+          rdata,
+        });
+        if qtype != TYPE_CNAME && depth < MAX_CNAME_DEPTH {
+          let target = target.to_lowercase();
+          lookup(entries, &target, qtype, depth + 1, answers);
+        }
+      }
+      DnsEntry::Txt { values, .. } => {
+        if qtype != TYPE_TXT && qtype != TYPE_ANY {
+          continue;
+        }
+        let mut rdata = Vec::new();
+        for value in values {
+          for chunk in value.as_bytes().chunks(255) {
+            rdata.push(chunk.len() as u8);
+            rdata.extend_from_slice(chunk);
+          }
+        }
+        answers.push(Answer {
+          name: name.to_owned(),
+          rtype: TYPE_TXT,
+          rdata,
+        });
+      }
+      DnsEntry::Srv {
+        target,
+        port,
+        priority,
+        weight,
+        ..
+      } => {
+        if qtype != TYPE_SRV && qtype != TYPE_ANY {
+          continue;
+        }
+        let mut rdata = Vec::new();
+        rdata.extend(priority.to_be_bytes());
+        rdata.extend(weight.to_be_bytes());
+        rdata.extend(port.to_be_bytes());
+        write_name(&mut rdata, target);
+        answers.push(Answer {
+          name: name.to_owned(),
+          rtype: TYPE_SRV,
+          rdata,
+        });
+      }
+    }
+  }
+  !found.is_empty()
+}
+
+/// Build a response to a query
+/// The answers are dropped and the response flagged as truncated
+/// when it's bigger than the max size
+fn build_response(
+  query: &[u8],
+  question: Option<&Question>,
+  rcode: u16,
+  answers: &[Answer],
+  max_size: usize,
+) -> Vec<u8> {
+  let query_flags = read_u16(query, 2).unwrap_or_default();
+  // Keep the opcode and the recursion desired bit of the query
+  // and set the response, authoritative and recursion available bits
+  let flags = 0x8000 | 0x0400 | 0x0080 | (query_flags & 0x7900) | rcode;
+  let mut buf = Vec::with_capacity(512);
+  buf.extend_from_slice(query.get(0..2).unwrap_or(&[0, 0]));
+  buf.extend(flags.to_be_bytes());
+  buf.extend((question.is_some() as u16).to_be_bytes());
+  buf.extend((answers.len() as u16).to_be_bytes());
+  buf.extend([0, 0, 0, 0]);
+  if let Some(question) = question {
+    buf.extend_from_slice(&query[12..question.end]);
+  }
+  let header_size = buf.len();
+  for answer in answers {
+    write_name(&mut buf, &answer.name);
+    buf.extend(answer.rtype.to_be_bytes());
+    buf.extend(CLASS_IN.to_be_bytes());
+    buf.extend(TTL.to_be_bytes());
+    buf.extend((answer.rdata.len() as u16).to_be_bytes());
+    buf.extend_from_slice(&answer.rdata);
+  }
+  if buf.len() > max_size {
+    buf.truncate(header_size);
+    buf[2] |= 0x02;
+    buf[6..8].copy_from_slice(&[0, 0]);
+  }
+  buf
+}
+
+/// Build an error response to a query
+fn error_response(query: &[u8], rcode: u16) -> Vec<u8> {
+  let question = read_question(query);
+  build_response(query, question.as_ref(), rcode, &[], usize::MAX)
+}
+
+impl NativeDns {
+  /// Answer a query from the entries or ask to forward it
+  /// Invalid queries and responses are ignored
+  fn handle(&self, query: &[u8], max_size: usize) -> Option<Reply> {
+    let flags = read_u16(query, 2)?;
+    if flags & 0x8000 != 0 {
+      return None;
+    }
+    if (flags >> 11) & 0xF != 0 {
+      return Some(Reply::Answer(error_response(query, RCODE_NOTIMP)));
+    }
+    let Some(question) =
+      read_question(query).filter(|_| read_u16(query, 4) == Some(1))
+    else {
+      return Some(Reply::Answer(error_response(query, RCODE_FORMERR)));
+    };
+    if question.qclass != CLASS_IN {
+      return Some(Reply::Forward);
+    }
+    let mut answers = Vec::new();
+    let known = {
+      let entries = self.entries.read().ok()?;
+      lookup(&entries, &question.name, question.qtype, 0, &mut answers)
+    };
+    let rcode = match known {
+      true => 0,
+      false if is_subdomain(&question.name, &self.zone) => RCODE_NXDOMAIN,
+      false => return Some(Reply::Forward),
+    };
+    Some(Reply::Answer(build_response(
+      query,
+      Some(&question),
+      rcode,
+      &answers,
+      max_size,
+    )))
+  }
+
+  /// Send a query to the upstream servers and return the first answer
+  /// Responds with a server failure when no upstream answer
+  fn forward(&self, query: &[u8]) -> Vec<u8> {
+    let mut buf = [0; 65535];
+    for upstream in &self.upstreams {
+      let bind_addr = match upstream {
+        SocketAddr::V4(_) => SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+        SocketAddr::V6(_) => SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)),
+      };
+      let Ok(socket) = UdpSocket::bind(bind_addr) else {
+        continue;
+      };
+      let timeout = Some(Duration::from_secs(FORWARD_TIMEOUT));
+      if socket.set_read_timeout(timeout).is_err()
+        || socket.send_to(query, upstream).is_err()
+      {
+        continue;
+      }
+      while let Ok((len, from)) = socket.recv_from(&mut buf) {
+        if from == *upstream && buf.get(0..2) == query.get(0..2) {
+          return buf[..len].to_vec();
+        }
+      }
+      eprintln!("[WARNING] No answer from upstream dns server {upstream}");
+    }
+    error_response(query, RCODE_SERVFAIL)
+  }
+
+  /// Forward the queued queries until the queue is closed
+  fn forward_worker(&self) {
+    loop {
+      let job = match self.forward_receiver.lock() {
+        Ok(receiver) => receiver.recv(),
+        Err(_) => return,
+      };
+      let Ok((query, send)) = job else {
+        return;
+      };
+      send(self.forward(&query));
+    }
+  }
+
+  /// Answer a query, the forwarded queries are answered by the callback
+  /// from a worker to not block the server
+  /// The queries of the clients not allowed to recurse are refused
+  fn reply<F>(&self, query: Vec<u8>, peer: IpAddr, max_size: usize, send: F)
+  where
+    F: FnOnce(Vec<u8>) + Send + 'static,
+  {
+    match self.handle(&query, max_size) {
+      None => {}
+      Some(Reply::Answer(response)) => send(response),
+      Some(Reply::Forward) if !is_recursion_allowed(peer) => {
+        send(error_response(&query, RCODE_REFUSED));
+      }
+      Some(Reply::Forward) => {
+        match self.forward_sender.try_send((query, Box::new(send))) {
+          Ok(()) => {}
+          Err(TrySendError::Full((query, send)))
+          | Err(TrySendError::Disconnected((query, send))) => {
+            eprintln!("[WARNING] Too many dns queries to forward");
+            send(error_response(&query, RCODE_SERVFAIL));
+          }
+        }
+      }
+    }
+  }
+
+  /// Serve the queries received over udp
+  /// The responses are limited to 512 bytes without edns
+  fn serve_udp(&self, socket: UdpSocket) {
+    let mut buf = [0; 65535];
+    loop {
+      let (len, peer) = match socket.recv_from(&mut buf) {
+        Ok(res) => res,
+        Err(err) => {
+          eprintln!("[WARNING] Unable to receive dns query got error: {err}");
+          continue;
+        }
+      };
+      let query = buf[..len].to_vec();
+      let max_size = match read_u16(&query, 10) {
+        Some(arcount) if arcount > 0 => 1232,
+        _ => 512,
+      };
+      let Ok(socket) = socket.try_clone() else {
+        continue;
+      };
+      self.reply(query, peer.ip(), max_size, move |response| {
+        let _ = socket.send_to(&response, peer);
+      });
+    }
+  }
+
+  /// Serve the queries of a tcp connection, each message is prefixed by its size
+  /// The responses are written under a lock since the forwarded ones
+  /// are sent from the workers while the next queries are answered
+  fn serve_tcp_stream(&self, mut stream: TcpStream) {
+    let timeout = Some(Duration::from_secs(FORWARD_TIMEOUT * 5));
+    let _ = stream.set_read_timeout(timeout);
+    let Ok(peer) = stream.peer_addr() else {
+      return;
+    };
+    let Ok(writer) = stream.try_clone() else {
+      return;
+    };
+    let writer = Arc::new(Mutex::new(writer));
+    loop {
+      let mut size = [0; 2];
+      if stream.read_exact(&mut size).is_err() {
+        return;
+      }
+      let mut query = vec![0; u16::from_be_bytes(size) as usize];
+      if stream.read_exact(&mut query).is_err() {
+        return;
+      }
+      let writer = writer.clone();
+      self.reply(query, peer.ip(), u16::MAX as usize, move |response| {
+        let mut message = (response.len() as u16).to_be_bytes().to_vec();
+        message.extend(response);
+        if let Ok(mut writer) = writer.lock() {
+          let _ = writer.write_all(&message);
+        }
+      });
+    }
+  }
+
+  /// Serve the tcp connections, the new connections are closed
+  /// while too many connections are served
+  fn serve_tcp(&self, listener: TcpListener) {
+    for stream in listener.incoming().flatten() {
+      let count = self.tcp_connections.fetch_add(1, Ordering::SeqCst);
+      if count >= MAX_TCP_CONNECTIONS {
+        self.tcp_connections.fetch_sub(1, Ordering::SeqCst);
+        eprintln!("[WARNING] Too many dns tcp connections");
+        continue;
+      }
+      let dns = self.clone();
+      thread::spawn(move || {
+        dns.serve_tcp_stream(stream);
+        dns.tcp_connections.fetch_sub(1, Ordering::SeqCst);
+      });
+    }
+  }
+}
+
+/// The entries are replaced in memory and served right away
+impl DnsBackend for NativeDns {
+  fn zone(&self) -> &str {
+    &self.zone
+  }
+
+  /// Start to serve the queries on udp and tcp
+  fn ensure(&self) -> Result<(), ErrorHint> {
+    println!("[INFO] Starting native dns server on {}", &self.listen);
+    let socket = UdpSocket::bind(&self.listen).map_err(|err| {
+      ErrorHint::Error(format!(
+        "unable to listen udp on {} got error: {err}",
+        &self.listen
+      ))
+    })?;
+    let listener = TcpListener::bind(&self.listen).map_err(|err| {
+      ErrorHint::Error(format!(
+        "unable to listen tcp on {} got error: {err}",
+        &self.listen
+      ))
+    })?;
+    for _ in 0..FORWARD_WORKERS {
+      let dns = self.clone();
+      thread::spawn(move || dns.forward_worker());
+    }
+    let dns = self.clone();
+    thread::spawn(move || dns.serve_udp(socket));
+    let dns = self.clone();
+    thread::spawn(move || dns.serve_tcp(listener));
+    Ok(())
+  }
+
+  fn write_entries(
+    &self,
+    name: &str,
+    entries: &[DnsEntry],
+  ) -> Result<(), ErrorHint> {
+    let mut store = self.entries.write().map_err(|err| {
+      ErrorHint::Error(format!("unable to update dns entries got error: {err}"))
+    })?;
+    store.insert(name.to_owned(), entries.to_vec());
+    Ok(())
+  }
+
+  fn remove_entries(&self, name: &str) -> Result<(), ErrorHint> {
+    let mut store = self.entries.write().map_err(|err| {
+      ErrorHint::Error(format!("unable to remove dns entries got error: {err}"))
+    })?;
+    store.remove(name);
+    Ok(())
+  }
+
+  fn list_entries(&self) -> Result<Vec<String>, ErrorHint> {
+    Ok(self.entries()?.into_keys().collect())
+  }
+
+  fn entries(&self) -> Result<HashMap<String, Vec<DnsEntry>>, ErrorHint> {
+    let store = self.entries.read().map_err(|err| {
+      ErrorHint::Error(format!("unable to read dns entries got error: {err}"))
+    })?;
+    Ok(store.clone())
+  }
+
+  fn need_restart(&self) -> bool {
+    false
+  }
+}
+
+/// Create a new native dns server listening on the given address
+/// The invalid upstream servers are ignored
+pub(crate) fn new(
+  listen: &str,
+  zone: Option<String>,
+  dns: &[String],
+) -> NativeDns {
+  let upstreams = dns
+    .iter()
+    .filter_map(|dns| {
+      let upstream = parse_upstream(dns);
+      if upstream.is_none() {
+        eprintln!("[WARNING] Ignoring invalid dns server {dns}");
+      }
+      upstream
+    })
+    .collect();
+  let (forward_sender, forward_receiver) = mpsc::sync_channel(FORWARD_QUEUE);
+  NativeDns {
+    listen: listen.to_owned(),
+    zone: zone
+      .map(|zone| zone.trim_matches('.').to_lowercase())
+      .unwrap_or(zone::DEFAULT_ZONE.to_owned()),
+    upstreams,
+    entries: Arc::new(RwLock::new(HashMap::new())),
+    forward_sender,
+    forward_receiver: Arc::new(Mutex::new(forward_receiver)),
+    tcp_connections: Arc::new(AtomicUsize::new(0)),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn query(id: u16, name: &str, qtype: u16) -> Vec<u8> {
+    let mut query = id.to_be_bytes().to_vec();
+    query.extend([0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+    write_name(&mut query, name);
+    query.extend(qtype.to_be_bytes());
+    query.extend(CLASS_IN.to_be_bytes());
+    query
+  }
+
+  fn send(socket: &UdpSocket, query: &[u8]) -> Vec<u8> {
+    let mut buf = [0; 4096];
+    socket.send(query).unwrap();
+    let len = socket.recv(&mut buf).unwrap();
+    buf[..len].to_vec()
+  }
+
+  fn rcode(response: &[u8]) -> u8 {
+    response[3] & 0x0F
+  }
+
+  fn answer_count(response: &[u8]) -> u16 {
+    read_u16(response, 6).unwrap()
+  }
+
+  fn contains(response: &[u8], bytes: &[u8]) -> bool {
+    response.windows(bytes.len()).any(|window| window == bytes)
+  }
+
+  #[test]
+  fn upstreams() {
+    assert_eq!(
+      parse_upstream("1.1.1.1"),
+      Some("1.1.1.1:53".parse().unwrap())
+    );
+    assert_eq!(
+      parse_upstream("1.1.1.1#5353"),
+      Some("1.1.1.1:5353".parse().unwrap())
+    );
+    assert_eq!(
+      parse_upstream("[::1]:5353"),
+      Some("[::1]:5353".parse().unwrap())
+    );
+    assert_eq!(parse_upstream("dns.local"), None);
+  }
+
+  #[test]
+  fn recursion() {
+    for ip in ["127.0.0.1", "10.1.0.2", "172.17.0.2", "192.168.1.2", "::1"] {
+      assert!(is_recursion_allowed(ip.parse().unwrap()), "{ip}");
+    }
+    for ip in ["1.1.1.1", "172.32.0.1", "2001:db8::1", "::ffff:8.8.8.8"] {
+      assert!(!is_recursion_allowed(ip.parse().unwrap()), "{ip}");
+    }
+    let dns = new("127.0.0.1:15355", None, &[]);
+    let (sender, receiver) = mpsc::channel();
+    let peer = "1.1.1.1".parse().unwrap();
+    dns.reply(query(1, "example.com", TYPE_A), peer, 512, move |res| {
+      let _ = sender.send(res);
+    });
+    let response = receiver.try_recv().unwrap();
+    assert_eq!(rcode(&response) as u16, RCODE_REFUSED);
+  }
+
+  #[test]
+  fn names() {
+    let mut packet = vec![0; 12];
+    write_name(&mut packet, "App.Global.nanocl.internal");
+    // A pointer to the name at offset 12
+    packet.extend([0xC0, 12]);
+    assert_eq!(
+      read_name(&packet, 12),
+      Some(("app.global.nanocl.internal".to_owned(), 40))
+    );
+    assert_eq!(
+      read_name(&packet, 40),
+      Some(("app.global.nanocl.internal".to_owned(), 42))
+    );
+    // A pointer to itself is rejected
+    assert_eq!(read_name(&[0xC0, 0], 0), None);
+  }
+
+  #[test]
+  fn serve() {
+    let dns = new("127.0.0.1:15353", None, &[]);
+    dns.ensure().unwrap();
+    let domain = "app.global.nanocl.internal";
+    let entries = [
+      DnsEntry::Host {
+        name: domain.to_owned(),
+        ip_address: "10.0.0.2".to_owned(),
+      },
+      DnsEntry::Host {
+        name: domain.to_owned(),
+        ip_address: "10.0.0.3".to_owned(),
+      },
+      DnsEntry::Srv {
+        name: format!("_80._tcp.{domain}"),
+        target: domain.to_owned(),
+        port: 80,
+        priority: 0,
+        weight: 0,
+      },
+    ];
+    dns.write_entries("app.global", &entries).unwrap();
+    dns
+      .write_entries(
+        "db.record",
+        &[DnsEntry::Cname {
+          name: "db.internal".to_owned(),
+          target: domain.to_owned(),
+        }],
+      )
+      .unwrap();
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket
+      .set_read_timeout(Some(Duration::from_secs(5)))
+      .unwrap();
+    socket.connect("127.0.0.1:15353").unwrap();
+    // Every instance is returned
+    let response = send(&socket, &query(1, domain, TYPE_A));
+    assert_eq!(&response[0..2], &[0, 1]);
+    assert_eq!(rcode(&response), 0);
+    assert_eq!(answer_count(&response), 2);
+    assert!(contains(&response, &[10, 0, 0, 2]));
+    assert!(contains(&response, &[10, 0, 0, 3]));
+    // The name exists without an AAAA record
+    let response = send(&socket, &query(2, domain, TYPE_AAAA));
+    assert_eq!(rcode(&response), 0);
+    assert_eq!(answer_count(&response), 0);
+    let response =
+      send(&socket, &query(3, &format!("_80._tcp.{domain}"), TYPE_SRV));
+    assert_eq!(answer_count(&response), 1);
+    assert!(contains(&response, &[0, 0, 0, 0, 0, 80]));
+    // The cname is followed
+    let response = send(&socket, &query(4, "db.internal", TYPE_A));
+    assert_eq!(answer_count(&response), 3);
+    // Unknown names of the zone don't exist
+    let response =
+      send(&socket, &query(5, "old.global.nanocl.internal", TYPE_A));
+    assert_eq!(rcode(&response) as u16, RCODE_NXDOMAIN);
+    // Other names fail without upstream
+    let response = send(&socket, &query(6, "example.com", TYPE_A));
+    assert_eq!(rcode(&response) as u16, RCODE_SERVFAIL);
+    // The updates are served right away
+    dns.write_entries("app.global", &entries[1..]).unwrap();
+    let response = send(&socket, &query(7, domain, TYPE_A));
+    assert_eq!(answer_count(&response), 1);
+    assert!(!contains(&response, &[10, 0, 0, 2]));
+    dns.remove_entries("app.global").unwrap();
+    let response = send(&socket, &query(8, domain, TYPE_A));
+    assert_eq!(rcode(&response) as u16, RCODE_NXDOMAIN);
+  }
+
+  #[test]
+  fn limits() {
+    // An upstream that never answer
+    let upstream = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let upstream = upstream.local_addr().unwrap().to_string();
+    let dns = new("127.0.0.1:15354", None, &[upstream]);
+    // The queries exceeding the queue fail right away
+    let (sender, receiver) = mpsc::channel();
+    for id in 0..=FORWARD_QUEUE {
+      let sender = sender.clone();
+      let query = query(id as u16, "example.com", TYPE_A);
+      dns.reply(query, Ipv4Addr::LOCALHOST.into(), 512, move |res| {
+        let _ = sender.send(res);
+      });
+    }
+    let response = receiver.try_recv().unwrap();
+    assert_eq!(read_u16(&response, 0), Some(FORWARD_QUEUE as u16));
+    assert_eq!(rcode(&response) as u16, RCODE_SERVFAIL);
+    assert!(receiver.try_recv().is_err());
+    dns.ensure().unwrap();
+    let mut buf = [0; 512];
+    // The tcp connections exceeding the limit are closed
+    let streams = (0..MAX_TCP_CONNECTIONS)
+      .map(|_| TcpStream::connect("127.0.0.1:15354").unwrap())
+      .collect::<Vec<_>>();
+    let mut stream = TcpStream::connect("127.0.0.1:15354").unwrap();
+    stream
+      .set_read_timeout(Some(Duration::from_secs(5)))
+      .unwrap();
+    assert_eq!(stream.read(&mut buf).unwrap(), 0);
+    drop(streams);
+  }
+}
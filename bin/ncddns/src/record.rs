@@ -0,0 +1,345 @@
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use nanocld_client::NanocldClient;
+use nanocld_client::stubs::cargo::CargoInspect;
+use nanocld_client::stubs::resource::{Resource, ResourcePartial, ResourceQuery};
+use nanocld_client::stubs::dns::{ResourceDnsRecord, DnsRecord, DnsRecordTarget};
+
+use crate::error::ErrorHint;
+use crate::backend::{DnsBackend, DnsEntry};
+
+/// Name of the group of entries of a resource
+/// The `.record` suffix avoid conflicts with the cargo keys
+pub(crate) fn entries_name(key: &str) -> String {
+  format!("{key}.record")
+}
+
+/// Check if a domain is valid for dnsmasq
+/// `_` is allowed for the service and protocol labels of srv records
+fn is_domain(domain: &str) -> bool {
+  !domain.is_empty()
+    && !domain.starts_with('.')
+    && !domain.ends_with('.')
+    && domain
+      .chars()
+      .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// Split a cargo key into its name and namespace
+fn split_cargo_key(key: &str) -> Result<(String, String), ErrorHint> {
+  match key.split_once('.') {
+    Some((name, namespace)) if !name.is_empty() && !namespace.is_empty() => {
+      Ok((name.to_owned(), namespace.to_owned()))
+    }
+    _ => Err(ErrorHint::Warning(format!(
+      "Invalid cargo key expect cargo_name.namespace got: {key}"
+    ))),
+  }
+}
+
+fn validate_target(
+  name: &str,
+  target: &DnsRecordTarget,
+  ipv6: bool,
+) -> Result<(), ErrorHint> {
+  match target {
+    DnsRecordTarget::Ip(ip) => {
+      let valid = match ipv6 {
+        true => ip.parse::<Ipv6Addr>().is_ok(),
+        false => ip.parse::<Ipv4Addr>().is_ok(),
+      };
+      if !valid {
+        return Err(ErrorHint::Warning(format!(
+          "Invalid dns record {name}: {ip} is not a valid ip address"
+        )));
+      }
+    }
+    DnsRecordTarget::Cargo(key) => {
+      split_cargo_key(key)?;
+    }
+  }
+  Ok(())
+}
+
+/// Parse and validate the config of a `DnsRecord` resource
+pub(crate) fn serialize_dns_record(
+  resource: &ResourcePartial,
+) -> Result<ResourceDnsRecord, ErrorHint> {
+  let dns_record =
+    serde_json::from_value::<ResourceDnsRecord>(resource.config.to_owned())
+      .map_err(|err| {
+        ErrorHint::Warning(format!(
+          "Unable to parse dns record {}: {err}",
+          resource.name
+        ))
+      })?;
+  let name = &dns_record.name;
+  if !is_domain(name) {
+    return Err(ErrorHint::Warning(format!(
+      "Invalid dns record {name}: not a valid domain"
+    )));
+  }
+  if dns_record.records.is_empty() {
+    return Err(ErrorHint::Warning(format!(
+      "Invalid dns record {name}: a record is required"
+    )));
+  }
+  for record in &dns_record.records {
+    match record {
+      DnsRecord::A(target) => validate_target(name, target, false)?,
+      DnsRecord::Aaaa(target) => validate_target(name, target, true)?,
+      DnsRecord::Cname(target) if !is_domain(target) => {
+        return Err(ErrorHint::Warning(format!(
+          "Invalid dns record {name}: {target} is not a valid domain"
+        )));
+      }
+      DnsRecord::Srv(srv) if !is_domain(&srv.target) => {
+        return Err(ErrorHint::Warning(format!(
+          "Invalid dns record {name}: {} is not a valid domain",
+          srv.target
+        )));
+      }
+      DnsRecord::Txt(values) => {
+        let invalid = values
+          .iter()
+          .find(|value| value.chars().any(|c| c == '"' || c.is_control()));
+        if let Some(value) = invalid {
+          return Err(ErrorHint::Warning(format!(
+            "Invalid dns record {name}: {value} contains a quote or a control character"
+          )));
+        }
+      }
+      _ => {}
+    }
+  }
+  Ok(dns_record)
+}
+
+/// Get the ip addresses of the instances of a cargo
+/// on the network of its namespace
+pub(crate) fn cargo_addresses(cargo: &CargoInspect, ipv6: bool) -> Vec<String> {
+  cargo
+    .instances
+    .iter()
+    .filter_map(|instance| {
+      let networks = instance
+        .container
+        .network_settings
+        .to_owned()
+        .unwrap_or_default()
+        .networks
+        .unwrap_or_default();
+      let network = networks.get(&cargo.namespace_name)?;
+      let ip_address = match ipv6 {
+        true => network.global_ipv6_address.to_owned(),
+        false => network.ip_address.to_owned(),
+      };
+      ip_address.filter(|ip_address| !ip_address.is_empty())
+    })
+    .collect()
+}
+
+/// Generate the entries of a record
+/// The addresses are the resolved ip of the target of A and AAAA records
+pub(crate) fn gen_record(
+  name: &str,
+  record: &DnsRecord,
+  addresses: &[String],
+) -> Vec<DnsEntry> {
+  match record {
+    DnsRecord::A(_) | DnsRecord::Aaaa(_) => addresses
+      .iter()
+      .map(|ip_address| DnsEntry::Address {
+        domain: name.to_owned(),
+        ip_address: ip_address.to_owned(),
+      })
+      .collect(),
+    DnsRecord::Cname(target) => vec![DnsEntry::Cname {
+      name: name.to_owned(),
+      target: target.to_owned(),
+    }],
+    DnsRecord::Txt(values) => vec![DnsEntry::Txt {
+      name: name.to_owned(),
+      values: values.to_owned(),
+    }],
+    DnsRecord::Srv(srv) => vec![DnsEntry::Srv {
+      name: name.to_owned(),
+      target: srv.target.to_owned(),
+      port: srv.port,
+      priority: srv.priority.unwrap_or_default(),
+      weight: srv.weight.unwrap_or_default(),
+    }],
+  }
+}
+
+/// Resolve the ip addresses of the target of an A or AAAA record
+async fn resolve_target(
+  client: &NanocldClient,
+  target: &DnsRecordTarget,
+  ipv6: bool,
+) -> Result<Vec<String>, ErrorHint> {
+  match target {
+    DnsRecordTarget::Ip(ip_address) => Ok(vec![ip_address.to_owned()]),
+    DnsRecordTarget::Cargo(key) => {
+      let (name, namespace) = split_cargo_key(key)?;
+      let cargo =
+        client
+          .inspect_cargo(&name, Some(namespace))
+          .await
+          .map_err(|err| {
+            ErrorHint::Warning(format!(
+              "unable to inspect cargo {key} got error: {err}"
+            ))
+          })?;
+      Ok(cargo_addresses(&cargo, ipv6))
+    }
+  }
+}
+
+/// Write the entries of a `DnsRecord` resource stored with the given key
+pub(crate) async fn render(
+  client: &NanocldClient,
+  backend: &dyn DnsBackend,
+  key: &str,
+  resource: &ResourcePartial,
+) -> Result<(), ErrorHint> {
+  let dns_record = serialize_dns_record(resource)?;
+  let mut entries = Vec::new();
+  for record in &dns_record.records {
+    let addresses = match record {
+      DnsRecord::A(target) => resolve_target(client, target, false).await?,
+      DnsRecord::Aaaa(target) => resolve_target(client, target, true).await?,
+      _ => Vec::new(),
+    };
+    entries.extend(gen_record(&dns_record.name, record, &addresses));
+  }
+  backend.write_entries(&entries_name(key), &entries)
+}
+
+/// Check if a record resolve its addresses from the given cargo
+pub(crate) fn targets_cargo(dns_record: &ResourceDnsRecord, key: &str) -> bool {
+  dns_record.records.iter().any(|record| match record {
+    DnsRecord::A(DnsRecordTarget::Cargo(target))
+    | DnsRecord::Aaaa(DnsRecordTarget::Cargo(target)) => target == key,
+    _ => false,
+  })
+}
+
+/// List the `DnsRecord` resources of every namespace
+pub(crate) async fn list(
+  client: &NanocldClient,
+) -> Result<Vec<Resource>, ErrorHint> {
+  let query = ResourceQuery {
+    kind: Some("DnsRecord".into()),
+    ..Default::default()
+  };
+  client.list_resource(Some(query)).await.map_err(|err| {
+    ErrorHint::Warning(format!("unable to list dns records got error: {err}"))
+  })
+}
+
+/// Render again the records resolving their addresses from a cargo
+/// so they follow its instances across redeploys
+/// Returns true if a records file has been updated
+pub(crate) async fn update_records_targeting(
+  client: &NanocldClient,
+  backend: &dyn DnsBackend,
+  cargo_key: &str,
+) -> Result<bool, ErrorHint> {
+  let mut updated = false;
+  for resource in list(client).await? {
+    let key = resource.key.to_owned();
+    let resource: ResourcePartial = resource.into();
+    let Ok(dns_record) = serialize_dns_record(&resource) else {
+      continue;
+    };
+    if !targets_cargo(&dns_record, cargo_key) {
+      continue;
+    }
+    println!(
+      "[INFO] Updating dns record {} targeting cargo : {cargo_key}",
+      dns_record.name
+    );
+    if let Err(err) = render(client, backend, &key, &resource).await {
+      eprintln!("{err}");
+    }
+    updated = true;
+  }
+  Ok(updated)
+}
+
+#[cfg(test)]
+mod tests {
+  use nanocld_client::stubs::dns::DnsSrvRecord;
+
+  use crate::dnsmasq::render as render_entries;
+
+  use super::*;
+
+  fn resource(config: serde_json::Value) -> ResourcePartial {
+    ResourcePartial {
+      name: "db".into(),
+      kind: "DnsRecord".into(),
+      version: "v0.1".into(),
+      namespace: None,
+      config,
+      owner_refs: None,
+      labels: None,
+    }
+  }
+
+  #[test]
+  fn validate() {
+    let valid = resource(serde_json::json!({
+      "Name": "db.internal",
+      "Records": [
+        { "A": { "Cargo": "db.global" } },
+        { "Aaaa": { "Ip": "fd00::2" } },
+        { "Txt": ["v=spf1 -all"] },
+        { "Srv": { "Target": "db.internal", "Port": 5432 } },
+      ]
+    }));
+    assert!(serialize_dns_record(&valid).is_ok());
+    let invalids = [
+      serde_json::json!({ "Name": "db.internal", "Records": [] }),
+      serde_json::json!({ "Name": "db internal", "Records": [{ "Cname": "db" }] }),
+      serde_json::json!({ "Name": "db.internal", "Records": [{ "A": { "Ip": "fd00::2" } }] }),
+      serde_json::json!({ "Name": "db.internal", "Records": [{ "A": { "Cargo": "db" } }] }),
+      serde_json::json!({ "Name": "db.internal", "Records": [{ "Txt": ["\"quoted\""] }] }),
+    ];
+    for invalid in invalids {
+      assert!(serialize_dns_record(&resource(invalid)).is_err());
+    }
+  }
+
+  #[test]
+  fn records() {
+    let addresses = ["10.0.0.2".to_owned(), "10.0.0.3".to_owned()];
+    let a = DnsRecord::A(DnsRecordTarget::Cargo("db.global".into()));
+    assert_eq!(
+      render_entries(&gen_record("db.internal", &a, &addresses)),
+      "address=/db.internal/10.0.0.2\naddress=/db.internal/10.0.0.3\n"
+    );
+    assert_eq!(render_entries(&gen_record("db.internal", &a, &[])), "");
+    let cname = DnsRecord::Cname("nanocl.db.global.local".into());
+    assert_eq!(
+      render_entries(&gen_record("db.internal", &cname, &[])),
+      "cname=db.internal,nanocl.db.global.local\n"
+    );
+    let txt = DnsRecord::Txt(vec!["a=1".into(), "b=2".into()]);
+    assert_eq!(
+      render_entries(&gen_record("db.internal", &txt, &[])),
+      "txt-record=db.internal,\"a=1\",\"b=2\"\n"
+    );
+    let srv = DnsRecord::Srv(DnsSrvRecord {
+      target: "db.internal".into(),
+      port: 5432,
+      priority: Some(10),
+      weight: None,
+    });
+    assert_eq!(
+      render_entries(&gen_record("_postgres._tcp.internal", &srv, &[])),
+      "srv-host=_postgres._tcp.internal,db.internal,5432,10,0\n"
+    );
+  }
+}
@@ -1,30 +1,180 @@
-use nanocld_client::NanocldClient;
 use ntex::web;
 
-use nanocld_client::stubs::cargo::CargoInspect;
+use nanocld_client::NanocldClient;
+use nanocld_client::stubs::resource::{ResourcePartial, ResourceRuleQuery};
 
-use crate::utils;
-use crate::dnsmasq::Dnsmasq;
+use crate::{utils, record, backend};
+use crate::backend::Backend;
 use crate::error::HttpError;
 
-#[web::post("/dns/entry")]
-async fn dns_entry(
-  dnsmasq: web::types::State<Dnsmasq>,
-  web::types::Json(payload): web::types::Json<CargoInspect>,
+#[web::put("/rules")]
+async fn apply_rule(
+  web::types::Query(qs): web::types::Query<ResourceRuleQuery>,
+  web::types::Json(payload): web::types::Json<ResourcePartial>,
+  backend: web::types::State<Backend>,
 ) -> Result<web::HttpResponse, HttpError> {
   let client = NanocldClient::connect_with_unix_default();
-  let domains = utils::gen_cargo_domains(&payload)?;
-  dnsmasq.generate_domains_file(&payload.key, &domains)?;
-  utils::restart_dns_service(&client).await?;
+  let key = qs.key_or(&payload.name).to_owned();
+  record::render(&client, &**backend, &key, &payload).await?;
+  utils::restart_dns_service(&client, &**backend).await?;
+  Ok(web::HttpResponse::Ok().json(&payload))
+}
+
+#[web::post("/rules/validate")]
+async fn validate_rule(
+  web::types::Json(payload): web::types::Json<ResourcePartial>,
+) -> Result<web::HttpResponse, HttpError> {
+  record::serialize_dns_record(&payload)?;
   Ok(web::HttpResponse::Ok().finish())
 }
 
-#[web::delete("/dns/entry/{key}")]
-async fn dns_entry_delete() -> Result<web::HttpResponse, HttpError> {
+#[web::delete("/rules/{name}")]
+async fn remove_rule(
+  path: web::types::Path<String>,
+  backend: web::types::State<Backend>,
+) -> Result<web::HttpResponse, HttpError> {
+  let client = NanocldClient::connect_with_unix_default();
+  let name = path.into_inner();
+  println!("[INFO] Removing dns record : {name}");
+  backend.remove_entries(&record::entries_name(&name))?;
+  utils::restart_dns_service(&client, &**backend).await?;
+  Ok(web::HttpResponse::Ok().finish())
+}
+
+#[web::get("/records")]
+async fn list_records(
+  backend: web::types::State<Backend>,
+) -> Result<web::HttpResponse, HttpError> {
+  let summaries = backend::list_summaries(&**backend)?;
+  Ok(web::HttpResponse::Ok().json(&summaries))
+}
+
+#[web::get("/resolve/{name}")]
+async fn resolve_name(
+  path: web::types::Path<String>,
+  backend: web::types::State<Backend>,
+) -> Result<web::HttpResponse, HttpError> {
+  let resolve = backend::resolve(&**backend, &path.into_inner())?;
+  Ok(web::HttpResponse::Ok().json(&resolve))
+}
+
+#[web::post("/sync")]
+async fn sync_records(
+  backend: web::types::State<Backend>,
+) -> Result<web::HttpResponse, HttpError> {
+  let client = NanocldClient::connect_with_unix_default();
+  utils::sync_daemon_state(&client, &**backend).await?;
   Ok(web::HttpResponse::Ok().finish())
 }
 
 pub fn configure(config: &mut web::ServiceConfig) {
-  config.service(dns_entry);
-  config.service(dns_entry_delete);
+  config.service(apply_rule);
+  config.service(validate_rule);
+  config.service(remove_rule);
+  config.service(list_records);
+  config.service(resolve_name);
+  config.service(sync_records);
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::Arc;
+
+  use ntex::http::StatusCode;
+  use nanocld_client::stubs::dns::{DnsEntrySummary, DnsResolve, DnsResolveAnswer};
+
+  use super::*;
+  use crate::backend::{DnsBackend, DnsEntry};
+
+  fn generate_server(backend: Backend) -> ntex::web::test::TestServer {
+    ntex::web::test::server(move || {
+      web::App::new().state(backend.clone()).configure(configure)
+    })
+  }
+
+  #[ntex::test]
+  async fn records() {
+    let dns = crate::native::new("127.0.0.1:0", None, &[]);
+    dns
+      .write_entries(
+        "app.global",
+        &[DnsEntry::Host {
+          name: "app.global.nanocl.internal".to_owned(),
+          ip_address: "10.0.0.2".to_owned(),
+        }],
+      )
+      .unwrap();
+    let backend: Backend = Arc::new(dns);
+    let srv = generate_server(backend.clone());
+
+    let mut res = srv.get("/records").send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let entries = res.json::<Vec<DnsEntrySummary>>().await.unwrap();
+    assert_eq!(
+      entries,
+      vec![DnsEntrySummary {
+        source: "app.global".to_owned(),
+        kind: "Host".to_owned(),
+        name: "app.global.nanocl.internal".to_owned(),
+        value: "10.0.0.2".to_owned(),
+      }]
+    );
+
+    let mut res = srv
+      .get("/resolve/App.Global.nanocl.internal")
+      .send()
+      .await
+      .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let resolve = res.json::<DnsResolve>().await.unwrap();
+    assert_eq!(resolve.name, "app.global.nanocl.internal");
+    assert_eq!(resolve.zone, "nanocl.internal");
+    assert_eq!(resolve.answer, DnsResolveAnswer::Local);
+    assert_eq!(resolve.entries, entries);
+
+    let mut res = srv
+      .get("/resolve/db.global.nanocl.internal")
+      .send()
+      .await
+      .unwrap();
+    let resolve = res.json::<DnsResolve>().await.unwrap();
+    assert_eq!(resolve.answer, DnsResolveAnswer::NotFound);
+
+    // The entries of the cargoes that don't exist are removed
+    let res = srv.post("/sync").send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(!backend
+      .list_entries()
+      .unwrap()
+      .contains(&"app.global".into()));
+  }
+
+  #[ntex::test]
+  async fn validate() {
+    let backend: Backend =
+      Arc::new(crate::native::new("127.0.0.1:0", None, &[]));
+    let srv = generate_server(backend);
+    let mut resource = serde_json::json!({
+      "Name": "dns-test",
+      "Kind": "DnsRecord",
+      "Version": "v0.1",
+      "Config": {
+        "Name": "dns-test.internal",
+        "Records": [{ "A": { "Ip": "10.0.0.9" } }]
+      }
+    });
+    let res = srv
+      .post("/rules/validate")
+      .send_json(&resource)
+      .await
+      .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    resource["Config"]["Name"] = serde_json::json!("dns test");
+    let res = srv
+      .post("/rules/validate")
+      .send_json(&resource)
+      .await
+      .unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+  }
 }
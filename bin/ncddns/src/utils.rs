@@ -1,7 +1,10 @@
+use nanocld_client::stubs::vm::VmInspect;
 use nanocld_client::stubs::cargo::CargoInspect;
+use nanocld_client::stubs::proxy::ResourceProxyRule;
 
-use crate::dnsmasq::Dnsmasq;
+use crate::zone;
 use crate::error::ErrorHint;
+use crate::backend::{DnsBackend, DnsEntry};
 
 /// Generate cargo domains records
 pub(crate) fn gen_cargo_domains(
@@ -56,37 +59,113 @@ pub(crate) fn gen_cargo_domains(
   Ok(domains)
 }
 
+/// Name of the cargo running dnsmasq in the system namespace
+const DNS_CARGO: &str = "ndns";
+
+/// Check if the dns entries of a cargo are managed by the controller
+/// The system cargoes are ignored so restarting dnsmasq doesn't loop
+pub(crate) fn is_managed(cargo: &CargoInspect) -> bool {
+  cargo.namespace_name != "system"
+}
+
+/// Write the dns entries of a cargo
+/// with its domain in the zone of its namespace
+/// and the srv records of the ports declared by its `ExposedPorts`
+/// or by the proxy rules targeting it
+/// The entries are removed when the cargo has no running instance
+pub(crate) fn update_cargo_domains(
+  cargo: &CargoInspect,
+  backend: &dyn DnsBackend,
+  proxy_rules: &[ResourceProxyRule],
+) -> Result<(), ErrorHint> {
+  let domains = gen_cargo_domains(cargo)?;
+  if domains.is_empty() {
+    return backend.remove_entries(&cargo.key);
+  }
+  let addresses = domains
+    .iter()
+    .map(|(_, ip_address)| ip_address.to_owned())
+    .collect::<Vec<_>>();
+  let domain = zone::cargo_domain(cargo, backend.zone());
+  let ports = zone::service_ports(cargo, proxy_rules);
+  println!(
+    "[DEBUG] Zone entry generated {domain} with {} ports",
+    ports.len()
+  );
+  let mut entries = domains
+    .into_iter()
+    .map(|(domain, ip_address)| DnsEntry::Address { domain, ip_address })
+    .collect::<Vec<_>>();
+  entries.extend(zone::gen_zone_records(&domain, &addresses, &ports));
+  backend.write_entries(&cargo.key, &entries)
+}
+
+/// Name of the group of entries of a vm
+/// The `.vm` suffix avoid conflicts with the cargo keys
+pub(crate) fn vm_entries_name(key: &str) -> String {
+  format!("{key}.vm")
+}
+
+/// Write the dns entries of a vm with its domain in the zone of its namespace
+/// The entries are removed when the vm isn't running
+pub(crate) fn update_vm_domains(
+  vm: &VmInspect,
+  backend: &dyn DnsBackend,
+) -> Result<(), ErrorHint> {
+  let name = vm_entries_name(&vm.key);
+  let addresses = zone::vm_addresses(vm);
+  if addresses.is_empty() {
+    return backend.remove_entries(&name);
+  }
+  let domain = zone::vm_domain(vm, backend.zone());
+  println!("[DEBUG] Entry generated {domain} for vm {}", vm.key);
+  backend
+    .write_entries(&name, &zone::gen_zone_records(&domain, &addresses, &[]))
+}
+
 /// Restart dnsmask to apply the new configuration
+/// Nothing is done when the backend apply the changes by itself
 pub(crate) async fn restart_dns_service(
   client: &nanocld_client::NanocldClient,
+  backend: &dyn DnsBackend,
 ) -> Result<(), ErrorHint> {
-  let cargo = "dns";
+  if !backend.need_restart() {
+    return Ok(());
+  }
   let namespace = Some(String::from("system"));
   client
-    .stop_cargo(cargo, namespace.to_owned())
+    .stop_cargo(DNS_CARGO, namespace.to_owned())
     .await
     .map_err(|err| {
       ErrorHint::Warning(format!("unable to stop dns cargo got error: {err}"))
     })?;
-  client.start_cargo(cargo, namespace).await.map_err(|err| {
-    ErrorHint::Warning(format!("unable to start dns cargo got error: {err}"))
-  })?;
+  client
+    .start_cargo(DNS_CARGO, namespace)
+    .await
+    .map_err(|err| {
+      ErrorHint::Warning(format!("unable to start dns cargo got error: {err}"))
+    })?;
   Ok(())
 }
 
-/// Get all cargos and create dns records
+/// Get all cargos, vms and `DnsRecord` resources and create dns records
+/// The records of the cargoes, vms and resources that doesn't exist anymore are removed
 /// This function is called at startup
 /// To ensure that no data is lost
 pub(crate) async fn sync_daemon_state(
   client: &nanocld_client::NanocldClient,
-  dnsmasq: &Dnsmasq,
+  backend: &dyn DnsBackend,
 ) -> Result<(), ErrorHint> {
   println!("[INFO] Syncing daemon state");
-  dnsmasq.clear_domains()?;
   let namespaces = client.list_namespace().await.map_err(|err| {
     ErrorHint::Warning(format!("unable to list namespaces got error: {err}"))
   })?;
+  let proxy_rules = zone::list_proxy_rules(client).await?;
+  let mut keys = Vec::new();
   for namespace in namespaces {
+    if namespace.name == "system" {
+      continue;
+    }
     let cargos = client
       .list_cargo(Some(namespace.name.to_owned()))
       .await
@@ -94,9 +173,6 @@ pub(crate) async fn sync_daemon_state(
         ErrorHint::Warning(format!("unable to list cargos got error: {err}"))
       })?;
     for cargo in cargos {
-      if cargo.name == "dns" && cargo.namespace_name == "system" {
-        continue;
-      }
       let cargo = client
         .inspect_cargo(&cargo.name, Some(namespace.name.to_owned()))
         .await
@@ -105,11 +181,44 @@ pub(crate) async fn sync_daemon_state(
             "unable to inspect cargo got error: {err}"
           ))
         })?;
-      let domains = gen_cargo_domains(&cargo)?;
-      dnsmasq.generate_domains_file(&cargo.key, &domains)?;
+      keys.push(cargo.key.to_owned());
+      if let Err(err) = update_cargo_domains(&cargo, backend, &proxy_rules) {
+        eprintln!("{err}");
+      }
     }
+    let vms = client
+      .list_vm(Some(namespace.name.to_owned()))
+      .await
+      .map_err(|err| {
+        ErrorHint::Warning(format!("unable to list vms got error: {err}"))
+      })?;
+    for vm in vms {
+      let vm = client
+        .inspect_vm(&vm.name, Some(namespace.name.to_owned()))
+        .await
+        .map_err(|err| {
+          ErrorHint::Warning(format!("unable to inspect vm got error: {err}"))
+        })?;
+      keys.push(vm_entries_name(&vm.key));
+      if let Err(err) = update_vm_domains(&vm, backend) {
+        eprintln!("{err}");
+      }
+    }
+  }
+  for resource in crate::record::list(client).await? {
+    keys.push(crate::record::entries_name(&resource.key));
+    let key = resource.key.to_owned();
+    let resource = resource.into();
+    if let Err(err) =
+      crate::record::render(client, backend, &key, &resource).await
+    {
+      eprintln!("{err}");
+    }
+  }
+  for name in backend.remove_stale_entries(&keys)? {
+    println!("[INFO] Removed stale dns entries : {name}");
   }
-  restart_dns_service(client).await?;
+  restart_dns_service(client, backend).await?;
   println!("[INFO] Daemon state synced");
   Ok(())
 }
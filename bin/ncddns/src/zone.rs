@@ -0,0 +1,338 @@
+use std::collections::BTreeSet;
+
+use nanocld_client::NanocldClient;
+use nanocld_client::stubs::vm::VmInspect;
+use nanocld_client::stubs::cargo::CargoInspect;
+use nanocld_client::stubs::resource::ResourceQuery;
+use nanocld_client::stubs::proxy::{
+  ResourceProxyRule, ProxyRule, LocationTarget, StreamTarget,
+  ProxyStreamProtocol,
+};
+
+use crate::error::ErrorHint;
+use crate::backend::DnsEntry;
+
+/// Zone used when none is given with `--zone`
+pub(crate) const DEFAULT_ZONE: &str = "nanocl.internal";
+
+/// A port published with a srv record as a port and a protocol
+pub(crate) type ServicePort = (u16, String);
+
+/// Domain of a cargo inside the zone of its namespace
+/// like `<cargo>.<namespace>.<zone>`
+pub(crate) fn cargo_domain(cargo: &CargoInspect, zone: &str) -> String {
+  format!("{}.{}.{zone}", cargo.name, cargo.namespace_name)
+}
+
+/// Whether a hostname is a dns label of a-z, 0-9 and - up to 63 characters
+/// It's written as is in the config of the backends
+fn is_hostname(hostname: &str) -> bool {
+  !hostname.is_empty()
+    && hostname.len() <= 63
+    && !hostname.starts_with('-')
+    && !hostname.ends_with('-')
+    && hostname
+      .chars()
+      .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+}
+
+/// Domain of a vm inside the zone of its namespace
+/// like `<hostname or name>.<namespace>.<zone>`
+/// The name is used when the hostname isn't a valid dns label
+pub(crate) fn vm_domain(vm: &VmInspect, zone: &str) -> String {
+  let name = vm
+    .config
+    .hostname
+    .as_deref()
+    .filter(|hostname| is_hostname(hostname))
+    .unwrap_or(&vm.name);
+  format!("{name}.{}.{zone}", vm.namespace_name)
+}
+
+/// Ip addresses of the running instances of a vm
+/// on the network of its namespace
+pub(crate) fn vm_addresses(vm: &VmInspect) -> Vec<String> {
+  vm.instances
+    .iter()
+    .filter(|instance| instance.state.as_deref() == Some("running"))
+    .filter_map(|instance| {
+      let networks = instance
+        .network_settings
+        .to_owned()
+        .unwrap_or_default()
+        .networks
+        .unwrap_or_default();
+      let ip_address = networks.get(&vm.namespace_name)?.ip_address.to_owned();
+      ip_address.filter(|ip_address| !ip_address.is_empty())
+    })
+    .collect()
+}
+
+/// Ports declared by the `ExposedPorts` of a cargo like `80/tcp`
+pub(crate) fn exposed_ports(cargo: &CargoInspect) -> Vec<ServicePort> {
+  cargo
+    .config
+    .container
+    .exposed_ports
+    .to_owned()
+    .unwrap_or_default()
+    .into_keys()
+    .filter_map(|exposed_port| {
+      let (port, protocol) = match exposed_port.split_once('/') {
+        Some((port, protocol)) => (port, protocol),
+        None => (exposed_port.as_str(), "tcp"),
+      };
+      let port = port.parse::<u16>().ok()?;
+      Some((port, protocol.to_lowercase()))
+    })
+    .collect()
+}
+
+/// Ports of a cargo targeted by a proxy rule
+pub(crate) fn proxy_rule_ports(
+  proxy_rule: &ResourceProxyRule,
+  cargo_key: &str,
+) -> Vec<ServicePort> {
+  let mut ports = Vec::new();
+  match &proxy_rule.rule {
+    ProxyRule::Http(http) => {
+      for location in &http.locations {
+        match &location.target {
+          LocationTarget::Cargo(target) if target.key == cargo_key => {
+            ports.push((target.port, "tcp".to_owned()));
+          }
+          LocationTarget::Split(splits) => {
+            for split in splits {
+              if split.cargo.key == cargo_key {
+                ports.push((split.cargo.port, "tcp".to_owned()));
+              }
+            }
+          }
+          _ => {}
+        }
+      }
+    }
+    ProxyRule::Stream(streams) => {
+      for stream in streams {
+        let protocol = match stream.protocol {
+          ProxyStreamProtocol::Tcp => "tcp",
+          ProxyStreamProtocol::Udp => "udp",
+        };
+        match &stream.target {
+          StreamTarget::Cargo(target) if target.key == cargo_key => {
+            ports.push((target.port, protocol.to_owned()));
+          }
+          StreamTarget::Sni(routes) => {
+            for route in routes {
+              if route.cargo.key == cargo_key {
+                ports.push((route.cargo.port, "tcp".to_owned()));
+              }
+            }
+          }
+          _ => {}
+        }
+      }
+    }
+  }
+  ports
+}
+
+/// Keys of the cargoes targeted by a proxy rule
+pub(crate) fn proxy_rule_targets(
+  proxy_rule: &ResourceProxyRule,
+) -> Vec<String> {
+  let mut keys = Vec::new();
+  match &proxy_rule.rule {
+    ProxyRule::Http(http) => {
+      for location in &http.locations {
+        match &location.target {
+          LocationTarget::Cargo(target) => keys.push(target.key.to_owned()),
+          LocationTarget::Split(splits) => {
+            keys.extend(splits.iter().map(|split| split.cargo.key.to_owned()))
+          }
+          _ => {}
+        }
+      }
+    }
+    ProxyRule::Stream(streams) => {
+      for stream in streams {
+        match &stream.target {
+          StreamTarget::Cargo(target) => keys.push(target.key.to_owned()),
+          StreamTarget::Sni(routes) => {
+            keys.extend(routes.iter().map(|route| route.cargo.key.to_owned()))
+          }
+          _ => {}
+        }
+      }
+    }
+  }
+  keys.sort();
+  keys.dedup();
+  keys
+}
+
+/// List the proxy rules of every namespace
+/// The rules that can't be parsed are ignored
+pub(crate) async fn list_proxy_rules(
+  client: &NanocldClient,
+) -> Result<Vec<ResourceProxyRule>, ErrorHint> {
+  let query = ResourceQuery {
+    kind: Some("ProxyRule".into()),
+    ..Default::default()
+  };
+  let resources = client.list_resource(Some(query)).await.map_err(|err| {
+    ErrorHint::Warning(format!("unable to list proxy rules got error: {err}"))
+  })?;
+  Ok(
+    resources
+      .into_iter()
+      .filter_map(|resource| serde_json::from_value(resource.config).ok())
+      .collect(),
+  )
+}
+
+/// Ports of a cargo to publish with srv records
+/// from its `ExposedPorts` and the proxy rules targeting it
+pub(crate) fn service_ports(
+  cargo: &CargoInspect,
+  proxy_rules: &[ResourceProxyRule],
+) -> Vec<ServicePort> {
+  let mut ports = exposed_ports(cargo).into_iter().collect::<BTreeSet<_>>();
+  for proxy_rule in proxy_rules {
+    ports.extend(proxy_rule_ports(proxy_rule, &cargo.key));
+  }
+  ports.into_iter().collect()
+}
+
+/// Generate the entries of the zone of a cargo
+/// One host entry per instance so the domain resolve to every instance
+/// and one srv entry per port like `_<port>._<protocol>.<domain>`
+pub(crate) fn gen_zone_records(
+  domain: &str,
+  addresses: &[String],
+  ports: &[ServicePort],
+) -> Vec<DnsEntry> {
+  let mut entries = addresses
+    .iter()
+    .map(|ip_address| DnsEntry::Host {
+      name: domain.to_owned(),
+      ip_address: ip_address.to_owned(),
+    })
+    .collect::<Vec<_>>();
+  if addresses.is_empty() {
+    return entries;
+  }
+  for (port, protocol) in ports {
+    entries.push(DnsEntry::Srv {
+      name: format!("_{port}._{protocol}.{domain}"),
+      target: domain.to_owned(),
+      port: *port,
+      priority: 0,
+      weight: 0,
+    });
+  }
+  entries
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn zone_records() {
+    let addresses = ["10.0.0.2".to_owned(), "10.0.0.3".to_owned()];
+    let ports = [(80, "tcp".to_owned()), (53, "udp".to_owned())];
+    assert_eq!(
+      crate::dnsmasq::render(&gen_zone_records(
+        "app.global.nanocl.internal",
+        &addresses,
+        &ports
+      )),
+      "host-record=app.global.nanocl.internal,10.0.0.2\n\
+       host-record=app.global.nanocl.internal,10.0.0.3\n\
+       srv-host=_80._tcp.app.global.nanocl.internal,app.global.nanocl.internal,80,0,0\n\
+       srv-host=_53._udp.app.global.nanocl.internal,app.global.nanocl.internal,53,0,0\n"
+    );
+    assert!(
+      gen_zone_records("app.global.nanocl.internal", &[], &ports).is_empty()
+    );
+  }
+
+  #[test]
+  fn vm_entries() {
+    let instances = serde_json::json!([
+      {
+        "State": "running",
+        "NetworkSettings": {
+          "Networks": { "global": { "IPAddress": "10.0.0.4" } }
+        }
+      },
+      {
+        "State": "exited",
+        "NetworkSettings": {
+          "Networks": { "global": { "IPAddress": "" } }
+        }
+      }
+    ]);
+    let mut vm = VmInspect {
+      key: "ubuntu.global".into(),
+      name: "ubuntu".into(),
+      namespace_name: "global".into(),
+      instances: serde_json::from_value(instances).unwrap(),
+      ..Default::default()
+    };
+    assert_eq!(vm_addresses(&vm), vec!["10.0.0.4".to_owned()]);
+    assert_eq!(
+      vm_domain(&vm, DEFAULT_ZONE),
+      "ubuntu.global.nanocl.internal"
+    );
+    vm.config.hostname = Some("builder".into());
+    assert_eq!(
+      vm_domain(&vm, DEFAULT_ZONE),
+      "builder.global.nanocl.internal"
+    );
+    vm.config.hostname = Some("builder\nhost-record=example.com".into());
+    assert_eq!(
+      vm_domain(&vm, DEFAULT_ZONE),
+      "ubuntu.global.nanocl.internal"
+    );
+    vm.config.hostname = Some("a".repeat(64));
+    assert_eq!(
+      vm_domain(&vm, DEFAULT_ZONE),
+      "ubuntu.global.nanocl.internal"
+    );
+  }
+
+  #[test]
+  fn proxy_rule_service_ports() {
+    let proxy_rule =
+      serde_json::from_value::<ResourceProxyRule>(serde_json::json!({
+        "Watch": ["app.global"],
+        "Rule": {
+          "Stream": [
+            {
+              "Network": "Public",
+              "Protocol": "Udp",
+              "Port": 5353,
+              "Target": { "Cargo": { "Key": "app.global", "Port": 53 } }
+            },
+            {
+              "Network": "Public",
+              "Protocol": "Tcp",
+              "Port": 8080,
+              "Target": { "Cargo": { "Key": "other.global", "Port": 80 } }
+            }
+          ]
+        }
+      }))
+      .unwrap();
+    assert_eq!(
+      proxy_rule_ports(&proxy_rule, "app.global"),
+      vec![(53, "udp".to_owned())]
+    );
+    assert_eq!(
+      proxy_rule_targets(&proxy_rule),
+      vec!["app.global".to_owned(), "other.global".to_owned()]
+    );
+  }
+}
@@ -0,0 +1,418 @@
+use std::fs;
+use std::path::Path;
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use instant_acme::{
+  Account, AccountCredentials, AuthorizationStatus, ChallengeType, Identifier,
+  NewAccount, NewOrder, Order, OrderStatus,
+};
+
+use nanocld_client::NanocldClient;
+use nanocld_client::stubs::resource::{ResourceQuery, ResourcePartial};
+use nanocld_client::stubs::proxy::{ProxyAcmeConfig, ProxyHttpSsl, ProxyRule};
+
+use crate::utils;
+use crate::nginx::Nginx;
+use crate::error::ErrorHint;
+
+/// Directory used when the proxy rule doesn't specify one
+const LETS_ENCRYPT_URL: &str = "https://acme-v02.api.letsencrypt.org/directory";
+/// Certificates are renewed when they expire in less than 30 days
+const RENEW_BEFORE: u64 = 30 * 24 * 60 * 60;
+/// Delay in seconds between two checks of the certificates
+const CHECK_INTERVAL: u64 = 60;
+/// Delay in seconds before retrying a failed order
+const RETRY_DELAY: u64 = 10 * 60;
+
+fn acme_error(ctx: &str, err: impl std::fmt::Display) -> ErrorHint {
+  ErrorHint::warning(9, format!("{ctx}: {err}"))
+}
+
+fn now() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .unwrap_or_default()
+    .as_secs()
+}
+
+/// Directory where the http-01 challenges are written
+pub(crate) fn challenge_dir(nginx: &Nginx) -> String {
+  format!("{}/acme/challenges", nginx.state_dir)
+}
+
+/// Path of the certificate chain and private key of a domain
+pub(crate) fn certificate_paths(
+  nginx: &Nginx,
+  domain: &str,
+) -> (String, String) {
+  let dir = format!("{}/acme/certs/{domain}", nginx.state_dir);
+  (format!("{dir}/fullchain.pem"), format!("{dir}/privkey.pem"))
+}
+
+/// Nginx location serving the http-01 challenges
+pub(crate) fn gen_challenge_location(nginx: &Nginx) -> String {
+  format!(
+    "
+  location ^~ /.well-known/acme-challenge/ {{
+    default_type text/plain;
+    alias {}/;
+  }}
+",
+    challenge_dir(nginx)
+  )
+}
+
+/// Number of seconds before the certificate of a domain expires
+/// Returns None when there is no readable certificate
+pub(crate) fn certificate_expires_in(
+  nginx: &Nginx,
+  domain: &str,
+) -> Option<u64> {
+  let (certificate, certificate_key) = certificate_paths(nginx, domain);
+  if !Path::new(&certificate_key).exists() {
+    return None;
+  }
+  let data = fs::read(certificate).ok()?;
+  let (_, pem) = x509_parser::pem::parse_x509_pem(&data).ok()?;
+  let cert = pem.parse_x509().ok()?;
+  let not_after = cert.validity().not_after.timestamp();
+  Some((not_after.max(0) as u64).saturating_sub(now()))
+}
+
+/// Check if a valid certificate exists for the domain
+pub(crate) fn has_certificate(nginx: &Nginx, domain: &str) -> bool {
+  certificate_expires_in(nginx, domain).unwrap_or_default() > 0
+}
+
+/// Check if a certificate must be requested or renewed for the domain
+pub(crate) fn needs_certificate(nginx: &Nginx, domain: &str) -> bool {
+  match certificate_expires_in(nginx, domain) {
+    Some(expires_in) => expires_in < RENEW_BEFORE,
+    None => true,
+  }
+}
+
+/// Load the account of the email for the directory or create it
+/// The credentials are stored in the state directory to be reused
+async fn load_account(
+  nginx: &Nginx,
+  config: &ProxyAcmeConfig,
+) -> Result<Account, ErrorHint> {
+  let directory_url = config
+    .directory_url
+    .clone()
+    .unwrap_or(LETS_ENCRYPT_URL.into());
+  let account_id = format!("{}-{directory_url}", config.email)
+    .replace(|c: char| !c.is_ascii_alphanumeric(), "-");
+  let path = format!("{}/acme/accounts/{account_id}.json", nginx.state_dir);
+  if let Ok(data) = fs::read_to_string(&path) {
+    let credentials = serde_json::from_str::<AccountCredentials>(&data)
+      .map_err(|err| acme_error("Unable to parse acme account", err))?;
+    return Account::from_credentials(credentials)
+      .map_err(|err| acme_error("Unable to load acme account", err));
+  }
+  let contact = format!("mailto:{}", config.email);
+  let (account, credentials) = Account::create(
+    &NewAccount {
+      contact: &[&contact],
+      terms_of_service_agreed: true,
+      only_return_existing: false,
+    },
+    &directory_url,
+    None,
+  )
+  .await
+  .map_err(|err| acme_error("Unable to create acme account", err))?;
+  let data = serde_json::to_string(&credentials)
+    .map_err(|err| acme_error("Unable to serialize acme account", err))?;
+  utils::write_private_file(&path, &data)
+    .map_err(|err| acme_error("Unable to save acme account", err))?;
+  Ok(account)
+}
+
+/// Wait for the order to be ready then download the certificate chain
+/// Returns the certificate chain and the private key
+async fn finalize_order(
+  order: &mut Order,
+  domain: &str,
+) -> Result<(String, String), ErrorHint> {
+  let mut tries = 0;
+  loop {
+    ntex::time::sleep(Duration::from_secs(2)).await;
+    let state = order
+      .refresh()
+      .await
+      .map_err(|err| acme_error("Unable to refresh acme order", err))?;
+    match state.status {
+      OrderStatus::Ready => break,
+      OrderStatus::Invalid => {
+        return Err(ErrorHint::warning(
+          9,
+          format!("Acme order for {domain} is invalid"),
+        ))
+      }
+      _ => {}
+    }
+    tries += 1;
+    if tries > 30 {
+      return Err(ErrorHint::warning(
+        9,
+        format!("Acme order for {domain} timed out"),
+      ));
+    }
+  }
+  let mut params = rcgen::CertificateParams::new(vec![domain.to_owned()]);
+  params.distinguished_name = rcgen::DistinguishedName::new();
+  let cert = rcgen::Certificate::from_params(params)
+    .map_err(|err| acme_error("Unable to generate private key", err))?;
+  let csr = cert
+    .serialize_request_der()
+    .map_err(|err| acme_error("Unable to generate csr", err))?;
+  order
+    .finalize(&csr)
+    .await
+    .map_err(|err| acme_error("Unable to finalize acme order", err))?;
+  let chain = loop {
+    match order
+      .certificate()
+      .await
+      .map_err(|err| acme_error("Unable to download certificate", err))?
+    {
+      Some(chain) => break chain,
+      None => ntex::time::sleep(Duration::from_secs(1)).await,
+    }
+  };
+  Ok((chain, cert.serialize_private_key_pem()))
+}
+
+/// Write the key authorization of a http-01 challenge served by nginx
+/// Returns the path of the written challenge
+pub(crate) fn write_challenge(
+  nginx: &Nginx,
+  token: &str,
+  key_authorization: &str,
+) -> Result<String, ErrorHint> {
+  let path = format!("{}/{token}", challenge_dir(nginx));
+  fs::write(&path, key_authorization)
+    .map_err(|err| acme_error("Unable to write acme challenge", err))?;
+  Ok(path)
+}
+
+/// Save the certificate chain and the private key issued for a domain
+/// The private key is only readable by the proxy
+pub(crate) fn save_certificate(
+  nginx: &Nginx,
+  domain: &str,
+  chain: &str,
+  private_key: &str,
+) -> Result<(), ErrorHint> {
+  let (certificate, certificate_key) = certificate_paths(nginx, domain);
+  let dir = format!("{}/acme/certs/{domain}", nginx.state_dir);
+  fs::create_dir_all(&dir)
+    .map_err(|err| acme_error("Unable to create certificate directory", err))?;
+  utils::write_private_file(&certificate_key, private_key)
+    .map_err(|err| acme_error("Unable to save private key", err))?;
+  fs::write(certificate, chain)
+    .map_err(|err| acme_error("Unable to save certificate", err))?;
+  Ok(())
+}
+
+/// Request a certificate for the domain
+/// The http-01 challenges are served by nginx from the challenge directory
+pub(crate) async fn order_certificate(
+  nginx: &Nginx,
+  domain: &str,
+  config: &ProxyAcmeConfig,
+) -> Result<(), ErrorHint> {
+  let account = load_account(nginx, config).await?;
+  let identifiers = [Identifier::Dns(domain.to_owned())];
+  let mut order = account
+    .new_order(&NewOrder {
+      identifiers: &identifiers,
+    })
+    .await
+    .map_err(|err| acme_error("Unable to create acme order", err))?;
+  let authorizations = order
+    .authorizations()
+    .await
+    .map_err(|err| acme_error("Unable to get acme authorizations", err))?;
+  let mut challenges = Vec::new();
+  for authz in &authorizations {
+    if let AuthorizationStatus::Valid = authz.status {
+      continue;
+    }
+    let challenge = authz
+      .challenges
+      .iter()
+      .find(|challenge| challenge.r#type == ChallengeType::Http01)
+      .ok_or(ErrorHint::warning(
+        9,
+        format!("No http-01 challenge available for {domain}"),
+      ))?;
+    let path = write_challenge(
+      nginx,
+      &challenge.token,
+      order.key_authorization(challenge).as_str(),
+    )?;
+    challenges.push(path);
+    order
+      .set_challenge_ready(&challenge.url)
+      .await
+      .map_err(|err| acme_error("Unable to validate acme challenge", err))?;
+  }
+  let res = finalize_order(&mut order, domain).await;
+  for path in challenges {
+    let _ = fs::remove_file(path);
+  }
+  let (chain, private_key) = res?;
+  save_certificate(nginx, domain, &chain, &private_key)?;
+  log::info!("Certificate for {domain} issued");
+  Ok(())
+}
+
+/// Request or renew the certificates of every ProxyRule using acme
+/// The nginx configuration is regenerated and reloaded once a certificate
+/// is issued
+async fn renew(
+  client: &NanocldClient,
+  nginx: &Nginx,
+  failures: &mut HashMap<String, u64>,
+) -> Result<(), ErrorHint> {
+  let query = ResourceQuery {
+    kind: Some("ProxyRule".into()),
+    ..Default::default()
+  };
+  let resources = client.list_resource(Some(query)).await.map_err(|err| {
+    ErrorHint::warning(
+      8,
+      format!("Unable to list resources from nanocl: {err}"),
+    )
+  })?;
+  for resource in resources {
+    let key = resource.key.to_owned();
+    let resource: ResourcePartial = resource.into();
+    let Ok(proxy_rule) = utils::serialize_proxy_rule(&resource) else {
+      continue;
+    };
+    let ProxyRule::Http(rule) = &proxy_rule.rule else {
+      continue;
+    };
+    let (Some(ProxyHttpSsl::Acme(ssl)), Some(domain)) =
+      (&rule.ssl, &rule.domain)
+    else {
+      continue;
+    };
+    if !needs_certificate(nginx, domain) {
+      continue;
+    }
+    if let Some(failed_at) = failures.get(domain) {
+      if now() - failed_at < RETRY_DELAY {
+        continue;
+      }
+    }
+    log::info!("Requesting certificate for {domain}");
+    if let Err(err) = order_certificate(nginx, domain, &ssl.acme).await {
+      err.print();
+      failures.insert(domain.to_owned(), now());
+      continue;
+    }
+    failures.remove(domain);
+    if let Err(err) =
+      utils::create_resource_conf(client, nginx, &key, &resource).await
+    {
+      err.print();
+      continue;
+    }
+    if let Err(err) = utils::reload_config(client).await {
+      err.print();
+    }
+  }
+  Ok(())
+}
+
+/// Loop checking the certificates of the proxy rules using acme
+pub(crate) async fn run(client: NanocldClient, nginx: Nginx) {
+  let mut failures = HashMap::new();
+  loop {
+    if let Err(err) = renew(&client, &nginx, &mut failures).await {
+      err.print();
+    }
+    ntex::time::sleep(Duration::from_secs(CHECK_INTERVAL)).await;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use crate::utils::tests::*;
+
+  #[test]
+  fn challenge_location() {
+    let nginx = Nginx::new("/tmp/nginx", "/tmp/nginx/state");
+    let location = gen_challenge_location(&nginx);
+    assert!(location.contains("location ^~ /.well-known/acme-challenge/"));
+    assert!(location.contains("alias /tmp/nginx/state/acme/challenges/;"));
+  }
+
+  #[test]
+  fn missing_certificate() {
+    let nginx = Nginx::new("/tmp/nginx", "/tmp/nginx/state");
+    assert!(!has_certificate(&nginx, "missing.nanocl.internal"));
+    assert!(needs_certificate(&nginx, "missing.nanocl.internal"));
+  }
+
+  #[test]
+  fn saved_certificate() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let nginx = Nginx::new("/tmp/nginx", "/tmp/nginx/acme-test");
+    nginx.ensure().unwrap();
+    let domain = "saved.nanocl.internal";
+    let path = write_challenge(&nginx, "token", "token.thumbprint").unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "token.thumbprint");
+    assert!(path.starts_with(&challenge_dir(&nginx)));
+    let cert =
+      rcgen::generate_simple_self_signed(vec![domain.to_owned()]).unwrap();
+    save_certificate(
+      &nginx,
+      domain,
+      &cert.serialize_pem().unwrap(),
+      &cert.serialize_private_key_pem(),
+    )
+    .unwrap();
+    assert!(has_certificate(&nginx, domain));
+    assert!(!needs_certificate(&nginx, domain));
+    let (certificate, certificate_key) = certificate_paths(&nginx, domain);
+    assert_eq!(
+      certificate,
+      "/tmp/nginx/acme-test/acme/certs/saved.nanocl.internal/fullchain.pem"
+    );
+    let mode = fs::metadata(certificate_key).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+  }
+
+  /// Order a certificate from a local pebble server
+  /// Pebble must run with `PEBBLE_VA_ALWAYS_VALID=1` and its root
+  /// certificate must be trusted using `SSL_CERT_FILE`:
+  /// ACME_DIRECTORY_URL=https://localhost:14000/dir cargo test -- --ignored
+  #[ntex::test]
+  #[ignore]
+  async fn order_pebble() {
+    before();
+    let directory_url = std::env::var("ACME_DIRECTORY_URL")
+      .unwrap_or("https://localhost:14000/dir".into());
+    let nginx = Nginx::new("/tmp/nginx", "/tmp/nginx/pebble");
+    nginx.ensure().unwrap();
+    let config = ProxyAcmeConfig {
+      email: "admin@nanocl.internal".into(),
+      directory_url: Some(directory_url),
+    };
+    order_certificate(&nginx, "pebble.nanocl.internal", &config)
+      .await
+      .unwrap();
+    assert!(has_certificate(&nginx, "pebble.nanocl.internal"));
+    assert!(!needs_certificate(&nginx, "pebble.nanocl.internal"));
+  }
+}
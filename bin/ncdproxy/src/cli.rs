@@ -5,6 +5,9 @@ pub(crate) struct Cli {
   /// Path to nginx config directory
   #[clap(long)]
   pub(crate) conf_dir: Option<String>,
+  /// Path to the state directory where certificates are stored
+  #[clap(long)]
+  pub(crate) state_dir: Option<String>,
 }
 
 #[cfg(test)]
@@ -15,8 +18,15 @@ mod tests {
   fn parse() {
     let args = Cli::parse_from(["nanocl-ncdproxy", "--conf-dir", "/etc/nginx"]);
     assert_eq!(args.conf_dir, Some("/etc/nginx".into()));
+    let args = Cli::parse_from([
+      "nanocl-ncdproxy",
+      "--state-dir",
+      "/var/lib/nanocl/proxy",
+    ]);
+    assert_eq!(args.state_dir, Some("/var/lib/nanocl/proxy".into()));
     let args = Cli::parse_from(["nanocl-ncdproxy"]);
     assert_eq!(args.conf_dir, None);
+    assert_eq!(args.state_dir, None);
     let _ = Cli::try_parse();
   }
 }
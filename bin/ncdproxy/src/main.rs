@@ -1,8 +1,11 @@
 mod cli;
+mod acme;
 mod error;
 mod nginx;
+mod middleware;
 mod utils;
 mod service;
+mod reconciler;
 mod network_log;
 
 use clap::Parser;
@@ -30,11 +33,15 @@ async fn boot(cli: &cli::Cli) -> nginx::Nginx {
 
   log::info!("nanocl-ncdproxy v{}", env!("CARGO_PKG_VERSION"));
 
-  let nginx = nginx::new(&cli.conf_dir.clone().unwrap_or("/etc/nginx".into()));
+  let nginx = nginx::new(
+    &cli.conf_dir.clone().unwrap_or("/etc/nginx".into()),
+    &cli
+      .state_dir
+      .clone()
+      .unwrap_or("/var/lib/nanocl/proxy".into()),
+  );
   let client = NanocldClient::connect_with_unix_default();
 
-  network_log::run();
-
   if let Err(err) = nginx.ensure() {
     err.exit();
   }
@@ -43,8 +50,9 @@ async fn boot(cli: &cli::Cli) -> nginx::Nginx {
     err.exit();
   }
 
-  if let Err(err) = utils::sync_resources(&client, &nginx).await {
-    err.exit();
+  // The proxy may not be ready yet, the reconciler will retry
+  if let Err(err) = reconciler::reconcile(&client, &nginx).await {
+    err.print();
   }
 
   nginx
@@ -56,95 +64,28 @@ async fn on_event(
   event: Event,
 ) -> Result<(), error::ErrorHint> {
   match event {
-    Event::CargoStarted(ev) => {
-      let resources = utils::list_resource_by_cargo(
-        &ev.name,
-        Some(ev.namespace_name),
-        &client,
-      )
-      .await?;
-      for resource in resources {
-        let resource: ResourcePartial = resource.into();
-        if let Err(err) =
-          utils::create_resource_conf(&client, &nginx, &resource).await
-        {
-          err.print();
-        }
-      }
-      utils::reload_config(&client).await?;
-    }
-    Event::CargoStopped(ev) => {
-      let resources = utils::list_resource_by_cargo(
-        &ev.name,
-        Some(ev.namespace_name),
-        &client,
-      )
-      .await?;
-      for resource in resources {
-        let resource: ResourcePartial = resource.into();
-        let proxy_rule = utils::serialize_proxy_rule(&resource)?;
-        if let Err(err) = nginx
-          .delete_conf_file(&resource.name, &proxy_rule.rule.into())
-          .await
-        {
-          err.print();
-        }
-      }
-      utils::reload_config(&client).await?;
-    }
-    Event::CargoDeleted(ev) => {
-      let resources = utils::list_resource_by_cargo(
-        &ev.name,
-        Some(ev.namespace_name),
-        &client,
-      )
-      .await?;
-      for resource in resources {
-        let resource: ResourcePartial = resource.into();
-        let proxy_rule = utils::serialize_proxy_rule(&resource)?;
-        if let Err(err) = nginx
-          .delete_conf_file(&resource.name, &proxy_rule.rule.into())
-          .await
-        {
-          err.print();
-        }
-      }
-      utils::reload_config(&client).await?;
+    Event::CargoStarted(_)
+    | Event::CargoStopped(_)
+    | Event::CargoPatched(_)
+    | Event::CargoDeleted(_)
+    | Event::VmStarted(_)
+    | Event::VmStopped(_)
+    | Event::VmPatched(_)
+    | Event::VmDeleted(_) => {
+      reconciler::reconcile(&client, &nginx).await?;
     }
-    Event::ResourceCreated(ev) => {
-      if ev.kind.as_str() != "ProxyRule" {
-        return Ok(());
-      }
-      let resource: ResourcePartial = ev.as_ref().clone().into();
-      if let Err(err) =
-        utils::create_resource_conf(&client, &nginx, &resource).await
-      {
-        err.print();
-      }
-      utils::reload_config(&client).await?;
-    }
-    Event::ResourcePatched(ev) => {
-      if ev.kind.as_str() != "ProxyRule" {
-        return Ok(());
-      }
+    Event::ResourceCreated(ev) | Event::ResourcePatched(ev)
+      if matches!(ev.kind.as_str(), "Certificate" | "Secret") =>
+    {
       let resource: ResourcePartial = ev.as_ref().clone().into();
-      if let Err(err) =
-        utils::create_resource_conf(&client, &nginx, &resource).await
-      {
-        err.print();
-      }
-      utils::reload_config(&client).await?;
+      utils::update_rules_using(&resource, &client, &nginx).await?;
     }
-    Event::ResourceDeleted(ev) => {
-      if ev.kind.as_str() != "ProxyRule" {
-        return Ok(());
-      }
-      let resource: ResourcePartial = ev.as_ref().clone().into();
-      let proxy_rule = utils::serialize_proxy_rule(&resource)?;
-      let _ = nginx
-        .delete_conf_file(&ev.name, &proxy_rule.rule.into())
-        .await;
-      utils::reload_config(&client).await?;
+    Event::ResourceCreated(ev)
+    | Event::ResourcePatched(ev)
+    | Event::ResourceDeleted(ev)
+      if ev.kind.as_str() == "ProxyRule" =>
+    {
+      reconciler::reconcile(&client, &nginx).await?;
     }
     // Ignore other events
     _ => {}
@@ -204,6 +145,29 @@ fn main() -> std::io::Result<()> {
       });
     });
 
+    let n = nginx.clone();
+    rt::Arbiter::new().exec_fn(move || {
+      let client = NanocldClient::connect_with_unix_default();
+      ntex::rt::spawn(async move {
+        acme::run(client, n).await;
+      });
+    });
+
+    let n = nginx.clone();
+    rt::Arbiter::new().exec_fn(move || {
+      let client = NanocldClient::connect_with_unix_default();
+      ntex::rt::spawn(async move {
+        reconciler::run(client, n).await;
+      });
+    });
+
+    rt::Arbiter::new().exec_fn(move || {
+      let client = NanocldClient::connect_with_unix_default();
+      ntex::rt::spawn(async move {
+        network_log::run(client).await;
+      });
+    });
+
     let mut server = HttpServer::new(move || {
       App::new()
         .state(nginx.clone())
@@ -227,8 +191,13 @@ mod tests {
 
   #[ntex::test]
   async fn boot() {
-    let cli =
-      super::cli::Cli::parse_from(["ncdproxy", "--conf-dir", "/tmp/nginx"]);
+    let cli = super::cli::Cli::parse_from([
+      "ncdproxy",
+      "--conf-dir",
+      "/tmp/nginx",
+      "--state-dir",
+      "/tmp/nginx/state",
+    ]);
 
     super::boot(&cli).await;
   }
@@ -0,0 +1,608 @@
+use std::net::IpAddr;
+
+use ring::digest;
+use ring::rand::{SecureRandom, SystemRandom};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+
+use nanocld_client::NanocldClient;
+use nanocld_client::stubs::proxy::{
+  ProxyHttpLocation, ProxyHeaders, ProxyBasicAuth, ProxyRateLimit, ProxyCache,
+};
+
+use crate::nginx::Nginx;
+use crate::error::ErrorHint;
+use crate::utils::is_unit;
+
+fn invalid(path: &str, msg: String) -> ErrorHint {
+  ErrorHint::warning(4, format!("Invalid location {path}: {msg}"))
+}
+
+fn is_name(value: &str) -> bool {
+  !value.is_empty()
+    && value
+      .chars()
+      .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Values are rendered between double quotes
+fn is_quotable(value: &str) -> bool {
+  !value.contains(['"', '\\', '\n', '\r'])
+}
+
+fn is_cidr(value: &str) -> bool {
+  let (addr, prefix) = match value.split_once('/') {
+    Some((addr, prefix)) => (addr, Some(prefix)),
+    None => (value, None),
+  };
+  let Ok(addr) = addr.parse::<IpAddr>() else {
+    return false;
+  };
+  let max = if addr.is_ipv4() { 32 } else { 128 };
+  match prefix {
+    Some(prefix) => prefix.parse::<u8>().map_or(false, |p| p <= max),
+    None => true,
+  }
+}
+
+fn validate_headers(
+  path: &str,
+  headers: &ProxyHeaders,
+) -> Result<(), ErrorHint> {
+  for (name, value) in headers.set.clone().unwrap_or_default() {
+    if !is_name(&name) {
+      return Err(invalid(path, format!("invalid header name {name}")));
+    }
+    if !is_quotable(&value) {
+      return Err(invalid(path, format!("invalid value for header {name}")));
+    }
+  }
+  for name in headers.remove.clone().unwrap_or_default() {
+    if !is_name(&name) {
+      return Err(invalid(path, format!("invalid header name {name}")));
+    }
+  }
+  Ok(())
+}
+
+/// Validate the options of a location before they are rendered
+pub(crate) fn validate_location(
+  location: &ProxyHttpLocation,
+) -> Result<(), ErrorHint> {
+  let path = &location.path;
+  if let Some(headers) = &location.request_headers {
+    validate_headers(path, headers)?;
+  }
+  if let Some(headers) = &location.response_headers {
+    validate_headers(path, headers)?;
+  }
+  if let Some(basic_auth) = &location.basic_auth {
+    if !is_name(&basic_auth.secret) {
+      return Err(invalid(
+        path,
+        format!("invalid secret name {}", basic_auth.secret),
+      ));
+    }
+    if !is_quotable(basic_auth.realm.as_deref().unwrap_or_default()) {
+      return Err(invalid(path, "invalid basic auth realm".into()));
+    }
+  }
+  if let Some(rate_limit) = &location.rate_limit {
+    let valid_rate = match rate_limit.rate.split_once("r/") {
+      Some((count, unit)) => is_unit(count, &[]) && ["s", "m"].contains(&unit),
+      None => false,
+    };
+    if !valid_rate {
+      return Err(invalid(
+        path,
+        format!("invalid rate {} expect like 10r/s", rate_limit.rate),
+      ));
+    }
+    let key = rate_limit.key.as_deref().unwrap_or_default();
+    if !is_quotable(key) || key.contains(char::is_whitespace) {
+      return Err(invalid(path, format!("invalid rate limit key {key}")));
+    }
+  }
+  let cidrs = [&location.allow, &location.deny];
+  for cidr in cidrs.into_iter().flatten().flatten() {
+    if !is_cidr(cidr) {
+      return Err(invalid(path, format!("invalid address or cidr {cidr}")));
+    }
+  }
+  if let Some(size) = &location.client_max_body_size {
+    if !is_unit(size, &["k", "K", "m", "M", "g", "G"]) {
+      return Err(invalid(path, format!("invalid body size {size}")));
+    }
+  }
+  if let Some(timeouts) = &location.timeouts {
+    let timeouts = [&timeouts.connect, &timeouts.read, &timeouts.send];
+    for timeout in timeouts.into_iter().flatten() {
+      if !is_unit(timeout, &["ms", "s", "m", "h"]) {
+        return Err(invalid(path, format!("invalid timeout {timeout}")));
+      }
+    }
+  }
+  if let Some(cache) = &location.cache {
+    validate_cache(path, cache)?;
+  }
+  Ok(())
+}
+
+fn validate_cache(path: &str, cache: &ProxyCache) -> Result<(), ErrorHint> {
+  let sizes = [&cache.zone_size, &cache.max_size];
+  for size in sizes.into_iter().flatten() {
+    if !is_unit(size, &["k", "K", "m", "M", "g", "G"]) {
+      return Err(invalid(path, format!("invalid cache size {size}")));
+    }
+  }
+  for valid in cache.valid.clone().unwrap_or_default() {
+    if !is_unit(&valid.ttl, &["s", "m", "h", "d"]) {
+      return Err(invalid(path, format!("invalid cache ttl {}", valid.ttl)));
+    }
+    for status in valid.status.unwrap_or_default() {
+      let is_status = status.len() == 3 && is_unit(&status, &[]);
+      if status != "any" && !is_status {
+        return Err(invalid(path, format!("invalid cache status {status}")));
+      }
+    }
+  }
+  for bypass in cache.bypass.clone().unwrap_or_default() {
+    let name = bypass.trim_start_matches('$');
+    if !bypass.starts_with('$') || !is_name(name) {
+      return Err(invalid(path, format!("invalid cache bypass {bypass}")));
+    }
+  }
+  let key = cache.key.as_deref().unwrap_or_default();
+  if !is_quotable(key) {
+    return Err(invalid(path, format!("invalid cache key {key}")));
+  }
+  Ok(())
+}
+
+/// Name of the `limit_req_zone` of a location
+fn rate_limit_zone(key: &str, index: usize) -> String {
+  let key = key.replace(|c: char| !c.is_ascii_alphanumeric(), "_");
+  format!("nanocl_{key}_{index}")
+}
+
+/// Name of the `proxy_cache` zone of a location
+fn cache_zone(key: &str, index: usize) -> String {
+  let key = key.replace(|c: char| !c.is_ascii_alphanumeric(), "_");
+  format!("nanocl_cache_{key}_{index}")
+}
+
+/// Directory where the responses of the locations of a rule are cached
+pub(crate) fn cache_dir(nginx: &Nginx, key: &str) -> String {
+  format!("{}/cache/{key}", nginx.state_dir)
+}
+
+/// Generate the `proxy_cache_path` of the locations with a cache
+/// They are written in the http context outside of the server block
+pub(crate) fn gen_cache_paths(
+  key: &str,
+  locations: &[ProxyHttpLocation],
+  nginx: &Nginx,
+) -> String {
+  locations
+    .iter()
+    .enumerate()
+    .filter_map(|(index, location)| {
+      let cache = location.cache.as_ref()?;
+      let dir = cache_dir(nginx, key);
+      let zone = cache_zone(key, index);
+      let zone_size = cache.zone_size.clone().unwrap_or("10m".into());
+      let max_size = match &cache.max_size {
+        Some(max_size) => format!(" max_size={max_size}"),
+        None => String::default(),
+      };
+      Some(format!(
+        "proxy_cache_path {dir}/{index} levels=1:2 keys_zone={zone}:{zone_size}{max_size};\n"
+      ))
+    })
+    .collect()
+}
+
+/// Remove the cached responses of a rule
+pub(crate) fn purge_cache(nginx: &Nginx, key: &str) -> Result<(), ErrorHint> {
+  let dir = cache_dir(nginx, key);
+  if !std::path::Path::new(&dir).exists() {
+    return Ok(());
+  }
+  std::fs::remove_dir_all(&dir).map_err(|err| {
+    ErrorHint::error(13, format!("Unable to purge cache {dir}: {err}"))
+  })
+}
+
+fn gen_cache(zone: &str, cache: &ProxyCache) -> String {
+  let mut options = format!("    proxy_cache {zone};\n");
+  if let Some(key) = &cache.key {
+    options += &format!("    proxy_cache_key \"{key}\";\n");
+  }
+  for valid in cache.valid.clone().unwrap_or_default() {
+    let status = match valid.status {
+      Some(status) if !status.is_empty() => format!("{} ", status.join(" ")),
+      _ => String::default(),
+    };
+    options += &format!("    proxy_cache_valid {status}{};\n", valid.ttl);
+  }
+  if let Some(bypass) = &cache.bypass {
+    let bypass = bypass.join(" ");
+    options += &format!("    proxy_cache_bypass {bypass};\n");
+    options += &format!("    proxy_no_cache {bypass};\n");
+  }
+  options += "    add_header X-Cache-Status $upstream_cache_status;\n";
+  options
+}
+
+/// Generate the `limit_req_zone` of the locations with a rate limit
+/// They are written in the http context outside of the server block
+pub(crate) fn gen_rate_limit_zones(
+  key: &str,
+  locations: &[ProxyHttpLocation],
+) -> String {
+  locations
+    .iter()
+    .enumerate()
+    .filter_map(|(index, location)| {
+      let rate_limit = location.rate_limit.as_ref()?;
+      let zone = rate_limit_zone(key, index);
+      let rate = &rate_limit.rate;
+      let limit_key = rate_limit
+        .key
+        .clone()
+        .unwrap_or("$binary_remote_addr".into());
+      Some(format!(
+        "limit_req_zone {limit_key} zone={zone}:10m rate={rate};\n"
+      ))
+    })
+    .collect()
+}
+
+fn sorted_headers(headers: &ProxyHeaders) -> Vec<(String, String)> {
+  let mut set = headers
+    .set
+    .clone()
+    .unwrap_or_default()
+    .into_iter()
+    .collect::<Vec<_>>();
+  set.sort();
+  set
+}
+
+fn gen_rate_limit(zone: &str, rate_limit: &ProxyRateLimit) -> String {
+  let mut limit = format!("    limit_req zone={zone}");
+  if let Some(burst) = rate_limit.burst {
+    limit += &format!(" burst={burst}");
+  }
+  if rate_limit.nodelay.unwrap_or_default() {
+    limit += " nodelay";
+  }
+  limit + ";\n"
+}
+
+fn ssha(password: &str, salt: &[u8]) -> String {
+  let mut ctx = digest::Context::new(&digest::SHA1_FOR_LEGACY_USE_ONLY);
+  ctx.update(password.as_bytes());
+  ctx.update(salt);
+  let hash = [ctx.finish().as_ref(), salt].concat();
+  format!("{{SSHA}}{}", STANDARD.encode(hash))
+}
+
+/// Hash a password for a htpasswd file with the `{SSHA}` scheme
+fn hash_password(password: &str) -> Result<String, ErrorHint> {
+  let mut salt = [0; 8];
+  SystemRandom::new().fill(&mut salt).map_err(|_| {
+    ErrorHint::error(11, "Unable to generate password salt".into())
+  })?;
+  Ok(ssha(password, &salt))
+}
+
+/// Check if a `{SSHA}` hash matches a password
+fn verify_password(password: &str, hash: &str) -> bool {
+  let Some(encoded) = hash.strip_prefix("{SSHA}") else {
+    return false;
+  };
+  let Ok(decoded) = STANDARD.decode(encoded) else {
+    return false;
+  };
+  if decoded.len() <= 20 {
+    return false;
+  }
+  ssha(password, &decoded[20..]) == hash
+}
+
+/// Write the htpasswd file of a basic auth from its `Secret` resource
+async fn write_htpasswd(
+  basic_auth: &ProxyBasicAuth,
+  namespace: &Option<String>,
+  client: &NanocldClient,
+  nginx: &Nginx,
+) -> Result<String, ErrorHint> {
+  let name = &basic_auth.secret;
+  let secret = client
+    .inspect_resource_secret(name, namespace.clone())
+    .await
+    .map_err(|err| {
+      ErrorHint::warning(11, format!("Unable to get secret {name}: {err}"))
+    })?;
+  let file = match namespace {
+    Some(namespace) => format!("auth/{name}.{namespace}.htpasswd"),
+    None => format!("auth/{name}.htpasswd"),
+  };
+  // The hashes of the current file are kept when the passwords didn't change
+  // so the file is only updated when needed
+  let current = std::fs::read_to_string(format!("{}/{file}", nginx.state_dir))
+    .unwrap_or_default();
+  let mut users = secret.data.into_iter().collect::<Vec<_>>();
+  users.sort();
+  let mut content = String::new();
+  for (username, password) in users {
+    let hash = current
+      .lines()
+      .filter_map(|line| line.split_once(':'))
+      .find(|(user, hash)| {
+        *user == username && verify_password(&password, hash)
+      })
+      .map(|(_, hash)| hash.to_owned());
+    let hash = match hash {
+      Some(hash) => hash,
+      None => hash_password(&password)?,
+    };
+    content += &format!("{username}:{hash}\n");
+  }
+  let dir = format!("{}/auth", nginx.files_dir);
+  std::fs::create_dir_all(&dir).map_err(|err| {
+    ErrorHint::error(
+      11,
+      format!("Unable to create directory {dir} got error : {err}"),
+    )
+  })?;
+  let path = format!("{}/{file}", nginx.files_dir);
+  std::fs::write(&path, content).map_err(|err| {
+    ErrorHint::error(11, format!("Unable to write {path}: {err}"))
+  })?;
+  Ok(path)
+}
+
+/// Generate the directives of the options of a location
+/// `key` and `index` identify the rate limit and cache zones of the location
+pub(crate) async fn gen_location_options(
+  key: &str,
+  index: usize,
+  location: &ProxyHttpLocation,
+  namespace: &Option<String>,
+  client: &NanocldClient,
+  nginx: &Nginx,
+) -> Result<String, ErrorHint> {
+  validate_location(location)?;
+  let mut options = String::new();
+  for cidr in location.deny.clone().unwrap_or_default() {
+    options += &format!("    deny {cidr};\n");
+  }
+  if let Some(allow) = &location.allow {
+    for cidr in allow {
+      options += &format!("    allow {cidr};\n");
+    }
+    options += "    deny all;\n";
+  }
+  if let Some(basic_auth) = &location.basic_auth {
+    let path = write_htpasswd(basic_auth, namespace, client, nginx).await?;
+    let realm = basic_auth.realm.clone().unwrap_or("Restricted".into());
+    options += &format!("    auth_basic \"{realm}\";\n");
+    options += &format!("    auth_basic_user_file {path};\n");
+  }
+  if let Some(rate_limit) = &location.rate_limit {
+    options += &gen_rate_limit(&rate_limit_zone(key, index), rate_limit);
+  }
+  if let Some(size) = &location.client_max_body_size {
+    options += &format!("    client_max_body_size {size};\n");
+  }
+  if let Some(timeouts) = &location.timeouts {
+    if let Some(connect) = &timeouts.connect {
+      options += &format!("    proxy_connect_timeout {connect};\n");
+    }
+    if let Some(read) = &timeouts.read {
+      options += &format!("    proxy_read_timeout {read};\n");
+    }
+    if let Some(send) = &timeouts.send {
+      options += &format!("    proxy_send_timeout {send};\n");
+    }
+  }
+  if let Some(cache) = &location.cache {
+    options += &gen_cache(&cache_zone(key, index), cache);
+  }
+  if location.websocket.unwrap_or_default() {
+    options += "    proxy_http_version 1.1;\n";
+    options += "    proxy_set_header Upgrade $http_upgrade;\n";
+    options += "    proxy_set_header Connection \"upgrade\";\n";
+  }
+  if let Some(headers) = &location.request_headers {
+    for (name, value) in sorted_headers(headers) {
+      options += &format!("    proxy_set_header {name} \"{value}\";\n");
+    }
+    for name in headers.remove.clone().unwrap_or_default() {
+      options += &format!("    proxy_set_header {name} \"\";\n");
+    }
+  }
+  if let Some(headers) = &location.response_headers {
+    for (name, value) in sorted_headers(headers) {
+      options += &format!("    add_header {name} \"{value}\" always;\n");
+    }
+    for name in headers.remove.clone().unwrap_or_default() {
+      options += &format!("    proxy_hide_header {name};\n");
+    }
+  }
+  Ok(options)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn location(options: serde_json::Value) -> ProxyHttpLocation {
+    let mut location = serde_json::json!({
+      "Path": "/",
+      "Target": {
+        "Cargo": {
+          "Key": "get-started.global",
+          "Port": 9000,
+        },
+      },
+    });
+    for (key, value) in options.as_object().unwrap() {
+      location[key] = value.clone();
+    }
+    serde_json::from_value(location).unwrap()
+  }
+
+  #[test]
+  fn validate() {
+    let valid = location(serde_json::json!({
+      "RequestHeaders": { "Set": { "X-Env": "prod" }, "Remove": ["X-Debug"] },
+      "ResponseHeaders": { "Set": { "X-Frame-Options": "DENY" } },
+      "RateLimit": { "Rate": "10r/s", "Burst": 20, "Nodelay": true },
+      "Allow": ["10.0.0.0/8", "::1"],
+      "Deny": ["10.0.0.1"],
+      "ClientMaxBodySize": "10m",
+      "Timeouts": { "Connect": "5s", "Read": "60s" },
+      "Websocket": true,
+    }));
+    assert!(validate_location(&valid).is_ok());
+    let invalids = [
+      serde_json::json!({ "RequestHeaders": { "Set": { "X Env": "prod" } } }),
+      serde_json::json!({ "ResponseHeaders": { "Set": { "X-Env": "a\"b" } } }),
+      serde_json::json!({ "RateLimit": { "Rate": "10/s" } }),
+      serde_json::json!({ "Allow": ["10.0.0.0/33"] }),
+      serde_json::json!({ "Deny": ["example.com"] }),
+      serde_json::json!({ "ClientMaxBodySize": "10mb" }),
+      serde_json::json!({ "Timeouts": { "Send": "1 day" } }),
+      serde_json::json!({ "BasicAuth": { "Secret": "../passwd" } }),
+      serde_json::json!({ "Cache": { "ZoneSize": "10mb" } }),
+      serde_json::json!({ "Cache": { "Valid": [{ "Status": ["2xx"], "Ttl": "10m" }] } }),
+      serde_json::json!({ "Cache": { "Bypass": ["$cookie_session;"] } }),
+    ];
+    for invalid in invalids {
+      assert!(validate_location(&location(invalid)).is_err());
+    }
+  }
+
+  #[test]
+  fn rate_limit_zones() {
+    let locations = [
+      location(serde_json::json!({})),
+      location(serde_json::json!({ "RateLimit": { "Rate": "10r/s" } })),
+    ];
+    assert_eq!(
+      gen_rate_limit_zones("get-started.global", &locations),
+      "limit_req_zone $binary_remote_addr zone=nanocl_get_started_global_1:10m rate=10r/s;\n"
+    );
+  }
+
+  #[test]
+  fn cache() {
+    let nginx = Nginx::new("/tmp/nginx", "/tmp/nginx/state");
+    let cache = location(serde_json::json!({
+      "Cache": {
+        "MaxSize": "1g",
+        "Valid": [
+          { "Status": ["200", "302"], "Ttl": "10m" },
+          { "Status": ["any"], "Ttl": "1m" },
+        ],
+        "Bypass": ["$cookie_session", "$http_authorization"],
+      },
+    }));
+    assert!(validate_location(&cache).is_ok());
+    let locations = [location(serde_json::json!({})), cache.clone()];
+    assert_eq!(
+      gen_cache_paths("get-started.global", &locations, &nginx),
+      "proxy_cache_path /tmp/nginx/state/cache/get-started.global/1 levels=1:2 keys_zone=nanocl_cache_get_started_global_1:10m max_size=1g;\n"
+    );
+    let options = gen_cache(
+      "nanocl_cache_get_started_global_1",
+      cache.cache.as_ref().unwrap(),
+    );
+    assert!(
+      options.contains("    proxy_cache nanocl_cache_get_started_global_1;\n")
+    );
+    assert!(options.contains("    proxy_cache_valid 200 302 10m;\n"));
+    assert!(options.contains("    proxy_cache_valid any 1m;\n"));
+    assert!(options.contains(
+      "    proxy_cache_bypass $cookie_session $http_authorization;\n"
+    ));
+    assert!(options
+      .contains("    proxy_no_cache $cookie_session $http_authorization;\n"));
+  }
+
+  #[ntex::test]
+  async fn location_options() {
+    use nanocld_client::stubs::resource::ResourcePartial;
+
+    let client = NanocldClient::connect_with_unix_default();
+    let nginx = Nginx::new("/tmp/nginx", "/tmp/nginx/state");
+    let options = location(serde_json::json!({
+      "RateLimit": { "Rate": "10r/s", "Burst": 20, "Nodelay": true },
+      "Allow": ["10.0.0.0/8"],
+      "Deny": ["10.0.0.1"],
+      "Websocket": true,
+    }));
+    assert_eq!(
+      gen_location_options("get-started", 0, &options, &None, &client, &nginx)
+        .await
+        .unwrap(),
+      "    deny 10.0.0.1;
+    allow 10.0.0.0/8;
+    deny all;
+    limit_req zone=nanocl_get_started_0 burst=20 nodelay;
+    proxy_http_version 1.1;
+    proxy_set_header Upgrade $http_upgrade;
+    proxy_set_header Connection \"upgrade\";
+"
+    );
+
+    let _ = client.delete_resource("location-options-users", None).await;
+    client
+      .create_resource(&ResourcePartial {
+        name: "location-options-users".into(),
+        kind: "Secret".into(),
+        version: "v0.1".into(),
+        config: serde_json::json!({
+          "Data": { "admin": "password" },
+        }),
+        namespace: None,
+        owner_refs: None,
+        labels: None,
+      })
+      .await
+      .unwrap();
+    let auth = location(serde_json::json!({
+      "BasicAuth": { "Secret": "location-options-users", "Realm": "Get started" },
+    }));
+    let htpasswd = "/tmp/nginx/state/auth/location-options-users.htpasswd";
+    assert_eq!(
+      gen_location_options("get-started", 0, &auth, &None, &client, &nginx)
+        .await
+        .unwrap(),
+      format!(
+        "    auth_basic \"Get started\";\n    auth_basic_user_file {htpasswd};\n"
+      )
+    );
+    let users = std::fs::read_to_string(htpasswd).unwrap();
+    assert!(users.starts_with("admin:{SSHA}"));
+    assert!(!users.contains("password"));
+    client
+      .delete_resource("location-options-users", None)
+      .await
+      .unwrap();
+  }
+
+  #[test]
+  fn password() {
+    let hash = hash_password("password").unwrap();
+    assert!(hash.starts_with("{SSHA}"));
+    // sha1 digest and 8 bytes salt
+    assert_eq!(STANDARD.decode(&hash[6..]).unwrap().len(), 28);
+    assert!(verify_password("password", &hash));
+    assert!(!verify_password("other", &hash));
+    assert!(!verify_password("password", "{SSHA}invalid"));
+  }
+}
@@ -1,95 +1,336 @@
-/// Watch for change inside the access log directory
-/// and print them to the console
-/// that way we can see what is happening in real time with docker logs
-/// so the daemon will be able to save them to the database
-/// and broadcast them in real time
-use std::fs::File;
-use std::path::Path;
+/// Read the json access logs written by nginx and send their new entries
+/// to the daemon so it can save them to the database.
+/// The position of the last entry sent is saved next to the log
+/// that way no entry is lost or sent twice when ncdproxy restart
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
 use std::time::Duration;
-use std::io::{Read, Seek, BufReader, SeekFrom};
+use std::io::{Read, Seek, SeekFrom};
+use std::os::unix::fs::MetadataExt;
 
-use ntex::rt;
-use notify::{Config, Watcher, RecursiveMode, RecommendedWatcher};
+use nanocld_client::NanocldClient;
+use nanocld_client::error::NanocldClientError;
 
-pub fn print_last_line(path: &Path) {
-  if !path.exists() {
-    return;
+use crate::error::ErrorHint;
+
+/// Directory of the access logs written by nginx
+const ACCESS_LOG_DIR: &str = "/var/log/nginx/access";
+
+/// Interval in seconds between two reads of the access logs
+const READ_INTERVAL: u64 = 2;
+
+/// Maximum number of bytes read from a log at once
+const READ_SIZE: u64 = 4_000_000;
+
+/// Maximum number of entries sent to the daemon in one request
+const BATCH_SIZE: usize = 500;
+
+/// Maximum number of bytes of the entries sent in one request
+/// The daemon refuse the requests bigger than 4 MB
+const BATCH_BYTES: usize = 1_000_000;
+
+/// Position of the last entry sent of a log
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct Position {
+  /// Inode of the log, it changes when the log is rotated
+  /// It's unknown for the offsets saved without it
+  inode: Option<u64>,
+  offset: u64,
+}
+
+fn offset_path(path: &Path) -> PathBuf {
+  let mut offset_path = path.as_os_str().to_owned();
+  offset_path.push(".offset");
+  PathBuf::from(offset_path)
+}
+
+/// Parse a saved position written as `<inode> <offset>`
+fn parse_position(position: &str) -> Position {
+  let mut words = position.split_whitespace();
+  match (words.next(), words.next()) {
+    (Some(inode), Some(offset)) => Position {
+      inode: inode.parse().ok(),
+      offset: offset.parse().unwrap_or_default(),
+    },
+    (Some(offset), None) => Position {
+      inode: None,
+      offset: offset.parse().unwrap_or_default(),
+    },
+    _ => Position::default(),
   }
-  let file = File::open(path).unwrap();
-  let mut buf_reader = BufReader::new(file);
-
-  let mut pos = buf_reader.seek(SeekFrom::End(-2)).unwrap();
-  let mut last_line = String::new();
-
-  while pos > 0 {
-    buf_reader.seek(SeekFrom::Start(pos)).unwrap();
-    let mut buffer = [0; 1];
-    let res = buf_reader.read_exact(&mut buffer);
-    if buffer[0] == b'\n' || res.is_err() {
-      break;
-    }
-    last_line.insert(0, buffer[0] as char);
-    pos -= 1;
+}
+
+/// Get the saved position of a log
+/// Without a saved position only the entries written from now are read
+fn load_position(path: &Path) -> Position {
+  match fs::read_to_string(offset_path(path)) {
+    Ok(position) => parse_position(&position),
+    Err(_) => fs::metadata(path)
+      .map(|meta| Position {
+        inode: Some(meta.ino()),
+        offset: meta.len(),
+      })
+      .unwrap_or_default(),
   }
+}
 
-  let file_name = path.file_name().unwrap().to_str().unwrap();
+fn save_position(path: &Path, position: Position) -> Result<(), ErrorHint> {
+  let offset_path = offset_path(path);
+  let inode = position.inode.unwrap_or_default();
+  let content = format!("{inode} {}", position.offset);
+  fs::write(&offset_path, content).map_err(|err| {
+    ErrorHint::error(
+      14,
+      format!("Unable to save offset {}: {err}", offset_path.display()),
+    )
+  })
+}
 
-  log::debug!("{}", file_name);
-  match file_name {
-    "http.log" => {
-      println!("#HTTP {last_line}");
+/// Read the complete lines of a log written after a position
+/// Each line is returned with the position of its end
+/// The log is read from the start when it has been rotated
+/// or when it's smaller than the offset since it has been truncated
+fn read_lines(
+  path: &Path,
+  position: Position,
+  max_size: u64,
+) -> std::io::Result<Vec<(Position, String)>> {
+  let mut file = File::open(path)?;
+  let meta = file.metadata()?;
+  let inode = Some(meta.ino());
+  let rotated = position.inode.is_some() && position.inode != inode;
+  let mut offset = if rotated || meta.len() < position.offset {
+    0
+  } else {
+    position.offset
+  };
+  file.seek(SeekFrom::Start(offset))?;
+  let mut buf = Vec::new();
+  file.take(max_size).read_to_end(&mut buf)?;
+  let mut lines = Vec::new();
+  let mut start = 0;
+  for (index, byte) in buf.iter().enumerate() {
+    if *byte != b'\n' {
+      continue;
     }
-    "stream.log" => {
-      println!("#STREAM {last_line}");
+    let line = String::from_utf8_lossy(&buf[start..index]).to_string();
+    offset += (index + 1 - start) as u64;
+    start = index + 1;
+    if !line.trim().is_empty() {
+      lines.push((Position { inode, offset }, line));
     }
-    _ => {}
   }
+  // A line bigger than the buffer would block the log forever
+  if lines.is_empty() && buf.len() as u64 == max_size {
+    log::warn!(
+      "Skipping a line bigger than {max_size} in {}",
+      path.display()
+    );
+    let offset = offset + max_size;
+    lines.push((Position { inode, offset }, String::default()));
+  }
+  Ok(lines)
 }
 
-pub(crate) fn run() {
-  rt::Arbiter::new().exec_fn(|| {
-    let path = Path::new("/var/log/nginx/access");
-    if !path.exists() {
-      log::debug!("{} doesn't exists", path.display());
-    }
-    let (tx, rx) = std::sync::mpsc::channel();
-
-    // Automatically select the best implementation for your platform.
-    // You can also access each implementation directly e.g. INotifyWatcher.
-    let mut watcher = RecommendedWatcher::new(
-      tx,
-      Config::default()
-        .with_compare_contents(true)
-        .with_poll_interval(Duration::from_secs(2)),
-    )
-    .unwrap();
+/// Access logs written by nginx
+#[derive(Debug, Clone, Copy)]
+enum AccessLog {
+  Http,
+  Stream,
+}
 
-    // Add a path to be watched. All files and directories at that path and
-    // below will be monitored for changes.
-    watcher.watch(path, RecursiveMode::Recursive).unwrap();
+impl AccessLog {
+  fn path(&self) -> PathBuf {
+    let file_name = match self {
+      AccessLog::Http => "http.log",
+      AccessLog::Stream => "stream.log",
+    };
+    Path::new(ACCESS_LOG_DIR).join(file_name)
+  }
 
-    log::debug!("watching change of: {}", path.display());
-    for res in rx {
+  /// Send metrics to the daemon
+  /// A batch refused by the daemon is split until the refused entries
+  /// are found so only them are skipped
+  async fn send(
+    &self,
+    client: &NanocldClient,
+    metrics: &[serde_json::Value],
+  ) -> Result<(), ErrorHint> {
+    let mut pending = vec![metrics];
+    while let Some(metrics) = pending.pop() {
+      let res = match self {
+        AccessLog::Http => client.create_http_metrics(metrics).await,
+        AccessLog::Stream => client.create_stream_metrics(metrics).await,
+      };
       match res {
-        Ok(event) => match &event.kind {
-          notify::EventKind::Modify(e) => match e {
-            notify::event::ModifyKind::Data(_) => {
-              log::debug!("modified event: {:?}", event);
-              let path = &event.paths.get(0);
-              if let Some(path) = path {
-                print_last_line(path)
-              }
-            }
-            notify::event::ModifyKind::Other => {}
-            _ => {}
-          },
-          notify::EventKind::Other => {
-            log::debug!("other");
+        Ok(_) => {}
+        Err(NanocldClientError::Api(err)) if err.status.is_client_error() => {
+          if let [metric] = metrics {
+            log::warn!("Skipping {self:?} metric {metric}: {err}");
+            continue;
           }
-          _ => {}
-        },
-        Err(e) => println!("watch error: {:?}", e),
+          let (first, second) = metrics.split_at(metrics.len() / 2);
+          pending.push(second);
+          pending.push(first);
+        }
+        Err(err) => {
+          return Err(ErrorHint::warning(
+            14,
+            format!("Unable to send {self:?} metrics: {err}"),
+          ));
+        }
+      }
+    }
+    Ok(())
+  }
+}
+
+/// Split lines in batches of at most `BATCH_SIZE` entries and `BATCH_BYTES`
+fn batches(lines: &[(Position, String)]) -> Vec<&[(Position, String)]> {
+  let mut batches = Vec::new();
+  let mut start = 0;
+  let mut size = 0;
+  for (index, (_, line)) in lines.iter().enumerate() {
+    let is_full =
+      index - start == BATCH_SIZE || size + line.len() > BATCH_BYTES;
+    if index > start && is_full {
+      batches.push(&lines[start..index]);
+      start = index;
+      size = 0;
+    }
+    size += line.len();
+  }
+  if start < lines.len() {
+    batches.push(&lines[start..]);
+  }
+  batches
+}
+
+/// Send the new entries of an access log to the daemon
+/// The position is saved after each batch sent to the daemon
+async fn ingest(
+  client: &NanocldClient,
+  access_log: AccessLog,
+) -> Result<(), ErrorHint> {
+  let path = &access_log.path();
+  if !path.exists() {
+    return Ok(());
+  }
+  let lines =
+    read_lines(path, load_position(path), READ_SIZE).map_err(|err| {
+      ErrorHint::error(
+        14,
+        format!("Unable to read access log {}: {err}", path.display()),
+      )
+    })?;
+  for batch in batches(&lines) {
+    let metrics = batch
+      .iter()
+      .filter(|(_, line)| !line.is_empty())
+      .filter_map(|(_, line)| match serde_json::from_str(line) {
+        Ok(metric) => Some(metric),
+        Err(err) => {
+          log::warn!("Skipping invalid access log entry {line}: {err}");
+          None
+        }
+      })
+      .collect::<Vec<serde_json::Value>>();
+    if !metrics.is_empty() {
+      access_log.send(client, &metrics).await?;
+    }
+    if let Some((position, _)) = batch.last() {
+      save_position(path, *position)?;
+    }
+  }
+  Ok(())
+}
+
+/// Loop sending the entries of the access logs to the daemon
+pub(crate) async fn run(client: NanocldClient) {
+  loop {
+    for access_log in [AccessLog::Http, AccessLog::Stream] {
+      if let Err(err) = ingest(&client, access_log).await {
+        err.print();
       }
     }
-  });
+    ntex::time::sleep(Duration::from_secs(READ_INTERVAL)).await;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn lines() {
+    let path = Path::new("/tmp/ncdproxy-read-lines.log");
+    fs::write(path, "{\"status\":\"200\"}\n\n{\"status\":\"404\"}\n{\"sta")
+      .unwrap();
+    let inode = Some(fs::metadata(path).unwrap().ino());
+    let at = |offset| Position { inode, offset };
+    let lines = read_lines(path, at(0), READ_SIZE).unwrap();
+    assert_eq!(
+      lines,
+      vec![
+        (at(17), "{\"status\":\"200\"}".to_owned()),
+        (at(35), "{\"status\":\"404\"}".to_owned()),
+      ]
+    );
+    // The partial line is read once complete
+    fs::write(
+      path,
+      "{\"status\":\"200\"}\n\n{\"status\":\"404\"}\n{\"status\":\"500\"}\n",
+    )
+    .unwrap();
+    let lines = read_lines(path, at(35), READ_SIZE).unwrap();
+    assert_eq!(lines, vec![(at(52), "{\"status\":\"500\"}".to_owned())]);
+    // A truncated log is read from the start
+    fs::write(path, "{\"status\":\"201\"}\n").unwrap();
+    let lines = read_lines(path, at(52), READ_SIZE).unwrap();
+    assert_eq!(lines, vec![(at(17), "{\"status\":\"201\"}".to_owned())]);
+    // A line bigger than the buffer is skipped
+    let lines = read_lines(path, at(0), 4).unwrap();
+    assert_eq!(lines, vec![(at(4), String::default())]);
+    // A rotated log is read from the start even when it's bigger
+    let rotated = Position {
+      inode: inode.map(|inode| inode + 1),
+      offset: 1,
+    };
+    let lines = read_lines(path, rotated, READ_SIZE).unwrap();
+    assert_eq!(lines, vec![(at(17), "{\"status\":\"201\"}".to_owned())]);
+    fs::remove_file(path).unwrap();
+  }
+
+  #[test]
+  fn positions() {
+    let position = Position {
+      inode: Some(42),
+      offset: 17,
+    };
+    assert_eq!(parse_position("42 17\n"), position);
+    // Offsets saved before the inode
+    assert_eq!(
+      parse_position("17"),
+      Position {
+        inode: None,
+        offset: 17
+      }
+    );
+    assert_eq!(parse_position(""), Position::default());
+  }
+
+  #[test]
+  fn split_batches() {
+    let position = Position::default();
+    let lines = (0..BATCH_SIZE + 1)
+      .map(|_| (position, "{}".to_owned()))
+      .collect::<Vec<_>>();
+    let sizes = batches(&lines).iter().map(|b| b.len()).collect::<Vec<_>>();
+    assert_eq!(sizes, vec![BATCH_SIZE, 1]);
+    let big = "a".repeat(BATCH_BYTES / 2 + 1);
+    let lines = vec![(position, big.clone()), (position, big.clone())];
+    let sizes = batches(&lines).iter().map(|b| b.len()).collect::<Vec<_>>();
+    assert_eq!(sizes, vec![1, 1]);
+    assert!(batches(&[]).is_empty());
+  }
 }
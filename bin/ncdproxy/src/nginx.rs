@@ -1,4 +1,7 @@
-use std::{fs, str::FromStr};
+use std::{fs, str::FromStr, sync::Arc, collections::HashMap};
+use std::sync::Mutex as SyncMutex;
+
+use futures::lock::{Mutex, MutexGuard};
 
 use crate::error::ErrorHint;
 
@@ -42,20 +45,351 @@ impl FromStr for NginxConfKind {
   }
 }
 
+/// Directory of the conf dir listing the files rendered for each rule
+/// It isn't included by nginx
+const RULES_DIR: &str = "rules";
+
+/// Directories of the conf dir copied in staging
+const CONF_DIRS: [&str; 4] =
+  ["conf.d", "sites-enabled", "streams-enabled", RULES_DIR];
+
+/// Directories of the conf dir where the proxy rules are rendered
+const MANAGED_DIRS: [&str; 3] = ["sites-enabled", "streams-enabled", RULES_DIR];
+
+/// Changes needed to go from the current files to the desired ones
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct ConfDiff {
+  /// Configuration files to write relative to the conf dir
+  pub(crate) write: Vec<String>,
+  /// Configuration files to remove relative to the conf dir
+  pub(crate) remove: Vec<String>,
+  /// Files used by the configuration to write relative to the files dir
+  pub(crate) files: Vec<String>,
+}
+
+impl ConfDiff {
+  pub(crate) fn is_empty(&self) -> bool {
+    self.write.is_empty() && self.remove.is_empty() && self.files.is_empty()
+  }
+}
+
+/// Compare the desired configuration files with the current ones
+pub(crate) fn diff_conf(
+  desired: &HashMap<String, String>,
+  current: &HashMap<String, String>,
+) -> ConfDiff {
+  let mut write = desired
+    .iter()
+    .filter(|(name, content)| current.get(*name) != Some(content))
+    .map(|(name, _)| name.clone())
+    .collect::<Vec<_>>();
+  let mut remove = current
+    .keys()
+    .filter(|name| !desired.contains_key(*name))
+    .cloned()
+    .collect::<Vec<_>>();
+  write.sort();
+  remove.sort();
+  ConfDiff {
+    write,
+    remove,
+    files: Vec::new(),
+  }
+}
+
+/// Read the files of a directory and its subdirectories
+/// Names are relative to the given directory
+fn read_files(dir: &str, prefix: &str, files: &mut HashMap<String, Vec<u8>>) {
+  let Ok(entries) = fs::read_dir(dir) else {
+    return;
+  };
+  for entry in entries.flatten() {
+    let path = entry.path();
+    let name = format!("{prefix}{}", entry.file_name().to_string_lossy());
+    if path.is_dir() {
+      read_files(&path.to_string_lossy(), &format!("{name}/"), files);
+    } else if let Ok(content) = fs::read(&path) {
+      files.insert(name, content);
+    }
+  }
+}
+
 #[derive(Clone, Debug)]
 pub(crate) struct Nginx {
   pub(crate) conf_dir: String,
+  pub(crate) state_dir: String,
+  /// Directory of the files used by the configuration like certificates
+  /// and htpasswd, they are rendered and tested in staging as well
+  pub(crate) files_dir: String,
+  /// Prevent concurrent changes of the staging directory
+  staging_lock: Arc<Mutex<()>>,
+  /// Configuration files written since they were last recorded
+  /// relative to the conf dir
+  written: Arc<SyncMutex<Vec<String>>>,
 }
 
 impl Nginx {
-  pub(crate) fn new(conf_dir: &str) -> Self {
+  pub(crate) fn new(conf_dir: &str, state_dir: &str) -> Self {
     Self {
       conf_dir: conf_dir.to_owned(),
+      state_dir: state_dir.to_owned(),
+      files_dir: state_dir.to_owned(),
+      staging_lock: Arc::new(Mutex::new(())),
+      written: Arc::default(),
+    }
+  }
+
+  /// Wait until no other configuration is staged
+  pub(crate) async fn lock_staging(&self) -> MutexGuard<'_, ()> {
+    self.staging_lock.lock().await
+  }
+
+  /// Copy the files of the included directories from a conf dir to another
+  fn copy_conf_dirs(from: &str, to: &str) -> Result<(), ErrorHint> {
+    for dir in CONF_DIRS {
+      let from_dir = format!("{from}/{dir}");
+      let to_dir = format!("{to}/{dir}");
+      fs::create_dir_all(&to_dir).map_err(|err| {
+        ErrorHint::error(
+          2,
+          format!("Cannot create directory {to_dir} got error : {err}"),
+        )
+      })?;
+      let Ok(entries) = fs::read_dir(&from_dir) else {
+        continue;
+      };
+      for entry in entries.flatten() {
+        let path = entry.path();
+        if !path.is_file() {
+          continue;
+        }
+        let to_path =
+          format!("{to_dir}/{}", entry.file_name().to_string_lossy());
+        fs::copy(&path, &to_path).map_err(|err| {
+          ErrorHint::error(
+            1,
+            format!("Unable to copy {} to {to_path}: {err}", path.display()),
+          )
+        })?;
+      }
+    }
+    Ok(())
+  }
+
+  /// Create a staging copy of the current configuration
+  /// Files are rendered in the returned instance to be tested before
+  /// being applied with [apply_staging](Nginx::apply_staging)
+  pub(crate) fn prepare_staging(&self) -> Result<Nginx, ErrorHint> {
+    let staging = Nginx {
+      conf_dir: format!("{}/staging", self.state_dir),
+      state_dir: self.state_dir.clone(),
+      files_dir: format!("{}/staging/files", self.state_dir),
+      staging_lock: self.staging_lock.clone(),
+      written: Arc::default(),
+    };
+    if std::path::Path::new(&staging.conf_dir).exists() {
+      fs::remove_dir_all(&staging.conf_dir).map_err(|err| {
+        ErrorHint::error(
+          3,
+          format!(
+            "Cannot remove directory {} got error : {err}",
+            staging.conf_dir
+          ),
+        )
+      })?;
+    }
+    Self::copy_conf_dirs(&self.conf_dir, &staging.conf_dir)?;
+    Ok(staging)
+  }
+
+  /// Read the rendered configuration files
+  /// Names are relative to the conf dir
+  pub(crate) fn read_conf_files(&self) -> HashMap<String, String> {
+    let mut files = HashMap::new();
+    for dir in MANAGED_DIRS {
+      let Ok(entries) = fs::read_dir(format!("{}/{dir}", self.conf_dir)) else {
+        continue;
+      };
+      for entry in entries.flatten() {
+        let path = entry.path();
+        if !path.is_file() {
+          continue;
+        }
+        let Ok(content) = fs::read_to_string(&path) else {
+          continue;
+        };
+        let name = format!("{dir}/{}", entry.file_name().to_string_lossy());
+        files.insert(name, content);
+      }
+    }
+    files
+  }
+
+  /// Compare a staging directory with the current configuration
+  /// Returns the desired configuration files with the paths of the staged
+  /// files replaced by their final paths and the changes to apply
+  pub(crate) fn diff_staging(
+    &self,
+    staging: &Nginx,
+  ) -> (HashMap<String, String>, ConfDiff) {
+    let staged_dir = format!("{}/", staging.files_dir);
+    let files_dir = format!("{}/", self.files_dir);
+    let desired = staging
+      .read_conf_files()
+      .into_iter()
+      .map(|(name, content)| (name, content.replace(&staged_dir, &files_dir)))
+      .collect::<HashMap<_, _>>();
+    let mut diff = diff_conf(&desired, &self.read_conf_files());
+    let mut staged = HashMap::new();
+    read_files(&staging.files_dir, "", &mut staged);
+    diff.files = staged
+      .into_iter()
+      .filter(|(name, content)| {
+        fs::read(format!("{}/{name}", self.files_dir)).ok().as_ref()
+          != Some(content)
+      })
+      .map(|(name, _)| name)
+      .collect();
+    diff.files.sort();
+    (desired, diff)
+  }
+
+  /// Write the staged files and the desired configuration of a diff
+  /// then remove the configuration files that are no longer desired
+  pub(crate) fn apply_diff(
+    &self,
+    staging: &Nginx,
+    desired: &HashMap<String, String>,
+    diff: &ConfDiff,
+  ) -> Result<(), ErrorHint> {
+    for name in &diff.files {
+      let from = format!("{}/{name}", staging.files_dir);
+      let to = format!("{}/{name}", self.files_dir);
+      if let Some(dir) = std::path::Path::new(&to).parent() {
+        fs::create_dir_all(dir).map_err(|err| {
+          ErrorHint::error(
+            2,
+            format!("Cannot create directory {} : {err}", dir.display()),
+          )
+        })?;
+      }
+      // The permissions of the staged file are kept
+      fs::copy(&from, &to).map_err(|err| {
+        ErrorHint::error(1, format!("Unable to copy {from} to {to}: {err}"))
+      })?;
+    }
+    for name in &diff.write {
+      let path = format!("{}/{name}", self.conf_dir);
+      fs::write(&path, &desired[name]).map_err(|err| {
+        ErrorHint::error(
+          1,
+          format!("Unable to create new site file {path} got error: {err}"),
+        )
+      })?;
+    }
+    for name in &diff.remove {
+      let path = format!("{}/{name}", self.conf_dir);
+      fs::remove_file(&path).map_err(|err| {
+        ErrorHint::warning(
+          3,
+          format!("Unable to delete site file {path} got error: {err}"),
+        )
+      })?;
+    }
+    Ok(())
+  }
+
+  /// Take the configuration files written since the last call
+  pub(crate) fn take_written(&self) -> Vec<String> {
+    let mut written =
+      self.written.lock().unwrap_or_else(|err| err.into_inner());
+    std::mem::take(&mut *written)
+  }
+
+  /// Read the files rendered for a rule relative to the conf dir
+  fn read_rule_files(&self, key: &str) -> Option<Vec<String>> {
+    let path = format!("{}/{RULES_DIR}/{key}", self.conf_dir);
+    let content = fs::read_to_string(path).ok()?;
+    Some(content.lines().map(String::from).collect())
+  }
+
+  /// Record the files written for a rule since the last record
+  /// so its configuration can be kept when it fails to render
+  pub(crate) fn record_rule_files(&self, key: &str) -> Result<(), ErrorHint> {
+    let mut files = self.take_written();
+    files.sort();
+    files.dedup();
+    let path = format!("{}/{RULES_DIR}/{key}", self.conf_dir);
+    fs::write(&path, files.join("\n")).map_err(|err| {
+      ErrorHint::error(1, format!("Unable to write {path} got error: {err}"))
+    })
+  }
+
+  /// Remove the files rendered for a rule from a staging directory
+  /// The files recorded for another rule like a shared upstream are kept
+  pub(crate) fn remove_rule_conf(&self, key: &str) {
+    let mut files = self.take_written();
+    files.extend(self.read_rule_files(key).unwrap_or_default());
+    let rules_dir = format!("{}/{RULES_DIR}", self.conf_dir);
+    let shared = fs::read_dir(&rules_dir)
+      .into_iter()
+      .flatten()
+      .flatten()
+      .map(|entry| entry.file_name().to_string_lossy().to_string())
+      .filter(|name| name != key)
+      .filter_map(|name| self.read_rule_files(&name))
+      .flatten()
+      .collect::<Vec<_>>();
+    for name in files.iter().filter(|name| !shared.contains(name)) {
+      let _ = fs::remove_file(format!("{}/{name}", self.conf_dir));
+    }
+    let _ = fs::remove_file(format!("{rules_dir}/{key}"));
+  }
+
+  /// Keep the current configuration of a rule in a staging directory
+  /// The files recorded for the rule are copied when they are missing
+  /// in staging
+  pub(crate) fn keep_rule_conf(
+    &self,
+    key: &str,
+    staging: &Nginx,
+  ) -> Result<(), ErrorHint> {
+    let mut files = match self.read_rule_files(key) {
+      Some(files) => files,
+      // Rules rendered before their files were recorded
+      None => ["sites-enabled", "streams-enabled"]
+        .map(|dir| format!("{dir}/{key}.conf"))
+        .to_vec(),
+    };
+    files.push(format!("{RULES_DIR}/{key}"));
+    for name in files {
+      let from = format!("{}/{name}", self.conf_dir);
+      let to = format!("{}/{name}", staging.conf_dir);
+      if !std::path::Path::new(&from).exists()
+        || std::path::Path::new(&to).exists()
+      {
+        continue;
+      }
+      fs::copy(&from, &to).map_err(|err| {
+        ErrorHint::error(1, format!("Unable to copy {from} to {to}: {err}"))
+      })?;
     }
+    Ok(())
+  }
+
+  /// Apply the tested changes of the staging directory
+  /// Files removed in staging are removed from the conf dir
+  pub(crate) fn apply_staging(&self, staging: &Nginx) -> Result<(), ErrorHint> {
+    let (desired, diff) = self.diff_staging(staging);
+    self.apply_diff(staging, &desired, &diff)
   }
 
   #[inline]
-  fn gen_conf_path(&self, name: &str, kind: &NginxConfKind) -> String {
+  pub(crate) fn gen_conf_path(
+    &self,
+    name: &str,
+    kind: &NginxConfKind,
+  ) -> String {
     match kind {
       NginxConfKind::Site => {
         format!("{}/sites-enabled/{name}.conf", &self.conf_dir)
@@ -68,6 +402,14 @@ impl Nginx {
 
   #[inline]
   pub(crate) fn ensure(&self) -> Result<(), ErrorHint> {
+    // Ensure the directory of the rendered files of each rule exists
+    let rules_dir = format!("{}/{RULES_DIR}", self.conf_dir);
+    fs::create_dir_all(&rules_dir).map_err(|err| {
+      ErrorHint::error(
+        2,
+        format!("Cannot create directory {rules_dir} got error : {err}"),
+      )
+    })?;
     // Ensure sites-enabled directory exists
     let sites_enabled_dir = format!("{}/sites-enabled", self.conf_dir);
     fs::create_dir_all(&sites_enabled_dir).map_err(|err| {
@@ -98,6 +440,16 @@ impl Nginx {
         ),
       )
     })?;
+    // Ensure acme directories exists
+    for dir in ["accounts", "certs", "challenges"] {
+      let acme_dir = format!("{}/acme/{dir}", self.state_dir);
+      fs::create_dir_all(&acme_dir).map_err(|err| {
+        ErrorHint::error(
+          2,
+          format!("Cannot create directory {acme_dir} got error : {err}"),
+        )
+      })?;
+    }
     Ok(())
   }
 
@@ -142,6 +494,12 @@ impl Nginx {
         format!("Unable to create new site file {path} got error: {err}"),
       )
     })?;
+    let name = path.trim_start_matches(&format!("{}/", self.conf_dir));
+    self
+      .written
+      .lock()
+      .unwrap_or_else(|err| err.into_inner())
+      .push(name.to_owned());
     Ok(())
   }
 
@@ -172,45 +530,228 @@ impl Nginx {
 
   #[inline]
   pub(crate) fn clear_conf(&self) -> Result<(), ErrorHint> {
-    let sites_enabled_dir = format!("{}/sites-enabled", self.conf_dir);
-    fs::remove_dir_all(&sites_enabled_dir).map_err(|err| {
-      ErrorHint::error(
-        3,
-        format!(
-          "Cannot remove directory {sites_enabled_dir} got error : {err}",
-        ),
-      )
-    })?;
-    let streams_enabled_dir = format!("{}/streams-enabled", self.conf_dir);
-    fs::remove_dir_all(&streams_enabled_dir).map_err(|err| {
-      ErrorHint::error(
-        3,
-        format!(
-          "Cannot remove directory {streams_enabled_dir} got error : {err}",
-        ),
-      )
-    })?;
-    fs::create_dir_all(&sites_enabled_dir).map_err(|err| {
-      ErrorHint::error(
-        3,
-        format!(
-          "Cannot create directory {sites_enabled_dir} got error : {err}",
-        ),
-      )
-    })?;
-    fs::create_dir_all(&streams_enabled_dir).map_err(|err| {
-      ErrorHint::error(
-        3,
-        format!(
-          "Cannot create directory {streams_enabled_dir} got error : {err}",
-        ),
-      )
-    })?;
+    for dir in MANAGED_DIRS {
+      let dir = format!("{}/{dir}", self.conf_dir);
+      if std::path::Path::new(&dir).exists() {
+        fs::remove_dir_all(&dir).map_err(|err| {
+          ErrorHint::error(
+            3,
+            format!("Cannot remove directory {dir} got error : {err}"),
+          )
+        })?;
+      }
+      fs::create_dir_all(&dir).map_err(|err| {
+        ErrorHint::error(
+          3,
+          format!("Cannot create directory {dir} got error : {err}"),
+        )
+      })?;
+    }
     Ok(())
   }
 }
 
 /// Create a new nginx instance
-pub(crate) fn new(config_path: &str) -> Nginx {
-  Nginx::new(config_path)
+pub(crate) fn new(config_path: &str, state_dir: &str) -> Nginx {
+  Nginx::new(config_path, state_dir)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn diff() {
+    let desired = HashMap::from([
+      ("sites-enabled/app.conf".to_owned(), "new".to_owned()),
+      (
+        "sites-enabled/app.global-9000.conf".to_owned(),
+        "same".to_owned(),
+      ),
+      ("streams-enabled/db.conf".to_owned(), "new".to_owned()),
+    ]);
+    let current = HashMap::from([
+      ("sites-enabled/app.conf".to_owned(), "old".to_owned()),
+      (
+        "sites-enabled/app.global-9000.conf".to_owned(),
+        "same".to_owned(),
+      ),
+      ("sites-enabled/deleted.conf".to_owned(), "old".to_owned()),
+    ]);
+    let diff = diff_conf(&desired, &current);
+    assert_eq!(
+      diff,
+      ConfDiff {
+        write: vec![
+          "sites-enabled/app.conf".to_owned(),
+          "streams-enabled/db.conf".to_owned(),
+        ],
+        remove: vec!["sites-enabled/deleted.conf".to_owned()],
+        files: Vec::new(),
+      }
+    );
+    assert!(diff_conf(&desired, &desired).is_empty());
+  }
+
+  #[test]
+  fn apply_staging() {
+    let dir = "/tmp/nginx/staging-test";
+    let _ = fs::remove_dir_all(dir);
+    let nginx = Nginx::new(&format!("{dir}/conf"), &format!("{dir}/state"));
+    nginx.ensure().unwrap();
+    nginx
+      .write_conf_file("app", "server {}", &NginxConfKind::Site)
+      .unwrap();
+    nginx
+      .write_conf_file("other", "server {}", &NginxConfKind::Site)
+      .unwrap();
+
+    // The rule app is patched from a site to a stream using a certificate
+    let staging = nginx.prepare_staging().unwrap();
+    fs::remove_file(staging.gen_conf_path("app", &NginxConfKind::Site))
+      .unwrap();
+    fs::create_dir_all(format!("{}/certs/app", staging.files_dir)).unwrap();
+    fs::write(format!("{}/certs/app/cert.pem", staging.files_dir), "cert")
+      .unwrap();
+    let stream = format!(
+      "server {{\n  ssl_certificate {}/certs/app/cert.pem;\n}}",
+      staging.files_dir
+    );
+    staging
+      .write_conf_file("app", &stream, &NginxConfKind::Stream)
+      .unwrap();
+    // Nothing is written in the live directories before being applied
+    assert!(!std::path::Path::new(&format!("{dir}/state/certs")).exists());
+
+    let (_, diff) = nginx.diff_staging(&staging);
+    assert_eq!(
+      diff,
+      ConfDiff {
+        write: vec!["streams-enabled/app.conf".to_owned()],
+        remove: vec!["sites-enabled/app.conf".to_owned()],
+        files: vec!["certs/app/cert.pem".to_owned()],
+      }
+    );
+    nginx.apply_staging(&staging).unwrap();
+    let files = nginx.read_conf_files();
+    assert!(!files.contains_key("sites-enabled/app.conf"));
+    assert!(files.contains_key("sites-enabled/other.conf"));
+    assert_eq!(
+      files["streams-enabled/app.conf"],
+      format!(
+        "server {{\n  ssl_certificate {dir}/state/certs/app/cert.pem;\n}}"
+      )
+    );
+    assert_eq!(
+      fs::read_to_string(format!("{dir}/state/certs/app/cert.pem")).unwrap(),
+      "cert"
+    );
+    assert!(nginx.diff_staging(&staging).1.is_empty());
+  }
+
+  #[test]
+  fn keep_rule_conf() {
+    let dir = "/tmp/nginx/keep-test";
+    let _ = fs::remove_dir_all(dir);
+    let nginx = Nginx::new(&format!("{dir}/conf"), &format!("{dir}/state"));
+    nginx.ensure().unwrap();
+    // The upstreams are found from the recorded files of the rule
+    nginx
+      .write_conf_file("app", "server {}", &NginxConfKind::Site)
+      .unwrap();
+    nginx
+      .write_conf_file("app-0-web.global-80", "upstream", &NginxConfKind::Site)
+      .unwrap();
+    nginx.record_rule_files("app").unwrap();
+    nginx
+      .write_conf_file("app-2", "server {}", &NginxConfKind::Site)
+      .unwrap();
+    nginx
+      .write_conf_file(
+        "app-2-0-web.global-80",
+        "upstream",
+        &NginxConfKind::Site,
+      )
+      .unwrap();
+    nginx.record_rule_files("app-2").unwrap();
+    assert_eq!(
+      nginx.read_rule_files("app").unwrap(),
+      vec![
+        "sites-enabled/app-0-web.global-80.conf".to_owned(),
+        "sites-enabled/app.conf".to_owned(),
+      ]
+    );
+
+    // Every rule is removed and app fails to render
+    let staging = nginx.prepare_staging().unwrap();
+    staging.clear_conf().unwrap();
+    nginx.keep_rule_conf("app", &staging).unwrap();
+    let (_, diff) = nginx.diff_staging(&staging);
+    assert_eq!(
+      diff,
+      ConfDiff {
+        write: Vec::new(),
+        remove: vec![
+          "rules/app-2".to_owned(),
+          "sites-enabled/app-2-0-web.global-80.conf".to_owned(),
+          "sites-enabled/app-2.conf".to_owned(),
+        ],
+        files: Vec::new(),
+      }
+    );
+
+    // A rendered upstream is not replaced by the current one
+    let staging = nginx.prepare_staging().unwrap();
+    staging.clear_conf().unwrap();
+    staging
+      .write_conf_file("app-0-web.global-80", "new", &NginxConfKind::Site)
+      .unwrap();
+    nginx.keep_rule_conf("app", &staging).unwrap();
+    let (_, diff) = nginx.diff_staging(&staging);
+    assert_eq!(
+      diff.write,
+      vec!["sites-enabled/app-0-web.global-80.conf".to_owned()]
+    );
+    assert!(staging
+      .read_conf_files()
+      .contains_key("sites-enabled/app.conf"));
+  }
+
+  #[test]
+  fn remove_rule_conf() {
+    let dir = "/tmp/nginx/remove-test";
+    let _ = fs::remove_dir_all(dir);
+    let nginx = Nginx::new(&format!("{dir}/conf"), &format!("{dir}/state"));
+    nginx.ensure().unwrap();
+    let staging = nginx.prepare_staging().unwrap();
+    staging
+      .write_conf_file("web", "server {}", &NginxConfKind::Site)
+      .unwrap();
+    staging
+      .write_conf_file("vm-web.global-80", "upstream", &NginxConfKind::Site)
+      .unwrap();
+    staging.record_rule_files("web").unwrap();
+    staging
+      .write_conf_file("app", "server {}", &NginxConfKind::Site)
+      .unwrap();
+    staging
+      .write_conf_file("vm-web.global-80", "upstream", &NginxConfKind::Site)
+      .unwrap();
+    staging
+      .write_conf_file("app-0-app.global-80", "upstream", &NginxConfKind::Site)
+      .unwrap();
+
+    // The upstream shared with web is kept
+    staging.remove_rule_conf("app");
+    let mut files = staging.read_conf_files().into_keys().collect::<Vec<_>>();
+    files.sort();
+    assert_eq!(
+      files,
+      vec![
+        "rules/web".to_owned(),
+        "sites-enabled/vm-web.global-80.conf".to_owned(),
+        "sites-enabled/web.conf".to_owned(),
+      ]
+    );
+  }
 }
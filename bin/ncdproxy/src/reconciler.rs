@@ -0,0 +1,99 @@
+use std::time::Duration;
+
+use nanocld_client::NanocldClient;
+use nanocld_client::stubs::resource::{ResourceQuery, ResourcePartial};
+
+use crate::utils;
+use crate::error::ErrorHint;
+use crate::nginx::Nginx;
+
+/// Interval in seconds between two reconciliations
+const RECONCILE_INTERVAL: u64 = 30;
+
+/// Render the rules in a new staging directory
+/// A rule that can't be rendered keeps its current configuration,
+/// when `test_each` is set the configuration is tested after each rule
+/// so the rules refused by nginx keep their current configuration too
+async fn stage_rules(
+  client: &NanocldClient,
+  nginx: &Nginx,
+  rules: &[(String, ResourcePartial)],
+  test_each: bool,
+) -> Result<Nginx, ErrorHint> {
+  let staging = nginx.prepare_staging()?;
+  staging.clear_conf()?;
+  for (key, resource) in rules {
+    let mut res =
+      utils::render_resource_conf(client, &staging, key, resource).await;
+    if res.is_ok() && test_each {
+      res = utils::test_config(client, &staging).await;
+    }
+    if let Err(err) = res {
+      err.print();
+      staging.remove_rule_conf(key);
+      nginx.keep_rule_conf(key, &staging)?;
+    }
+  }
+  Ok(staging)
+}
+
+/// Render the configuration of every proxy rule and update the files
+/// that changed, nginx is reloaded only when something changed
+/// A rule that can't be rendered or is refused by nginx keeps its current
+/// configuration, only the files of the rules that no longer exist
+/// are removed
+pub(crate) async fn reconcile(
+  client: &NanocldClient,
+  nginx: &Nginx,
+) -> Result<(), ErrorHint> {
+  let query = ResourceQuery {
+    kind: Some("ProxyRule".into()),
+    ..Default::default()
+  };
+  let resources = client.list_resource(Some(query)).await.map_err(|err| {
+    ErrorHint::warning(
+      8,
+      format!("Unable to list resources from nanocl: {err}"),
+    )
+  })?;
+  let rules = resources
+    .into_iter()
+    .map(|resource| (resource.key.to_owned(), resource.into()))
+    .collect::<Vec<(String, ResourcePartial)>>();
+  let _lock = nginx.lock_staging().await;
+  let mut staging = stage_rules(client, nginx, &rules, false).await?;
+  if nginx.diff_staging(&staging).1.is_empty() {
+    log::debug!("Proxy configuration is up to date");
+    return Ok(());
+  }
+  if let Err(err) = utils::test_config(client, &staging).await {
+    err.print();
+    log::warn!("Testing each proxy rule to find the invalid ones");
+    staging = stage_rules(client, nginx, &rules, true).await?;
+    utils::test_config(client, &staging).await?;
+  }
+  let (desired, diff) = nginx.diff_staging(&staging);
+  if diff.is_empty() {
+    log::debug!("Proxy configuration is up to date");
+    return Ok(());
+  }
+  log::info!(
+    "Reconciling proxy configuration write: {:?} remove: {:?} files: {:?}",
+    diff.write,
+    diff.remove,
+    diff.files
+  );
+  nginx.apply_diff(&staging, &desired, &diff)?;
+  utils::reload_config(client).await?;
+  Ok(())
+}
+
+/// Loop reconciling the proxy configuration
+pub(crate) async fn run(client: NanocldClient, nginx: Nginx) {
+  loop {
+    ntex::time::sleep(Duration::from_secs(RECONCILE_INTERVAL)).await;
+    if let Err(err) = reconcile(&client, &nginx).await {
+      err.print();
+    }
+  }
+}
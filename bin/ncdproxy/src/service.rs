@@ -1,27 +1,81 @@
 use ntex::web;
 
 use nanocld_client::NanocldClient;
-use nanocld_client::stubs::resource::ResourcePartial;
+use nanocld_client::stubs::resource::{ResourcePartial, ResourceRuleQuery};
 
 use crate::{
-  utils,
+  utils, middleware,
   error::HttpError,
   nginx::{Nginx, NginxConfKind},
 };
 
 #[web::put("/rules")]
 async fn apply_rule(
-  web::types::Json(payload): web::types::Json<ResourcePartial>,
+  web::types::Query(qs): web::types::Query<ResourceRuleQuery>,
+  web::types::Json(mut payload): web::types::Json<ResourcePartial>,
   nginx: web::types::State<Nginx>,
 ) -> Result<web::HttpResponse, HttpError> {
   let client = NanocldClient::connect_with_unix_default();
 
-  utils::create_resource_conf(&client, &nginx, &payload).await?;
+  // The rule is owned by the cargoes and vms it watch unless owners are given
+  if payload
+    .owner_refs
+    .as_ref()
+    .map_or(true, |refs| refs.is_empty())
+  {
+    let proxy_rule = utils::serialize_proxy_rule(&payload)?;
+    let owner_refs = utils::gen_owner_refs(&proxy_rule, &client).await;
+    payload.owner_refs = Some(owner_refs);
+  }
+
+  let key = qs.key_or(&payload.name).to_owned();
+  utils::create_resource_conf(&client, &nginx, &key, &payload).await?;
   utils::reload_config(&client).await?;
 
   Ok(web::HttpResponse::Ok().json(&payload))
 }
 
+#[web::post("/rules/validate")]
+async fn validate_rule(
+  web::types::Json(payload): web::types::Json<ResourcePartial>,
+) -> Result<web::HttpResponse, HttpError> {
+  utils::serialize_proxy_rule(&payload)?;
+
+  Ok(web::HttpResponse::Ok().finish())
+}
+
+#[web::post("/rules/{name}/cache/purge")]
+async fn purge_rule_cache(
+  path: web::types::Path<String>,
+  nginx: web::types::State<Nginx>,
+) -> Result<web::HttpResponse, HttpError> {
+  let name = path.into_inner();
+
+  log::info!("Purging cache of rule: {name}");
+
+  middleware::purge_cache(&nginx, &name)?;
+
+  Ok(web::HttpResponse::Ok().finish())
+}
+
+#[web::delete("/rules/{name}")]
+async fn remove_rule_by_name(
+  path: web::types::Path<String>,
+  nginx: web::types::State<Nginx>,
+) -> Result<web::HttpResponse, HttpError> {
+  let name = path.into_inner();
+
+  log::info!("Deleting rule: {name}");
+
+  let site = nginx.delete_conf_file(&name, &NginxConfKind::Site).await;
+  let stream = nginx.delete_conf_file(&name, &NginxConfKind::Stream).await;
+  if let (Err(err), Err(_)) = (site, stream) {
+    return Err(err.into());
+  }
+
+  Ok(web::HttpResponse::Ok().finish())
+}
+
 #[web::delete("/rules/{kind}/{name}")]
 async fn remove_rule(
   path: web::types::Path<(String, String)>,
@@ -40,7 +94,10 @@ async fn remove_rule(
 
 pub fn ntex_config(config: &mut web::ServiceConfig) {
   config.service(apply_rule);
+  config.service(validate_rule);
+  config.service(purge_rule_cache);
   config.service(remove_rule);
+  config.service(remove_rule_by_name);
 }
 
 #[cfg(test)]
@@ -48,7 +105,9 @@ mod tests {
   use super::*;
 
   use ntex::http::StatusCode;
-  use nanocld_client::stubs::resource::ResourcePartial;
+  use nanocld_client::stubs::resource::{
+    ResourcePartial, ResourceOwnerRef, ResourceOwnerKind,
+  };
 
   use crate::utils::tests;
 
@@ -64,10 +123,31 @@ mod tests {
 
     let resource = serde_yaml::from_value::<ResourcePartial>(resource).unwrap();
 
-    let res = test_srv.put("/rules").send_json(&resource).await.unwrap();
+    let res = test_srv
+      .post("/rules/validate")
+      .send_json(&resource)
+      .await
+      .unwrap();
 
     assert_eq!(res.status(), StatusCode::OK);
 
+    let mut res = test_srv
+      .put(format!("/rules?Key={}", resource.name))
+      .send_json(&resource)
+      .await
+      .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let applied = res.json::<ResourcePartial>().await.unwrap();
+    assert_eq!(
+      applied.owner_refs,
+      Some(vec![ResourceOwnerRef {
+        kind: ResourceOwnerKind::Cargo,
+        key: "get-started.global".to_owned(),
+      }])
+    );
+
     let res = test_srv
       .delete(format!("/rules/site/{}", resource.name))
       .send()
@@ -75,5 +155,253 @@ mod tests {
       .unwrap();
 
     assert_eq!(res.status(), StatusCode::OK);
+
+    let res = test_srv
+      .put(format!("/rules?Key={}", resource.name))
+      .send_json(&resource)
+      .await
+      .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = test_srv
+      .delete(format!("/rules/{}", resource.name))
+      .send()
+      .await
+      .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let mut invalid = resource.clone();
+    invalid.config = serde_json::json!({ "Watch": [] });
+    let res = test_srv
+      .post("/rules/validate")
+      .send_json(&invalid)
+      .await
+      .unwrap();
+
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+  }
+
+  #[ntex::test]
+  async fn rules_acme() {
+    tests::before();
+    let nginx = tests::render_nginx("acme");
+    let resource =
+      tests::parse_resource(include_str!("../tests/resource_acme.yml"));
+
+    // The challenges are served over http until a certificate is issued
+    let conf = tests::render_resource(&nginx, &resource).await;
+    assert!(conf.contains("location ^~ /.well-known/acme-challenge/"));
+    assert!(
+      conf.contains("alias /tmp/nginx/render/acme/state/acme/challenges/;")
+    );
+    assert!(!conf.contains("ssl_certificate"));
+    assert!(!conf.contains("listen 443"));
+
+    let cert =
+      rcgen::generate_simple_self_signed(vec!["get-started.com".into()])
+        .unwrap();
+    crate::acme::save_certificate(
+      &nginx,
+      "get-started.com",
+      &cert.serialize_pem().unwrap(),
+      &cert.serialize_private_key_pem(),
+    )
+    .unwrap();
+    let conf = tests::render_resource(&nginx, &resource).await;
+    assert!(conf.contains("location ^~ /.well-known/acme-challenge/"));
+    assert!(conf.contains("listen 443 http2 ssl;"));
+    assert!(conf.contains(
+      "ssl_certificate      /tmp/nginx/render/acme/state/acme/certs/get-started.com/fullchain.pem;"
+    ));
+    assert!(conf.contains(
+      "ssl_certificate_key  /tmp/nginx/render/acme/state/acme/certs/get-started.com/privkey.pem;"
+    ));
+
+    let mut invalid = resource.clone();
+    invalid.config["Rule"]["Http"]
+      .as_object_mut()
+      .unwrap()
+      .remove("Domain");
+    assert!(utils::serialize_proxy_rule(&invalid).is_err());
+  }
+
+  #[ntex::test]
+  async fn rules_certificate_ref() {
+    use std::os::unix::fs::PermissionsExt;
+
+    tests::before();
+    let client = NanocldClient::connect_with_unix_default();
+    let nginx = tests::render_nginx("certificate-ref");
+    let resource = tests::parse_resource(include_str!(
+      "../tests/resource_certificate_ref.yml"
+    ));
+
+    let cert =
+      rcgen::generate_simple_self_signed(vec!["get-started.com".into()])
+        .unwrap();
+    let _ = client.delete_resource("get-started-cert", None).await;
+    client
+      .create_resource(&ResourcePartial {
+        name: "get-started-cert".into(),
+        kind: "Certificate".into(),
+        version: "v0.1".into(),
+        config: serde_json::json!({
+          "Certificate": cert.serialize_pem().unwrap(),
+          "CertificateKey": cert.serialize_private_key_pem(),
+        }),
+        namespace: None,
+        owner_refs: None,
+        labels: None,
+      })
+      .await
+      .unwrap();
+
+    let conf = tests::render_resource(&nginx, &resource).await;
+    let dir = "/tmp/nginx/render/certificate-ref/state/certs/get-started-cert";
+    assert!(
+      conf.contains(&format!("ssl_certificate      {dir}/fullchain.pem;"))
+    );
+    assert!(conf.contains(&format!("ssl_certificate_key  {dir}/privkey.pem;")));
+    let key = std::fs::read_to_string(format!("{dir}/privkey.pem")).unwrap();
+    assert_eq!(key, cert.serialize_private_key_pem());
+    let mode = std::fs::metadata(format!("{dir}/privkey.pem"))
+      .unwrap()
+      .permissions()
+      .mode();
+    assert_eq!(mode & 0o777, 0o600);
+
+    client
+      .delete_resource("get-started-cert", None)
+      .await
+      .unwrap();
+
+    // A certificate key path can't be used with a certificate ref
+    let mut invalid = resource.clone();
+    invalid.config["Rule"]["Http"]["Ssl"]["CertificateKey"] =
+      serde_json::json!("/random/path/to/key");
+    invalid.config["Rule"]["Http"]["Ssl"]["Certificate"] =
+      serde_json::json!("/random/path/to/cert");
+    assert!(utils::serialize_proxy_rule(&invalid).is_err());
+
+    let mut invalid = resource.clone();
+    invalid.config["Rule"]["Http"]["Ssl"] =
+      serde_json::json!({ "Certificate": "/random/path/to/cert" });
+    assert!(utils::serialize_proxy_rule(&invalid).is_err());
+  }
+
+  /// Render the fixtures of the rules with the resources of the daemon,
+  /// their directives are tested next to the generators
+  #[ntex::test]
+  async fn rules_render() {
+    use nanocld_client::stubs::cargo::CargoDeleteQuery;
+    use nanocld_client::stubs::cargo_config::CargoConfigPartial;
+
+    tests::before();
+    let client = NanocldClient::connect_with_unix_default();
+
+    let _ = client.delete_resource("get-started-users", None).await;
+    client
+      .create_resource(&ResourcePartial {
+        name: "get-started-users".into(),
+        kind: "Secret".into(),
+        version: "v0.1".into(),
+        config: serde_json::json!({
+          "Data": { "admin": "password" },
+        }),
+        namespace: None,
+        owner_refs: None,
+        labels: None,
+      })
+      .await
+      .unwrap();
+    let canary = CargoConfigPartial {
+      name: "get-started-v2".into(),
+      container: bollard_next::container::Config {
+        image: Some("nexthat/nanocl-get-started:latest".into()),
+        ..Default::default()
+      },
+      ..Default::default()
+    };
+    client.create_cargo(&canary, None).await.unwrap();
+    client.start_cargo("get-started-v2", None).await.unwrap();
+
+    let fixtures = [
+      (
+        "middleware",
+        include_str!("../tests/resource_middleware.yml"),
+      ),
+      ("split", include_str!("../tests/resource_split.yml")),
+      ("sni", include_str!("../tests/resource_sni.yml")),
+    ];
+    for (name, fixture) in fixtures {
+      let nginx = tests::render_nginx(name);
+      let resource = tests::parse_resource(fixture);
+      let conf = tests::render_resource(&nginx, &resource).await;
+      assert!(conf.contains("proxy_pass"), "{name} is not proxied");
+    }
+
+    client
+      .delete_resource("get-started-users", None)
+      .await
+      .unwrap();
+    client
+      .delete_cargo(
+        "get-started-v2",
+        &CargoDeleteQuery {
+          force: Some(true),
+          ..Default::default()
+        },
+      )
+      .await
+      .unwrap();
+  }
+
+  #[ntex::test]
+  async fn rules_invalid_nginx_config() {
+    let test_srv = tests::generate_server(ntex_config);
+
+    let resource: &str = include_str!("../tests/resource_redirect.yml");
+    let yaml: serde_yaml::Value = serde_yaml::from_str(resource).unwrap();
+    let resource = yaml["Resources"][0].clone();
+    let mut resource =
+      serde_yaml::from_value::<ResourcePartial>(resource).unwrap();
+    resource.name = "proxy-get-started-invalid".into();
+    // Pass our validation but is refused by nginx
+    resource.config["Rule"]["Http"]["Locations"][0]["RateLimit"] = serde_json::json!({
+      "Rate": "10r/s",
+      "Key": "$nanocl_unknown_variable",
+    });
+
+    let res = test_srv
+      .put(format!("/rules?Key={}", resource.name))
+      .send_json(&resource)
+      .await
+      .unwrap();
+
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    assert!(!std::path::Path::new(
+      "/tmp/nginx/sites-enabled/proxy-get-started-invalid.conf"
+    )
+    .exists());
+  }
+
+  #[ntex::test]
+  async fn rules_cache_purge() {
+    let test_srv = tests::generate_server(ntex_config);
+
+    let dir = "/tmp/nginx/state/cache/proxy-get-started-cache/0";
+    std::fs::create_dir_all(dir).unwrap();
+    std::fs::write(format!("{dir}/response"), "cached").unwrap();
+
+    let res = test_srv
+      .post("/rules/proxy-get-started-cache/cache/purge")
+      .send()
+      .await
+      .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(!std::path::Path::new(dir).exists());
   }
 }
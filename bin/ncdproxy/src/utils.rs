@@ -1,20 +1,44 @@
+use std::net::{IpAddr, Ipv4Addr};
+
 use nanocld_client::{
   NanocldClient,
   stubs::{
     proxy::{StreamTarget, ProxyStreamProtocol},
     cargo::{CargoInspect, CreateExecOptions},
-    resource::{ResourceQuery, ResourcePartial},
+    vm::VmInspect,
+    resource::{
+      ResourceQuery, ResourcePartial, ResourceOwnerRef, ResourceOwnerKind,
+    },
   },
 };
 
 use nanocld_client::stubs::proxy::{
   ProxyRuleHttp, CargoTarget, ProxyHttpLocation, ProxyRuleStream,
-  LocationTarget, ResourceProxyRule, ProxyRule,
+  LocationTarget, ResourceProxyRule, ProxyRule, ProxyHttpSsl, ProxySslConfig,
+  LoadBalancing, UriTarget, SplitTarget, SniRoute, VmTarget, ProxyNetwork,
 };
 
+use futures::StreamExt;
+use bollard_next::service::ContainerSummary;
+
+use crate::acme;
+use crate::middleware;
 use crate::error::ErrorHint;
 use crate::nginx::{Nginx, NginxConfKind};
 
+/// Check a domain name, a leading `*.` is accepted when `wildcard` is set
+pub(crate) fn is_domain(domain: &str, wildcard: bool) -> bool {
+  let host = match domain.strip_prefix("*.") {
+    Some(host) if wildcard => host,
+    _ => domain,
+  };
+  !host.is_empty()
+    && host.split('.').all(|label| {
+      !label.is_empty()
+        && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    })
+}
+
 /// Serialize a ProxyRule
 pub(crate) fn serialize_proxy_rule(
   resource: &ResourcePartial,
@@ -30,9 +54,190 @@ pub(crate) fn serialize_proxy_rule(
           ),
         )
       })?;
+  let ssl_configs = match &proxy_rule.rule {
+    ProxyRule::Http(rule) => {
+      validate_network(&resource.name, &rule.network)?;
+      for location in &rule.locations {
+        middleware::validate_location(location)?;
+        match &location.target {
+          LocationTarget::Cargo(target) => validate_cargo_target(target)?,
+          LocationTarget::Split(targets) => {
+            validate_split(&location.path, targets)?;
+            for target in targets {
+              validate_cargo_target(&target.cargo)?;
+            }
+          }
+          _ => {}
+        }
+      }
+      let is_acme = matches!(rule.ssl, Some(ProxyHttpSsl::Acme(_)));
+      match &rule.domain {
+        None if is_acme => {
+          return Err(ErrorHint::warning(
+            4,
+            format!(
+              "Unable to use acme for proxy rule {name}: a domain is required",
+              name = resource.name,
+            ),
+          ));
+        }
+        // Http-01 challenges can't issue wildcard certificates
+        Some(domain) if !is_domain(domain, !is_acme) => {
+          return Err(ErrorHint::warning(
+            4,
+            format!(
+              "Unable to parse proxy rule {name}: {domain} is not a valid domain",
+              name = resource.name,
+            ),
+          ));
+        }
+        _ => {}
+      }
+      match &rule.ssl {
+        Some(ProxyHttpSsl::Config(ssl)) => vec![ssl],
+        _ => vec![],
+      }
+    }
+    ProxyRule::Stream(rules) => {
+      for rule in rules {
+        validate_network(&resource.name, &rule.network)?;
+        if let StreamTarget::Cargo(target) = &rule.target {
+          validate_cargo_target(target)?;
+        }
+        if let StreamTarget::Sni(routes) = &rule.target {
+          validate_sni(&resource.name, rule, routes)?;
+          for route in routes {
+            validate_cargo_target(&route.cargo)?;
+          }
+        }
+        if let StreamTarget::Uri(target) = &rule.target {
+          validate_uri_target(target)?;
+          if target.ssl.is_some() && rule.protocol == ProxyStreamProtocol::Udp {
+            return Err(ErrorHint::warning(
+              4,
+              format!(
+                "Unable to use ssl to {} with udp for proxy rule {name}",
+                target.uri,
+                name = resource.name,
+              ),
+            ));
+          }
+        }
+      }
+      rules.iter().filter_map(|rule| rule.ssl.as_ref()).collect()
+    }
+  };
+  for ssl in ssl_configs {
+    let has_paths = ssl.certificate.is_some() && ssl.certificate_key.is_some();
+    if has_paths == ssl.certificate_ref.is_some() {
+      return Err(ErrorHint::warning(
+        4,
+        format!(
+          "Unable to parse ssl of proxy rule {name}: either a CertificateRef or a Certificate and a CertificateKey are required",
+          name = resource.name,
+        ),
+      ));
+    }
+  }
   Ok(proxy_rule)
 }
 
+/// Write a file only readable by the proxy
+/// Used for private keys and credentials
+pub(crate) fn write_private_file(
+  path: &str,
+  data: &str,
+) -> std::io::Result<()> {
+  use std::io::Write;
+  use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+  let mut file = std::fs::OpenOptions::new()
+    .write(true)
+    .create(true)
+    .truncate(true)
+    .mode(0o600)
+    .open(path)?;
+  // The mode is only used on creation
+  file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+  file.write_all(data.as_bytes())
+}
+
+/// Write the material of the `Certificate` resource referenced by a ssl config
+/// in the state directory and use its paths instead of the reference
+async fn resolve_certificate(
+  ssl: &mut ProxySslConfig,
+  namespace: &Option<String>,
+  client: &NanocldClient,
+  nginx: &Nginx,
+) -> Result<(), ErrorHint> {
+  let Some(name) = &ssl.certificate_ref else {
+    return Ok(());
+  };
+  let material = client
+    .inspect_resource_certificate(name, namespace.clone(), true)
+    .await
+    .map_err(|err| {
+      ErrorHint::warning(10, format!("Unable to get certificate {name}: {err}"))
+    })?;
+  let Some(certificate_key) = &material.certificate_key else {
+    return Err(ErrorHint::warning(
+      10,
+      format!("Unable to get the key of certificate {name}"),
+    ));
+  };
+  let dir = match namespace {
+    Some(namespace) => format!("{}/certs/{name}.{namespace}", nginx.files_dir),
+    None => format!("{}/certs/{name}", nginx.files_dir),
+  };
+  let write = |file: &str, data: &str| {
+    let path = format!("{dir}/{file}");
+    write_private_file(&path, data).map_err(|err| {
+      ErrorHint::error(
+        10,
+        format!("Unable to write certificate file {path}: {err}"),
+      )
+    })?;
+    Ok::<_, ErrorHint>(path)
+  };
+  std::fs::create_dir_all(&dir).map_err(|err| {
+    ErrorHint::error(
+      10,
+      format!("Unable to create directory {dir} got error : {err}"),
+    )
+  })?;
+  ssl.certificate = Some(write("fullchain.pem", &material.certificate)?);
+  ssl.certificate_key = Some(write("privkey.pem", certificate_key)?);
+  ssl.dh_param = match &material.dh_param {
+    Some(dh_param) => Some(write("dhparam.pem", dh_param)?),
+    None => ssl.dh_param.take(),
+  };
+  Ok(())
+}
+
+/// Resolve every `Certificate` resource referenced by a proxy rule
+async fn resolve_certificates(
+  proxy_rule: &mut ResourceProxyRule,
+  namespace: &Option<String>,
+  client: &NanocldClient,
+  nginx: &Nginx,
+) -> Result<(), ErrorHint> {
+  match &mut proxy_rule.rule {
+    ProxyRule::Http(rule) => {
+      if let Some(ProxyHttpSsl::Config(ssl)) = &mut rule.ssl {
+        resolve_certificate(ssl, namespace, client, nginx).await?;
+      }
+    }
+    ProxyRule::Stream(rules) => {
+      for rule in rules {
+        if let Some(ssl) = &mut rule.ssl {
+          resolve_certificate(ssl, namespace, client, nginx).await?;
+        }
+      }
+    }
+  }
+  Ok(())
+}
+
 async fn get_namespace_addr(
   name: &str,
   client: &NanocldClient,
@@ -65,79 +270,271 @@ async fn get_namespace_addr(
   Ok(ip_address)
 }
 
+/// Validate the addresses and interfaces names of a network
+fn validate_network(
+  name: &str,
+  network: &ProxyNetwork,
+) -> Result<(), ErrorHint> {
+  match network {
+    ProxyNetwork::Ip(ip_address) => {
+      parse_ip_address(name, ip_address)?;
+    }
+    ProxyNetwork::Interface(interface) => {
+      let valid = !interface.is_empty()
+        && interface.chars().all(|c| {
+          c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '@')
+        });
+      if !valid {
+        return Err(ErrorHint::warning(
+          4,
+          format!("Invalid interface {interface} for resource {name}"),
+        ));
+      }
+    }
+    _ => {}
+  }
+  Ok(())
+}
+
+fn parse_ip_address(name: &str, ip_address: &str) -> Result<IpAddr, ErrorHint> {
+  ip_address.parse::<IpAddr>().map_err(|err| {
+    ErrorHint::warning(
+      4,
+      format!("Invalid ip address {ip_address} for resource {name}: {err}"),
+    )
+  })
+}
+
+/// Parse the addresses of the output of `ip -o addr show`
+fn parse_interface_addrs(output: &str) -> Vec<IpAddr> {
+  output
+    .lines()
+    .filter_map(|line| {
+      let mut words = line.split_whitespace();
+      words.find(|word| *word == "inet" || *word == "inet6")?;
+      let (ip_address, _) = words.next()?.split_once('/')?;
+      ip_address.parse::<IpAddr>().ok()
+    })
+    .collect()
+}
+
+/// Get the addresses of a network interface of the node
+/// The proxy is running on the network of the node so we ask him
+async fn get_interface_addrs(
+  name: &str,
+  interface: &str,
+  client: &NanocldClient,
+) -> Result<Vec<IpAddr>, ErrorHint> {
+  let cmd = [
+    "ip", "-o", "addr", "show", "dev", interface, "scope", "global",
+  ];
+  let output =
+    exec_proxy(client, cmd.into_iter().map(String::from).collect()).await?;
+  let ip_addresses = parse_interface_addrs(&output);
+  if ip_addresses.is_empty() {
+    return Err(ErrorHint::warning(
+      4,
+      format!(
+        "Unable to find an address of interface {interface} for resource {name}: {}",
+        output.trim()
+      ),
+    ));
+  }
+  Ok(ip_addresses)
+}
+
+/// Format a listen address, ipv6 addresses are enclosed in brackets
+fn format_listen(ip_address: &IpAddr, port: u16) -> String {
+  match ip_address {
+    IpAddr::V4(ip_address) => format!("{ip_address}:{port}"),
+    IpAddr::V6(ip_address) => format!("[{ip_address}]:{port}"),
+  }
+}
+
+/// Get the addresses to listen on for the network of a rule
 async fn get_listen(
   name: &str,
-  network: &str,
+  network: &ProxyNetwork,
   port: u16,
   client: &NanocldClient,
-) -> Result<String, ErrorHint> {
-  match network {
-    "Public" => Ok(format!("{port}")),
-    "Internal" => Ok(format!("127.0.0.1:{port}")),
-    network if network.starts_with("Nsp.") => {
-      let namespace = network.trim_start_matches("Nsp.");
+) -> Result<Vec<String>, ErrorHint> {
+  let ip_addresses = match network {
+    ProxyNetwork::Public => return Ok(vec![format!("{port}")]),
+    ProxyNetwork::Internal => vec![IpAddr::V4(Ipv4Addr::LOCALHOST)],
+    ProxyNetwork::Private => {
+      let info = client.info().await.map_err(|err| {
+        ErrorHint::warning(
+          5,
+          format!("Unable to get the advertised address of the node: {err}"),
+        )
+      })?;
+      vec![parse_ip_address(name, &info.advertise_addr)?]
+    }
+    ProxyNetwork::Nsp(namespace) => {
       let ip_address = get_namespace_addr(namespace, client).await?;
-      Ok(format!("{ip_address}:{port}"))
+      vec![parse_ip_address(name, &ip_address)?]
     }
-    _ => Err(ErrorHint::warning(
-      4,
-      format!("Unsupported network {network} for resource {name}"),
-    )),
+    ProxyNetwork::Ip(ip_address) => vec![parse_ip_address(name, ip_address)?],
+    ProxyNetwork::Interface(interface) => {
+      get_interface_addrs(name, interface, client).await?
+    }
+  };
+  Ok(
+    ip_addresses
+      .iter()
+      .map(|ip_address| format_listen(ip_address, port))
+      .collect(),
+  )
+}
+
+/// Generate the listen directives of a server block
+fn gen_listen(listen: &[String], params: &str) -> String {
+  listen
+    .iter()
+    .map(|address| format!("  listen {address}{params};\n"))
+    .collect()
+}
+
+/// Generate the load balancing directive of an upstream
+/// `ip_hash` doesn't exist for streams so the client address is hashed
+fn gen_load_balancing(
+  kind: &NginxConfKind,
+  load_balancing: &Option<LoadBalancing>,
+) -> String {
+  match load_balancing {
+    None => "  hash $remote_addr consistent;\n".into(),
+    Some(LoadBalancing::RoundRobin) => String::default(),
+    Some(LoadBalancing::LeastConn) => "  least_conn;\n".into(),
+    Some(LoadBalancing::IpHash) => match kind {
+      NginxConfKind::Site => "  ip_hash;\n".into(),
+      NginxConfKind::Stream => "  hash $remote_addr;\n".into(),
+    },
+    Some(LoadBalancing::Hash { key }) => format!("  hash {key};\n"),
+    Some(LoadBalancing::Random) => "  random;\n".into(),
+  }
+}
+
+/// Check a number followed by one of the given units
+pub(crate) fn is_unit(value: &str, units: &[&str]) -> bool {
+  let digits = value.trim_end_matches(|c: char| !c.is_ascii_digit());
+  let unit = &value[digits.len()..];
+  !digits.is_empty()
+    && digits.chars().all(|c| c.is_ascii_digit())
+    && (unit.is_empty() || units.contains(&unit))
+}
+
+/// Validate the upstream options of a cargo target
+/// since they are rendered as is in the nginx config
+fn validate_cargo_target(target: &CargoTarget) -> Result<(), ErrorHint> {
+  if let Some(LoadBalancing::Hash { key }) = &target.load_balancing {
+    let is_invalid = |c: char| {
+      c.is_whitespace() || [';', '{', '}', '"', '\'', '#'].contains(&c)
+    };
+    if key.is_empty() || key.contains(is_invalid) {
+      return Err(ErrorHint::warning(
+        4,
+        format!("Invalid hash key {key} of target {}", target.key),
+      ));
+    }
+  }
+  if let Some(fail_timeout) = &target.fail_timeout {
+    if !is_unit(fail_timeout, &["ms", "s", "m", "h"]) {
+      return Err(ErrorHint::warning(
+        4,
+        format!(
+          "Invalid fail timeout {fail_timeout} of target {}",
+          target.key
+        ),
+      ));
+    }
+  }
+  Ok(())
+}
+
+/// Generate the parameters of each server of an upstream
+fn gen_server_params(target: &CargoTarget) -> String {
+  let mut params = String::new();
+  if let Some(max_fails) = target.max_fails {
+    params += &format!(" max_fails={max_fails}");
   }
+  if let Some(fail_timeout) = &target.fail_timeout {
+    params += &format!(" fail_timeout={fail_timeout}");
+  }
+  params
 }
 
+/// Get the ip address of a container on the network of his namespace
+/// The key is the key of the cargo or the vm owning the container
+fn container_ip_address(
+  container: &ContainerSummary,
+  key: &str,
+  namespace: &str,
+) -> Result<String, ErrorHint> {
+  let networks = container
+    .network_settings
+    .clone()
+    .unwrap_or_default()
+    .networks
+    .unwrap_or_default();
+  let network = networks.get(namespace).ok_or(ErrorHint::warning(
+    5,
+    format!(
+      "Unable to find network for container {} for {key} in namespace {namespace}",
+      container.id.clone().unwrap_or_default(),
+    ),
+  ))?;
+  let ip_address = network.ip_address.clone().ok_or(ErrorHint::warning(
+    5,
+    format!(
+      "Unable to find ip address for container {} for {key} in namespace {namespace}",
+      container.id.clone().unwrap_or_default(),
+    ),
+  ))?;
+  Ok(ip_address)
+}
+
+/// Create the upstream of a cargo target
+/// The upstream is scoped to the rule and the location using it,
+/// so targets of the same cargo with different options don't overwrite
+/// each other
 fn create_cargo_upstream(
   kind: &NginxConfKind,
-  port: u16,
+  scope: &str,
+  target: &CargoTarget,
   cargo: &CargoInspect,
   nginx: &Nginx,
 ) -> Result<String, ErrorHint> {
+  let port = target.port;
   let ip_addresses = cargo
     .instances
     .iter()
     .map(|node_container| {
-      let container = node_container.container.clone();
-      let networks = container
-        .network_settings
-        .clone()
-        .unwrap_or_default()
-        .networks
-        .unwrap_or_default();
-      let network =
-        networks
-          .get(&cargo.namespace_name)
-          .ok_or(ErrorHint::warning(
-            5,
-            format!(
-      "Unable to find network for container {} for cargo {} in namespace {}",
-      container.id.clone().unwrap_or_default(),
-      cargo.key,
-      cargo.namespace_name,
-    ),
-          ))?;
-      let ip_address = network.ip_address.clone().ok_or(ErrorHint::warning(
-        5,
-        format!(
-      "Unable to find ip address for container {} for cargo {} in namespace {}",
-      container.id.unwrap_or_default(),
-      cargo.key,
-      cargo.namespace_name,
-    ),
-      ))?;
-      Ok::<_, ErrorHint>(ip_address)
+      container_ip_address(
+        &node_container.container,
+        &cargo.key,
+        &cargo.namespace_name,
+      )
     })
     .collect::<Result<Vec<String>, ErrorHint>>()?;
-  let upstream_key = format!("{}-{}", cargo.key, port);
+  // Nginx refuse an upstream without server
+  if ip_addresses.is_empty() {
+    return Err(ErrorHint::warning(
+      5,
+      format!("Unable to find a running instance for cargo {}", cargo.key),
+    ));
+  }
+  let upstream_key = format!("{scope}-{}-{port}", cargo.key);
+  let load_balancing = gen_load_balancing(kind, &target.load_balancing);
+  let params = gen_server_params(target);
   let upstream = format!(
     "
 upstream {upstream_key} {{
-  hash $remote_addr consistent;
-{}
+{load_balancing}{}
 }}
 ",
     ip_addresses
       .iter()
-      .map(|ip_address| format!("  server {ip_address}:{port};"))
+      .map(|ip_address| format!("  server {ip_address}:{port}{params};"))
       .collect::<Vec<String>>()
       .join("\n")
   );
@@ -147,11 +544,11 @@ upstream {upstream_key} {{
 
 async fn gen_cargo_upstream(
   kind: &NginxConfKind,
+  scope: &str,
   target: &CargoTarget,
   client: &NanocldClient,
   nginx: &Nginx,
 ) -> Result<String, ErrorHint> {
-  let port = target.port;
   let (cargo_name, namespace) = extract_target_cargo(&target.key)?;
   let cargo = client
     .inspect_cargo(&cargo_name, Some(namespace.clone()))
@@ -164,7 +561,100 @@ async fn gen_cargo_upstream(
       ),
       )
     })?;
-  create_cargo_upstream(kind, port, &cargo, nginx)
+  create_cargo_upstream(kind, scope, target, &cargo, nginx)
+}
+
+/// Create the upstream of a vm from the ip address of his runtime container
+fn create_vm_upstream(
+  kind: &NginxConfKind,
+  target: &VmTarget,
+  vm: &VmInspect,
+  nginx: &Nginx,
+) -> Result<String, ErrorHint> {
+  let port = target.port;
+  let container = vm.instances.first().ok_or(ErrorHint::warning(
+    5,
+    format!("Unable to find a running instance for vm {}", vm.key),
+  ))?;
+  let ip_address =
+    container_ip_address(container, &vm.key, &vm.namespace_name)?;
+  let upstream_key = format!("vm-{}-{port}", vm.key);
+  let upstream = format!(
+    "
+upstream {upstream_key} {{
+  server {ip_address}:{port};
+}}
+"
+  );
+  nginx.write_conf_file(&upstream_key, &upstream, kind)?;
+  Ok(upstream_key)
+}
+
+async fn gen_vm_upstream(
+  kind: &NginxConfKind,
+  target: &VmTarget,
+  client: &NanocldClient,
+  nginx: &Nginx,
+) -> Result<String, ErrorHint> {
+  let (vm_name, namespace) = extract_target_cargo(&target.key)?;
+  let vm = client
+    .inspect_vm(&vm_name, Some(namespace.clone()))
+    .await
+    .map_err(|err| {
+      ErrorHint::warning(
+        6,
+        format!(
+          "Unable to inspect vm {vm_name} in namespace {namespace}: {err}"
+        ),
+      )
+    })?;
+  create_vm_upstream(kind, target, &vm, nginx)
+}
+
+/// Resolve the owners of a rule from the cargoes and vms it watch
+/// A key is owned by a vm when the rule target this vm or when a vm
+/// with this key exists, otherwise it's owned by a cargo
+pub(crate) async fn gen_owner_refs(
+  proxy_rule: &ResourceProxyRule,
+  client: &NanocldClient,
+) -> Vec<ResourceOwnerRef> {
+  let vm_targets = match &proxy_rule.rule {
+    ProxyRule::Http(rule) => rule
+      .locations
+      .iter()
+      .filter_map(|location| match &location.target {
+        LocationTarget::Vm(target) => Some(target.key.clone()),
+        _ => None,
+      })
+      .collect::<Vec<_>>(),
+    ProxyRule::Stream(rules) => rules
+      .iter()
+      .filter_map(|rule| match &rule.target {
+        StreamTarget::Vm(target) => Some(target.key.clone()),
+        _ => None,
+      })
+      .collect::<Vec<_>>(),
+  };
+  let mut owner_refs = Vec::new();
+  for key in &proxy_rule.watch {
+    let is_vm = vm_targets.contains(key)
+      || match extract_target_cargo(key) {
+        Ok((name, namespace)) => {
+          client.inspect_vm(&name, Some(namespace)).await.is_ok()
+        }
+        Err(_) => false,
+      };
+    let kind = if is_vm {
+      ResourceOwnerKind::Vm
+    } else {
+      ResourceOwnerKind::Cargo
+    };
+    owner_refs.push(ResourceOwnerRef {
+      kind,
+      key: key.clone(),
+    });
+  }
+  owner_refs
 }
 
 fn extract_target_cargo(key: &str) -> Result<(String, String), ErrorHint> {
@@ -196,19 +686,141 @@ async fn gen_unix_stream(
   Ok(upstream_key)
 }
 
+/// Validate the cargoes of a split target
+fn validate_split(
+  path: &str,
+  targets: &[SplitTarget],
+) -> Result<(), ErrorHint> {
+  if targets.iter().all(|target| target.weight == 0) {
+    return Err(ErrorHint::warning(
+      4,
+      format!("Invalid split for location {path}: a weight must be positive"),
+    ));
+  }
+  for (index, target) in targets.iter().enumerate() {
+    let key = &target.cargo.key;
+    if targets[..index].iter().any(|other| &other.cargo.key == key) {
+      return Err(ErrorHint::warning(
+        4,
+        format!("Invalid split for location {path}: {key} is duplicated"),
+      ));
+    }
+  }
+  Ok(())
+}
+
+/// Generate a `split_clients` block sharing the requests between upstreams
+/// Returns the variable containing the selected upstream and the block
+fn gen_split_clients(
+  key: &str,
+  index: usize,
+  upstreams: &[(u16, String)],
+) -> (String, String) {
+  let variable = format!(
+    "nanocl_split_{}_{index}",
+    key.replace(|c: char| !c.is_ascii_alphanumeric(), "_")
+  );
+  let total = upstreams
+    .iter()
+    .map(|(weight, _)| *weight as f64)
+    .sum::<f64>();
+  let last = upstreams.len().saturating_sub(1);
+  let entries = upstreams
+    .iter()
+    .enumerate()
+    .map(|(entry, (weight, upstream_key))| {
+      // The last entry take the rest to avoid rounding errors
+      if entry == last {
+        return format!("  * {upstream_key};\n");
+      }
+      let percent = *weight as f64 * 100.0 / total;
+      format!("  {percent:.2}% {upstream_key};\n")
+    })
+    .collect::<String>();
+  let split_clients = format!(
+    "
+split_clients \"${{remote_addr}}${{http_user_agent}}\" ${variable} {{
+{entries}}}
+"
+  );
+  (variable, split_clients)
+}
+
+/// Generate the locations of an http rule
+/// Returns the blocks needed in the http context and the locations
 async fn gen_locations(
-  location_rules: &Vec<ProxyHttpLocation>,
+  key: &str,
+  namespace: &Option<String>,
+  location_rules: &[ProxyHttpLocation],
   client: &NanocldClient,
   nginx: &Nginx,
-) -> Result<Vec<String>, ErrorHint> {
+) -> Result<(String, Vec<String>), ErrorHint> {
+  let mut splits = String::new();
   let mut locations = Vec::new();
-  for rule in location_rules {
+  for (index, rule) in location_rules.iter().enumerate() {
     let path = &rule.path;
+    let options = middleware::gen_location_options(
+      key, index, rule, namespace, client, nginx,
+    )
+    .await?;
 
     match &rule.target {
       LocationTarget::Cargo(cargo_target) => {
+        let upstream_key = gen_cargo_upstream(
+          &NginxConfKind::Site,
+          &format!("{key}-{index}"),
+          cargo_target,
+          client,
+          nginx,
+        )
+        .await?;
+        let location = format!(
+          "
+  location {path} {{
+    proxy_pass http://{upstream_key};
+    proxy_set_header Host $host;
+    proxy_set_header X-Forwarded-Scheme $scheme;
+    proxy_set_header X-Forwarded-Proto  $scheme;
+    proxy_set_header X-Forwarded-For    $proxy_add_x_forwarded_for;
+    proxy_set_header X-Real-IP          $remote_addr;
+{options}  }}"
+        );
+        locations.push(location);
+      }
+      LocationTarget::Split(targets) => {
+        let mut upstreams = Vec::new();
+        // Cargoes without weight are not receiving requests
+        for target in targets.iter().filter(|target| target.weight > 0) {
+          // The cargoes of a split are unique
+          let upstream_key = gen_cargo_upstream(
+            &NginxConfKind::Site,
+            &format!("{key}-{index}"),
+            &target.cargo,
+            client,
+            nginx,
+          )
+          .await?;
+          upstreams.push((target.weight, upstream_key));
+        }
+        let (variable, split_clients) =
+          gen_split_clients(key, index, &upstreams);
+        splits += &split_clients;
+        let location = format!(
+          "
+  location {path} {{
+    proxy_pass http://${variable};
+    proxy_set_header Host $host;
+    proxy_set_header X-Forwarded-Scheme $scheme;
+    proxy_set_header X-Forwarded-Proto  $scheme;
+    proxy_set_header X-Forwarded-For    $proxy_add_x_forwarded_for;
+    proxy_set_header X-Real-IP          $remote_addr;
+{options}  }}"
+        );
+        locations.push(location);
+      }
+      LocationTarget::Vm(vm_target) => {
         let upstream_key =
-          gen_cargo_upstream(&NginxConfKind::Site, cargo_target, client, nginx)
+          gen_vm_upstream(&NginxConfKind::Site, vm_target, client, nginx)
             .await?;
         let location = format!(
           "
@@ -219,7 +831,7 @@ async fn gen_locations(
     proxy_set_header X-Forwarded-Proto  $scheme;
     proxy_set_header X-Forwarded-For    $proxy_add_x_forwarded_for;
     proxy_set_header X-Real-IP          $remote_addr;
-  }}"
+{options}  }}"
         );
         locations.push(location);
       }
@@ -233,7 +845,7 @@ async fn gen_locations(
     proxy_set_header X-Forwarded-Proto  $scheme;
     proxy_set_header X-Forwarded-For    $proxy_add_x_forwarded_for;
     proxy_set_header X-Real-IP          $remote_addr;
-  }}
+{options}  }}
   "
         );
         locations.push(location);
@@ -245,7 +857,7 @@ async fn gen_locations(
             format!(
               "
   location {path} {{
-    return {redirect} {url};
+{options}    return {redirect} {url};
   }}
 "
             )
@@ -255,7 +867,7 @@ async fn gen_locations(
               "
   location {path} {{
     proxy_pass {url};
-  }}
+{options}  }}
 "
             )
           }
@@ -264,48 +876,103 @@ async fn gen_locations(
       }
     }
   }
-  Ok(locations)
+  Ok((splits, locations))
+}
+
+/// Generate the ssl part of an http server block
+/// When `acme` is true the challenges are not redirected to https
+fn gen_http_ssl(
+  listen_https: &[String],
+  certificate: &str,
+  certificate_key: &str,
+  dh_param: &Option<String>,
+  acme: bool,
+) -> String {
+  let ssl_dh_param = match dh_param {
+    Some(ssl_dh_param) => {
+      format!("\n  ssl_dhparam          {ssl_dh_param};\n")
+    }
+    None => String::default(),
+  };
+  let redirect = if acme {
+    "
+  set $redirect_https \"\";
+  if ($scheme != https) {
+    set $redirect_https 1;
+  }
+  if ($request_uri ~ \"^/\\.well-known/acme-challenge/\") {
+    set $redirect_https \"\";
+  }
+  if ($redirect_https) {
+    return 301 https://$host$request_uri;
+  }"
+  } else {
+    "
+  if ($scheme != https) {
+    return 301 https://$host$request_uri;
+  }"
+  };
+  let listen = gen_listen(listen_https, " http2 ssl");
+  format!(
+    "
+{listen}{redirect}
+
+  ssl_certificate      {certificate};
+  ssl_certificate_key  {certificate_key};{ssl_dh_param}
+    "
+  )
 }
 
 async fn gen_http_server_block(
   name: &str,
+  namespace: &Option<String>,
   rule: &ProxyRuleHttp,
   client: &NanocldClient,
   nginx: &Nginx,
 ) -> Result<String, ErrorHint> {
   let listen_http = get_listen(name, &rule.network, 80, client).await?;
-  let locations = gen_locations(&rule.locations, client, nginx)
-    .await?
-    .join("\n");
+  let (splits, locations) =
+    gen_locations(name, namespace, &rule.locations, client, nginx).await?;
+  let locations = locations.join("\n");
+  let zones = middleware::gen_rate_limit_zones(name, &rule.locations);
+  let caches = middleware::gen_cache_paths(name, &rule.locations, nginx);
   let http_host = match &rule.domain {
     Some(domain) => format!("  server_name {domain};"),
     None => String::default(),
   };
 
-  let ssl = if let Some(ssl) = &rule.ssl {
-    let certificate = &ssl.certificate;
-    let certificate_key = &ssl.certificate_key;
-    let ssl_dh_param = match &ssl.dh_param {
-      Some(ssl_dh_param) => {
-        format!("\n  ssl_dhparam          {ssl_dh_param};\n")
+  let ssl = match &rule.ssl {
+    Some(ProxyHttpSsl::Config(ssl)) => {
+      let listen_https = get_listen(name, &rule.network, 443, client).await?;
+      gen_http_ssl(
+        &listen_https,
+        &ssl.certificate.clone().unwrap_or_default(),
+        &ssl.certificate_key.clone().unwrap_or_default(),
+        &ssl.dh_param,
+        false,
+      )
+    }
+    Some(ProxyHttpSsl::Acme(_)) => {
+      let domain = rule.domain.clone().unwrap_or_default();
+      let challenge = acme::gen_challenge_location(nginx);
+      // Serve the challenges over http until a certificate is issued
+      if acme::has_certificate(nginx, &domain) {
+        let (certificate, certificate_key) =
+          acme::certificate_paths(nginx, &domain);
+        let listen_https = get_listen(name, &rule.network, 443, client).await?;
+        let ssl = gen_http_ssl(
+          &listen_https,
+          &certificate,
+          &certificate_key,
+          &None,
+          true,
+        );
+        format!("{challenge}{ssl}")
+      } else {
+        challenge
       }
-      None => String::default(),
-    };
-    let listen_https = get_listen(name, &rule.network, 443, client).await?;
-    format!(
-      "
-  listen {listen_https} http2 ssl;
-
-  if ($scheme != https) {{
-    return 301 https://$host$request_uri;
-  }}
-
-  ssl_certificate      {certificate};
-  ssl_certificate_key  {certificate_key};{ssl_dh_param}
-    "
-    )
-  } else {
-    String::default()
+    }
+    None => String::default(),
   };
 
   let includes = match &rule.includes {
@@ -317,43 +984,260 @@ async fn gen_http_server_block(
     None => String::default(),
   };
 
+  let listen_http = gen_listen(&listen_http, "");
   let conf = format!(
-    "
+    "{zones}{caches}{splits}
 server {{
-  listen {listen_http};
-{http_host}{ssl}{includes}
+{listen_http}{http_host}{ssl}{includes}
 {locations}
 }}\n",
   );
   Ok(conf)
 }
 
+/// Parse the host and the port of a stream uri like `tcp://host:port`
+fn parse_stream_uri(uri: &str) -> Result<(String, u16), ErrorHint> {
+  let invalid =
+    || ErrorHint::warning(4, format!("Invalid uri {uri} expect host:port"));
+  let addr = uri
+    .strip_prefix("tcp://")
+    .or_else(|| uri.strip_prefix("udp://"))
+    .unwrap_or(uri);
+  let (host, port) = addr.rsplit_once(':').ok_or_else(invalid)?;
+  let port = port.parse::<u16>().map_err(|_| invalid())?;
+  let is_host = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '.';
+  let valid_host = match host.strip_prefix('[') {
+    Some(ipv6) => ipv6
+      .strip_suffix(']')
+      .map_or(false, |ip| ip.parse::<std::net::Ipv6Addr>().is_ok()),
+    None => !host.is_empty() && host.chars().all(is_host),
+  };
+  if !valid_host {
+    return Err(invalid());
+  }
+  Ok((host.to_owned(), port))
+}
+
+/// Validate the options of an uri target rendered in the nginx config
+fn validate_uri_target(target: &UriTarget) -> Result<(), ErrorHint> {
+  let invalid = |msg: String| {
+    ErrorHint::warning(4, format!("Invalid uri target {}: {msg}", target.uri))
+  };
+  parse_stream_uri(&target.uri)?;
+  if let Some(resolver) = &target.resolver {
+    if resolver.is_empty() {
+      return Err(invalid("a resolver address is required".into()));
+    }
+    for addr in resolver {
+      if addr.parse::<std::net::IpAddr>().is_err()
+        && addr.parse::<std::net::SocketAddr>().is_err()
+      {
+        return Err(invalid(format!("resolver {addr} is not an ip address")));
+      }
+    }
+  }
+  let Some(ssl) = &target.ssl else {
+    return Ok(());
+  };
+  if let Some(server_name) = &ssl.server_name {
+    if !is_domain(server_name, false) {
+      return Err(invalid(format!("{server_name} is not a valid server name")));
+    }
+  }
+  if let Some(path) = &ssl.trusted_certificate {
+    let is_path = |c: char| c.is_ascii_alphanumeric() || "/-_.".contains(c);
+    if !path.starts_with('/')
+      || !path.chars().all(is_path)
+      || path.split('/').any(|part| part == "..")
+    {
+      return Err(invalid(format!(
+        "trusted certificate {path} must be an absolute path"
+      )));
+    }
+  }
+  Ok(())
+}
+
+/// Generate the proxy pass of a stream rule targeting an uri
+/// Return a tuple of (map block, server directives)
+/// When a resolver is set the address is stored in a variable so nginx
+/// resolve it while running instead of only when the config is loaded
+fn gen_uri_stream(
+  resource_name: &str,
+  index: usize,
+  target: &UriTarget,
+) -> Result<(String, String), ErrorHint> {
+  let (host, port) = parse_stream_uri(&target.uri)?;
+  let addr = format!("{host}:{port}");
+  let (map, mut directives) = match &target.resolver {
+    Some(resolver) if host.parse::<std::net::IpAddr>().is_err() => {
+      let variable = format!(
+        "nanocl_{}_{index}",
+        resource_name.replace(|c: char| !c.is_ascii_alphanumeric(), "_")
+      );
+      let map = format!(
+        "
+map \"\" ${variable} {{
+  default {addr};
+}}
+"
+      );
+      let directives = format!(
+        "  resolver {} valid=30s;\n  proxy_pass ${variable};\n",
+        resolver.join(" ")
+      );
+      (map, directives)
+    }
+    _ => (String::default(), format!("  proxy_pass {addr};\n")),
+  };
+  if let Some(ssl) = &target.ssl {
+    let server_name = ssl.server_name.clone().unwrap_or(host);
+    directives += "  proxy_ssl on;\n";
+    directives += "  proxy_ssl_server_name on;\n";
+    directives += &format!("  proxy_ssl_name {server_name};\n");
+    if ssl.verify.unwrap_or(true) {
+      let trusted_certificate = ssl
+        .trusted_certificate
+        .clone()
+        .unwrap_or("/etc/ssl/certs/ca-certificates.crt".into());
+      directives += "  proxy_ssl_verify on;\n";
+      directives +=
+        &format!("  proxy_ssl_trusted_certificate {trusted_certificate};\n");
+    }
+  }
+  Ok((map, directives))
+}
+
+/// Validate the routes of a sni target
+/// Tls is passed through so the rule must be tcp without ssl
+fn validate_sni(
+  name: &str,
+  rule: &ProxyRuleStream,
+  routes: &[SniRoute],
+) -> Result<(), ErrorHint> {
+  let invalid = |msg: String| {
+    ErrorHint::warning(
+      4,
+      format!("Invalid sni target for proxy rule {name}: {msg}"),
+    )
+  };
+  if rule.protocol != ProxyStreamProtocol::Tcp || rule.ssl.is_some() {
+    return Err(invalid("the protocol must be Tcp without Ssl".into()));
+  }
+  if routes.is_empty() {
+    return Err(invalid("a route is required".into()));
+  }
+  let is_host = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '.';
+  for (index, route) in routes.iter().enumerate() {
+    if route.domains.is_empty() {
+      return Err(invalid(format!("route {} has no domain", route.cargo.key)));
+    }
+    for domain in &route.domains {
+      let host = domain.strip_prefix("*.").unwrap_or(domain);
+      if host.is_empty() || !host.chars().all(is_host) {
+        return Err(invalid(format!("{domain} is not a valid domain")));
+      }
+      let duplicated = routes[..index]
+        .iter()
+        .chain(std::iter::once(route))
+        .flat_map(|other| other.domains.iter())
+        .filter(|other| other.eq_ignore_ascii_case(domain))
+        .count()
+        > 1;
+      if duplicated {
+        return Err(invalid(format!("{domain} is duplicated")));
+      }
+    }
+  }
+  Ok(())
+}
+
+/// Generate the proxy pass of a stream rule routing by server name
+/// Return a tuple of (map block, server directives)
+/// A connection without a matching server name is closed
+fn gen_sni_map(
+  resource_name: &str,
+  index: usize,
+  routes: &[(Vec<String>, String)],
+) -> (String, String) {
+  let variable = format!(
+    "nanocl_sni_{}_{index}",
+    resource_name.replace(|c: char| !c.is_ascii_alphanumeric(), "_")
+  );
+  let entries = routes
+    .iter()
+    .flat_map(|(domains, upstream_key)| {
+      domains
+        .iter()
+        .map(move |domain| format!("  {domain} {upstream_key};\n"))
+    })
+    .collect::<String>();
+  let map = format!(
+    "
+map $ssl_preread_server_name ${variable} {{
+  hostnames;
+{entries}}}
+"
+  );
+  let directives = format!("  ssl_preread on;\n  proxy_pass ${variable};\n");
+  (map, directives)
+}
+
 async fn gen_stream_server_block(
   resource_name: &str,
+  index: usize,
   rule: &ProxyRuleStream,
   client: &NanocldClient,
   nginx: &Nginx,
 ) -> Result<String, ErrorHint> {
   let port = rule.port;
-  let mut listen =
-    get_listen(resource_name, &rule.network, port, client).await?;
+  let listen = get_listen(resource_name, &rule.network, port, client).await?;
 
-  let upstream_key = match &rule.target {
+  let (map, proxy_pass) = match &rule.target {
     StreamTarget::Cargo(cargo_target) => {
-      gen_cargo_upstream(&NginxConfKind::Stream, cargo_target, client, nginx)
-        .await?
+      let upstream_key = gen_cargo_upstream(
+        &NginxConfKind::Stream,
+        &format!("{resource_name}-{index}"),
+        cargo_target,
+        client,
+        nginx,
+      )
+      .await?;
+      (String::default(), format!("  proxy_pass {upstream_key};\n"))
+    }
+    StreamTarget::Vm(vm_target) => {
+      let upstream_key =
+        gen_vm_upstream(&NginxConfKind::Stream, vm_target, client, nginx)
+          .await?;
+      (String::default(), format!("  proxy_pass {upstream_key};\n"))
     }
     StreamTarget::Unix(unix_target) => {
-      gen_unix_stream(unix_target, nginx).await?
+      let upstream_key = gen_unix_stream(unix_target, nginx).await?;
+      (String::default(), format!("  proxy_pass {upstream_key};\n"))
+    }
+    StreamTarget::Uri(uri_target) => {
+      gen_uri_stream(resource_name, index, uri_target)?
     }
-    StreamTarget::Uri(_) => {
-      return Err(ErrorHint::error(99, "Not implemented".into()))
+    StreamTarget::Sni(routes) => {
+      let mut upstreams = Vec::new();
+      for (route_index, route) in routes.iter().enumerate() {
+        let upstream_key = gen_cargo_upstream(
+          &NginxConfKind::Stream,
+          &format!("{resource_name}-{index}-{route_index}"),
+          &route.cargo,
+          client,
+          nginx,
+        )
+        .await?;
+        upstreams.push((route.domains.clone(), upstream_key));
+      }
+      gen_sni_map(resource_name, index, &upstreams)
     }
   };
 
   let ssl = if let Some(ssl) = &rule.ssl {
-    let certificate = &ssl.certificate;
-    let certificate_key = &ssl.certificate_key;
+    let certificate = ssl.certificate.clone().unwrap_or_default();
+    let certificate_key = ssl.certificate_key.clone().unwrap_or_default();
     let ssl_dh_param = match &ssl.dh_param {
       Some(ssl_dh_param) => {
         format!("\n  ssl_dhparam          {ssl_dh_param};\n")
@@ -369,15 +1253,14 @@ async fn gen_stream_server_block(
   } else {
     String::default()
   };
-  if rule.protocol == ProxyStreamProtocol::Udp {
-    listen = format!("{} udp", listen);
-  }
+  let listen = match rule.protocol {
+    ProxyStreamProtocol::Udp => gen_listen(&listen, " udp"),
+    ProxyStreamProtocol::Tcp => gen_listen(&listen, ""),
+  };
   let conf = format!(
-    "
+    "{map}
 server {{
-  listen {listen};
-  proxy_pass {upstream_key};
-{ssl}
+{listen}{proxy_pass}{ssl}
 }}
 "
   );
@@ -392,18 +1275,21 @@ async fn resource_to_nginx_conf(
   client: &NanocldClient,
   nginx: &Nginx,
   name: &str,
+  namespace: &Option<String>,
   resource_proxy: &ResourceProxyRule,
 ) -> Result<(NginxConfKind, String), ErrorHint> {
   let conf = match &resource_proxy.rule {
     ProxyRule::Http(rule) => {
-      let conf = gen_http_server_block(name, rule, client, nginx).await?;
+      let conf =
+        gen_http_server_block(name, namespace, rule, client, nginx).await?;
       (NginxConfKind::Site, conf)
     }
     ProxyRule::Stream(rules) => {
       let mut conf = String::new();
 
-      for rule in rules {
-        conf += &gen_stream_server_block(name, rule, client, nginx).await?;
+      for (index, rule) in rules.iter().enumerate() {
+        conf +=
+          &gen_stream_server_block(name, index, rule, client, nginx).await?;
       }
 
       (NginxConfKind::Stream, conf)
@@ -435,19 +1321,108 @@ pub(crate) async fn reload_config(
   Ok(())
 }
 
+/// Execute a command inside the proxy and return his output
+async fn exec_proxy(
+  client: &NanocldClient,
+  cmd: Vec<String>,
+) -> Result<String, ErrorHint> {
+  let exec = CreateExecOptions {
+    cmd: Some(cmd),
+    attach_stdout: Some(true),
+    attach_stderr: Some(true),
+    ..Default::default()
+  };
+  let mut stream = client
+    .exec_cargo("nproxy", exec, Some("system".into()))
+    .await
+    .map_err(|err| {
+      ErrorHint::warning(98, format!("Unable to exec in proxy: {err}"))
+    })?;
+  let mut output = String::new();
+  while let Some(log) = stream.next().await {
+    if let Ok(log) = log {
+      output.push_str(&log.data);
+    }
+  }
+  Ok(output)
+}
+
+/// Test the configuration rendered in a staging directory
+/// This function will run `nginx -t` inside the proxy with the includes
+/// of the main configuration pointing to the staging directory
+pub(crate) async fn test_config(
+  client: &NanocldClient,
+  staging: &Nginx,
+) -> Result<(), ErrorHint> {
+  let conf_dir = &staging.conf_dir;
+  let script = format!(
+    "sed \
+      -e 's#/etc/nginx/conf.d/#{conf_dir}/conf.d/#' \
+      -e 's#/etc/nginx/sites-enabled/#{conf_dir}/sites-enabled/#' \
+      -e 's#/etc/nginx/streams-enabled/#{conf_dir}/streams-enabled/#' \
+      /etc/nginx/nginx.conf > {conf_dir}/nginx.conf \
+      && nginx -t -c {conf_dir}/nginx.conf 2>&1"
+  );
+  let output =
+    exec_proxy(client, vec!["sh".into(), "-c".into(), script]).await?;
+  if !output.contains("test is successful") {
+    return Err(ErrorHint::warning(
+      12,
+      format!("Invalid nginx config: {}", output.trim()),
+    ));
+  }
+  Ok(())
+}
+
+/// Render the configuration files of a resource
+/// The files are written in the conf dir of the given nginx instance
+/// and named after the key the resource is stored with
+/// The written files are recorded for the rule
+/// The resource must be a ProxyRule
+pub(crate) async fn render_resource_conf(
+  client: &NanocldClient,
+  nginx: &Nginx,
+  key: &str,
+  resource: &ResourcePartial,
+) -> Result<(), ErrorHint> {
+  // Only the files of this rule are recorded
+  nginx.take_written();
+  let mut proxy_rule = serialize_proxy_rule(resource)?;
+  resolve_certificates(&mut proxy_rule, &resource.namespace, client, nginx)
+    .await?;
+  let (kind, conf) = resource_to_nginx_conf(
+    client,
+    nginx,
+    key,
+    &resource.namespace,
+    &proxy_rule,
+  )
+  .await?;
+  nginx.write_conf_file(key, &conf, &kind)?;
+  nginx.record_rule_files(key)?;
+  Ok(())
+}
+
 /// Create a new resource configuration
-/// This function will create a new configuration file for the given resource
-/// and reload the nginx configuration
+/// This function will render the configuration of the given resource
+/// in a staging directory and test it with nginx before applying it,
+/// the current configuration is left untouched when the test fail
 /// The resource must be a ProxyRule
 pub(crate) async fn create_resource_conf(
   client: &NanocldClient,
   nginx: &Nginx,
-  resource: &nanocld_client::stubs::resource::ResourcePartial,
+  key: &str,
+  resource: &ResourcePartial,
 ) -> Result<(), ErrorHint> {
-  let proxy_rule = serialize_proxy_rule(resource)?;
-  let (kind, conf) =
-    resource_to_nginx_conf(client, nginx, &resource.name, &proxy_rule).await?;
-  nginx.write_conf_file(&resource.name, &conf, &kind)?;
+  let _lock = nginx.lock_staging().await;
+  let staging = nginx.prepare_staging()?;
+  // The rule may change from a site to a stream
+  for kind in [NginxConfKind::Site, NginxConfKind::Stream] {
+    let _ = std::fs::remove_file(staging.gen_conf_path(key, &kind));
+  }
+  render_resource_conf(client, &staging, key, resource).await?;
+  test_config(client, &staging).await?;
+  nginx.apply_staging(&staging)?;
   Ok(())
 }
 
@@ -475,14 +1450,43 @@ pub(crate) async fn create_resource_conf(
 //   Ok(resources)
 // }
 
-/// Sync resources from nanocl daemon
-/// This function will remove all old configs and generate new ones
-pub(crate) async fn sync_resources(
+/// Check if a proxy rule reference the `Secret` resource with the given name
+fn uses_secret(proxy_rule: &ResourceProxyRule, name: &str) -> bool {
+  let ProxyRule::Http(rule) = &proxy_rule.rule else {
+    return false;
+  };
+  rule.locations.iter().any(|location| {
+    location
+      .basic_auth
+      .as_ref()
+      .map_or(false, |basic_auth| basic_auth.secret == name)
+  })
+}
+
+/// Check if a proxy rule reference the `Certificate` resource with the given name
+fn uses_certificate(proxy_rule: &ResourceProxyRule, name: &str) -> bool {
+  let is_ref =
+    |ssl: &ProxySslConfig| ssl.certificate_ref.as_deref() == Some(name);
+  match &proxy_rule.rule {
+    ProxyRule::Http(rule) => {
+      matches!(&rule.ssl, Some(ProxyHttpSsl::Config(ssl)) if is_ref(ssl))
+    }
+    ProxyRule::Stream(rules) => rules
+      .iter()
+      .any(|rule| rule.ssl.as_ref().map_or(false, is_ref)),
+  }
+}
+
+/// Regenerate the configuration of the proxy rules using a `Certificate`
+/// or a `Secret` resource so the new material is written and used by nginx
+pub(crate) async fn update_rules_using(
+  used: &ResourcePartial,
   client: &NanocldClient,
   nginx: &Nginx,
 ) -> Result<(), ErrorHint> {
   let query = ResourceQuery {
     kind: Some("ProxyRule".into()),
+    namespace: used.namespace.clone(),
     ..Default::default()
   };
   let resources = client.list_resource(Some(query)).await.map_err(|err| {
@@ -491,53 +1495,43 @@ pub(crate) async fn sync_resources(
       format!("Unable to list resources from nanocl: {err}"),
     )
   })?;
-
-  // remove old configs
-  let _ = nginx.clear_conf();
-
+  let mut updated = false;
   for resource in resources {
-    if let Err(err) =
-      create_resource_conf(client, nginx, &resource.into()).await
+    let key = resource.key.to_owned();
+    let resource: ResourcePartial = resource.into();
+    let Ok(proxy_rule) = serialize_proxy_rule(&resource) else {
+      continue;
+    };
+    let uses = match used.kind.as_str() {
+      "Certificate" => uses_certificate(&proxy_rule, &used.name),
+      "Secret" => uses_secret(&proxy_rule, &used.name),
+      _ => false,
+    };
+    if resource.namespace != used.namespace || !uses {
+      continue;
+    }
+    if let Err(err) = create_resource_conf(client, nginx, &key, &resource).await
     {
       err.print();
     }
+    updated = true;
+  }
+  if updated {
+    reload_config(client).await?;
   }
-  reload_config(client).await?;
   Ok(())
 }
 
-/// List resources from nanocl daemon
-/// This function will list all resources that contains the target key
-/// in the watch list
-/// The target key is the name of the cargo @ the namespace
-/// The namespace is optional, if not provided, it will be set to "global"
-pub(crate) async fn list_resource_by_cargo(
-  name: &str,
-  namespace: Option<String>,
-  client: &NanocldClient,
-) -> Result<Vec<nanocld_client::stubs::resource::Resource>, ErrorHint> {
-  let namespace = namespace.unwrap_or("global".into());
-  let target_key = format!("{name}.{namespace}");
-  let query = ResourceQuery {
-    contains: Some(serde_json::json!({ "Watch": [target_key] }).to_string()),
-    kind: Some("ProxyRule".into()),
-  };
-  let resources = client.list_resource(Some(query)).await.map_err(|err| {
-    ErrorHint::warning(
-      4,
-      format!("Unable to list resources from nanocl daemon: {err}"),
-    )
-  })?;
-  Ok(resources)
-}
-
 #[cfg(test)]
 pub(crate) mod tests {
   use std::process::Output;
 
   use ntex::web::{ServiceConfig, error::BlockingError};
 
-  use crate::nginx::Nginx;
+  use nanocld_client::NanocldClient;
+  use nanocld_client::stubs::resource::ResourcePartial;
+
+  use crate::nginx::{Nginx, NginxConfKind};
 
   type Config = fn(&mut ServiceConfig);
 
@@ -574,10 +1568,552 @@ pub(crate) mod tests {
 
   pub fn generate_server(routes: Config) -> ntex::web::test::TestServer {
     before();
-    let nginx = Nginx::new("/tmp/nginx");
+    let nginx = Nginx::new("/tmp/nginx", "/tmp/nginx/state");
     // Create test server
     ntex::web::test::server(move || {
       ntex::web::App::new().state(nginx.clone()).configure(routes)
     })
   }
+
+  /// Parse the first resource of a test file
+  pub(crate) fn parse_resource(data: &str) -> ResourcePartial {
+    let yaml: serde_yaml::Value = serde_yaml::from_str(data).unwrap();
+    let resource = yaml["Resources"][0].clone();
+    serde_yaml::from_value::<ResourcePartial>(resource).unwrap()
+  }
+
+  /// Create an empty nginx instance to render the configuration of a test
+  pub(crate) fn render_nginx(name: &str) -> Nginx {
+    let dir = format!("/tmp/nginx/render/{name}");
+    let _ = std::fs::remove_dir_all(&dir);
+    let nginx = Nginx::new(&dir, &format!("{dir}/state"));
+    nginx.ensure().unwrap();
+    nginx
+  }
+
+  /// Render the configuration of a resource and return the generated file
+  pub(crate) async fn render_resource(
+    nginx: &Nginx,
+    resource: &ResourcePartial,
+  ) -> String {
+    let client = NanocldClient::connect_with_unix_default();
+    super::render_resource_conf(&client, nginx, &resource.name, resource)
+      .await
+      .unwrap();
+    let proxy_rule = super::serialize_proxy_rule(resource).unwrap();
+    let kind: NginxConfKind = proxy_rule.rule.into();
+    let path = nginx.gen_conf_path(&resource.name, &kind);
+    std::fs::read_to_string(path).unwrap()
+  }
+
+  #[test]
+  fn domains() {
+    use super::is_domain;
+
+    assert!(is_domain("get-started.com", false));
+    assert!(is_domain("*.get-started.com", true));
+    assert!(!is_domain("*.get-started.com", false));
+    for domain in ["", "../certs", "get-started.com/", "a..com", "a.com;"] {
+      assert!(!is_domain(domain, true));
+    }
+    let mut resource =
+      parse_resource(include_str!("../tests/resource_acme.yml"));
+    resource.config["Rule"]["Http"]["Domain"] = "*.get-started.com".into();
+    assert!(super::serialize_proxy_rule(&resource).is_err());
+    resource.config["Rule"]["Http"]["Domain"] = "../get-started.com".into();
+    assert!(super::serialize_proxy_rule(&resource).is_err());
+  }
+
+  #[test]
+  fn listen_addresses() {
+    use std::net::IpAddr;
+
+    use super::{format_listen, gen_listen, parse_interface_addrs};
+
+    let output = "\
+2: eth0    inet 192.168.1.12/24 brd 192.168.1.255 scope global dynamic eth0\\       valid_lft 85740sec preferred_lft 85740sec
+2: eth0    inet6 2a01:e0a:1::12/64 scope global dynamic mngtmpaddr \\       valid_lft 86315sec preferred_lft 86315sec
+";
+    let ip_addresses = parse_interface_addrs(output);
+    assert_eq!(
+      ip_addresses,
+      vec![
+        "192.168.1.12".parse::<IpAddr>().unwrap(),
+        "2a01:e0a:1::12".parse::<IpAddr>().unwrap(),
+      ]
+    );
+    let listen = ip_addresses
+      .iter()
+      .map(|ip_address| format_listen(ip_address, 443))
+      .collect::<Vec<_>>();
+    assert_eq!(listen, vec!["192.168.1.12:443", "[2a01:e0a:1::12]:443"]);
+    assert_eq!(
+      gen_listen(&listen, " http2 ssl"),
+      "  listen 192.168.1.12:443 http2 ssl;\n  listen [2a01:e0a:1::12]:443 http2 ssl;\n"
+    );
+    assert!(parse_interface_addrs("Device \"eth9\" does not exist.").is_empty());
+  }
+
+  #[test]
+  fn networks() {
+    use nanocld_client::stubs::proxy::ProxyNetwork;
+
+    let parse = |network: serde_json::Value| {
+      serde_json::from_value::<ProxyNetwork>(network)
+    };
+    assert_eq!(parse("Public".into()).unwrap(), ProxyNetwork::Public);
+    assert_eq!(
+      parse(serde_json::json!({ "Ip": "::" })).unwrap(),
+      ProxyNetwork::Ip("::".into())
+    );
+    // Networks of the rules created before they were typed
+    assert_eq!(
+      parse("Nsp.global".into()).unwrap(),
+      ProxyNetwork::Nsp("global".into())
+    );
+    assert!(parse("Unknown".into()).is_err());
+    assert_eq!(
+      serde_json::to_value(ProxyNetwork::Nsp("global".into())).unwrap(),
+      serde_json::json!({ "Nsp": "global" })
+    );
+  }
+
+  #[test]
+  fn vm_upstream() {
+    use std::collections::HashMap;
+
+    use bollard_next::service::{
+      ContainerSummary, ContainerSummaryNetworkSettings, EndpointSettings,
+    };
+    use nanocld_client::stubs::{proxy::VmTarget, vm::VmInspect};
+
+    use super::create_vm_upstream;
+    use crate::nginx::NginxConfKind;
+
+    let nginx = Nginx::new("/tmp/nginx", "/tmp/nginx/state");
+    nginx.ensure().unwrap();
+    let target = VmTarget {
+      key: "ubuntu.global".into(),
+      port: 22,
+    };
+    let mut vm = VmInspect {
+      key: "ubuntu.global".into(),
+      name: "ubuntu".into(),
+      namespace_name: "global".into(),
+      ..Default::default()
+    };
+    assert!(
+      create_vm_upstream(&NginxConfKind::Stream, &target, &vm, &nginx).is_err()
+    );
+    vm.instances = vec![ContainerSummary {
+      network_settings: Some(ContainerSummaryNetworkSettings {
+        networks: Some(HashMap::from([(
+          "global".to_owned(),
+          EndpointSettings {
+            ip_address: Some("172.18.0.2".into()),
+            ..Default::default()
+          },
+        )])),
+      }),
+      ..Default::default()
+    }];
+    let upstream_key =
+      create_vm_upstream(&NginxConfKind::Stream, &target, &vm, &nginx).unwrap();
+    assert_eq!(upstream_key, "vm-ubuntu.global-22");
+    let upstream = std::fs::read_to_string(
+      "/tmp/nginx/streams-enabled/vm-ubuntu.global-22.conf",
+    )
+    .unwrap();
+    assert!(upstream.contains("server 172.18.0.2:22;"));
+  }
+
+  #[test]
+  fn split_clients() {
+    use nanocld_client::stubs::proxy::SplitTarget;
+
+    use super::{gen_split_clients, validate_split};
+
+    let upstreams = [
+      (90, "app-v1.prod-9000".to_owned()),
+      (10, "app-v2.prod-9000".to_owned()),
+    ];
+    let (variable, split_clients) =
+      gen_split_clients("canary.prod", 0, &upstreams);
+    assert_eq!(variable, "nanocl_split_canary_prod_0");
+    assert!(split_clients.contains("$nanocl_split_canary_prod_0 {"));
+    assert!(split_clients.contains("  90.00% app-v1.prod-9000;\n"));
+    assert!(split_clients.contains("  * app-v2.prod-9000;\n"));
+    let upstreams = [
+      (1, "app-v1.prod-9000".to_owned()),
+      (1, "app-v2.prod-9000".to_owned()),
+      (1, "app-v3.prod-9000".to_owned()),
+    ];
+    let (_, split_clients) = gen_split_clients("canary.prod", 0, &upstreams);
+    assert!(split_clients.contains("  33.33% app-v1.prod-9000;\n"));
+    assert!(split_clients.contains("  33.33% app-v2.prod-9000;\n"));
+    assert!(split_clients.contains("  * app-v3.prod-9000;\n"));
+
+    let targets: Vec<SplitTarget> = serde_json::from_value(serde_json::json!([
+      { "Cargo": { "Key": "app-v1.prod", "Port": 9000 }, "Weight": 90 },
+      { "Cargo": { "Key": "app-v2.prod", "Port": 9000 }, "Weight": 10 },
+    ]))
+    .unwrap();
+    assert!(validate_split("/", &targets).is_ok());
+    let mut unweighted = targets.clone();
+    for target in &mut unweighted {
+      target.weight = 0;
+    }
+    assert!(validate_split("/", &unweighted).is_err());
+    let duplicated = [targets[0].clone(), targets[0].clone()];
+    assert!(validate_split("/", &duplicated).is_err());
+  }
+
+  #[test]
+  fn load_balancing() {
+    use nanocld_client::stubs::proxy::{CargoTarget, LoadBalancing};
+
+    use super::{gen_load_balancing, gen_server_params, validate_cargo_target};
+    use crate::nginx::NginxConfKind;
+
+    let site = NginxConfKind::Site;
+    let stream = NginxConfKind::Stream;
+    assert_eq!(
+      gen_load_balancing(&site, &None),
+      "  hash $remote_addr consistent;\n"
+    );
+    assert_eq!(
+      gen_load_balancing(&site, &Some(LoadBalancing::RoundRobin)),
+      ""
+    );
+    assert_eq!(
+      gen_load_balancing(&site, &Some(LoadBalancing::LeastConn)),
+      "  least_conn;\n"
+    );
+    assert_eq!(
+      gen_load_balancing(&site, &Some(LoadBalancing::IpHash)),
+      "  ip_hash;\n"
+    );
+    assert_eq!(
+      gen_load_balancing(&stream, &Some(LoadBalancing::IpHash)),
+      "  hash $remote_addr;\n"
+    );
+    let hash = LoadBalancing::Hash {
+      key: "$request_uri".into(),
+    };
+    assert_eq!(
+      gen_load_balancing(&site, &Some(hash)),
+      "  hash $request_uri;\n"
+    );
+
+    let target: CargoTarget = serde_json::from_value(serde_json::json!({
+      "Key": "get-started.global",
+      "Port": 9000,
+      "LoadBalancing": "LeastConn",
+      "MaxFails": 3,
+      "FailTimeout": "10s",
+    }))
+    .unwrap();
+    assert!(validate_cargo_target(&target).is_ok());
+    assert_eq!(gen_server_params(&target), " max_fails=3 fail_timeout=10s");
+    for fail_timeout in ["", "s", "10s;", "10 s", "10y"] {
+      let invalid = CargoTarget {
+        fail_timeout: Some(fail_timeout.into()),
+        ..target.clone()
+      };
+      assert!(validate_cargo_target(&invalid).is_err());
+    }
+    for key in ["", "$request_uri; deny all", "$uri}", "$uri\n"] {
+      let invalid = CargoTarget {
+        load_balancing: Some(LoadBalancing::Hash { key: key.into() }),
+        ..target.clone()
+      };
+      assert!(validate_cargo_target(&invalid).is_err());
+    }
+    let hash = CargoTarget {
+      load_balancing: Some(LoadBalancing::Hash {
+        key: "$request_uri".into(),
+      }),
+      ..target
+    };
+    assert!(validate_cargo_target(&hash).is_ok());
+  }
+
+  #[test]
+  fn cargo_upstream() {
+    use std::collections::HashMap;
+
+    use bollard_next::service::{
+      ContainerSummary, ContainerSummaryNetworkSettings, EndpointSettings,
+    };
+    use nanocld_client::stubs::node::NodeContainerSummary;
+    use nanocld_client::stubs::{cargo::CargoInspect, proxy::CargoTarget};
+
+    use super::create_cargo_upstream;
+    use crate::nginx::NginxConfKind;
+
+    let nginx = Nginx::new("/tmp/nginx", "/tmp/nginx/state");
+    nginx.ensure().unwrap();
+    let instance = |ip_address: &str| NodeContainerSummary {
+      node: "test".into(),
+      ip_address: "127.0.0.1".into(),
+      container: ContainerSummary {
+        network_settings: Some(ContainerSummaryNetworkSettings {
+          networks: Some(HashMap::from([(
+            "global".to_owned(),
+            EndpointSettings {
+              ip_address: Some(ip_address.into()),
+              ..Default::default()
+            },
+          )])),
+        }),
+        ..Default::default()
+      },
+    };
+    let cargo = CargoInspect {
+      key: "get-started.global".into(),
+      name: "get-started".into(),
+      namespace_name: "global".into(),
+      instances: vec![instance("172.18.0.2"), instance("172.18.0.3")],
+      ..Default::default()
+    };
+    let target: CargoTarget = serde_json::from_value(serde_json::json!({
+      "Key": "get-started.global",
+      "Port": 9000,
+      "LoadBalancing": "RoundRobin",
+      "MaxFails": 3,
+    }))
+    .unwrap();
+    let upstream_key = create_cargo_upstream(
+      &NginxConfKind::Site,
+      "rule.global-0",
+      &target,
+      &cargo,
+      &nginx,
+    )
+    .unwrap();
+    assert_eq!(upstream_key, "rule.global-0-get-started.global-9000");
+    // Another location of the rule gets its own upstream
+    let other_key = create_cargo_upstream(
+      &NginxConfKind::Site,
+      "rule.global-1",
+      &CargoTarget {
+        load_balancing: None,
+        max_fails: None,
+        ..target
+      },
+      &cargo,
+      &nginx,
+    )
+    .unwrap();
+    assert_eq!(other_key, "rule.global-1-get-started.global-9000");
+    let upstream = std::fs::read_to_string(format!(
+      "/tmp/nginx/sites-enabled/{upstream_key}.conf"
+    ))
+    .unwrap();
+    assert_eq!(
+      upstream,
+      "
+upstream rule.global-0-get-started.global-9000 {
+  server 172.18.0.2:9000 max_fails=3;
+  server 172.18.0.3:9000 max_fails=3;
+}
+"
+    );
+    let other = std::fs::read_to_string(format!(
+      "/tmp/nginx/sites-enabled/{other_key}.conf"
+    ))
+    .unwrap();
+    assert!(other.contains("  hash $remote_addr consistent;\n"));
+    assert!(other.contains("  server 172.18.0.2:9000;\n"));
+  }
+
+  #[test]
+  fn sni_map() {
+    use nanocld_client::stubs::proxy::{
+      ProxyRuleStream, ProxyStreamProtocol, StreamTarget,
+    };
+
+    use super::{gen_sni_map, validate_sni};
+
+    let upstreams = [
+      (
+        vec!["app.example.com".to_owned()],
+        "app.global-443".to_owned(),
+      ),
+      (
+        vec!["*.example.com".to_owned(), "example.org".to_owned()],
+        "web.global-443".to_owned(),
+      ),
+    ];
+    let (map, directives) = gen_sni_map("tls.global", 0, &upstreams);
+    assert!(
+      map.contains("map $ssl_preread_server_name $nanocl_sni_tls_global_0 {")
+    );
+    assert!(map.contains("  hostnames;\n"));
+    assert!(map.contains("  app.example.com app.global-443;\n"));
+    assert!(map.contains("  *.example.com web.global-443;\n"));
+    assert!(map.contains("  example.org web.global-443;\n"));
+    assert_eq!(
+      directives,
+      "  ssl_preread on;\n  proxy_pass $nanocl_sni_tls_global_0;\n"
+    );
+
+    let rule: ProxyRuleStream = serde_json::from_value(serde_json::json!({
+      "Network": "Public",
+      "Protocol": "Tcp",
+      "Port": 443,
+      "Target": {
+        "Sni": [
+          {
+            "Domains": ["app.example.com"],
+            "Cargo": { "Key": "app.global", "Port": 443 }
+          },
+          {
+            "Domains": ["*.example.com"],
+            "Cargo": { "Key": "web.global", "Port": 443 }
+          }
+        ]
+      }
+    }))
+    .unwrap();
+    let StreamTarget::Sni(routes) = &rule.target else {
+      panic!("expect a sni target");
+    };
+    assert!(validate_sni("tls", &rule, routes).is_ok());
+    let mut duplicated = routes.clone();
+    duplicated[1].domains.push("APP.example.com".into());
+    assert!(validate_sni("tls", &rule, &duplicated).is_err());
+    let mut invalid = routes.clone();
+    invalid[0].domains = vec!["app example.com".into()];
+    assert!(validate_sni("tls", &rule, &invalid).is_err());
+    let udp = ProxyRuleStream {
+      protocol: ProxyStreamProtocol::Udp,
+      ..rule.clone()
+    };
+    assert!(validate_sni("tls", &udp, routes).is_err());
+  }
+
+  #[ntex::test]
+  async fn owner_refs() {
+    use nanocld_client::stubs::proxy::ResourceProxyRule;
+    use nanocld_client::stubs::resource::{ResourceOwnerRef, ResourceOwnerKind};
+
+    let proxy_rule =
+      serde_json::from_value::<ResourceProxyRule>(serde_json::json!({
+        "Watch": ["ubuntu.global", "get-started.global"],
+        "Rule": {
+          "Stream": [{
+            "Network": "Public",
+            "Protocol": "Tcp",
+            "Port": 2222,
+            "Target": { "Vm": { "Key": "ubuntu.global", "Port": 22 } }
+          }]
+        }
+      }))
+      .unwrap();
+    let client = NanocldClient::connect_with_unix_default();
+    let owner_refs = super::gen_owner_refs(&proxy_rule, &client).await;
+    assert_eq!(
+      owner_refs,
+      vec![
+        ResourceOwnerRef {
+          kind: ResourceOwnerKind::Vm,
+          key: "ubuntu.global".to_owned(),
+        },
+        ResourceOwnerRef {
+          kind: ResourceOwnerKind::Cargo,
+          key: "get-started.global".to_owned(),
+        },
+      ]
+    );
+  }
+
+  #[test]
+  fn uri_stream() {
+    use nanocld_client::stubs::proxy::{UriTarget, ProxyUpstreamSsl};
+
+    use super::{parse_stream_uri, gen_uri_stream, validate_uri_target};
+
+    assert_eq!(
+      parse_stream_uri("tcp://db.example.com:5432").unwrap(),
+      ("db.example.com".to_owned(), 5432)
+    );
+    assert_eq!(
+      parse_stream_uri("[::1]:5432").unwrap(),
+      ("[::1]".to_owned(), 5432)
+    );
+    assert!(parse_stream_uri("db.example.com").is_err());
+    assert!(parse_stream_uri("db example:5432").is_err());
+
+    let target = UriTarget {
+      uri: "192.168.1.10:5432".into(),
+      resolver: None,
+      ssl: None,
+    };
+    let (map, directives) = gen_uri_stream("db.global", 0, &target).unwrap();
+    assert_eq!(map, "");
+    assert_eq!(directives, "  proxy_pass 192.168.1.10:5432;\n");
+
+    let target = UriTarget {
+      uri: "db.example.com:5432".into(),
+      resolver: Some(vec!["1.1.1.1".into(), "[::1]:53".into()]),
+      ssl: Some(ProxyUpstreamSsl::default()),
+    };
+    assert!(validate_uri_target(&target).is_ok());
+    let (map, directives) = gen_uri_stream("db.global", 1, &target).unwrap();
+    assert!(map.contains("$nanocl_db_global_1"));
+    assert!(map.contains("default db.example.com:5432;"));
+    assert!(directives.contains("resolver 1.1.1.1 [::1]:53 valid=30s;"));
+    assert!(directives.contains("proxy_pass $nanocl_db_global_1;"));
+    assert!(directives.contains("proxy_ssl_name db.example.com;"));
+    assert!(directives.contains("proxy_ssl_verify on;"));
+    assert!(directives.contains(
+      "proxy_ssl_trusted_certificate /etc/ssl/certs/ca-certificates.crt;"
+    ));
+
+    let unverified = UriTarget {
+      ssl: Some(ProxyUpstreamSsl {
+        verify: Some(false),
+        ..Default::default()
+      }),
+      ..target.clone()
+    };
+    let (_, directives) = gen_uri_stream("db.global", 1, &unverified).unwrap();
+    assert!(!directives.contains("proxy_ssl_verify"));
+
+    let invalids = [
+      UriTarget {
+        resolver: Some(vec!["dns.example.com".into()]),
+        ..target.clone()
+      },
+      UriTarget {
+        resolver: Some(vec!["1.1.1.1 valid=1s".into()]),
+        ..target.clone()
+      },
+      UriTarget {
+        ssl: Some(ProxyUpstreamSsl {
+          server_name: Some("db.example.com; deny all".into()),
+          ..Default::default()
+        }),
+        ..target.clone()
+      },
+      UriTarget {
+        ssl: Some(ProxyUpstreamSsl {
+          trusted_certificate: Some("certs/ca.pem".into()),
+          ..Default::default()
+        }),
+        ..target.clone()
+      },
+      UriTarget {
+        ssl: Some(ProxyUpstreamSsl {
+          trusted_certificate: Some("/etc/ssl/../../ca.pem".into()),
+          ..Default::default()
+        }),
+        ..target
+      },
+    ];
+    for invalid in invalids {
+      assert!(validate_uri_target(&invalid).is_err());
+    }
+  }
 }
@@ -21,6 +21,9 @@ pub struct DaemonConfig {
   pub nodes: Vec<String>,
   /// Address to advertise to other nodes
   pub advertise_addr: String,
+  /// File containing the key used to encrypt secrets and certificate keys
+  /// The same key must be used by every node of the cluster
+  pub secret_key_file: Option<String>,
 }
 
 /// Configuration File of the daemon
@@ -38,6 +41,8 @@ pub struct DaemonConfigFile {
   pub gateway: Option<String>,
   /// Hostname to use for the node automatically detected if not set
   pub hostname: Option<String>,
+  /// File containing the key used to encrypt secrets and certificate keys
+  pub secret_key_file: Option<String>,
 }
 
 impl Default for DaemonConfig {
@@ -50,6 +55,7 @@ impl Default for DaemonConfig {
       gateway: String::default(),
       nodes: Vec::default(),
       advertise_addr: String::default(),
+      secret_key_file: None,
     }
   }
 }
@@ -0,0 +1,116 @@
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+
+/// Address of an A or AAAA record
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
+pub enum DnsRecordTarget {
+  /// A fixed ip address
+  Ip(String),
+  /// The key of a cargo, the ip addresses of its instances are used
+  Cargo(String),
+}
+
+/// A service record
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
+pub struct DnsSrvRecord {
+  /// The domain of the host providing the service
+  pub target: String,
+  /// The port of the service
+  pub port: u16,
+  /// The priority of the host, lower is preferred
+  #[cfg_attr(
+    feature = "serde",
+    serde(default, skip_serializing_if = "Option::is_none")
+  )]
+  pub priority: Option<u16>,
+  /// The weight of the host among hosts with the same priority
+  #[cfg_attr(
+    feature = "serde",
+    serde(default, skip_serializing_if = "Option::is_none")
+  )]
+  pub weight: Option<u16>,
+}
+
+/// A dns record
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
+pub enum DnsRecord {
+  /// Ipv4 address
+  A(DnsRecordTarget),
+  /// Ipv6 address
+  Aaaa(DnsRecordTarget),
+  /// Alias to another domain
+  Cname(String),
+  /// Text values
+  Txt(Vec<String>),
+  /// Service location
+  Srv(DnsSrvRecord),
+}
+
+/// Define custom dns records for a domain
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
+pub struct ResourceDnsRecord {
+  /// The domain of the records
+  pub name: String,
+  /// The records
+  pub records: Vec<DnsRecord>,
+}
+
+/// An entry served by the dns controller
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
+pub struct DnsEntrySummary {
+  /// What generated the entry, the key of a cargo,
+  /// a vm ending with `.vm` or a `DnsRecord` ending with `.record`
+  pub source: String,
+  /// Kind of the entry `Address`, `Host`, `Cname`, `Txt` or `Srv`
+  pub kind: String,
+  /// The name the entry is answering for,
+  /// an `Address` also answer for the subdomains of its name
+  pub name: String,
+  /// The value of the entry
+  pub value: String,
+}
+
+/// How a name is answered by the dns controller
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
+pub enum DnsResolveAnswer {
+  /// The name is answered from the entries
+  Local,
+  /// The name is inside the zone but no entry own it
+  NotFound,
+  /// The name is forwarded to the upstream dns servers
+  Forwarded,
+}
+
+/// Explain what a name resolve to
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
+pub struct DnsResolve {
+  /// The name to resolve
+  pub name: String,
+  /// The zone of the cargo domains
+  pub zone: String,
+  /// How the name is answered
+  pub answer: DnsResolveAnswer,
+  /// The entries answering for the name and the targets of its cnames
+  pub entries: Vec<DnsEntrySummary>,
+}
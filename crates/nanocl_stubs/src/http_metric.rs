@@ -45,7 +45,7 @@ pub struct HttpMetricListQuery {
 }
 
 #[cfg(feature = "serde")]
-fn deserialize_status_between<'de, D>(
+pub(crate) fn deserialize_status_between<'de, D>(
   deserializer: D,
 ) -> Result<Option<(i64, Option<i64>)>, D::Error>
 where
@@ -14,5 +14,7 @@ pub mod vm;
 pub mod vm_config;
 pub mod vm_image;
 pub mod proxy;
+pub mod dns;
 pub mod metric;
 pub mod http_metric;
+pub mod stream_metric;
@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 #[cfg(feature = "serde")]
 use serde::{Serialize, Deserialize};
 
@@ -19,11 +21,87 @@ pub enum ProxyRule {
 #[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
 pub struct ProxySslConfig {
   /// Path to the certificate
-  pub certificate: String,
+  pub certificate: Option<String>,
   /// Path to the certificate key
-  pub certificate_key: String,
+  pub certificate_key: Option<String>,
   /// Path to the dhparam file
   pub dh_param: Option<String>,
+  /// Name of a `Certificate` resource to use instead of paths
+  pub certificate_ref: Option<String>,
+}
+
+/// Config of a `Certificate` resource
+/// The certificate key is encrypted by the daemon before being stored
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
+pub struct ResourceCertificate {
+  /// Pem encoded certificate chain
+  pub certificate: String,
+  /// Pem encoded private key
+  pub certificate_key: String,
+  /// Pem encoded dh parameters
+  pub dh_param: Option<String>,
+}
+
+/// Query of the certificate endpoint of a resource
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
+pub struct ResourceCertificateQuery {
+  /// Namespace of the resource
+  pub namespace: Option<String>,
+  /// Include the decrypted private key
+  /// Only allowed over the unix socket of the daemon
+  pub with_key: Option<bool>,
+}
+
+/// Material of a `Certificate` resource returned by the daemon
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
+pub struct ResourceCertificateInspect {
+  /// Pem encoded certificate chain
+  pub certificate: String,
+  /// Pem encoded private key when requested with `WithKey`
+  pub certificate_key: Option<String>,
+  /// Pem encoded dh parameters
+  pub dh_param: Option<String>,
+}
+
+/// Account used to request certificates with ACME
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
+pub struct ProxyAcmeConfig {
+  /// Email of the ACME account
+  pub email: String,
+  /// Url of the ACME directory, default to Let's Encrypt
+  pub directory_url: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
+pub struct ProxySslAcme {
+  /// The ACME account and directory to use
+  pub acme: ProxyAcmeConfig,
+}
+
+/// Ssl modes of an http rule
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(untagged))]
+pub enum ProxyHttpSsl {
+  /// Certificates are requested and renewed by the proxy using HTTP-01 challenges
+  Acme(ProxySslAcme),
+  /// Certificates are provided
+  Config(ProxySslConfig),
 }
 
 /// Defines a proxy rule target
@@ -36,6 +114,48 @@ pub struct CargoTarget {
   pub key: String,
   /// The cargo port
   pub port: u16,
+  /// How requests are distributed between the cargo instances
+  #[cfg_attr(
+    feature = "serde",
+    serde(default, skip_serializing_if = "Option::is_none")
+  )]
+  pub load_balancing: Option<LoadBalancing>,
+  /// Number of failed attempts before an instance is unavailable
+  #[cfg_attr(
+    feature = "serde",
+    serde(default, skip_serializing_if = "Option::is_none")
+  )]
+  pub max_fails: Option<u16>,
+  /// Time an instance is unavailable after max fails like `10s`
+  #[cfg_attr(
+    feature = "serde",
+    serde(default, skip_serializing_if = "Option::is_none")
+  )]
+  pub fail_timeout: Option<String>,
+}
+
+/// Load balancing strategy of a cargo target
+/// When not set requests are distributed with a consistent hash
+/// of the client address
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
+pub enum LoadBalancing {
+  /// Distribute requests evenly
+  RoundRobin,
+  /// Send requests to the instance with the least active connections
+  LeastConn,
+  /// Send requests of a client address to the same instance
+  IpHash,
+  /// Send requests with the same key to the same instance
+  #[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
+  Hash {
+    /// Nginx expression used as key like `$request_uri`
+    key: String,
+  },
+  /// Send requests to a random instance
+  Random,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -83,6 +203,46 @@ pub enum LocationTarget {
   Http(HttpTarget),
   /// Target a specific unix socket
   Unix(String),
+  /// Split the requests between multiple cargoes
+  Split(Vec<SplitTarget>),
+  /// Target an existing virtual machine
+  Vm(VmTarget),
+}
+
+/// Defines a proxy rule target to a virtual machine
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
+pub struct VmTarget {
+  /// The vm key
+  pub key: String,
+  /// The port of the service inside the vm
+  pub port: u16,
+}
+
+/// A cargo receiving a share of the requests of a split target
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
+pub struct SplitTarget {
+  /// Share of the requests relative to the sum of the weights
+  pub weight: u16,
+  /// The cargo receiving the requests
+  pub cargo: CargoTarget,
+}
+
+/// Server names routed to a cargo by a sni target
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
+pub struct SniRoute {
+  /// Server names to match, a leading `*.` match every subdomain
+  pub domains: Vec<String>,
+  /// The cargo receiving the connections
+  pub cargo: CargoTarget,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -90,8 +250,49 @@ pub enum LocationTarget {
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
 pub struct UriTarget {
-  /// Uri to target
+  /// Uri to target like `host:port` or `tcp://host:port`
   pub uri: String,
+  /// Dns servers used to resolve the host while running
+  /// When not set the host is resolved when the config is loaded
+  #[cfg_attr(
+    feature = "serde",
+    serde(default, skip_serializing_if = "Option::is_none")
+  )]
+  pub resolver: Option<Vec<String>>,
+  /// Use tls to connect to the target
+  #[cfg_attr(
+    feature = "serde",
+    serde(default, skip_serializing_if = "Option::is_none")
+  )]
+  pub ssl: Option<ProxyUpstreamSsl>,
+}
+
+/// Tls origination to an upstream
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
+pub struct ProxyUpstreamSsl {
+  /// Server name sent with SNI and verified, default to the host of the uri
+  #[cfg_attr(
+    feature = "serde",
+    serde(default, skip_serializing_if = "Option::is_none")
+  )]
+  pub server_name: Option<String>,
+  /// Verify the certificate of the upstream, default to true
+  /// Only disable it for upstreams on a trusted network
+  /// since the connection is then open to man in the middle attacks
+  #[cfg_attr(
+    feature = "serde",
+    serde(default, skip_serializing_if = "Option::is_none")
+  )]
+  pub verify: Option<bool>,
+  /// Path to the trusted CA certificates used for the verification
+  #[cfg_attr(
+    feature = "serde",
+    serde(default, skip_serializing_if = "Option::is_none")
+  )]
+  pub trusted_certificate: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -105,6 +306,11 @@ pub enum StreamTarget {
   Uri(UriTarget),
   /// Target a specific unix socket
   Unix(String),
+  /// Target an existing virtual machine
+  Vm(VmTarget),
+  /// Route tls connections to cargoes by the server name sent by the client
+  /// without terminating them
+  Sni(Vec<SniRoute>),
 }
 
 /// Proxy rules modes
@@ -117,14 +323,83 @@ pub enum ProxyStreamProtocol {
   Udp,
 }
 
+/// Network where a proxy rule is listening
+/// Ipv6 is only used when asked with an `Ip` like `::` or an `Interface`
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+  feature = "serde",
+  serde(rename_all = "PascalCase", try_from = "ProxyNetworkDef")
+)]
+pub enum ProxyNetwork {
+  /// Every ipv4 address of the node
+  Public,
+  /// The address advertised by the node
+  Private,
+  /// The ipv4 loopback of the node
+  Internal,
+  /// The gateway of the network of a namespace
+  Nsp(String),
+  /// An ipv4 or ipv6 address of the node
+  Ip(String),
+  /// The addresses of a network interface of the node
+  Interface(String),
+}
+
+/// Network of a proxy rule as it's deserialized
+/// Rules created before the network was typed use `Nsp.<namespace>`
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ProxyNetworkDef {
+  Typed(TypedProxyNetwork),
+  Legacy(String),
+}
+
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+enum TypedProxyNetwork {
+  Public,
+  Private,
+  Internal,
+  Nsp(String),
+  Ip(String),
+  Interface(String),
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<ProxyNetworkDef> for ProxyNetwork {
+  type Error = String;
+
+  fn try_from(network: ProxyNetworkDef) -> Result<Self, Self::Error> {
+    let network = match network {
+      ProxyNetworkDef::Typed(network) => match network {
+        TypedProxyNetwork::Public => Self::Public,
+        TypedProxyNetwork::Private => Self::Private,
+        TypedProxyNetwork::Internal => Self::Internal,
+        TypedProxyNetwork::Nsp(namespace) => Self::Nsp(namespace),
+        TypedProxyNetwork::Ip(ip_address) => Self::Ip(ip_address),
+        TypedProxyNetwork::Interface(interface) => Self::Interface(interface),
+      },
+      ProxyNetworkDef::Legacy(network) => match network.strip_prefix("Nsp.") {
+        Some(namespace) => Self::Nsp(namespace.to_owned()),
+        None => return Err(format!("unknown network {network}")),
+      },
+    };
+    Ok(network)
+  }
+}
+
 /// Proxy rules modes
 #[derive(Debug, Clone)]
 #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
 pub struct ProxyRuleStream {
-  /// Type of the network binding private | public | internal | namespace:$namespace_name
-  pub network: String,
+  /// Network where the port is opened
+  pub network: ProxyNetwork,
   /// Protocol to use Tcp | Udp
   pub protocol: ProxyStreamProtocol,
   /// The port to open on nodes
@@ -145,6 +420,210 @@ pub struct ProxyHttpLocation {
   pub path: String,
   /// The target cargo
   pub target: LocationTarget,
+  /// Headers of the request sent to the target
+  #[cfg_attr(
+    feature = "serde",
+    serde(default, skip_serializing_if = "Option::is_none")
+  )]
+  pub request_headers: Option<ProxyHeaders>,
+  /// Headers of the response sent to the client
+  #[cfg_attr(
+    feature = "serde",
+    serde(default, skip_serializing_if = "Option::is_none")
+  )]
+  pub response_headers: Option<ProxyHeaders>,
+  /// Require a basic authentication
+  #[cfg_attr(
+    feature = "serde",
+    serde(default, skip_serializing_if = "Option::is_none")
+  )]
+  pub basic_auth: Option<ProxyBasicAuth>,
+  /// Limit the rate of requests
+  #[cfg_attr(
+    feature = "serde",
+    serde(default, skip_serializing_if = "Option::is_none")
+  )]
+  pub rate_limit: Option<ProxyRateLimit>,
+  /// Only allow these addresses or cidr
+  #[cfg_attr(
+    feature = "serde",
+    serde(default, skip_serializing_if = "Option::is_none")
+  )]
+  pub allow: Option<Vec<String>>,
+  /// Deny these addresses or cidr
+  #[cfg_attr(
+    feature = "serde",
+    serde(default, skip_serializing_if = "Option::is_none")
+  )]
+  pub deny: Option<Vec<String>>,
+  /// Max size of the request body like `10m`
+  #[cfg_attr(
+    feature = "serde",
+    serde(default, skip_serializing_if = "Option::is_none")
+  )]
+  pub client_max_body_size: Option<String>,
+  /// Timeouts of the connection to the target
+  #[cfg_attr(
+    feature = "serde",
+    serde(default, skip_serializing_if = "Option::is_none")
+  )]
+  pub timeouts: Option<ProxyTimeouts>,
+  /// Forward websocket upgrade headers
+  #[cfg_attr(
+    feature = "serde",
+    serde(default, skip_serializing_if = "Option::is_none")
+  )]
+  pub websocket: Option<bool>,
+  /// Cache the responses of the target
+  #[cfg_attr(
+    feature = "serde",
+    serde(default, skip_serializing_if = "Option::is_none")
+  )]
+  pub cache: Option<ProxyCache>,
+}
+
+/// Headers to set or remove
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
+pub struct ProxyHeaders {
+  /// Headers to set by name
+  #[cfg_attr(
+    feature = "serde",
+    serde(default, skip_serializing_if = "Option::is_none")
+  )]
+  pub set: Option<HashMap<String, String>>,
+  /// Names of the headers to remove
+  #[cfg_attr(
+    feature = "serde",
+    serde(default, skip_serializing_if = "Option::is_none")
+  )]
+  pub remove: Option<Vec<String>>,
+}
+
+/// Basic authentication of a location
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
+pub struct ProxyBasicAuth {
+  /// Name of a `Secret` resource with passwords by username
+  pub secret: String,
+  /// Realm displayed to the client
+  #[cfg_attr(
+    feature = "serde",
+    serde(default, skip_serializing_if = "Option::is_none")
+  )]
+  pub realm: Option<String>,
+}
+
+/// Rate limit of a location
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
+pub struct ProxyRateLimit {
+  /// Allowed rate like `10r/s` or `60r/m`
+  pub rate: String,
+  /// Amount of requests queued above the rate
+  #[cfg_attr(
+    feature = "serde",
+    serde(default, skip_serializing_if = "Option::is_none")
+  )]
+  pub burst: Option<u32>,
+  /// Don't delay the queued requests
+  #[cfg_attr(
+    feature = "serde",
+    serde(default, skip_serializing_if = "Option::is_none")
+  )]
+  pub nodelay: Option<bool>,
+  /// Nginx expression used as key, default to `$binary_remote_addr`
+  #[cfg_attr(
+    feature = "serde",
+    serde(default, skip_serializing_if = "Option::is_none")
+  )]
+  pub key: Option<String>,
+}
+
+/// Timeouts of the connection to a target like `60s`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
+pub struct ProxyTimeouts {
+  /// Timeout to establish the connection
+  #[cfg_attr(
+    feature = "serde",
+    serde(default, skip_serializing_if = "Option::is_none")
+  )]
+  pub connect: Option<String>,
+  /// Timeout between two reads of the response
+  #[cfg_attr(
+    feature = "serde",
+    serde(default, skip_serializing_if = "Option::is_none")
+  )]
+  pub read: Option<String>,
+  /// Timeout between two writes of the request
+  #[cfg_attr(
+    feature = "serde",
+    serde(default, skip_serializing_if = "Option::is_none")
+  )]
+  pub send: Option<String>,
+}
+
+/// Cache of the responses of a location
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
+pub struct ProxyCache {
+  /// Size of the memory zone storing the keys like `10m`
+  #[cfg_attr(
+    feature = "serde",
+    serde(default, skip_serializing_if = "Option::is_none")
+  )]
+  pub zone_size: Option<String>,
+  /// Max size of the responses stored on disk like `1g`
+  #[cfg_attr(
+    feature = "serde",
+    serde(default, skip_serializing_if = "Option::is_none")
+  )]
+  pub max_size: Option<String>,
+  /// Time a response is kept by status codes
+  #[cfg_attr(
+    feature = "serde",
+    serde(default, skip_serializing_if = "Option::is_none")
+  )]
+  pub valid: Option<Vec<ProxyCacheValid>>,
+  /// Nginx variables bypassing the cache when not empty like `$cookie_session`
+  #[cfg_attr(
+    feature = "serde",
+    serde(default, skip_serializing_if = "Option::is_none")
+  )]
+  pub bypass: Option<Vec<String>>,
+  /// Nginx expression used as key default to `$scheme$proxy_host$request_uri`
+  #[cfg_attr(
+    feature = "serde",
+    serde(default, skip_serializing_if = "Option::is_none")
+  )]
+  pub key: Option<String>,
+}
+
+/// Time a response is kept in cache
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
+pub struct ProxyCacheValid {
+  /// Status codes like `200` or `any` default to `200 301 302`
+  #[cfg_attr(
+    feature = "serde",
+    serde(default, skip_serializing_if = "Option::is_none")
+  )]
+  pub status: Option<Vec<String>>,
+  /// Time to keep the responses like `10m`
+  pub ttl: String,
 }
 
 /// Defines a proxy rule http config
@@ -155,12 +634,12 @@ pub struct ProxyHttpLocation {
 pub struct ProxyRuleHttp {
   /// The domain
   pub domain: Option<String>,
-  /// Type of private | public | internal | namespace:$namespace_name
-  pub network: String,
+  /// Network where the rule is listening
+  pub network: ProxyNetwork,
   /// The locations to handle multiple paths
   pub locations: Vec<ProxyHttpLocation>,
   /// The ssl configuration
-  pub ssl: Option<ProxySslConfig>,
+  pub ssl: Option<ProxyHttpSsl>,
   /// Path to extra config file to include
   pub includes: Option<Vec<String>>,
 }
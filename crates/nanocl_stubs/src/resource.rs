@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 #[cfg(feature = "serde")]
 use serde::{Serialize, Deserialize};
 
@@ -9,6 +11,8 @@ use serde::{Serialize, Deserialize};
 pub struct ResourcePartial {
   /// The name of the resource
   pub name: String,
+  /// The namespace of the resource, the resource is not namespaced if empty
+  pub namespace: Option<String>,
   /// The kind of the resource
   pub kind: String,
   /// Version of the config
@@ -16,6 +20,51 @@ pub struct ResourcePartial {
   /// The config of the resource (json object)
   #[cfg_attr(feature = "utoipa", schema(value_type = HashMap<String, Any>))]
   pub config: serde_json::Value,
+  /// The owners of the resource, it's garbage collected when they are gone
+  #[cfg_attr(
+    feature = "serde",
+    serde(default, skip_serializing_if = "Option::is_none")
+  )]
+  pub owner_refs: Option<Vec<ResourceOwnerRef>>,
+  /// Labels of the resource used to select it
+  #[cfg_attr(
+    feature = "serde",
+    serde(default, skip_serializing_if = "Option::is_none")
+  )]
+  pub labels: Option<HashMap<String, String>>,
+}
+
+/// Config of a `Secret` resource
+/// The values are encrypted by the daemon before being stored
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
+pub struct ResourceSecret {
+  /// The secret values by name
+  pub data: HashMap<String, String>,
+}
+
+/// Kind of object that can own a resource
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ResourceOwnerKind {
+  Cargo,
+  Vm,
+  Namespace,
+}
+
+/// Reference to the owner of a resource
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
+pub struct ResourceOwnerRef {
+  /// The kind of the owner
+  pub kind: ResourceOwnerKind,
+  /// The key of the owner (`name.namespace` for cargoes and vms)
+  pub key: String,
 }
 
 #[derive(Debug, Clone)]
@@ -37,8 +86,12 @@ pub struct ResourcePatch {
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
 pub struct Resource {
+  /// The key of the resource
+  pub key: String,
   /// The name of the resource
   pub name: String,
+  /// The namespace of the resource if any
+  pub namespace_name: Option<String>,
   /// The creation date of the resource
   pub created_at: chrono::NaiveDateTime,
   /// The update date of the resource
@@ -52,19 +105,45 @@ pub struct Resource {
   /// The config of the resource as a json object
   #[cfg_attr(feature = "utoipa", schema(value_type = HashMap<String, Any>))]
   pub config: serde_json::Value,
+  /// The owners of the resource
+  #[cfg_attr(feature = "serde", serde(default))]
+  pub owner_refs: Vec<ResourceOwnerRef>,
+  /// Labels of the resource
+  #[cfg_attr(feature = "serde", serde(default))]
+  pub labels: HashMap<String, String>,
 }
 
 impl From<Resource> for ResourcePartial {
   fn from(resource: Resource) -> Self {
     Self {
       name: resource.name,
+      namespace: resource.namespace_name,
       kind: resource.kind,
       version: resource.version,
       config: resource.config,
+      owner_refs: Some(resource.owner_refs),
+      labels: Some(resource.labels),
     }
   }
 }
 
+/// Query of the `PUT /rules` endpoint of a controller
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
+pub struct ResourceRuleQuery {
+  /// The key the resource is stored with,
+  /// the name of the resource when not given
+  pub key: Option<String>,
+}
+
+impl ResourceRuleQuery {
+  /// The key the resource is stored with
+  pub fn key_or<'a>(&'a self, name: &'a str) -> &'a str {
+    self.key.as_deref().unwrap_or(name)
+  }
+}
+
 #[derive(Debug, Clone)]
 #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
@@ -81,10 +160,51 @@ pub struct ResourceConfig {
   pub data: serde_json::Value,
 }
 
+/// Migration shipped with a kind version
+/// It's used to upgrade resources from the previous version of the kind
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
+pub enum ResourceKindMigration {
+  /// A list of json patch operations (RFC 6902) applied to the config
+  Patch(
+    #[cfg_attr(feature = "utoipa", schema(value_type = Vec<Any>))]
+    Vec<serde_json::Value>,
+  ),
+  /// Ask the controller of the kind to migrate the config
+  Controller,
+}
+
+/// Payload used to migrate every resource of a kind to a given version
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
+pub struct ResourceMigrate {
+  /// The kind of the resources to migrate
+  pub kind: String,
+  /// The version to migrate to
+  pub version: String,
+}
+
 #[derive(Debug, Default)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
 pub struct ResourceQuery {
   pub kind: Option<String>,
+  pub namespace: Option<String>,
   pub contains: Option<String>,
+  /// Filter resources whose owners are all gone
+  pub orphaned: Option<bool>,
+  /// Label selector as a comma separated list of `key=value` or `key!=value`
+  pub labels: Option<String>,
+  /// JSON path predicates on the config as a comma separated list
+  /// of `$.Path.To.Field=value` or `$.Path.To.Field!=value`,
+  /// the value is a string unless written `:=` or `!:=` to match a json value
+  pub config_path: Option<String>,
+  /// Max amount of resources in response
+  pub limit: Option<i64>,
+  /// Offset of the first resource in response
+  pub offset: Option<i64>,
 }
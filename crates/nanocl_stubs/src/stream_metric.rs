@@ -0,0 +1,55 @@
+use uuid::Uuid;
+
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+
+#[cfg(feature = "serde")]
+use crate::http_metric::deserialize_status_between;
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
+pub struct StreamMetric {
+  pub key: Uuid,
+  pub created_at: chrono::NaiveDateTime,
+  pub expire_at: chrono::NaiveDateTime,
+  /// Date when the session started
+  pub date_gmt: chrono::NaiveDateTime,
+  /// Duration of the session in seconds
+  pub session_time: f64,
+  /// Bytes sent to the client
+  pub bytes_sent: i64,
+  /// Bytes received from the client
+  pub bytes_received: i64,
+  /// Status of the session 200 | 400 | 403 | 500 | 502 | 503
+  pub status: i64,
+  /// Port listened by the proxy
+  pub server_port: i64,
+  /// Protocol of the session TCP | UDP
+  pub protocol: String,
+  pub node_name: String,
+  pub remote_addr: String,
+  pub upstream_addr: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
+pub struct StreamMetricListQuery {
+  pub limit: Option<i64>,
+  pub offset: Option<i64>,
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
+pub struct StreamMetricCountQuery {
+  #[cfg_attr(
+    feature = "serde",
+    serde(default, deserialize_with = "deserialize_status_between")
+  )]
+  pub status: Option<(i64, Option<i64>)>,
+}
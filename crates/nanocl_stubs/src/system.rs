@@ -4,6 +4,7 @@ use bollard_next::service::SystemInfo;
 #[cfg(feature = "serde")]
 use serde::{Serialize, Deserialize};
 
+use super::vm::VmInspect;
 use super::cargo::CargoInspect;
 use super::resource::Resource;
 
@@ -17,6 +18,9 @@ pub struct HostInfo {
   pub docker: SystemInfo,
   /// HostGateway is the gateway address of the host
   pub host_gateway: String,
+  /// AdvertiseAddr is the address advertised by the node
+  #[cfg_attr(feature = "serde", serde(default))]
+  pub advertise_addr: String,
 }
 
 /// Version contain details about the current version nanocl
@@ -53,6 +57,14 @@ pub enum Event {
   ResourceDeleted(Box<Resource>),
   /// ResourcePatched is sent when a resource is patched
   ResourcePatched(Box<Resource>),
+  /// VmStarted is sent when a virtual machine is started
+  VmStarted(Box<VmInspect>),
+  /// VmStopped is sent when a virtual machine is stopped
+  VmStopped(Box<VmInspect>),
+  /// VmPatched is sent when a virtual machine is patched
+  VmPatched(Box<VmInspect>),
+  /// VmDeleted is sent when a virtual machine is deleted
+  VmDeleted(Box<VmInspect>),
 }
 
 impl std::fmt::Display for Event {
@@ -65,14 +77,18 @@ impl std::fmt::Display for Event {
       Event::CargoStopped(cargo) => write!(f, "CargoStopped({})", cargo.key),
       Event::CargoPatched(cargo) => write!(f, "CargoPatched({})", cargo.key),
       Event::ResourceCreated(resource) => {
-        write!(f, "ResourceCreated({})", resource.name)
+        write!(f, "ResourceCreated({})", resource.key)
       }
       Event::ResourceDeleted(resource) => {
-        write!(f, "ResourceDeleted({})", resource.name)
+        write!(f, "ResourceDeleted({})", resource.key)
       }
       Event::ResourcePatched(resource) => {
-        write!(f, "ResourcePatched({})", resource.name)
+        write!(f, "ResourcePatched({})", resource.key)
       }
+      Event::VmStarted(vm) => write!(f, "VmStarted({})", vm.key),
+      Event::VmStopped(vm) => write!(f, "VmStopped({})", vm.key),
+      Event::VmPatched(vm) => write!(f, "VmPatched({})", vm.key),
+      Event::VmDeleted(vm) => write!(f, "VmDeleted({})", vm.key),
     }
   }
 }
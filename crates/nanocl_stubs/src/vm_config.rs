@@ -60,6 +60,7 @@ pub struct VmConfigPartial {
   /// Name of the vm
   pub name: String,
   /// Hostname of the vm
+  /// A dns label of a-z, 0-9 and - up to 63 characters
   pub hostname: Option<String>,
   /// Default user of the vm (cloud)
   pub user: Option<String>,
@@ -88,6 +89,7 @@ pub struct VmConfigUpdate {
   /// Name of the vm
   pub name: Option<String>,
   /// Hostname of the vm
+  /// A dns label of a-z, 0-9 and - up to 63 characters
   pub hostname: Option<String>,
   /// Default user of the vm (cloud)
   pub user: Option<String>,
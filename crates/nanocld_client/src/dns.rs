@@ -0,0 +1,104 @@
+use nanocl_stubs::dns::{DnsEntrySummary, DnsResolve};
+
+use super::error::NanocldClientError;
+use super::http_client::NanocldClient;
+
+impl NanocldClient {
+  /// ## List dns records
+  ///
+  /// List the entries served by the dns controller
+  ///
+  /// ## Returns
+  ///
+  /// * [Result](Result) - The result of the operation
+  ///   * [Ok](Vec<DnsEntrySummary>) - The entries sorted by source and name
+  ///   * [Err](NanocldClientError) - An error if the operation failed
+  ///
+  /// ## Example
+  ///
+  /// ```no_run,ignore
+  /// use nanocld_client::NanocldClient;
+  ///
+  /// let client = NanocldClient::connect_with_unix_default();
+  /// let records = client.list_dns_records().await;
+  /// ```
+  ///
+  pub async fn list_dns_records(
+    &self,
+  ) -> Result<Vec<DnsEntrySummary>, NanocldClientError> {
+    let res = self
+      .send_get(format!("/{}/dns/records", &self.version), None::<String>)
+      .await?;
+
+    Self::res_json(res).await
+  }
+
+  /// ## Resolve dns
+  ///
+  /// Explain how a name is answered by the dns controller
+  /// The targets of the cnames are followed
+  ///
+  /// ## Arguments
+  ///
+  /// * [name](str) - The name to resolve
+  ///
+  /// ## Returns
+  ///
+  /// * [Result](Result) - The result of the operation
+  ///   * [Ok](DnsResolve) - The answer and the entries matching the name
+  ///   * [Err](NanocldClientError) - An error if the operation failed
+  ///
+  /// ## Example
+  ///
+  /// ```no_run,ignore
+  /// use nanocld_client::NanocldClient;
+  ///
+  /// let client = NanocldClient::connect_with_unix_default();
+  /// let resolve = client.resolve_dns("app.global.nanocl.internal").await;
+  /// ```
+  ///
+  pub async fn resolve_dns(
+    &self,
+    name: &str,
+  ) -> Result<DnsResolve, NanocldClientError> {
+    let res = self
+      .send_get(
+        format!("/{}/dns/resolve/{name}", &self.version),
+        None::<String>,
+      )
+      .await?;
+
+    Self::res_json(res).await
+  }
+
+  /// ## Sync dns
+  ///
+  /// Force the dns controller to sync its entries with the state of the daemon
+  ///
+  /// ## Returns
+  ///
+  /// * [Result](Result) - The result of the operation
+  ///   * [Ok](()) - The entries are synced
+  ///   * [Err](NanocldClientError) - An error if the operation failed
+  ///
+  /// ## Example
+  ///
+  /// ```no_run,ignore
+  /// use nanocld_client::NanocldClient;
+  ///
+  /// let client = NanocldClient::connect_with_unix_default();
+  /// client.sync_dns().await;
+  /// ```
+  ///
+  pub async fn sync_dns(&self) -> Result<(), NanocldClientError> {
+    self
+      .send_post(
+        format!("/{}/dns/sync", &self.version),
+        None::<String>,
+        None::<String>,
+      )
+      .await?;
+
+    Ok(())
+  }
+}
@@ -0,0 +1,42 @@
+use super::error::NanocldClientError;
+use super::http_client::NanocldClient;
+
+impl NanocldClient {
+  /// ## Create http metrics
+  ///
+  /// Save a batch of entries of the json http access log of the proxy
+  ///
+  /// ## Arguments
+  ///
+  /// * [metrics](Vec<serde_json::Value>) - The entries as written by nginx
+  ///
+  /// ## Returns
+  ///
+  /// * [Result](Result) - The result of the operation
+  ///   * [Ok](()) - The metrics are saved
+  ///   * [Err](NanocldClientError) - An error if the operation failed
+  ///
+  /// ## Example
+  ///
+  /// ```no_run,ignore
+  /// use nanocld_client::NanocldClient;
+  ///
+  /// let client = NanocldClient::connect_with_unix_default();
+  /// client.create_http_metrics(&metrics).await;
+  /// ```
+  ///
+  pub async fn create_http_metrics(
+    &self,
+    metrics: &[serde_json::Value],
+  ) -> Result<(), NanocldClientError> {
+    self
+      .send_post(
+        format!("/{}/http_metrics", &self.version),
+        Some(metrics),
+        None::<String>,
+      )
+      .await?;
+
+    Ok(())
+  }
+}
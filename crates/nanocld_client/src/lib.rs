@@ -8,6 +8,9 @@ pub(crate) mod resource;
 pub(crate) mod state;
 pub(crate) mod vm;
 pub(crate) mod vm_image;
+pub(crate) mod http_metric;
+pub(crate) mod stream_metric;
+pub(crate) mod dns;
 
 pub mod error;
 pub use http_client::*;
@@ -1,5 +1,8 @@
+use nanocl_stubs::generic::GenericNspQuery;
+use nanocl_stubs::proxy::{ResourceCertificateInspect, ResourceCertificateQuery};
 use nanocl_stubs::resource::{
   Resource, ResourcePartial, ResourceConfig, ResourceQuery, ResourcePatch,
+  ResourceMigrate, ResourceSecret,
 };
 
 use super::http_client::NanocldClient;
@@ -59,6 +62,9 @@ impl NanocldClient {
   /// let client = NanocldClient::connect_with_unix_default();
   /// let resource = client.create_resource(&ResourcePartial {
   ///   name: "my-resource".into(),
+  ///   namespace: None,
+  ///   owner_refs: None,
+  ///   labels: None,
   ///   kind: String::from("Custom")s,
   ///   // Your config
   ///   config: serde_json::json!({}),
@@ -87,6 +93,7 @@ impl NanocldClient {
   /// ## Arguments
   ///
   /// * [key](str) - The key of the resource to inspect
+  /// * [namespace](Option<String>) - The namespace of the resource
   ///
   /// ## Returns
   ///
@@ -100,23 +107,149 @@ impl NanocldClient {
   /// use nanocld_client::NanocldClient;
   ///
   /// let client = NanocldClient::connect_with_unix_default();
-  /// let resource = client.inspect_resource("my-resource").await;
+  /// let resource = client.inspect_resource("my-resource", None).await;
   /// ```
   ///
   pub async fn inspect_resource(
     &self,
     key: &str,
+    namespace: Option<String>,
   ) -> Result<Resource, NanocldClientError> {
     let res = self
       .send_get(
         format!("/{}/resources/{key}", &self.version),
-        None::<String>,
+        Some(GenericNspQuery { namespace }),
+      )
+      .await?;
+
+    Self::res_json(res).await
+  }
+
+  /// ## Inspect resource certificate
+  ///
+  /// Get the material of a `Certificate` resource
+  /// The decrypted key is only returned over the unix socket of the daemon
+  ///
+  /// ## Arguments
+  ///
+  /// * [key](str) - The key of the certificate resource
+  /// * [namespace](Option<String>) - The namespace of the resource
+  /// * [with_key](bool) - Include the decrypted key
+  ///
+  /// ## Returns
+  ///
+  /// * [Result](Result) - The result of the operation
+  ///   * [Ok](ResourceCertificateInspect) - The certificate material
+  ///   * [Err](NanocldClientError) - An error if the operation failed
+  ///
+  /// ## Example
+  ///
+  /// ```no_run,ignore
+  /// use nanocld_client::NanocldClient;
+  ///
+  /// let client = NanocldClient::connect_with_unix_default();
+  /// let certificate = client.inspect_resource_certificate("my-cert", None, false).await;
+  /// ```
+  ///
+  pub async fn inspect_resource_certificate(
+    &self,
+    key: &str,
+    namespace: Option<String>,
+    with_key: bool,
+  ) -> Result<ResourceCertificateInspect, NanocldClientError> {
+    let res = self
+      .send_get(
+        format!("/{}/resources/{key}/certificate", &self.version),
+        Some(ResourceCertificateQuery {
+          namespace,
+          with_key: Some(with_key),
+        }),
       )
       .await?;
 
     Self::res_json(res).await
   }
 
+  /// ## Inspect resource secret
+  ///
+  /// Get the data of a `Secret` resource with its values decrypted
+  /// Only allowed when connected to the unix socket of the daemon
+  ///
+  /// ## Arguments
+  ///
+  /// * [key](str) - The key of the secret resource
+  /// * [namespace](Option<String>) - The namespace of the resource
+  ///
+  /// ## Returns
+  ///
+  /// * [Result](Result) - The result of the operation
+  ///   * [Ok](ResourceSecret) - The secret data
+  ///   * [Err](NanocldClientError) - An error if the operation failed
+  ///
+  /// ## Example
+  ///
+  /// ```no_run,ignore
+  /// use nanocld_client::NanocldClient;
+  ///
+  /// let client = NanocldClient::connect_with_unix_default();
+  /// let secret = client.inspect_resource_secret("my-secret", None).await;
+  /// ```
+  ///
+  pub async fn inspect_resource_secret(
+    &self,
+    key: &str,
+    namespace: Option<String>,
+  ) -> Result<ResourceSecret, NanocldClientError> {
+    let res = self
+      .send_get(
+        format!("/{}/resources/{key}/secret", &self.version),
+        Some(GenericNspQuery { namespace }),
+      )
+      .await?;
+
+    Self::res_json(res).await
+  }
+
+  /// ## Purge resource cache
+  ///
+  /// Remove the cached responses of a `ProxyRule` resource
+  ///
+  /// ## Arguments
+  ///
+  /// * [key](str) - The key of the proxy rule
+  /// * [namespace](Option<String>) - The namespace of the resource
+  ///
+  /// ## Returns
+  ///
+  /// * [Result](Result) - The result of the operation
+  ///   * [Ok](()) - The cache is purged
+  ///   * [Err](NanocldClientError) - An error if the operation failed
+  ///
+  /// ## Example
+  ///
+  /// ```no_run,ignore
+  /// use nanocld_client::NanocldClient;
+  ///
+  /// let client = NanocldClient::connect_with_unix_default();
+  /// client.purge_resource_cache("my-rule", None).await;
+  /// ```
+  ///
+  pub async fn purge_resource_cache(
+    &self,
+    key: &str,
+    namespace: Option<String>,
+  ) -> Result<(), NanocldClientError> {
+    self
+      .send_post(
+        format!("/{}/resources/{key}/cache/purge", &self.version),
+        None::<String>,
+        Some(GenericNspQuery { namespace }),
+      )
+      .await?;
+
+    Ok(())
+  }
+
   /// ## Patch resource
   ///
   /// Patch an existing resource
@@ -125,6 +258,7 @@ impl NanocldClient {
   ///
   /// * [key](str) - The key of the resource to patch
   /// * [data](ResourcePartial) - The data to patch
+  /// * [namespace](Option<String>) - The namespace of the resource
   ///
   /// ## Returns
   ///
@@ -138,19 +272,20 @@ impl NanocldClient {
   /// use nanocld_client::NanocldClient;
   ///
   /// let client = NanocldClient::connect_with_unix_default();
-  /// let resource = client.patch_resource("my-resource", serde_json::json!({})).await;
+  /// let resource = client.patch_resource("my-resource", serde_json::json!({}), None).await;
   /// ```
   ///
   pub async fn patch_resource(
     &self,
     key: &str,
     config: &ResourcePatch,
+    namespace: Option<String>,
   ) -> Result<Resource, NanocldClientError> {
     let res = self
       .send_patch(
         format!("/{}/resources/{key}", &self.version),
         Some(config),
-        None::<String>,
+        Some(GenericNspQuery { namespace }),
       )
       .await?;
 
@@ -164,6 +299,7 @@ impl NanocldClient {
   /// ## Arguments
   ///
   /// * [key](str) - The key of the resource to delete
+  /// * [namespace](Option<String>) - The namespace of the resource
   ///
   /// ## Returns
   ///
@@ -177,17 +313,18 @@ impl NanocldClient {
   /// use nanocld_client::NanocldClient;
   ///
   /// let client = NanocldClient::connect_with_unix_default();
-  /// let resource = client.delete_resource("my-resource").await;
+  /// let resource = client.delete_resource("my-resource", None).await;
   /// ```
   ///
   pub async fn delete_resource(
     &self,
     key: &str,
+    namespace: Option<String>,
   ) -> Result<(), NanocldClientError> {
     self
       .send_delete(
         format!("/{}/resources/{key}", &self.version),
-        None::<String>,
+        Some(GenericNspQuery { namespace }),
       )
       .await?;
 
@@ -197,11 +334,12 @@ impl NanocldClient {
   pub async fn list_history_resource(
     &self,
     key: &str,
+    namespace: Option<String>,
   ) -> Result<Vec<ResourceConfig>, NanocldClientError> {
     let res = self
       .send_get(
         format!("/{}/resources/{key}/histories", &self.version),
-        None::<String>,
+        Some(GenericNspQuery { namespace }),
       )
       .await?;
 
@@ -212,11 +350,88 @@ impl NanocldClient {
     &self,
     name: &str,
     key: &str,
+    namespace: Option<String>,
   ) -> Result<Resource, NanocldClientError> {
     let res = self
       .send_patch(
         format!("/{}/resources/{name}/histories/{key}/reset", &self.version),
         None::<String>,
+        Some(GenericNspQuery { namespace }),
+      )
+      .await?;
+
+    Self::res_json(res).await
+  }
+
+  /// ## Migrate resource
+  ///
+  /// Migrate every resource of a kind to the given version of the kind
+  ///
+  /// ## Arguments
+  ///
+  /// * [kind](str) - The kind of the resources to migrate
+  /// * [version](str) - The version to migrate to
+  ///
+  /// ## Returns
+  ///
+  /// * [Result](Result) - The result of the operation
+  ///   * [Ok](Vec<Resource>) - The migrated resources
+  ///   * [Err](NanocldClientError) - An error if the operation failed
+  ///
+  /// ## Example
+  ///
+  /// ```no_run,ignore
+  /// use nanocld_client::NanocldClient;
+  ///
+  /// let client = NanocldClient::connect_with_unix_default();
+  /// let resources = client.migrate_resource("my-kind", "v2").await;
+  /// ```
+  ///
+  pub async fn migrate_resource(
+    &self,
+    kind: &str,
+    version: &str,
+  ) -> Result<Vec<Resource>, NanocldClientError> {
+    let res = self
+      .send_post(
+        format!("/{}/resources/migrate", &self.version),
+        Some(ResourceMigrate {
+          kind: kind.to_owned(),
+          version: version.to_owned(),
+        }),
+        None::<String>,
+      )
+      .await?;
+
+    Self::res_json(res).await
+  }
+
+  /// ## Prune resources
+  ///
+  /// Delete every resource whose owners are all gone
+  ///
+  /// ## Returns
+  ///
+  /// * [Result](Result) - The result of the operation
+  ///   * [Ok](Vec<Resource>) - The deleted resources
+  ///   * [Err](NanocldClientError) - An error if the operation failed
+  ///
+  /// ## Example
+  ///
+  /// ```no_run,ignore
+  /// use nanocld_client::NanocldClient;
+  ///
+  /// let client = NanocldClient::connect_with_unix_default();
+  /// let resources = client.prune_resource().await;
+  /// ```
+  ///
+  pub async fn prune_resource(
+    &self,
+  ) -> Result<Vec<Resource>, NanocldClientError> {
+    let res = self
+      .send_post(
+        format!("/{}/resources/prune", &self.version),
+        None::<String>,
         None::<String>,
       )
       .await?;
@@ -227,6 +442,7 @@ impl NanocldClient {
 
 #[cfg(test)]
 mod tests {
+  use nanocl_stubs::generic::GenericNspQuery;
   use nanocl_stubs::resource::{ResourcePartial, ResourcePatch};
 
   use super::*;
@@ -256,9 +472,12 @@ mod tests {
 
     let resource = ResourcePartial {
       name: "test_resource2".to_owned(),
+      namespace: None,
       version: "v0.0.1".to_owned(),
       kind: "Custom".to_owned(),
       config: config.clone(),
+      owner_refs: None,
+      labels: None,
     };
 
     // create
@@ -268,7 +487,10 @@ mod tests {
     assert_eq!(resource.kind, String::from("Custom"));
 
     // inspect
-    let resource = client.inspect_resource("test_resource2").await.unwrap();
+    let resource = client
+      .inspect_resource("test_resource2", None)
+      .await
+      .unwrap();
     assert_eq!(resource.name, "test_resource2");
     assert_eq!(resource.kind, String::from("Custom"));
 
@@ -279,7 +501,7 @@ mod tests {
 
     // patch
     let resource = client
-      .patch_resource("test_resource2", &new_resource)
+      .patch_resource("test_resource2", &new_resource, None)
       .await
       .unwrap();
 
@@ -288,12 +510,15 @@ mod tests {
 
     // history
     let history = client
-      .list_history_resource("test_resource2")
+      .list_history_resource("test_resource2", None)
       .await
       .unwrap();
     assert!(history.len() > 1);
 
     // delete
-    client.delete_resource("test_resource2").await.unwrap();
+    client
+      .delete_resource("test_resource2", None)
+      .await
+      .unwrap();
   }
 }